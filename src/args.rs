@@ -0,0 +1,77 @@
+//! Canonical forms of compiler arguments.
+//!
+//! CL.exe accepts a space between some flags and their value, as in
+//! `/I include` or `/D WIN32`. Joining those pairs into `/Iinclude` and
+//! `/DWIN32` keeps the value from being mistaken for a source file and lets
+//! every later rewrite treat the flag as one argument.
+
+/// Flags whose value may follow as the next argument
+const FLAGS_WITH_SEPARATE_VALUE: &[&str] = &["AI", "D", "external:I", "FI", "FU", "I", "U"];
+
+/// Check if `token` is one of these flags written without its value
+fn takes_separate_value(token: &str) -> bool {
+    token
+        .strip_prefix(['/', '-'])
+        .is_some_and(|flag| FLAGS_WITH_SEPARATE_VALUE.contains(&flag))
+}
+
+/// Join flags written apart from their value with that value. A flag at the
+/// end of the command is kept as it is.
+pub fn join_separated_flags(arguments: Vec<String>) -> Vec<String> {
+    let mut joined = Vec::with_capacity(arguments.len());
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        if takes_separate_value(&argument)
+            && let Some(value) = arguments.next()
+        {
+            joined.push(format!("{}{}", argument, value));
+        } else {
+            joined.push(argument);
+        }
+    }
+    joined
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_join_separated_flags() {
+        let arguments = strings(&[
+            "/D",
+            "WIN32",
+            "-I",
+            r#""C:\a b""#,
+            "/FI",
+            "pch.h",
+            "/external:I",
+            "sdk",
+            "/DX",
+            "/Dummy",
+            "main.cpp",
+            "/U",
+        ]);
+        assert_eq!(
+            join_separated_flags(arguments),
+            strings(&[
+                "/DWIN32",
+                r#"-I"C:\a b""#,
+                "/FIpch.h",
+                "/external:Isdk",
+                "/DX",
+                "/Dummy",
+                "main.cpp",
+                "/U"
+            ])
+        );
+    }
+}
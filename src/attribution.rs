@@ -0,0 +1,145 @@
+//! `--attribution`: which project a CL line belongs to when the log does not
+//! say.
+//!
+//! Builds run with `/m` but logged without node prefixes interleave the CL
+//! lines of different projects, and the project entered last is often the
+//! wrong one. The heuristic looks at the paths the line names instead: the
+//! project whose directory holds the object file or the sources wins.
+
+use crate::{long_path, path_key, validation::is_absolute};
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+
+/// How CL lines are attributed to projects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Attribution {
+    /// Only from the log: the project of the output prefix, else the project
+    /// entered last
+    #[default]
+    Strict,
+    /// Before falling back to the project entered last, use the project
+    /// whose directory contains the object file or the sources
+    Heuristic,
+}
+
+/// Path folded for comparisons, with `\` separators
+fn key(path: &str) -> String {
+    path_key(&path.trim_matches('"').replace('/', "\\"))
+}
+
+/// Check if `path` is `dir` or below it; both folded by [`key`]
+fn is_below(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('\\') || dir.ends_with('\\'))
+}
+
+/// The only item of `candidates`, if there is exactly one
+fn single<'a>(mut candidates: impl Iterator<Item = &'a PathBuf>) -> Option<&'a PathBuf> {
+    let first = candidates.next()?;
+    candidates.next().is_none().then_some(first)
+}
+
+/// Project of `projects` that the paths of a CL line point at: the one with
+/// the deepest directory containing an absolute path, else the only one
+/// whose directory has the relative sources on disk
+pub fn guess_project<'a>(
+    paths: &[String],
+    projects: impl Iterator<Item = &'a PathBuf> + Clone,
+) -> Option<&'a PathBuf> {
+    let dirs: Vec<(String, &PathBuf)> = projects
+        .clone()
+        .filter_map(|project| {
+            let project_key = key(&project.to_string_lossy());
+            let (dir, _) = project_key.rsplit_once('\\')?;
+            (!dir.is_empty()).then(|| (dir.to_string(), project))
+        })
+        .collect();
+
+    let absolute: Vec<String> = paths
+        .iter()
+        .filter(|path| is_absolute(path.trim_matches('"')))
+        .map(|path| key(path))
+        .collect();
+    let containing: Vec<&(String, &PathBuf)> = dirs
+        .iter()
+        .filter(|(dir, _)| absolute.iter().any(|path| is_below(path, dir)))
+        .collect();
+    if let Some(depth) = containing.iter().map(|(dir, _)| dir.len()).max() {
+        return single(
+            containing
+                .iter()
+                .filter(|(dir, _)| dir.len() == depth)
+                .map(|(_, project)| *project),
+        );
+    }
+
+    let relative: Vec<&str> = paths
+        .iter()
+        .map(|path| path.trim_matches('"'))
+        .filter(|path| !is_absolute(path))
+        .collect();
+    if relative.is_empty() {
+        return None;
+    }
+    single(projects.filter(|project| {
+        let dir = project.parent().unwrap_or(Path::new("."));
+        relative
+            .iter()
+            .all(|source| long_path::for_filesystem(&dir.join(source)).is_file())
+    }))
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_guess_project_by_absolute_path() {
+        let projects = [
+            PathBuf::from(r"C:\src\app\app.vcxproj"),
+            PathBuf::from(r"C:\src\app\plugin\plugin.vcxproj"),
+            PathBuf::from(r"C:\src\lib\lib.vcxproj"),
+        ];
+        let guess = |paths: &[&str]| {
+            let paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+            guess_project(&paths, projects.iter()).cloned()
+        };
+
+        // A relative source that is not on disk says nothing
+        assert_eq!(guess(&["a.cpp"]), None);
+        assert_eq!(
+            guess(&[r"c:/SRC/lib/x64/Debug/"]),
+            Some(projects[2].clone())
+        );
+        // The deepest project directory wins
+        assert_eq!(
+            guess(&[r"C:\src\app\plugin\p.cpp"]),
+            Some(projects[1].clone())
+        );
+        assert_eq!(guess(&[r"C:\src\application\a.cpp"]), None);
+    }
+
+    #[test]
+    fn test_guess_project_by_relative_source() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("only_a.cpp"), "").unwrap();
+        fs::write(a.join("both.cpp"), "").unwrap();
+        fs::write(b.join("both.cpp"), "").unwrap();
+        let projects = [a.join("a.vcxproj"), b.join("b.vcxproj")];
+
+        let paths = vec!["only_a.cpp".to_string()];
+        assert_eq!(guess_project(&paths, projects.iter()), Some(&projects[0]));
+        let paths = vec!["both.cpp".to_string()];
+        assert_eq!(guess_project(&paths, projects.iter()), None);
+    }
+}
@@ -0,0 +1,171 @@
+//! `--emit-clangd-config`: a `.clangd` fragment with the flags clangd needs
+//! to parse code written for the MSVC toolchain found in the log.
+//!
+//! clangd guesses the target and MSVC version from the machine it runs on,
+//! which often differs from the build machine. Both are read from the path
+//! of CL.exe, e.g. `...\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\cl.exe`:
+//! toolset 14.44 is compiler version 19.44 and `x64` is the target.
+
+use crate::{CompileCommand, tokenize_command_line};
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use log::{info, warn};
+use std::{fs, path::Path};
+
+/// First line of the files ms2cc writes; others are never overwritten
+const HEADER: &str = "# Generated by ms2cc --emit-clangd-config";
+
+/// MSVC toolchain of a CL.exe path
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct Toolchain {
+    /// Compiler version for `-fms-compatibility-version`, e.g. `19.44`
+    version: Option<String>,
+    /// Target triple, e.g. `x86_64-pc-windows-msvc`
+    target: Option<&'static str>,
+}
+
+/// Target triple of a `bin\Host*\<arch>` directory name
+fn target_triple(arch: &str) -> Option<&'static str> {
+    match arch.to_ascii_lowercase().as_str() {
+        "x64" | "amd64" => Some("x86_64-pc-windows-msvc"),
+        "x86" => Some("i686-pc-windows-msvc"),
+        "arm64" => Some("aarch64-pc-windows-msvc"),
+        "arm" => Some("thumbv7-pc-windows-msvc"),
+        _ => None,
+    }
+}
+
+/// Toolchain of the CL.exe at `path`, as far as the path tells
+fn toolchain(path: &str) -> Toolchain {
+    let parts: Vec<&str> = path.trim_matches('"').split(['\\', '/']).collect();
+    let version = parts
+        .windows(2)
+        .find(|pair| pair[0].eq_ignore_ascii_case("msvc"))
+        .and_then(|pair| {
+            let mut numbers = pair[1].split('.');
+            let major: u32 = numbers.next()?.parse().ok()?;
+            let minor: u32 = numbers.next()?.parse().ok()?;
+            // Toolset 14.x ships compiler 19.x
+            (major == 14).then(|| format!("19.{}", minor))
+        });
+    let target = parts
+        .len()
+        .checked_sub(2)
+        .and_then(|index| target_triple(parts[index]));
+    Toolchain { version, target }
+}
+
+/// The toolchain most CL.exe entries were compiled with
+fn common_toolchain(entries: &[CompileCommand]) -> Option<Toolchain> {
+    let mut counts: IndexMap<Toolchain, usize> = IndexMap::new();
+    for entry in entries.iter().filter(|entry| entry.compiler() == "cl") {
+        let Some(program) = tokenize_command_line(&entry.command).into_iter().next() else {
+            continue;
+        };
+        let toolchain = toolchain(&program);
+        if toolchain != Toolchain::default() {
+            *counts.entry(toolchain).or_default() += 1;
+        }
+    }
+    // max_by_key keeps the last of equal counts; prefer the first seen
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(toolchain, _)| toolchain)
+}
+
+/// The `.clangd` fragment adding the flags of `toolchain`
+fn fragment(toolchain: &Toolchain) -> String {
+    let mut text = format!("{}\nCompileFlags:\n  Add:\n", HEADER);
+    if let Some(target) = toolchain.target {
+        text.push_str(&format!("    - --target={}\n", target));
+    }
+    if let Some(version) = &toolchain.version {
+        text.push_str(&format!("    - -fms-compatibility-version={}\n", version));
+    }
+    text
+}
+
+/// Write the `.clangd` fragment for the toolchain of `entries` to `path`.
+/// A file not written by ms2cc is left alone.
+pub fn write(path: &Path, entries: &[CompileCommand]) -> Result<()> {
+    let Some(toolchain) = common_toolchain(entries) else {
+        warn!(
+            "No CL.exe path with a toolset version in the log; not writing {}",
+            path.display()
+        );
+        return Ok(());
+    };
+    if let Ok(existing) = fs::read_to_string(path)
+        && !existing.starts_with(HEADER)
+    {
+        warn!(
+            "{} was not written by ms2cc; not replacing it",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    fs::write(path, fragment(&toolchain))
+        .with_context(|| format!("Failed to write clangd config: {}", path.display()))?;
+    info!("Wrote clangd config to {}", path.display());
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const CL: &str = r"C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe";
+
+    fn entry(compiler: &str) -> CompileCommand {
+        CompileCommand {
+            directory: "/src".to_string(),
+            command: format!("\"{}\" /c a.cpp", compiler),
+            file: "a.cpp".to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_toolchain() {
+        assert_eq!(
+            toolchain(CL),
+            Toolchain {
+                version: Some("19.44".to_string()),
+                target: Some("x86_64-pc-windows-msvc"),
+            }
+        );
+        assert_eq!(
+            toolchain(r"C:\VC\bin\HostX64\arm64\cl.exe").target,
+            Some("aarch64-pc-windows-msvc")
+        );
+        assert_eq!(toolchain("cl.exe"), Toolchain::default());
+    }
+
+    #[test]
+    fn test_write_keeps_foreign_config() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(".clangd");
+        let entries = vec![entry(CL), entry(r"C:\LLVM\bin\clang-cl.exe")];
+
+        write(&path, &entries).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(HEADER));
+        assert!(text.contains("    - --target=x86_64-pc-windows-msvc\n"));
+        assert!(text.contains("    - -fms-compatibility-version=19.44\n"));
+
+        fs::write(&path, "CompileFlags:\n  Add: [-DMINE]\n").unwrap();
+        write(&path, &entries).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("MINE"));
+    }
+}
@@ -0,0 +1,465 @@
+//! Operations on the final list of database entries, and conversion of
+//! single compiler invocations into entries.
+
+use crate::{
+    CompileCommand, ParseOptions, ProjectContext,
+    diagnostics::{Diagnostics, DroppedSource},
+    long_path, parse_cl_command, path_to_normalized_string,
+    query::ends_with_components,
+    validation::is_absolute,
+};
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use indexmap::IndexMap;
+use log::info;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Number of duplicated files named in a `--dedupe error` failure
+const MAX_REPORTED_DUPLICATES: usize = 5;
+
+/// What to do with several entries for the same source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Dedupe {
+    /// Keep the first entry for each file
+    First,
+    /// Keep the last entry for each file
+    Last,
+    /// Keep every entry
+    #[default]
+    All,
+    /// Fail if any file has more than one entry
+    Error,
+}
+
+/// Remove duplicate entries for the same normalized absolute file path,
+/// ignoring case.
+/// Kept entries stay in the position of the first entry for their file.
+pub fn dedupe(entries: Vec<CompileCommand>, strategy: Dedupe) -> Result<Vec<CompileCommand>> {
+    if strategy == Dedupe::All {
+        return Ok(entries);
+    }
+
+    let total = entries.len();
+    let mut by_file: IndexMap<(String, Option<String>), Vec<CompileCommand>> =
+        IndexMap::with_capacity(total);
+    for entry in entries {
+        let key = (entry.file_key(), entry.configuration.clone());
+        by_file.entry(key).or_default().push(entry);
+    }
+
+    if strategy == Dedupe::Error {
+        let duplicates: Vec<String> = by_file
+            .iter()
+            .filter(|(_, group)| group.len() > 1)
+            .map(|(_, group)| format!("{} ({} entries)", group[0].absolute_file(), group.len()))
+            .collect();
+        if !duplicates.is_empty() {
+            bail!(
+                "{} file(s) have more than one entry: {}{}",
+                duplicates.len(),
+                duplicates[..duplicates.len().min(MAX_REPORTED_DUPLICATES)].join(", "),
+                if duplicates.len() > MAX_REPORTED_DUPLICATES {
+                    ", ..."
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    let kept: Vec<CompileCommand> = by_file
+        .into_values()
+        .filter_map(|mut group| match strategy {
+            Dedupe::Last => group.pop(),
+            _ => group.into_iter().next(),
+        })
+        .collect();
+
+    if kept.len() < total {
+        info!("Removed {} duplicate entries", total - kept.len());
+    }
+    Ok(kept)
+}
+
+/// Drop entries whose source file no longer exists, e.g. files deleted
+/// since an earlier run added them to the database
+pub fn prune_missing(mut entries: Vec<CompileCommand>) -> Vec<CompileCommand> {
+    let total = entries.len();
+    entries.retain(|entry| long_path::for_filesystem(Path::new(&entry.absolute_file())).exists());
+    if entries.len() < total {
+        info!(
+            "Pruned {} entries for missing source files",
+            total - entries.len()
+        );
+    }
+    entries
+}
+
+/// What the `directory` of each entry is set to
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DirectoryMode {
+    /// The project directory the compiler ran in
+    #[default]
+    Project,
+    /// The deepest directory containing every source file
+    SourceRoot,
+    /// A directory chosen by the user
+    Fixed(PathBuf),
+}
+
+impl DirectoryMode {
+    /// Parse `project`, `source-root` or `fixed:<path>`
+    pub fn parse(spec: &str) -> Result<Self> {
+        match spec {
+            "project" => Ok(Self::Project),
+            "source-root" => Ok(Self::SourceRoot),
+            _ => match spec.strip_prefix("fixed:") {
+                Some(path) if !path.is_empty() => Ok(Self::Fixed(PathBuf::from(path))),
+                _ => bail!(
+                    "Invalid directory mode '{}' (expected project, source-root or fixed:<path>)",
+                    spec
+                ),
+            },
+        }
+    }
+}
+
+/// Deepest directory containing the source files of all `entries`
+fn source_root(entries: &[CompileCommand]) -> Option<PathBuf> {
+    let mut files = entries.iter().map(CompileCommand::absolute_file);
+    let mut root = PathBuf::from(files.next()?).parent()?.to_path_buf();
+    for file in files {
+        while !Path::new(&file).starts_with(&root) {
+            if !root.pop() {
+                return None;
+            }
+        }
+    }
+    Some(root)
+}
+
+/// Flags whose value is a path, e.g. `/I..\include` or `/Foobj\`
+const PATH_FLAGS: &[&str] = &[
+    "AI",
+    "external:I",
+    "FI",
+    "FU",
+    "Fa",
+    "Fd",
+    "Fe",
+    "Fi",
+    "Fo",
+    "Fp",
+    "FR",
+    "Fr",
+    "I",
+];
+
+/// `value`, a path that may be quoted, made absolute against `directory`.
+/// Trailing separators are kept: `/Foobj\` names a directory.
+fn absolutize_value(value: &str, directory: &Path) -> String {
+    let quoted = value.len() >= 2 && value.starts_with('"') && value.ends_with('"');
+    let path = if quoted {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    };
+    let trimmed = path.trim_end_matches(['\\', '/']);
+    if trimmed.is_empty() || is_absolute(trimmed) {
+        return value.to_string();
+    }
+    let absolute = format!(
+        "{}{}",
+        path_to_normalized_string(&directory.join(trimmed)),
+        &path[trimmed.len()..]
+    );
+    if quoted {
+        format!("\"{}\"", absolute)
+    } else {
+        absolute
+    }
+}
+
+/// The path flag `argument` is written without its value, if it is one
+fn bare_path_flag(argument: &str) -> bool {
+    argument
+        .strip_prefix(['/', '-'])
+        .is_some_and(|name| PATH_FLAGS.contains(&name))
+}
+
+/// `arguments` with the relative source `file` and the relative values of
+/// path flags, joined or separate, made absolute against `directory`
+fn absolutize_arguments<'a>(
+    arguments: impl IntoIterator<Item = &'a str>,
+    file: &str,
+    directory: &Path,
+) -> Vec<String> {
+    let mut absolute = Vec::new();
+    let mut value_follows = false;
+    for argument in arguments {
+        let rewritten = if std::mem::take(&mut value_follows) || argument.trim_matches('"') == file
+        {
+            absolutize_value(argument, directory)
+        } else if bare_path_flag(argument) {
+            value_follows = true;
+            argument.to_string()
+        } else {
+            argument
+                .strip_prefix(['/', '-'])
+                .and_then(|name| {
+                    let flag = PATH_FLAGS.iter().find(|flag| name.starts_with(*flag))?;
+                    let split = argument.len() - name.len() + flag.len();
+                    let (prefix, value) = argument.split_at(split);
+                    Some(format!("{}{}", prefix, absolutize_value(value, directory)))
+                })
+                .unwrap_or_else(|| argument.to_string())
+        };
+        absolute.push(rewritten);
+    }
+    absolute
+}
+
+/// Set the `directory` of every entry as `mode` asks, writing `file`
+/// relative to it where it lies below it. Relative paths in the command,
+/// such as `/I..\include` and the source, are made absolute first so that
+/// they still name the same files.
+pub fn apply_directory_mode(entries: &mut [CompileCommand], mode: &DirectoryMode) {
+    let root = match mode {
+        DirectoryMode::Project => return,
+        DirectoryMode::SourceRoot => match source_root(entries) {
+            Some(root) => root,
+            None => {
+                info!("Source files share no root directory; keeping project directories");
+                return;
+            }
+        },
+        DirectoryMode::Fixed(path) => path.clone(),
+    };
+
+    let directory = path_to_normalized_string(&root);
+    for entry in entries {
+        let old_directory = PathBuf::from(&entry.directory);
+        let tokens = crate::tokenize_command_line(&entry.command);
+        entry.command = absolutize_arguments(
+            tokens.iter().map(String::as_str),
+            &entry.file,
+            &old_directory,
+        )
+        .join(" ");
+        if let Some(arguments) = &mut entry.arguments {
+            *arguments = absolutize_arguments(
+                arguments.iter().map(String::as_str),
+                &entry.file,
+                &old_directory,
+            );
+        }
+        entry.file = entry.absolute_file();
+        entry.relativize_file(&root);
+        entry.directory = directory.clone();
+    }
+}
+
+/// The sources of `dropped` that have no entry in the final database, e.g.
+/// because no other line of the log compiled them either
+pub fn missing_sources<'a>(
+    dropped: &'a [DroppedSource],
+    entries: &[CompileCommand],
+) -> Vec<&'a DroppedSource> {
+    let files: Vec<String> = entries.iter().map(CompileCommand::absolute_file).collect();
+    dropped
+        .iter()
+        .filter(|source| {
+            !files
+                .iter()
+                .any(|file| ends_with_components(file, &source.file))
+        })
+        .collect()
+}
+
+/// Write the sources that have no entry to `path` as a JSON array
+pub fn write_missing_sources(path: &Path, missing: &[&DroppedSource]) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create dropped sources file: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, missing)
+        .and_then(|()| writer.flush().map_err(serde_json::Error::io))
+        .with_context(|| format!("Failed to write dropped sources file: {}", path.display()))?;
+    info!(
+        "Wrote {} dropped source file(s) to {}",
+        missing.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Convert a single CL.exe command line run in `cwd` into one entry per
+/// source file, the way lines of a build log are converted
+pub fn from_command_line(line: &str, cwd: &Path) -> Result<Vec<CompileCommand>> {
+    from_command_line_with_options(line, cwd, &ParseOptions::default())
+}
+
+/// [`from_command_line`] for the compilers and flags configured in `options`
+pub fn from_command_line_with_options(
+    line: &str,
+    cwd: &Path,
+    options: &ParseOptions,
+) -> Result<Vec<CompileCommand>> {
+    let context = ProjectContext {
+        project_path: cwd.to_path_buf(),
+        project_dir: cwd.to_path_buf(),
+    };
+    // A single command has no log to report problems against
+    parse_cl_command(line, &context, options, &mut Diagnostics::default(), 1)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(directory: &str, file: &str, command: &str) -> CompileCommand {
+        CompileCommand {
+            directory: directory.to_string(),
+            command: command.to_string(),
+            arguments: None,
+            file: file.to_string(),
+            output: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    fn entries() -> Vec<CompileCommand> {
+        vec![
+            entry("/src", "a.cpp", "cl /c /DDEBUG a.cpp"),
+            entry("/src", "b.cpp", "cl /c b.cpp"),
+            entry("/other", "/src/a.cpp", "cl /c /DRELEASE a.cpp"),
+        ]
+    }
+
+    #[test]
+    fn test_missing_sources() {
+        let dropped_source = |file: &str| DroppedSource {
+            file: file.to_string(),
+            reason: crate::diagnostics::Category::NoProjectContext,
+            location: Default::default(),
+        };
+        let dropped = vec![dropped_source("src/a.cpp"), dropped_source("b.cpp")];
+        let entries = vec![entry("/proj", "src/a.cpp", "cl /c src/a.cpp")];
+
+        let missing = missing_sources(&dropped, &entries);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].file, "b.cpp");
+    }
+
+    #[test]
+    fn test_directory_mode() {
+        assert_eq!(
+            DirectoryMode::parse("source-root").unwrap(),
+            DirectoryMode::SourceRoot
+        );
+        assert_eq!(
+            DirectoryMode::parse("fixed:/build").unwrap(),
+            DirectoryMode::Fixed(PathBuf::from("/build"))
+        );
+        assert!(DirectoryMode::parse("fixed:").is_err());
+        assert!(DirectoryMode::parse("root").is_err());
+
+        let mut entries = vec![
+            entry("/src/app", "main.cpp", "cl /c main.cpp"),
+            entry("/src/lib", "/src/lib/util/util.cpp", "cl /c util.cpp"),
+        ];
+        apply_directory_mode(&mut entries, &DirectoryMode::SourceRoot);
+        assert_eq!(entries[0].directory, "/src");
+        assert_eq!(entries[0].file, "app/main.cpp");
+        assert_eq!(entries[1].file, "lib/util/util.cpp");
+
+        apply_directory_mode(
+            &mut entries,
+            &DirectoryMode::Fixed(PathBuf::from("/src/lib")),
+        );
+        assert_eq!(entries[0].directory, "/src/lib");
+        assert_eq!(entries[0].file, "/src/app/main.cpp");
+        assert_eq!(entries[1].file, "util/util.cpp");
+    }
+
+    #[test]
+    fn test_directory_mode_absolutizes_path_flags() {
+        let mut entries = vec![entry(
+            "/src/app",
+            "main.cpp",
+            r#"cl /c /I../include /I "gen dir" /FIpch.h /Foobj/ /I/sdk/inc /DX=1 main.cpp"#,
+        )];
+        entries[0].arguments = Some(vec!["cl".to_string(), "-I../include".to_string()]);
+        apply_directory_mode(&mut entries, &DirectoryMode::Fixed(PathBuf::from("/build")));
+
+        assert_eq!(
+            entries[0].command,
+            r#"cl /c /I/src/include /I "/src/app/gen dir" /FI/src/app/pch.h /Fo/src/app/obj/ /I/sdk/inc /DX=1 /src/app/main.cpp"#
+        );
+        assert_eq!(
+            entries[0].arguments.as_deref(),
+            Some(&["cl".to_string(), "-I/src/include".to_string()][..])
+        );
+        assert_eq!(entries[0].directory, "/build");
+        assert_eq!(entries[0].file, "/src/app/main.cpp");
+    }
+
+    #[test]
+    fn test_dedupe_first_and_last() {
+        let first = dedupe(entries(), Dedupe::First).unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].command, "cl /c /DDEBUG a.cpp");
+        assert_eq!(first[1].file, "b.cpp");
+
+        let last = dedupe(entries(), Dedupe::Last).unwrap();
+        assert_eq!(last.len(), 2);
+        assert_eq!(last[0].command, "cl /c /DRELEASE a.cpp");
+    }
+
+    #[test]
+    fn test_prune_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("kept.cpp"), "").unwrap();
+        let directory = dir.path().display().to_string();
+
+        let pruned = prune_missing(vec![
+            entry(&directory, "kept.cpp", "cl /c kept.cpp"),
+            entry(&directory, "deleted.cpp", "cl /c deleted.cpp"),
+        ]);
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].file, "kept.cpp");
+    }
+
+    #[test]
+    fn test_from_command_line() {
+        let commands = from_command_line(
+            r#""C:\VC\bin\cl.exe" /c /Yupch.h /DX a.cpp sub\b.c"#,
+            Path::new("proj"),
+        )
+        .unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].directory, "proj");
+        assert!(commands[0].command.contains("/c /DX"));
+        assert!(!commands[0].command.contains("/Yu"));
+        assert!(commands[1].file.ends_with("b.c"));
+
+        assert!(from_command_line("link.exe a.obj", Path::new("proj")).is_err());
+    }
+
+    #[test]
+    fn test_dedupe_all_and_error() {
+        assert_eq!(dedupe(entries(), Dedupe::All).unwrap().len(), 3);
+
+        let err = dedupe(entries(), Dedupe::Error).unwrap_err();
+        assert!(err.to_string().contains("(2 entries)"));
+        assert!(dedupe(entries()[..2].to_vec(), Dedupe::Error).is_ok());
+    }
+}
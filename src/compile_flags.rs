@@ -0,0 +1,170 @@
+//! `--format compile-flags`: a single `compile_flags.txt` for clangd instead
+//! of a database.
+//!
+//! Small projects compile every file with the same flags, and clangd applies
+//! `compile_flags.txt` to all files it finds next to or below it. The flags
+//! of the entries are combined into one list, one argument per line.
+
+use crate::{CompileCommand, split_arguments};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use indexmap::IndexSet;
+use log::info;
+use std::{io::Write, path::Path};
+
+/// What ms2cc writes to the output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// `compile_commands.json`
+    #[default]
+    Json,
+    /// `compile_commands.json` entries as JSON Lines, one entry per line
+    Ndjson,
+    /// `compile_flags.txt`
+    CompileFlags,
+}
+
+/// How the flags of all entries are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FlagSet {
+    /// Only flags every entry has
+    #[default]
+    Intersection,
+    /// Every flag any entry has
+    Union,
+}
+
+/// The file clangd reads when it finds no compilation database
+pub const FILE_NAME: &str = "compile_flags.txt";
+
+/// clangd treats the flags as clang's unless told otherwise
+const DRIVER_MODE: &str = "--driver-mode=cl";
+
+/// Flags naming outputs of one compilation, meaningless for the others
+const OUTPUT_FLAGS: &[&str] = &["Fo", "Fd", "Fa", "Fe", "Fi", "FR", "Fr"];
+
+/// Check if `argument` only makes sense for a single translation unit
+fn is_per_file(argument: &str, entry: &CompileCommand) -> bool {
+    if argument == entry.file || argument == entry.absolute_file() {
+        return true;
+    }
+    argument.strip_prefix(['/', '-']).is_some_and(|flag| {
+        flag == "c" || OUTPUT_FLAGS.iter().any(|output| flag.starts_with(output))
+    })
+}
+
+/// The arguments of `entry` that apply to every file, with relative include
+/// directories made absolute since they would resolve against the output
+fn entry_flags(entry: &CompileCommand) -> Vec<String> {
+    let arguments = match &entry.arguments {
+        Some(arguments) => arguments.clone(),
+        None => split_arguments(&entry.command),
+    };
+    arguments
+        .into_iter()
+        .skip(1)
+        .filter(|argument| !is_per_file(argument, entry))
+        .map(|argument| {
+            let Some(dir) = argument
+                .strip_prefix("/I")
+                .or_else(|| argument.strip_prefix("-I"))
+            else {
+                return argument;
+            };
+            if dir.is_empty() || Path::new(dir).is_absolute() {
+                return argument;
+            }
+            let absolute = Path::new(&entry.directory).join(dir);
+            format!("{}{}", &argument[..2], absolute.display())
+        })
+        .collect()
+}
+
+/// Combine the flags of all `entries`, in the order they first appear
+pub fn flags(entries: &[CompileCommand], set: FlagSet) -> Vec<String> {
+    let mut per_entry = entries
+        .iter()
+        .map(|entry| entry_flags(entry).into_iter().collect::<IndexSet<_>>());
+    let Some(first) = per_entry.next() else {
+        return Vec::new();
+    };
+
+    per_entry
+        .fold(first, |mut combined, flags| {
+            match set {
+                FlagSet::Intersection => combined.retain(|flag| flags.contains(flag)),
+                FlagSet::Union => combined.extend(flags),
+            }
+            combined
+        })
+        .into_iter()
+        .collect()
+}
+
+/// Write the combined flags of `entries` to `writer`, one per line
+pub fn write(mut writer: impl Write, entries: &[CompileCommand], set: FlagSet) -> Result<()> {
+    let flags = flags(entries, set);
+    writeln!(writer, "{}", DRIVER_MODE).context("Failed to write compile flags")?;
+    for flag in &flags {
+        writeln!(writer, "{}", flag).context("Failed to write compile flags")?;
+    }
+    writer.flush().context("Failed to write compile flags")?;
+
+    info!(
+        "Combined {} flag(s) from {} entries",
+        flags.len(),
+        entries.len()
+    );
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file: &str, flags: &str) -> CompileCommand {
+        CompileCommand {
+            directory: "/src".to_string(),
+            command: format!("cl.exe /c {} /Fo{}.obj {}", flags, file, file),
+            file: file.to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_intersection_and_union() {
+        let entries = vec![
+            entry("a.cpp", "/DA /DCOMMON /Iinclude"),
+            entry("b.cpp", "/DCOMMON /DB /Iinclude"),
+        ];
+        let include = format!("/I{}", Path::new("/src").join("include").display());
+
+        assert_eq!(
+            flags(&entries, FlagSet::Intersection),
+            vec!["/DCOMMON".to_string(), include.clone()]
+        );
+        assert_eq!(
+            flags(&entries, FlagSet::Union),
+            vec![
+                "/DA".to_string(),
+                "/DCOMMON".to_string(),
+                include,
+                "/DB".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let mut out = Vec::new();
+        write(&mut out, &[entry("a.cpp", "/W4")], FlagSet::Intersection).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "--driver-mode=cl\n/W4\n");
+    }
+}
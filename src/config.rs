@@ -0,0 +1,201 @@
+//! Repository-local defaults loaded from `.ms2cc.toml`.
+//!
+//! The file is discovered by walking from the working directory towards the
+//! filesystem root, the same way clang-format finds `.clang-format`.
+
+use crate::tokenizer::Tokenizer;
+use anyhow::{Context, Result, bail};
+use indexmap::IndexMap;
+use log::debug;
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the configuration file searched for in each ancestor directory
+pub const CONFIG_FILE_NAME: &str = ".ms2cc.toml";
+
+/// Profile used when `--profile` is not given
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Settings read from a `.ms2cc.toml` file. Every field is optional; command
+/// line arguments always take precedence over values from the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Default MSBuild log location
+    pub input_file: Option<PathBuf>,
+    /// Default compile_commands.json location
+    pub output_file: Option<PathBuf>,
+    /// Pretty-print JSON output
+    pub pretty_print: Option<bool>,
+    /// Replace the output file instead of merging
+    pub overwrite: Option<bool>,
+    /// Disable progress bars
+    pub no_progress: Option<bool>,
+    /// Source file extensions recognized on compiler command lines
+    pub extensions: Option<Vec<String>>,
+    /// Glob patterns of source files to keep; empty keeps everything
+    pub include: Vec<String>,
+    /// Glob patterns of source files to leave out of the database
+    pub exclude: Vec<String>,
+    /// Named sets of entry transformers, selected with `--profile`
+    pub profiles: IndexMap<String, Profile>,
+    /// How compiler command lines are split into arguments
+    pub tokenizer: Option<Tokenizer>,
+}
+
+/// A `[profiles.<name>]` table
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Transformers applied to every entry, in order
+    pub transformers: Vec<String>,
+}
+
+impl Config {
+    /// Transformer names for the requested profile. Without a request the
+    /// `default` profile is used if the file defines one.
+    pub fn profile_transformers(&self, name: Option<&str>) -> Result<&[String]> {
+        match name {
+            Some(name) => match self.profiles.get(name) {
+                Some(profile) => Ok(&profile.transformers),
+                None => bail!(
+                    "Unknown profile '{}' (not defined in {})",
+                    name,
+                    CONFIG_FILE_NAME
+                ),
+            },
+            None => Ok(self
+                .profiles
+                .get(DEFAULT_PROFILE)
+                .map_or(&[], |profile| profile.transformers.as_slice())),
+        }
+    }
+}
+
+/// Walk from `start` towards the root and return the first config file found
+pub fn discover(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Load a config file. Relative paths inside it are resolved against the
+/// directory containing the file, so the result does not depend on where
+/// ms2cc is started from.
+pub fn load(path: &Path) -> Result<Config> {
+    debug!("Loading configuration: {}", path.display());
+
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    let mut config: Config = toml::from_str(&text)
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    config.input_file = config.input_file.map(|p| base.join(p));
+    config.output_file = config.output_file.map(|p| base.join(p));
+
+    Ok(config)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_discover_finds_config_in_ancestor() {
+        let root = tempdir().unwrap();
+        let nested = root.path().join("a").join("b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.path().join(CONFIG_FILE_NAME), "").unwrap();
+
+        let found = discover(&nested).expect("config should be found");
+        assert_eq!(found, root.path().join(CONFIG_FILE_NAME));
+    }
+
+    #[test]
+    fn test_discover_prefers_nearest_config() {
+        let root = tempdir().unwrap();
+        let nested = root.path().join("a");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.path().join(CONFIG_FILE_NAME), "").unwrap();
+        fs::write(nested.join(CONFIG_FILE_NAME), "").unwrap();
+
+        assert_eq!(discover(&nested), Some(nested.join(CONFIG_FILE_NAME)));
+    }
+
+    #[test]
+    fn test_load_resolves_paths_relative_to_config() {
+        let root = tempdir().unwrap();
+        let path = root.path().join(CONFIG_FILE_NAME);
+        fs::write(
+            &path,
+            r#"
+input_file = "build.log"
+output_file = "out/compile_commands.json"
+pretty_print = true
+extensions = ["cpp", "ixx"]
+exclude = ["**/generated/**"]
+"#,
+        )
+        .unwrap();
+
+        let config = load(&path).unwrap();
+        assert_eq!(config.input_file, Some(root.path().join("build.log")));
+        assert_eq!(
+            config.output_file,
+            Some(root.path().join("out/compile_commands.json"))
+        );
+        assert_eq!(config.pretty_print, Some(true));
+        assert_eq!(config.overwrite, None);
+        assert_eq!(
+            config.extensions,
+            Some(vec!["cpp".to_string(), "ixx".to_string()])
+        );
+        assert_eq!(config.exclude, vec!["**/generated/**"]);
+    }
+
+    #[test]
+    fn test_load_rejects_unknown_keys() {
+        let root = tempdir().unwrap();
+        let path = root.path().join(CONFIG_FILE_NAME);
+        fs::write(&path, "output = \"x.json\"\n").unwrap();
+
+        assert!(load(&path).is_err());
+    }
+
+    #[test]
+    fn test_profile_transformers() {
+        let config: Config = toml::from_str(
+            r#"
+[profiles.default]
+transformers = ["normalize-drive-letter"]
+
+[profiles.ci]
+transformers = []
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.profile_transformers(None).unwrap(),
+            ["normalize-drive-letter"]
+        );
+        assert!(config.profile_transformers(Some("ci")).unwrap().is_empty());
+        assert!(config.profile_transformers(Some("missing")).is_err());
+        assert!(
+            Config::default()
+                .profile_transformers(None)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! [`CompilationDatabase`]: the entries of a `compile_commands.json` as one
+//! value, read and written the same way by the binary and by library users.
+//!
+//! Entries are written with their fields in a fixed order (`directory`,
+//! `command`, `arguments`, `file`, `output`) as a JSON array or as JSON
+//! Lines; both forms are read back.
+
+use crate::CompileCommand;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Order of the entries written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SortOrder {
+    /// By file, then directory, ignoring case
+    File,
+    /// By directory, then file, ignoring case
+    Directory,
+    /// As they were collected
+    #[default]
+    None,
+}
+
+impl SortOrder {
+    /// Key ordering `entry`; the command breaks ties so that entries for the
+    /// same file come out the same way whatever order they were found in
+    fn key(self, entry: &CompileCommand) -> (String, String, String) {
+        let file = entry.file_key();
+        let directory = entry.directory.to_lowercase();
+        match self {
+            Self::Directory => (directory, file, entry.command.clone()),
+            Self::File | Self::None => (file, directory, entry.command.clone()),
+        }
+    }
+}
+
+/// The entries of a compilation database, in order
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct CompilationDatabase(Vec<CompileCommand>);
+
+/// How a [`CompilationDatabase`] is written
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// Indent the JSON array; ignored for JSON Lines
+    pub pretty: bool,
+    /// One entry per line instead of a JSON array
+    pub json_lines: bool,
+    /// Order of the entries
+    pub sort: SortOrder,
+}
+
+impl CompilationDatabase {
+    /// Database of `entries`
+    pub fn new(entries: Vec<CompileCommand>) -> Self {
+        Self(entries)
+    }
+
+    /// Parse a database written as a JSON array or as JSON Lines
+    pub fn from_json(text: &str) -> serde_json::Result<Self> {
+        if text.trim_start().starts_with('[') {
+            return serde_json::from_str(text);
+        }
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<serde_json::Result<_>>()
+            .map(Self)
+    }
+
+    /// Read the database at `path`, failing if it is missing or malformed
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to open database: {}", path.display()))?;
+        Self::from_json(&text)
+            .with_context(|| format!("Failed to parse database: {}", path.display()))
+    }
+
+    /// The entries
+    pub fn entries(&self) -> &[CompileCommand] {
+        &self.0
+    }
+
+    /// The entries, to change them in place
+    pub fn entries_mut(&mut self) -> &mut Vec<CompileCommand> {
+        &mut self.0
+    }
+
+    /// The entries, taken out of the database
+    pub fn into_entries(self) -> Vec<CompileCommand> {
+        self.0
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Put the entries in `order`
+    pub fn sort(&mut self, order: SortOrder) {
+        if order != SortOrder::None {
+            self.0.sort_by_cached_key(|entry| order.key(entry));
+        }
+    }
+
+    /// Write the entries to `writer` as `options` say
+    pub fn to_writer<W: Write>(&self, mut writer: W, options: &WriteOptions) -> Result<()> {
+        let mut entries: Vec<&CompileCommand> = self.0.iter().collect();
+        if options.sort != SortOrder::None {
+            entries.sort_by_cached_key(|entry| options.sort.key(entry));
+        }
+
+        if options.json_lines {
+            for entry in entries {
+                serde_json::to_writer(&mut writer, entry).context("Failed to write JSON output")?;
+                writer
+                    .write_all(b"\n")
+                    .context("Failed to write JSON output")?;
+            }
+        } else if options.pretty {
+            serde_json::to_writer_pretty(&mut writer, &entries)
+                .context("Failed to write JSON output")?;
+        } else {
+            serde_json::to_writer(&mut writer, &entries).context("Failed to write JSON output")?;
+        }
+        Ok(())
+    }
+
+    /// Write the database to `path`
+    pub fn save(&self, path: &Path, options: &WriteOptions) -> Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create database: {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        self.to_writer(&mut writer, options)?;
+        writer
+            .flush()
+            .with_context(|| format!("Failed to write database: {}", path.display()))
+    }
+}
+
+impl From<Vec<CompileCommand>> for CompilationDatabase {
+    fn from(entries: Vec<CompileCommand>) -> Self {
+        Self(entries)
+    }
+}
+
+impl FromIterator<CompileCommand> for CompilationDatabase {
+    fn from_iter<I: IntoIterator<Item = CompileCommand>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for CompilationDatabase {
+    type Item = CompileCommand;
+    type IntoIter = std::vec::IntoIter<CompileCommand>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(directory: &str, file: &str) -> CompileCommand {
+        CompileCommand {
+            directory: directory.to_string(),
+            command: format!("cl.exe /c {}", file),
+            file: file.to_string(),
+            output: Some("a.obj".to_string()),
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("compile_commands.json");
+        let database =
+            CompilationDatabase::new(vec![entry("/src", "b.cpp"), entry("/src", "A.cpp")]);
+
+        for json_lines in [false, true] {
+            let options = WriteOptions {
+                json_lines,
+                sort: SortOrder::File,
+                ..Default::default()
+            };
+            database.save(&path, &options).unwrap();
+            let loaded = CompilationDatabase::load(&path).unwrap();
+            let files: Vec<&str> = loaded.entries().iter().map(|e| e.file.as_str()).collect();
+            assert_eq!(files, ["A.cpp", "b.cpp"]);
+        }
+        assert!(CompilationDatabase::load(&dir.path().join("missing.json")).is_err());
+    }
+
+    #[test]
+    fn test_sort_orders() {
+        let unsorted = vec![
+            entry("/b", "/src/z.cpp"),
+            entry("/a", "/src/Y.cpp"),
+            entry("/A", "/src/y.cpp"),
+            entry("/a", "/src/zz.cpp"),
+        ];
+        let sorted = |order| {
+            let mut database = CompilationDatabase::new(unsorted.clone());
+            database.sort(order);
+            database
+                .entries()
+                .iter()
+                .map(|e| format!("{} {}", e.directory, e.file))
+                .collect::<Vec<_>>()
+        };
+        // Entries differing only in case come out by command
+        assert_eq!(
+            sorted(SortOrder::File),
+            [
+                "/a /src/Y.cpp",
+                "/A /src/y.cpp",
+                "/b /src/z.cpp",
+                "/a /src/zz.cpp"
+            ]
+        );
+        assert_eq!(
+            sorted(SortOrder::Directory),
+            [
+                "/a /src/Y.cpp",
+                "/A /src/y.cpp",
+                "/a /src/zz.cpp",
+                "/b /src/z.cpp"
+            ]
+        );
+        assert_eq!(
+            sorted(SortOrder::None),
+            [
+                "/b /src/z.cpp",
+                "/a /src/Y.cpp",
+                "/A /src/y.cpp",
+                "/a /src/zz.cpp"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_json_reads_json_lines() {
+        let line = r#"{"directory":"/src","command":"cl /c a.cpp","file":"a.cpp"}"#;
+        let array = CompilationDatabase::from_json(&format!("[{}]", line)).unwrap();
+        let lines = CompilationDatabase::from_json(&format!(
+            "{}\n\n{}\n",
+            line,
+            line.replace("a.cpp", "b.cpp")
+        ))
+        .unwrap();
+        assert_eq!(array.len(), 1);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines.entries()[1].file, "b.cpp");
+        assert!(CompilationDatabase::from_json("{\"file\":").is_err());
+    }
+
+    #[test]
+    fn test_fields_are_written_in_fixed_order() {
+        let database = CompilationDatabase::new(vec![entry("/src", "a.cpp")]);
+        let mut buffer = Vec::new();
+        database
+            .to_writer(&mut buffer, &WriteOptions::default())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            r#"[{"directory":"/src","command":"cl.exe /c a.cpp","file":"a.cpp","output":"a.obj"}]"#
+        );
+    }
+}
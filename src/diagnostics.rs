@@ -0,0 +1,417 @@
+//! Warnings aggregated by category and reported once at the end of a run.
+//!
+//! With `--error-report` every occurrence is also kept with the log line it
+//! was found on and written out as JSON.
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use log::{info, warn};
+use serde::Serialize;
+use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Number of representative examples kept per category
+const MAX_EXAMPLES: usize = 3;
+
+/// Kinds of problems that can affect individual database entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    /// CL.exe command seen before any project marker
+    NoProjectContext,
+    /// CL.exe command without any recognized source file
+    NoSourceFiles,
+    /// Source file that does not exist on this machine
+    UnresolvedPath,
+    /// CL.exe command without /Fo while object paths were requested
+    MissingObjectFlag,
+    /// Several sources in one batch map to the same object file
+    AmbiguousObject,
+    /// Invocation of a compiler ms2cc does not convert
+    SkippedTool,
+    /// Unquoted compiler path with spaces, reassembled from several tokens
+    GuessedCompilerPath,
+    /// Command attributed to the last project because its prefix was unknown
+    InferredProject,
+    /// `@file.rsp` argument whose file could not be read
+    MissingResponseFile,
+    /// Log line that matched a pattern but could not be processed
+    FailedLine,
+}
+
+impl Category {
+    /// Whether the category means an entry is missing from the database or
+    /// wrong, as opposed to a guess or a tool that is skipped on purpose
+    pub fn is_incomplete(self) -> bool {
+        !matches!(
+            self,
+            Category::SkippedTool | Category::GuessedCompilerPath | Category::InferredProject
+        )
+    }
+
+    /// What went wrong, phrased to follow a count
+    fn description(self) -> &'static str {
+        match self {
+            Category::NoProjectContext => "compiler command(s) had no project context",
+            Category::NoSourceFiles => "compiler command(s) had no recognized source files",
+            Category::UnresolvedPath => "source file(s) do not exist on this machine",
+            Category::MissingObjectFlag => "compiler command(s) had no /Fo flag",
+            Category::AmbiguousObject => "source file(s) share an object file with another source",
+            Category::SkippedTool => "invocation(s) of unsupported compilers were skipped",
+            Category::GuessedCompilerPath => {
+                "compiler path(s) with spaces were unquoted and had to be guessed"
+            }
+            Category::InferredProject => {
+                "compiler command(s) were attributed to a project by guesswork"
+            }
+            Category::MissingResponseFile => "response file(s) could not be read",
+            Category::FailedLine => "log line(s) could not be processed",
+        }
+    }
+
+    /// Suggested fix
+    pub fn advice(self) -> &'static str {
+        match self {
+            Category::NoProjectContext => {
+                "Build with Verbosity=detailed so project markers are logged"
+            }
+            Category::NoSourceFiles => {
+                "Add the missing source extensions to `extensions` in .ms2cc.toml"
+            }
+            Category::UnresolvedPath => {
+                "Run ms2cc on the machine that produced the log, or check the project directories"
+            }
+            Category::MissingObjectFlag => {
+                "Objects are assumed to be in the project directory; their output fields may be wrong"
+            }
+            Category::AmbiguousObject => {
+                "Give the sources distinct names or object directories; their output fields were left out"
+            }
+            Category::SkippedTool => {
+                "Files built by these tools are missing from the database; clang-cl-compatible ones can be added with --compiler-executable"
+            }
+            Category::GuessedCompilerPath => {
+                "Check the compiler path in the database; use --strict-ambiguity to fail instead"
+            }
+            Category::InferredProject => {
+                "Build with Verbosity=detailed so every output prefix announces its project; use --strict-ambiguity to fail instead"
+            }
+            Category::MissingResponseFile => {
+                "Run ms2cc before the response files are deleted; their flags are missing from these entries"
+            }
+            Category::FailedLine => {
+                "Run with --log-level debug to see the error for each line; the entries from these lines are missing"
+            }
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+/// Occurrences of one category
+#[derive(Debug, Clone, Serialize)]
+pub struct CategorySummary {
+    pub category: Category,
+    pub count: usize,
+    /// The first few occurrences, with line numbers where known
+    pub examples: Vec<String>,
+}
+
+/// A line of a build log, shown as `path:line` like compiler messages so
+/// editors and terminals can jump to it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LogLocation {
+    pub path: PathBuf,
+    /// 1-based line number
+    pub line: usize,
+}
+
+impl fmt::Display for LogLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+/// One occurrence and the log line it was found on, for `--error-report`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Occurrence {
+    pub category: Category,
+    pub message: String,
+    #[serde(flatten)]
+    pub location: LogLocation,
+    /// Project file active on that line, if known
+    pub project: Option<String>,
+    /// The line as logged
+    pub raw_line: String,
+}
+
+/// A source file named in the log that no entry was made for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DroppedSource {
+    /// The file as logged
+    pub file: String,
+    /// Why no entry was made
+    pub reason: Category,
+    #[serde(flatten)]
+    pub location: LogLocation,
+}
+
+/// Where the scan currently is
+#[derive(Debug, Default)]
+struct Location {
+    location: LogLocation,
+    project: Option<String>,
+    raw_line: String,
+}
+
+/// Collects diagnostics during a scan
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    categories: IndexMap<Category, CategorySummary>,
+    /// Every occurrence, only kept after [`Self::keep_occurrences`]
+    occurrences: Option<Vec<Occurrence>>,
+    location: Location,
+    /// Log being scanned, for the locations of dropped sources
+    log: PathBuf,
+    dropped: Vec<DroppedSource>,
+}
+
+impl Diagnostics {
+    /// Keep every occurrence with its location, not just the first examples
+    pub fn keep_occurrences(&mut self) {
+        self.occurrences.get_or_insert_with(Vec::new);
+    }
+
+    /// Whether occurrences are kept, so callers can skip building locations
+    pub fn keeps_occurrences(&self) -> bool {
+        self.occurrences.is_some()
+    }
+
+    /// Attach the following occurrences to this log line
+    pub fn set_location(&mut self, location: LogLocation, project: Option<String>, raw_line: &str) {
+        self.location = Location {
+            location,
+            project,
+            raw_line: raw_line.to_string(),
+        };
+    }
+
+    /// Name the log the following line numbers refer to
+    pub fn set_log(&mut self, path: &Path) {
+        self.log = path.to_path_buf();
+    }
+
+    /// Record a source file named on `line` of the log that no entry was
+    /// made for
+    pub fn record_dropped(&mut self, reason: Category, file: &str, line: usize) {
+        self.dropped.push(DroppedSource {
+            file: file.trim_matches('"').to_string(),
+            reason,
+            location: LogLocation {
+                path: self.log.clone(),
+                line,
+            },
+        });
+    }
+
+    /// Source files that no entry was made for, in log order
+    pub fn dropped(&self) -> &[DroppedSource] {
+        &self.dropped
+    }
+
+    /// Record one occurrence. The example is only built while fewer than
+    /// `MAX_EXAMPLES` have been kept for the category, or when every
+    /// occurrence is kept.
+    pub fn record(&mut self, category: Category, example: impl FnOnce() -> String) {
+        let summary = self
+            .categories
+            .entry(category)
+            .or_insert_with(|| CategorySummary {
+                category,
+                count: 0,
+                examples: Vec::new(),
+            });
+        summary.count += 1;
+
+        let Some(occurrences) = &mut self.occurrences else {
+            if summary.examples.len() < MAX_EXAMPLES {
+                summary.examples.push(example());
+            }
+            return;
+        };
+        let message = example();
+        if summary.examples.len() < MAX_EXAMPLES {
+            summary.examples.push(message.clone());
+        }
+        occurrences.push(Occurrence {
+            category,
+            message,
+            location: self.location.location.clone(),
+            project: self.location.project.clone(),
+            raw_line: self.location.raw_line.clone(),
+        });
+    }
+
+    /// Number of occurrences of `category`
+    pub fn count(&self, category: Category) -> usize {
+        self.categories
+            .get(&category)
+            .map_or(0, |summary| summary.count)
+    }
+
+    /// Number of occurrences that left the database incomplete
+    pub fn incomplete_count(&self) -> usize {
+        self.categories
+            .values()
+            .filter(|summary| summary.category.is_incomplete())
+            .map(|summary| summary.count)
+            .sum()
+    }
+
+    /// Every occurrence, if they were kept
+    pub fn occurrences(&self) -> &[Occurrence] {
+        self.occurrences.as_deref().unwrap_or_default()
+    }
+
+    /// Write every occurrence to `path` as a JSON array
+    pub fn write_report(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create error report: {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self.occurrences())
+            .and_then(|()| writer.flush().map_err(serde_json::Error::io))
+            .with_context(|| format!("Failed to write error report: {}", path.display()))?;
+
+        info!(
+            "Wrote {} problem(s) to {}",
+            self.occurrences().len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Add the occurrences collected by another scan
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.dropped.extend(other.dropped);
+        if let Some(occurrences) = other.occurrences {
+            self.occurrences
+                .get_or_insert_with(Vec::new)
+                .extend(occurrences);
+        }
+        for (category, summary) in other.categories {
+            match self.categories.get_mut(&category) {
+                Some(own) => {
+                    own.count += summary.count;
+                    let room = MAX_EXAMPLES.saturating_sub(own.examples.len());
+                    own.examples.extend(summary.examples.into_iter().take(room));
+                }
+                None => {
+                    self.categories.insert(category, summary);
+                }
+            }
+        }
+    }
+
+    /// All categories with occurrences, in order of first occurrence
+    pub fn summaries(&self) -> Vec<CategorySummary> {
+        self.categories.values().cloned().collect()
+    }
+
+    /// Log one grouped warning per category
+    pub fn log_summary(&self) {
+        for summary in self.categories.values() {
+            warn!("{} {}", summary.count, summary.category);
+            for example in &summary.examples {
+                warn!("    e.g. {}", example);
+            }
+            warn!("    fix: {}", summary.category.advice());
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_counts_and_limits_examples() {
+        let mut diagnostics = Diagnostics::default();
+        for i in 0..5 {
+            diagnostics.record(Category::UnresolvedPath, || format!("file{}.cpp", i));
+        }
+        diagnostics.record(Category::SkippedTool, || "nvcc.exe".to_string());
+
+        let summaries = diagnostics.summaries();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].category, Category::UnresolvedPath);
+        assert_eq!(summaries[0].count, 5);
+        assert_eq!(summaries[1].category, Category::SkippedTool);
+        assert_eq!(diagnostics.incomplete_count(), 5);
+        assert_eq!(
+            summaries[0].examples,
+            vec!["file0.cpp", "file1.cpp", "file2.cpp"]
+        );
+    }
+
+    #[test]
+    fn test_kept_occurrences_have_locations() {
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.record(Category::SkippedTool, || "nvcc.exe".to_string());
+        assert!(diagnostics.occurrences().is_empty());
+
+        diagnostics.keep_occurrences();
+        for i in 0..5 {
+            let location = LogLocation {
+                path: PathBuf::from("msbuild.log"),
+                line: i + 10,
+            };
+            diagnostics.set_location(location, Some("p.vcxproj".to_string()), "  CL.exe /c");
+            diagnostics.record(Category::UnresolvedPath, || format!("file{}.cpp", i));
+        }
+
+        let occurrences = diagnostics.occurrences();
+        assert_eq!(occurrences.len(), 5);
+        assert_eq!(occurrences[4].message, "file4.cpp");
+        assert_eq!(occurrences[4].location.to_string(), "msbuild.log:14");
+        assert_eq!(occurrences[4].project.as_deref(), Some("p.vcxproj"));
+        assert_eq!(occurrences[4].raw_line, "  CL.exe /c");
+        assert_eq!(diagnostics.summaries()[1].examples.len(), MAX_EXAMPLES);
+    }
+
+    #[test]
+    fn test_extend_adds_counts() {
+        let mut first = Diagnostics::default();
+        first.record(Category::UnresolvedPath, || "a.cpp".to_string());
+        let mut second = Diagnostics::default();
+        second.record(Category::UnresolvedPath, || "b.cpp".to_string());
+        second.record(Category::SkippedTool, || "nvcc.exe".to_string());
+
+        first.extend(second);
+        let summaries = first.summaries();
+        assert_eq!(summaries[0].count, 2);
+        assert_eq!(summaries[0].examples, vec!["a.cpp", "b.cpp"]);
+        assert_eq!(summaries[1].category, Category::SkippedTool);
+    }
+
+    #[test]
+    fn test_summary_serializes_category_names() {
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.record(Category::MissingObjectFlag, || "line 7".to_string());
+
+        let json = serde_json::to_value(diagnostics.summaries()).unwrap();
+        assert_eq!(json[0]["category"], "missing_object_flag");
+        assert_eq!(json[0]["count"], 1);
+    }
+}
@@ -0,0 +1,137 @@
+//! `ms2cc diff`: compare two databases, e.g. before and after a change to
+//! the build.
+
+use crate::{CompileCommand, path_key, split_arguments};
+use indexmap::{IndexMap, IndexSet};
+
+/// Flags of one file that differ between the databases
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagChange {
+    pub file: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Differences between an old and a new database
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatabaseDiff {
+    /// Files only the new database covers
+    pub added: Vec<String>,
+    /// Files only the old database covers
+    pub removed: Vec<String>,
+    /// Files both cover with different flags
+    pub changed: Vec<FlagChange>,
+}
+
+impl DatabaseDiff {
+    /// Whether the databases cover the same files with the same flags
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// First entry of each file, by folded absolute path
+fn by_file(entries: &[CompileCommand]) -> IndexMap<String, &CompileCommand> {
+    let mut files = IndexMap::new();
+    for entry in entries {
+        files.entry(entry.file_key()).or_insert(entry);
+    }
+    files
+}
+
+/// Flags of `entry`: its arguments without the compiler and the source file
+fn flags(entry: &CompileCommand) -> IndexSet<String> {
+    let arguments = match &entry.arguments {
+        Some(arguments) => arguments.clone(),
+        None => split_arguments(&entry.command),
+    };
+    let file = path_key(&entry.file);
+    let absolute_file = entry.file_key();
+    arguments
+        .into_iter()
+        .skip(1)
+        .filter(|argument| {
+            let argument = path_key(argument.trim_matches('"'));
+            argument != file && argument != absolute_file
+        })
+        .collect()
+}
+
+/// Compare the files and flags of `old` and `new`
+pub fn diff(old: &[CompileCommand], new: &[CompileCommand]) -> DatabaseDiff {
+    let old = by_file(old);
+    let new = by_file(new);
+
+    let mut result = DatabaseDiff::default();
+    for (key, entry) in &old {
+        let Some(new_entry) = new.get(key) else {
+            result.removed.push(entry.absolute_file());
+            continue;
+        };
+        let old_flags = flags(entry);
+        let new_flags = flags(new_entry);
+        let added: Vec<String> = new_flags.difference(&old_flags).cloned().collect();
+        let removed: Vec<String> = old_flags.difference(&new_flags).cloned().collect();
+        if !added.is_empty() || !removed.is_empty() {
+            result.changed.push(FlagChange {
+                file: new_entry.absolute_file(),
+                added,
+                removed,
+            });
+        }
+    }
+    result.added = new
+        .iter()
+        .filter(|(key, _)| !old.contains_key(*key))
+        .map(|(_, entry)| entry.absolute_file())
+        .collect();
+    result
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(directory: &str, file: &str, flags: &str) -> CompileCommand {
+        CompileCommand {
+            directory: directory.to_string(),
+            command: format!("cl.exe /c {} \"{}\"", flags, file),
+            file: file.to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_diff_reports_files_and_flags() {
+        let old = vec![
+            entry("/src", "a.cpp", "/DOLD /W4"),
+            entry("/src", "b.cpp", "/W4"),
+            entry("/src", "gone.cpp", ""),
+        ];
+        let new = vec![
+            entry("/other", "/src/a.cpp", "/W4 /DNEW"),
+            entry("/src", "B.cpp", "/W4"),
+            entry("/src", "new.cpp", ""),
+        ];
+
+        let result = diff(&old, &new);
+        assert_eq!(result.added.len(), 1);
+        assert!(result.added[0].ends_with("new.cpp"));
+        assert_eq!(result.removed.len(), 1);
+        assert!(result.removed[0].ends_with("gone.cpp"));
+
+        // The source file spelled differently is not a flag change
+        assert_eq!(result.changed.len(), 1);
+        assert_eq!(result.changed[0].added, ["/DNEW"]);
+        assert_eq!(result.changed[0].removed, ["/DOLD"]);
+
+        assert!(diff(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,406 @@
+//! `ms2cc doctor`: inspect the build log and environment for common problems
+//! before a full conversion is attempted.
+
+use crate::{
+    LogPatterns,
+    database::CompilationDatabase,
+    input::{
+        InputKind, decode_reader, decompress, detect_compression, detect_input_kind, log_encoding,
+    },
+    project_line,
+};
+use anyhow::Result;
+use std::{
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, Cursor, Read},
+    path::Path,
+};
+use tempfile::NamedTempFile;
+
+// ----------------------------------------------------------------------------
+// Findings
+// ----------------------------------------------------------------------------
+
+/// How likely a finding is to break the generated database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Info => write!(f, "info"),
+        }
+    }
+}
+
+/// A single detected problem together with the suggested fix
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub problem: String,
+    pub fix: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, problem: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            severity,
+            problem: problem.into(),
+            fix: fix.into(),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Log inspection
+// ----------------------------------------------------------------------------
+
+/// Counters gathered from a single pass over the build log
+#[derive(Debug, Default)]
+struct LogSummary {
+    total_lines: usize,
+    invalid_utf8_lines: usize,
+    project_markers: usize,
+    compile_commands: usize,
+    response_file_commands: usize,
+    up_to_date_targets: usize,
+}
+
+/// Sniff the leading bytes of the log for encodings and formats we cannot read
+fn check_log_format(head: &[u8]) -> Option<Finding> {
+    if head.is_empty() {
+        return Some(Finding::new(
+            Severity::Error,
+            "The build log is empty",
+            "Re-run MSBuild with /fileLogger /fileLoggerParameters:LogFile=msbuild.log;Verbosity=detailed",
+        ));
+    }
+
+    match detect_input_kind(head) {
+        InputKind::Text => None,
+        InputKind::BinaryLog => Some(Finding::new(
+            Severity::Error,
+            "The input looks like a binary log (.binlog), not a text log",
+            "Replay it to text: msbuild msbuild.binlog /fileLogger /fileLoggerParameters:LogFile=msbuild.log;Verbosity=detailed",
+        )),
+        InputKind::Utf16Text => Some(Finding::new(
+            Severity::Info,
+            "The build log is UTF-16 encoded (usually PowerShell '>' redirection); it is transcoded while reading",
+            "Nothing to do, or use MSBuild's file logger for a smaller UTF-8 log: /fileLogger /fileLoggerParameters:LogFile=msbuild.log;Verbosity=detailed",
+        )),
+        InputKind::XmlLog => Some(Finding::new(
+            Severity::Error,
+            "The input is an XML log, not a text log",
+            "Use MSBuild's text file logger: /fileLogger /fileLoggerParameters:LogFile=msbuild.log;Verbosity=detailed",
+        )),
+        InputKind::TrackerLog => Some(Finding::new(
+            Severity::Error,
+            "The input is a file tracker log (.tlog), not a build log",
+            "Pass the MSBuild log instead: /fileLogger /fileLoggerParameters:LogFile=msbuild.log;Verbosity=detailed",
+        )),
+    }
+}
+
+/// Scan every line of the log and count the markers ms2cc depends on
+fn summarize_log(reader: impl BufRead, patterns: &LogPatterns) -> LogSummary {
+    let mut summary = LogSummary::default();
+
+    for raw in reader.split(b'\n') {
+        let Ok(raw) = raw else {
+            break;
+        };
+        summary.total_lines += 1;
+
+        let line = match String::from_utf8(raw) {
+            Ok(line) => line,
+            Err(e) => {
+                summary.invalid_utf8_lines += 1;
+                String::from_utf8_lossy(e.as_bytes()).into_owned()
+            }
+        };
+
+        if project_line::parse(&line).is_some_and(|parsed| parsed.cpp_project().is_some()) {
+            summary.project_markers += 1;
+        }
+
+        if patterns.compile_command.is_match(&line) {
+            summary.compile_commands += 1;
+            if line.split_whitespace().any(|t| t.starts_with('@')) {
+                summary.response_file_commands += 1;
+            }
+        }
+
+        if line.contains("Skipping target \"ClCompile\"") {
+            summary.up_to_date_targets += 1;
+        }
+    }
+
+    summary
+}
+
+/// Turn the log counters into findings
+fn check_log_summary(summary: &LogSummary) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    if summary.project_markers == 0 {
+        findings.push(Finding::new(
+            Severity::Error,
+            "No project markers found - the log verbosity is too low",
+            "Build with Verbosity=detailed (or /v:detailed) so project context is logged",
+        ));
+    }
+
+    if summary.compile_commands == 0 {
+        let fix = if summary.up_to_date_targets > 0 {
+            "Every ClCompile target was up to date; rebuild with /t:Rebuild to log compiler invocations"
+        } else {
+            "Make sure the build actually compiled C/C++ sources and used Verbosity=detailed"
+        };
+        findings.push(Finding::new(
+            Severity::Error,
+            "No CL.exe invocations found in the log",
+            fix,
+        ));
+    } else if summary.up_to_date_targets > 0 {
+        findings.push(Finding::new(
+            Severity::Info,
+            format!(
+                "{} ClCompile target(s) were skipped as up to date",
+                summary.up_to_date_targets
+            ),
+            "Entries for those projects are kept from the existing database; use /t:Rebuild with --overwrite for a complete database",
+        ));
+    }
+
+    if summary.response_file_commands > 0 {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!(
+                "{} CL.exe invocation(s) pass arguments through response files (@file.rsp)",
+                summary.response_file_commands
+            ),
+            "Flags stored in response files are read from disk; run ms2cc on the build machine before they are cleaned up",
+        ));
+    }
+
+    if summary.invalid_utf8_lines > 0 {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!(
+                "{} of {} lines are not valid UTF-8 and will be skipped",
+                summary.invalid_utf8_lines, summary.total_lines
+            ),
+            "Pass the log's code page with --input-encoding (e.g. windows-1252), or write the log with MSBuild's file logger, which always produces UTF-8",
+        ));
+    }
+
+    findings
+}
+
+/// Inspect the build log at `input_file`
+fn check_input(input_file: &Path, patterns: &LogPatterns) -> Vec<Finding> {
+    let mut file = match File::open(input_file) {
+        Ok(file) => BufReader::new(file),
+        Err(e) => {
+            return vec![Finding::new(
+                Severity::Error,
+                format!("Cannot open build log {}: {}", input_file.display(), e),
+                "Pass the log location with --input-file, or generate msbuild.log with /fileLogger",
+            )];
+        }
+    };
+
+    // Archived logs are inspected decompressed, like in a conversion run
+    let mut head = Vec::with_capacity(4096);
+    let read = file
+        .fill_buf()
+        .map(detect_compression)
+        .and_then(|compression| {
+            let mut file = decompress(file, compression)?;
+            file.by_ref().take(4096).read_to_end(&mut head)?;
+            Ok(file)
+        });
+    let file = match read {
+        Ok(file) => file,
+        Err(e) => {
+            return vec![Finding::new(
+                Severity::Error,
+                format!("Cannot read build log {}: {}", input_file.display(), e),
+                "Check file permissions and that no other process holds the log open exclusively",
+            )];
+        }
+    };
+
+    let kind = detect_input_kind(&head);
+    let mut findings = Vec::new();
+    if let Some(finding) = check_log_format(&head) {
+        if finding.severity == Severity::Error {
+            return vec![finding];
+        }
+        findings.push(finding);
+    }
+
+    // UTF-16 logs are readable once transcoded, like in a conversion run
+    let encoding = log_encoding(kind, &head, None).unwrap_or(None);
+    let reader = decode_reader(Cursor::new(head).chain(file), encoding);
+    findings.extend(check_log_summary(&summarize_log(reader, patterns)));
+    findings
+}
+
+// ----------------------------------------------------------------------------
+// Environment inspection
+// ----------------------------------------------------------------------------
+
+/// Inspect the output location and the host platform
+fn check_environment(output_file: &Path) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let parent = match output_file.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    if let Err(e) = NamedTempFile::new_in(parent) {
+        findings.push(Finding::new(
+            Severity::Error,
+            format!(
+                "Output directory {} is not writable: {}",
+                parent.display(),
+                e
+            ),
+            "Choose a different location with --output-file",
+        ));
+    }
+
+    if output_file.exists() && CompilationDatabase::load(output_file).is_err() {
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!(
+                "Existing {} is not a valid compilation database",
+                output_file.display()
+            ),
+            "Its entries cannot be merged and will be discarded; pass --overwrite to make this explicit",
+        ));
+    }
+
+    if !cfg!(windows) {
+        findings.push(Finding::new(
+            Severity::Info,
+            "ms2cc is not running on Windows",
+            "Windows paths in the log are resolved with this platform's path rules; run on Windows for exact results",
+        ));
+    }
+
+    findings
+}
+
+// ----------------------------------------------------------------------------
+// Entry point
+// ----------------------------------------------------------------------------
+
+/// Collect all findings, most severe first
+pub fn diagnose(input_file: &Path, output_file: &Path) -> Result<Vec<Finding>> {
+    let patterns = LogPatterns::new()?;
+
+    let mut findings = check_input(input_file, &patterns);
+    findings.extend(check_environment(output_file));
+    findings.sort_by_key(|f| f.severity);
+
+    Ok(findings)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_log(contents: &[u8]) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_check_log_format_detects_utf16() {
+        let finding = check_log_format(&[0xFF, 0xFE, b'P', 0, b'r', 0]).unwrap();
+        assert_eq!(finding.severity, Severity::Info);
+        assert!(finding.problem.contains("UTF-16"));
+    }
+
+    #[test]
+    fn test_check_log_format_detects_binlog() {
+        let finding = check_log_format(&[0x1F, 0x8B, 0x08, 0x00]).unwrap();
+        assert!(finding.problem.contains("binary log"));
+    }
+
+    #[test]
+    fn test_check_log_format_accepts_text() {
+        assert!(check_log_format(b"Build started 1/1/2025 10:00:00 AM.\r\n").is_none());
+    }
+
+    #[test]
+    fn test_check_input_reports_low_verbosity() {
+        let log = write_log(b"Build started.\nBuild succeeded.\n");
+        let findings = check_input(log.path(), &LogPatterns::new().unwrap());
+
+        assert!(findings.iter().any(|f| f.problem.contains("verbosity")));
+        assert!(findings.iter().any(|f| f.problem.contains("No CL.exe")));
+    }
+
+    #[test]
+    fn test_check_input_healthy_log() {
+        let log = write_log(
+            br#"Target "ClCompile" in file "x.targets" from project "C:\p\p.vcxproj":
+  C:\VC\bin\CL.exe /c main.cpp
+"#,
+        );
+        let findings = check_input(log.path(), &LogPatterns::new().unwrap());
+        assert!(findings.is_empty(), "unexpected findings: {:?}", findings);
+    }
+
+    #[test]
+    fn test_check_input_up_to_date_build() {
+        let log = write_log(
+            br#"Target "ClCompile" from project "C:\p\p.vcxproj":
+Skipping target "ClCompile" because all output files are up-to-date with respect to the input files.
+"#,
+        );
+        let findings = check_input(log.path(), &LogPatterns::new().unwrap());
+        let finding = findings
+            .iter()
+            .find(|f| f.problem.contains("No CL.exe"))
+            .unwrap();
+        assert!(finding.fix.contains("/t:Rebuild"));
+    }
+
+    #[test]
+    fn test_check_input_missing_file() {
+        let findings = check_input(
+            Path::new("definitely-missing-ms2cc.log"),
+            &LogPatterns::new().unwrap(),
+        );
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_diagnose_ranks_errors_first() {
+        let findings = diagnose(
+            Path::new("definitely-missing-ms2cc.log"),
+            Path::new("compile_commands.json"),
+        )
+        .unwrap();
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(findings.windows(2).all(|w| w[0].severity <= w[1].severity));
+    }
+}
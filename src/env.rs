@@ -0,0 +1,148 @@
+//! Environment variables logged by MSBuild (`Environment at start of build:`).
+//!
+//! Compilers pick up flags and include directories from the environment
+//! (`INCLUDE`, `CL`, `_CL_`) that never appear on the logged command line.
+//! The captured variables can expand `%VAR%` references in commands or be
+//! written to a side file with `--environment-file`. `CL` and `_CL_` are
+//! always folded into the commands, the way CL.exe applies them.
+
+use crate::tokenize_command_line;
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use log::info;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Environment variables by upper-case name, in log order
+pub type Environment = IndexMap<String, String>;
+
+/// Replace `%NAME%` references to variables in `env`. Names are matched
+/// case-insensitively, like Windows does; unknown references are kept.
+pub fn expand(text: &str, env: &Environment) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('%') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('%') else {
+            expanded.push_str(&rest[start..]);
+            return expanded;
+        };
+
+        match env.get(&after[..end].to_uppercase()) {
+            Some(value) if end > 0 => {
+                expanded.push_str(value);
+                rest = &after[end + 1..];
+            }
+            // Keep the `%` and look for a reference starting at the next one
+            _ => {
+                expanded.push('%');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Add the options of the `CL` variable in `env` after the compiler and
+/// those of `_CL_` at the end of `command`, as CL.exe does
+pub fn fold_compiler_options(command: &str, env: &Environment) -> String {
+    let options = |name: &str| {
+        env.get(name)
+            .map(|value| tokenize_command_line(value))
+            .unwrap_or_default()
+    };
+    let (prepend, append) = (options("CL"), options("_CL_"));
+    if prepend.is_empty() && append.is_empty() {
+        return command.to_string();
+    }
+
+    let mut tokens = tokenize_command_line(command);
+    let at = tokens.len().min(1);
+    tokens.splice(at..at, prepend);
+    tokens.extend(append);
+    tokens.join(" ")
+}
+
+/// Write the environment of each project directory to `path` as JSON
+pub fn write(
+    path: &Path,
+    environments: &IndexMap<String, Environment>,
+    pretty_print: bool,
+) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create environment file: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    if pretty_print {
+        serde_json::to_writer_pretty(&mut writer, environments)
+    } else {
+        serde_json::to_writer(&mut writer, environments)
+    }
+    .with_context(|| format!("Failed to write environment file: {}", path.display()))?;
+    writer
+        .flush()
+        .with_context(|| format!("Failed to write environment file: {}", path.display()))?;
+
+    info!(
+        "Wrote the environment of {} project director(ies) to {}",
+        environments.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let env: Environment = [
+            ("VCTOOLSDIR".to_string(), r"C:\VC".to_string()),
+            ("EMPTY".to_string(), String::new()),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            expand(r"/I%VCToolsDir%\include /DX=%EMPTY%", &env),
+            r"/IC:\VC\include /DX="
+        );
+        assert_eq!(
+            expand("/DP=50% /DQ=%UNKNOWN%", &env),
+            "/DP=50% /DQ=%UNKNOWN%"
+        );
+        assert_eq!(expand("%%VCTOOLSDIR%", &env), r"%C:\VC");
+    }
+
+    #[test]
+    fn test_fold_compiler_options() {
+        let mut env: Environment = [("CL".to_string(), "/DFOO  /W4".to_string())]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            fold_compiler_options("cl.exe /c a.cpp", &env),
+            "cl.exe /DFOO /W4 /c a.cpp"
+        );
+
+        env.insert("_CL_".to_string(), r#"/link "/LIBPATH:C:\x y""#.to_string());
+        env.shift_remove("CL");
+        assert_eq!(
+            fold_compiler_options("cl.exe /c a.cpp", &env),
+            r#"cl.exe /c a.cpp /link "/LIBPATH:C:\x y""#
+        );
+        assert_eq!(
+            fold_compiler_options("cl.exe /c a.cpp", &Environment::new()),
+            "cl.exe /c a.cpp"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Glob-based filtering of database entries by source file path.
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Decides which source files are kept in the database.
+///
+/// Patterns and paths are compared with forward slashes and without regard to
+/// case, so `**/Generated/**` matches `C:\src\generated\foo.cpp`. A file is
+/// kept if it matches an include pattern (or none are given) and no exclude
+/// pattern.
+#[derive(Debug)]
+pub struct PathFilter {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(Self {
+            include: build_glob_set(include)?,
+            exclude: build_glob_set(exclude)?,
+        })
+    }
+
+    /// Whether any filtering patterns are configured
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Check whether a source file passes the filter
+    pub fn is_match(&self, file: &str) -> bool {
+        let path = to_glob_path(file);
+        (self.include.is_empty() || self.include.is_match(&path)) && !self.exclude.is_match(&path)
+    }
+}
+
+/// Convert a path to the separator convention used for matching
+fn to_glob_path(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(&to_glob_path(pattern))
+            .case_insensitive(true)
+            .backslash_escape(false)
+            .build()
+            .with_context(|| format!("Invalid path glob: {}", pattern))?;
+        builder.add(glob);
+    }
+    builder.build().context("Failed to build path glob set")
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_filter_keeps_everything() {
+        let filter = PathFilter::new(&[], &[]).unwrap();
+        assert!(filter.is_empty());
+        assert!(filter.is_match(r"C:\src\main.cpp"));
+    }
+
+    #[test]
+    fn test_exclude_matches_windows_paths() {
+        let filter = PathFilter::new(&[], &["**/generated/**".to_string()]).unwrap();
+        assert!(!filter.is_match(r"C:\src\Generated\foo.cpp"));
+        assert!(filter.is_match(r"C:\src\lib\foo.cpp"));
+    }
+
+    #[test]
+    fn test_exclude_pattern_with_backslashes() {
+        let filter = PathFilter::new(&[], &[r"C:\src\third_party\**".to_string()]).unwrap();
+        assert!(!filter.is_match(r"C:\src\third_party\zlib\inflate.c"));
+        assert!(filter.is_match(r"C:\src\core\inflate.c"));
+    }
+
+    #[test]
+    fn test_include_restricts_to_subtree() {
+        let filter = PathFilter::new(
+            &[r"C:\src\engine\**".to_string()],
+            &["**/generated/**".to_string()],
+        )
+        .unwrap();
+        assert!(filter.is_match(r"C:\src\Engine\render.cpp"));
+        assert!(!filter.is_match(r"C:\src\engine\generated\shaders.cpp"));
+        assert!(!filter.is_match(r"C:\src\tools\main.cpp"));
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_error() {
+        assert!(PathFilter::new(&[], &["[".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! `--filter-cmd`: pipe entries through an external program as NDJSON.
+//!
+//! Every entry is written to the program's stdin as one JSON object per line.
+//! Each JSON object the program prints on stdout becomes an entry again, so it
+//! can rewrite entries, drop them, or add new ones.
+//!
+//! The MSBuild configuration and the failed state of an entry are not part
+//! of the JSON. Entries printed back with the `file` and `output` of an
+//! entry sent get them back, in order; new entries have neither.
+
+use crate::CompileCommand;
+use anyhow::{Context, Result, bail};
+use indexmap::IndexMap;
+use log::{debug, info};
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, BufWriter, Write},
+    process::{ChildStdout, Command, Stdio},
+    thread,
+};
+
+/// What the JSON of the entries sent leaves out, by `file` and `output`
+type Unserialized = IndexMap<(String, Option<String>), VecDeque<(bool, Option<String>)>>;
+
+/// Build a shell invocation so `program` can carry its own arguments and quoting
+fn shell_command(program: &str) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(program);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(program);
+        command
+    }
+}
+
+/// Read the entries printed by the program, giving back the configuration
+/// and failed state of the entry sent with the same `file` and `output`
+fn read_entries(
+    stdout: ChildStdout,
+    unserialized: &mut Unserialized,
+) -> Result<Vec<CompileCommand>> {
+    let mut filtered = Vec::new();
+    for (index, line) in BufReader::new(stdout).lines().enumerate() {
+        let line = line.context("Failed to read filter command output")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut entry: CompileCommand = serde_json::from_str(&line).with_context(|| {
+            format!(
+                "Filter command output line {} is not a compile command: {}",
+                index + 1,
+                line
+            )
+        })?;
+        if let Some((failed, configuration)) = unserialized
+            .get_mut(&(entry.file.clone(), entry.output.clone()))
+            .and_then(VecDeque::pop_front)
+        {
+            entry.failed = failed;
+            entry.configuration = configuration;
+        }
+        filtered.push(entry);
+    }
+    Ok(filtered)
+}
+
+/// Run `program` on `entries` and return the entries it prints back
+pub fn run(program: &str, entries: Vec<CompileCommand>) -> Result<Vec<CompileCommand>> {
+    debug!("Running filter command: {}", program);
+    let count = entries.len();
+    let mut unserialized = Unserialized::new();
+    for entry in &entries {
+        unserialized
+            .entry((entry.file.clone(), entry.output.clone()))
+            .or_default()
+            .push_back((entry.failed, entry.configuration.clone()));
+    }
+
+    let mut child = shell_command(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("Failed to start filter command: {}", program))?;
+
+    // Feed stdin from a separate thread so a program that writes before it has
+    // read all input cannot deadlock against us
+    let stdin = child.stdin.take().context("Filter command has no stdin")?;
+    let writer = thread::spawn(move || -> Result<()> {
+        let mut stdin = BufWriter::new(stdin);
+        for entry in &entries {
+            serde_json::to_writer(&mut stdin, entry).map_err(std::io::Error::from)?;
+            stdin.write_all(b"\n")?;
+        }
+        stdin.flush()?;
+        Ok(())
+    });
+
+    let stdout = child
+        .stdout
+        .take()
+        .context("Filter command has no stdout")?;
+    let filtered = match read_entries(stdout, &mut unserialized) {
+        Ok(filtered) => filtered,
+        Err(e) => {
+            // Do not leave the program running, or a zombie, behind
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = writer.join();
+            return Err(e);
+        }
+    };
+
+    let status = child.wait().context("Failed to wait for filter command")?;
+    let write_result = writer
+        .join()
+        .map_err(|_| anyhow::anyhow!("Filter command input thread panicked"))?;
+
+    if !status.success() {
+        bail!("Filter command failed ({}): {}", status, program);
+    }
+    // A program may exit successfully without reading all input (`head`), which
+    // closes the pipe under us; that is not an error
+    if let Err(e) = write_result {
+        let broken_pipe = e
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|io| io.kind() == std::io::ErrorKind::BrokenPipe);
+        if !broken_pipe {
+            return Err(e.context("Failed to send entries to filter command"));
+        }
+    }
+
+    info!(
+        "Filter command returned {} of {} entries",
+        filtered.len(),
+        count
+    );
+    Ok(filtered)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(windows)]
+    const PASS_THROUGH: &str = "findstr \"^\"";
+    #[cfg(not(windows))]
+    const PASS_THROUGH: &str = "cat";
+
+    #[cfg(windows)]
+    const DROP_B: &str = "findstr /v b.cpp";
+    #[cfg(not(windows))]
+    const DROP_B: &str = "grep -v b.cpp";
+
+    fn entry(file: &str) -> CompileCommand {
+        CompileCommand {
+            directory: "C:\\src".to_string(),
+            command: format!("cl /c {}", file),
+            file: file.to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip_preserves_entries() {
+        let result = run(PASS_THROUGH, vec![entry("a.cpp"), entry("b.cpp")]).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1].file, "b.cpp");
+        assert_eq!(result[1].command, "cl /c b.cpp");
+    }
+
+    #[test]
+    fn test_round_trip_keeps_configurations() {
+        let mut entries = Vec::new();
+        for configuration in ["Debug", "Release"] {
+            let mut entry = entry("a.cpp");
+            entry.configuration = Some(configuration.to_string());
+            entries.push(entry);
+        }
+        entries[1].failed = true;
+        entries.push(entry("b.cpp"));
+
+        let result = run(PASS_THROUGH, entries).unwrap();
+        assert_eq!(result[0].configuration.as_deref(), Some("Debug"));
+        assert_eq!(result[1].configuration.as_deref(), Some("Release"));
+        assert!(!result[0].failed && result[1].failed);
+        assert_eq!(result[2].configuration, None);
+    }
+
+    #[test]
+    fn test_program_can_drop_entries() {
+        let result = run(DROP_B, vec![entry("a.cpp"), entry("b.cpp")]).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].file, "a.cpp");
+    }
+
+    #[test]
+    fn test_failing_program_is_error() {
+        assert!(run("exit 3", vec![entry("a.cpp")]).is_err());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_program_may_stop_reading_early() {
+        let entries: Vec<_> = (0..20_000).map(|i| entry(&format!("f{}.cpp", i))).collect();
+        let result = run("head -n 1", entries).unwrap();
+        assert_eq!(result.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_output_is_error() {
+        let err = run("echo not-json", vec![]).unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+    }
+}
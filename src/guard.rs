@@ -0,0 +1,57 @@
+//! `ms2cc guard`: fail when a database lost files that a baseline covered.
+
+use crate::CompileCommand;
+use indexmap::IndexSet;
+
+/// Absolute path of each entry's file, in database order
+pub fn covered_files(entries: &[CompileCommand]) -> IndexSet<String> {
+    entries.iter().map(CompileCommand::absolute_file).collect()
+}
+
+/// Files covered by `baseline` but not by `current`
+pub fn missing_files(baseline: &[CompileCommand], current: &[CompileCommand]) -> Vec<String> {
+    let current = covered_files(current);
+    covered_files(baseline)
+        .into_iter()
+        .filter(|file| !current.contains(file))
+        .collect()
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(directory: &str, file: &str) -> CompileCommand {
+        CompileCommand {
+            directory: directory.to_string(),
+            command: format!("cl /c {}", file),
+            file: file.to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_missing_files_lists_lost_coverage() {
+        let baseline = vec![entry("/src", "a.cpp"), entry("/src", "b.cpp")];
+        let current = vec![entry("/src", "a.cpp"), entry("/src", "c.cpp")];
+
+        let missing = missing_files(&baseline, &current);
+        assert_eq!(missing.len(), 1);
+        assert!(missing[0].ends_with("b.cpp"));
+    }
+
+    #[test]
+    fn test_relative_and_absolute_files_match() {
+        let baseline = vec![entry("/src", "a.cpp")];
+        let current = vec![entry("/other", "/src/a.cpp")];
+
+        assert!(missing_files(&baseline, &current).is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+//! `--emit-header-entries`: compile commands for headers, borrowed from a
+//! nearby translation unit.
+//!
+//! clangd guesses flags for files missing from the database, and guesses
+//! badly for headers of MSBuild projects. Each header found below a project
+//! directory gets the command of a source file in the same directory or,
+//! failing that, of the project nearest to it.
+
+use crate::{CompileCommand, path_key, path_to_normalized_string, tokenize_command_line};
+use indexmap::{IndexMap, IndexSet};
+use log::{debug, info};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Extensions of the headers that get entries
+const HEADER_EXTENSIONS: &[&str] = &["h", "hh", "hpp", "hxx"];
+
+/// Check if `path` names a header
+fn is_header(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| HEADER_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Collect the headers below `dir`, skipping hidden directories
+fn find_headers(dir: &Path, headers: &mut Vec<PathBuf>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        debug!("Cannot read directory: {}", dir.display());
+        return;
+    };
+    let mut paths: Vec<PathBuf> = read_dir.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        let is_hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if is_hidden {
+            continue;
+        }
+        if path.is_dir() {
+            find_headers(&path, headers);
+        } else if is_header(&path) {
+            headers.push(path);
+        }
+    }
+}
+
+/// Project directories to search, without those nested in another one
+fn search_roots(entries: &[CompileCommand]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = entries
+        .iter()
+        .map(|entry| PathBuf::from(&entry.directory))
+        .collect::<IndexSet<_>>()
+        .into_iter()
+        .collect();
+    dirs.sort();
+
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        if !roots.iter().any(|root| dir.starts_with(root)) {
+            roots.push(dir);
+        }
+    }
+    roots
+}
+
+/// Entry to borrow flags from: a source file in the header's directory, or
+/// the first entry of the deepest project directory containing the header
+fn donor<'a>(
+    header: &Path,
+    by_source_dir: &IndexMap<PathBuf, &'a CompileCommand>,
+    entries: &'a [CompileCommand],
+) -> Option<&'a CompileCommand> {
+    if let Some(entry) = header.parent().and_then(|dir| by_source_dir.get(dir)) {
+        return Some(entry);
+    }
+    entries
+        .iter()
+        // max_by_key keeps the last of equal keys
+        .rev()
+        .filter(|entry| header.starts_with(&entry.directory))
+        .max_by_key(|entry| Path::new(&entry.directory).components().count())
+}
+
+/// The donor's command with its source file replaced by `header`
+fn header_entry(header: &str, donor: &CompileCommand) -> CompileCommand {
+    let donor_file = donor.absolute_file();
+    let mut parts: Vec<String> = tokenize_command_line(&donor.command)
+        .into_iter()
+        .filter(|token| {
+            let value = token.trim_matches('"');
+            value != donor.file && value != donor_file
+        })
+        .collect();
+
+    // A header's extension says nothing about the language; keep the donor's
+    let is_c = Path::new(&donor.file)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("c"));
+    parts.push(if is_c { "/TC" } else { "/TP" }.to_string());
+    parts.push(format!("\"{}\"", header));
+
+    CompileCommand {
+        directory: donor.directory.clone(),
+        command: parts.join(" "),
+        file: header.to_string(),
+        output: None,
+        arguments: None,
+        failed: false,
+        configuration: None,
+    }
+}
+
+/// Entries for the headers below the project directories of `entries` that
+/// do not have one yet
+pub fn synthesize(entries: &[CompileCommand]) -> Vec<CompileCommand> {
+    let donors: Vec<CompileCommand> = entries
+        .iter()
+        .filter(|entry| !entry.failed)
+        .cloned()
+        .collect();
+    let covered: IndexSet<String> = entries.iter().map(CompileCommand::file_key).collect();
+
+    let mut by_source_dir = IndexMap::new();
+    for entry in &donors {
+        if let Some(dir) = Path::new(&entry.absolute_file()).parent() {
+            by_source_dir.entry(dir.to_path_buf()).or_insert(entry);
+        }
+    }
+
+    let mut headers = Vec::new();
+    for root in search_roots(&donors) {
+        find_headers(&root, &mut headers);
+    }
+
+    let synthesized: Vec<CompileCommand> = headers
+        .iter()
+        .map(|header| path_to_normalized_string(header))
+        .filter(|header| !covered.contains(&path_key(header)))
+        .filter_map(|header| {
+            let donor = donor(Path::new(&header), &by_source_dir, &donors)?;
+            Some(header_entry(&header, donor))
+        })
+        .collect();
+
+    info!("Synthesized {} header entries", synthesized.len());
+    synthesized
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(directory: &Path, file: &Path, flags: &str) -> CompileCommand {
+        let file = file.display().to_string();
+        CompileCommand {
+            directory: directory.display().to_string(),
+            command: format!("cl.exe /c {} \"{}\"", flags, file),
+            file,
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_header_borrows_sibling_command() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("core")).unwrap();
+        fs::create_dir_all(root.join("include")).unwrap();
+        fs::create_dir_all(root.join(".vs")).unwrap();
+        for file in ["core/core.h", "include/api.hpp", ".vs/ignored.h"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let entries = vec![
+            entry(root, &root.join("main.cpp"), "/DMAIN"),
+            entry(root, &root.join("core").join("core.cpp"), "/DCORE"),
+        ];
+        let headers = synthesize(&entries);
+        assert_eq!(headers.len(), 2);
+
+        let core = headers.iter().find(|h| h.file.ends_with("core.h")).unwrap();
+        assert!(core.command.contains("/DCORE /TP"));
+        assert!(!core.command.contains("core.cpp"));
+
+        // No source file next to it: the project's entry is used
+        let api = headers
+            .iter()
+            .find(|h| h.file.ends_with("api.hpp"))
+            .unwrap();
+        assert!(api.command.contains("/DMAIN"));
+    }
+
+    #[test]
+    fn test_existing_header_entry_is_kept() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("util.h"), "").unwrap();
+
+        let entries = vec![
+            entry(root, &root.join("util.c"), ""),
+            entry(root, &root.join("util.h"), ""),
+        ];
+        assert!(synthesize(&entries).is_empty());
+
+        let header = header_entry("util.h", &entries[0]);
+        assert_eq!(header.command, "cl.exe /c /TC \"util.h\"");
+    }
+}
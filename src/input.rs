@@ -0,0 +1,388 @@
+//! Detection of the kind of build artifact passed as input.
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use flate2::bufread::MultiGzDecoder;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+/// `--input-file` value that reads the log from standard input
+const STDIN: &str = "-";
+
+/// What the `--input-file` values name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum InputFormat {
+    /// MSBuild logs
+    #[default]
+    Msbuild,
+    /// Ninja build directories containing a `build.ninja`
+    Ninja,
+}
+
+/// Kinds of build artifacts users commonly point ms2cc at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// Plain-text MSBuild log (UTF-8 or ASCII)
+    Text,
+    /// Text log encoded as UTF-16 (PowerShell 5.1 redirection), transcoded while reading
+    Utf16Text,
+    /// MSBuild binary log (`/bl`), a gzip stream
+    BinaryLog,
+    /// XML logger output
+    XmlLog,
+    /// File tracker log (`CL.read.1.tlog`)
+    TrackerLog,
+}
+
+impl fmt::Display for InputKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputKind::Text => write!(f, "text build log"),
+            InputKind::Utf16Text => write!(f, "UTF-16 text build log"),
+            InputKind::BinaryLog => write!(f, "MSBuild binary log"),
+            InputKind::XmlLog => write!(f, "XML build log"),
+            InputKind::TrackerLog => write!(f, "file tracker log"),
+        }
+    }
+}
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+/// Compression of an archived log, such as `msbuild.log.gz`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "Zstandard"),
+        }
+    }
+}
+
+/// Detect a compressed input from its leading bytes
+pub fn detect_compression(head: &[u8]) -> Option<Compression> {
+    if head.starts_with(GZIP_MAGIC) {
+        Some(Compression::Gzip)
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Some(Compression::Zstd)
+    } else {
+        None
+    }
+}
+
+/// Wrap `reader` so it yields the decompressed input
+pub fn decompress<'a, R: BufRead + 'a>(
+    reader: R,
+    compression: Option<Compression>,
+) -> io::Result<Box<dyn BufRead + 'a>> {
+    Ok(match compression {
+        None => Box::new(reader),
+        Some(Compression::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Some(Compression::Zstd) => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+    })
+}
+
+/// Classify an input from its leading bytes, after decompression
+pub fn detect_input_kind(head: &[u8]) -> InputKind {
+    if head.starts_with(GZIP_MAGIC) || looks_like_binlog_records(head) {
+        return InputKind::BinaryLog;
+    }
+
+    if head.starts_with(UTF16_LE_BOM) || head.starts_with(UTF16_BE_BOM) || looks_like_utf16(head) {
+        // Tracker logs are UTF-16 too; tell them apart by their first character
+        let text = head
+            .strip_prefix(UTF16_LE_BOM)
+            .or_else(|| head.strip_prefix(UTF16_BE_BOM))
+            .unwrap_or(head);
+        let first_char = text.iter().find(|&&b| b != 0).copied().unwrap_or(0);
+        return if first_char == b'^' {
+            InputKind::TrackerLog
+        } else {
+            InputKind::Utf16Text
+        };
+    }
+
+    let text = head.strip_prefix(UTF8_BOM).unwrap_or(head);
+    let text = text.trim_ascii_start();
+    if text.starts_with(b"<?xml") {
+        return InputKind::XmlLog;
+    }
+    if text.starts_with(b"^") {
+        return InputKind::TrackerLog;
+    }
+
+    InputKind::Text
+}
+
+/// A decompressed binary log: it starts with its format version, a small
+/// little-endian 32-bit integer
+fn looks_like_binlog_records(head: &[u8]) -> bool {
+    head.len() >= 4 && head[0] != 0 && head[0] < 0x20 && head[1..4] == [0, 0, 0]
+}
+
+/// UTF-16 without a BOM: ASCII text with every other byte NUL
+fn looks_like_utf16(head: &[u8]) -> bool {
+    let sample = &head[..head.len().min(512)];
+    sample.len() >= 4 && sample.iter().filter(|&&b| b == 0).count() * 3 > sample.len()
+}
+
+/// Fail with actionable advice if the input cannot be read as a text log
+pub fn ensure_text_log(kind: InputKind, path: &Path) -> Result<()> {
+    let advice = match kind {
+        InputKind::Text | InputKind::Utf16Text => return Ok(()),
+        InputKind::BinaryLog => {
+            "Replay it to a text log first: msbuild msbuild.binlog /fileLogger /fileLoggerParameters:LogFile=msbuild.log;Verbosity=detailed"
+        }
+        InputKind::XmlLog => "Use MSBuild's text file logger with Verbosity=detailed instead",
+        InputKind::TrackerLog => {
+            "Tracker logs only list file accesses; pass the MSBuild log written with Verbosity=detailed"
+        }
+    };
+
+    bail!("{} is a {}. {}", path.display(), kind, advice)
+}
+
+/// Check if `path` names standard input
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN
+}
+
+/// Open the log at `path`, or standard input for `-`. Also returns the size
+/// of the log if it is known up front.
+pub fn open(path: &Path) -> Result<(Box<dyn Read>, Option<u64>)> {
+    if is_stdin(path) {
+        return Ok((Box::new(io::stdin()), None));
+    }
+
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open input file: {}", path.display()))?;
+    let size = file.metadata()?.len();
+    Ok((Box::new(file), Some(size)))
+}
+
+/// Pick the encoding to transcode the log from. `label` is an explicit
+/// `--input-encoding` such as `utf-16le` or `windows-1252`; without one,
+/// UTF-16 is detected and anything else is read as UTF-8 (`None`).
+pub fn log_encoding(
+    kind: InputKind,
+    head: &[u8],
+    label: Option<&str>,
+) -> Result<Option<&'static Encoding>> {
+    if let Some(label) = label {
+        let Some(encoding) = Encoding::for_label(label.trim().as_bytes()) else {
+            bail!(
+                "Unknown input encoding '{}' (use a label such as utf-8, utf-16le, windows-1252 or ibm866)",
+                label
+            );
+        };
+        return Ok(Some(encoding));
+    }
+
+    if kind != InputKind::Utf16Text {
+        return Ok(None);
+    }
+    // Without a BOM, big-endian text starts with the zero byte of its first character
+    if head.starts_with(UTF16_BE_BOM)
+        || (!head.starts_with(UTF16_LE_BOM) && head.first() == Some(&0))
+    {
+        Ok(Some(UTF_16BE))
+    } else {
+        Ok(Some(UTF_16LE))
+    }
+}
+
+/// Wrap `reader` so it yields UTF-8. A byte order mark in the input takes
+/// precedence over `encoding`.
+pub fn decode_reader<'a, R: Read + 'a>(
+    reader: R,
+    encoding: Option<&'static Encoding>,
+) -> Box<dyn BufRead + 'a> {
+    match encoding {
+        Some(encoding) => Box::new(BufReader::new(
+            DecodeReaderBytesBuilder::new()
+                .encoding(Some(encoding))
+                .build(reader),
+        )),
+        None => Box::new(BufReader::new(reader)),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str, bom: bool) -> Vec<u8> {
+        let mut bytes = if bom {
+            UTF16_LE_BOM.to_vec()
+        } else {
+            Vec::new()
+        };
+        bytes.extend(text.encode_utf16().flat_map(|u| u.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn test_detect_plain_text() {
+        assert_eq!(
+            detect_input_kind(b"Build started 1/1/2025 10:00:00 AM.\r\n"),
+            InputKind::Text
+        );
+        assert_eq!(
+            detect_input_kind(b"\xEF\xBB\xBFBuild started."),
+            InputKind::Text
+        );
+        assert_eq!(detect_input_kind(b""), InputKind::Text);
+    }
+
+    #[test]
+    fn test_detect_binary_log() {
+        assert_eq!(
+            detect_input_kind(&[0x1F, 0x8B, 0x08, 0x00, 0x00]),
+            InputKind::BinaryLog
+        );
+    }
+
+    #[test]
+    fn test_decompress_logs() {
+        use std::io::Write;
+
+        let text = "Build started.\r\nCL.exe /c a.cpp\r\n";
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(text.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let zstd = zstd::encode_all(text.as_bytes(), 0).unwrap();
+
+        for (bytes, expected) in [
+            (gzip, Some(Compression::Gzip)),
+            (zstd, Some(Compression::Zstd)),
+            (text.as_bytes().to_vec(), None),
+        ] {
+            let compression = detect_compression(&bytes);
+            assert_eq!(compression, expected);
+
+            let mut reader = decompress(std::io::Cursor::new(bytes), compression).unwrap();
+            assert_eq!(
+                detect_input_kind(reader.fill_buf().unwrap()),
+                InputKind::Text
+            );
+            let mut decompressed = String::new();
+            reader.read_to_string(&mut decompressed).unwrap();
+            assert_eq!(decompressed, text);
+        }
+
+        // A binary log is a gzip stream of binary records
+        assert_eq!(
+            detect_input_kind(&[0x12, 0, 0, 0, 0x12, 0, 0, 0]),
+            InputKind::BinaryLog
+        );
+    }
+
+    #[test]
+    fn test_detect_utf16() {
+        assert_eq!(
+            detect_input_kind(&utf16le("Build started.", true)),
+            InputKind::Utf16Text
+        );
+        assert_eq!(
+            detect_input_kind(&utf16le("Build started.", false)),
+            InputKind::Utf16Text
+        );
+    }
+
+    #[test]
+    fn test_detect_xml_log() {
+        assert_eq!(
+            detect_input_kind(b"  <?xml version=\"1.0\"?><build>"),
+            InputKind::XmlLog
+        );
+    }
+
+    #[test]
+    fn test_detect_tracker_log() {
+        assert_eq!(
+            detect_input_kind(&utf16le("^C:\\SRC\\MAIN.CPP\r\n", true)),
+            InputKind::TrackerLog
+        );
+        assert_eq!(
+            detect_input_kind(&utf16le("^C:\\SRC\\MAIN.CPP\r\n", false)),
+            InputKind::TrackerLog
+        );
+        assert_eq!(
+            detect_input_kind(b"^C:\\SRC\\MAIN.CPP\r\n"),
+            InputKind::TrackerLog
+        );
+    }
+
+    #[test]
+    fn test_decode_utf16_log() {
+        for bom in [true, false] {
+            let bytes = utf16le("Build started.\r\nCL.exe /c a.cpp\r\n", bom);
+            let encoding = log_encoding(detect_input_kind(&bytes), &bytes, None).unwrap();
+            assert_eq!(encoding, Some(UTF_16LE));
+
+            let lines: Vec<String> = decode_reader(std::io::Cursor::new(bytes), encoding)
+                .lines()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(lines, vec!["Build started.", "CL.exe /c a.cpp"]);
+        }
+    }
+
+    #[test]
+    fn test_decode_explicit_codepage() {
+        let bytes = b"C:\\Users\\J\xfcrgen\\a.cpp".to_vec();
+        let encoding = log_encoding(InputKind::Text, &bytes, Some("windows-1252")).unwrap();
+
+        let mut text = String::new();
+        decode_reader(std::io::Cursor::new(bytes), encoding)
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "C:\\Users\\J\u{fc}rgen\\a.cpp");
+
+        assert!(log_encoding(InputKind::Text, b"", Some("nope")).is_err());
+        assert_eq!(log_encoding(InputKind::Text, b"Build", None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_open_reports_file_size() {
+        let mut log = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut log, b"Build started.").unwrap();
+
+        let (mut reader, size) = open(log.path()).unwrap();
+        assert_eq!(size, Some(14));
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "Build started.");
+
+        assert!(open(Path::new("missing.log")).is_err());
+    }
+
+    #[test]
+    fn test_ensure_text_log() {
+        let path = Path::new("msbuild.binlog");
+        assert!(ensure_text_log(InputKind::Text, path).is_ok());
+
+        let err = ensure_text_log(InputKind::BinaryLog, path).unwrap_err();
+        assert!(err.to_string().contains("binary log"));
+        assert!(err.to_string().contains("Replay"));
+    }
+}
@@ -0,0 +1,5596 @@
+//! Convert MSBuild logs to compile_commands.json for C/C++ language servers.
+//!
+//! [`generate`] runs the whole conversion and returns the entries, so other
+//! tools can embed ms2cc instead of running the `ms2cc` binary. The modules
+//! expose the individual steps for callers that need more control.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use indexmap::{IndexMap, IndexSet};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::{debug, error, info, trace, warn};
+use regex::Regex;
+use std::borrow::Cow;
+use std::mem::take;
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::{Component, Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tempfile::NamedTempFile;
+
+pub mod args;
+pub mod attribution;
+pub mod cancel;
+pub mod clangd;
+pub mod compile_commands;
+pub mod compile_flags;
+pub mod config;
+pub mod database;
+pub mod diagnostics;
+pub mod diff;
+pub mod doctor;
+pub mod env;
+pub mod filter;
+pub mod filter_cmd;
+pub mod guard;
+pub mod headers;
+pub mod input;
+pub mod link;
+pub mod long_path;
+pub mod ninja;
+pub mod p1689;
+pub mod path_map;
+pub mod pipeline;
+pub mod progress;
+pub mod project_graph;
+pub mod project_line;
+pub mod query;
+pub mod report;
+pub mod resolve;
+mod rsp;
+pub mod tlog;
+pub mod tokenizer;
+pub mod transform;
+pub mod validation;
+
+use attribution::Attribution;
+use cancel::CancellationToken;
+use compile_commands::{Dedupe, DirectoryMode};
+use config::Config;
+use database::CompilationDatabase;
+use diagnostics::{Category, Diagnostics, LogLocation};
+use env::Environment;
+use filter::PathFilter;
+use input::InputFormat;
+use link::LinkCommand;
+use path_map::{DriveMap, MapGeneratedSources, PathMap, Remap};
+use progress::{NoProgress, ProgressReader, ProgressSink};
+use project_graph::{ProjectEdge, ProjectGraph};
+use project_line::ProjectLine;
+use resolve::{
+    ChainResolver, FileIndexResolver, FoHeuristicResolver, ProjectContextResolver, ResolveContext,
+    SourceResolver,
+};
+use tlog::TlogIndex;
+use tokenizer::Tokenizer;
+use transform::{EntryTransformer, FlagFilter, SharedTransformer, TransformerRegistry};
+
+// ----------------------------------------------------------------------------
+// Data Structures
+// ----------------------------------------------------------------------------
+
+/// Context for the current project being processed
+#[derive(Debug, Clone)]
+struct ProjectContext {
+    /// Full path to the project file
+    project_path: PathBuf,
+    /// Directory containing the project file (for resolving relative paths)
+    project_dir: PathBuf,
+}
+
+impl ProjectContext {
+    /// Context of the project file at `project_path`
+    fn new(project_path: PathBuf) -> Self {
+        let project_dir = project_path
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."));
+        Self {
+            project_path,
+            project_dir,
+        }
+    }
+}
+
+/// A heuristic guess that `--strict-ambiguity` refuses to make
+#[derive(Debug)]
+struct AmbiguityError {
+    line_number: usize,
+    line: String,
+    reason: String,
+}
+
+impl std::fmt::Display for AmbiguityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Ambiguous input at line {}: {}\n  line: {}\n  (run without --strict-ambiguity to accept the guess)",
+            self.line_number,
+            self.reason,
+            self.line.trim()
+        )
+    }
+}
+
+impl std::error::Error for AmbiguityError {}
+
+/// Default source file extensions recognized on compiler command lines
+/// (`ixx` and `cppm` are C++20 module interface units)
+const DEFAULT_SOURCE_EXTENSIONS: &[&str] = &["c", "cpp", "cc", "cxx", "ixx", "cppm"];
+
+/// Compiler executables converted by default
+const DEFAULT_COMPILERS: &[&str] = &["cl.exe"];
+
+/// Options controlling how compiler command lines are parsed
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// Lowercase source file extensions without the leading dot
+    pub source_extensions: Vec<String>,
+    /// Record each entry's object file in the "output" field
+    pub emit_output: bool,
+    /// Treat heuristic guesses as errors instead of diagnostics
+    pub strict_ambiguity: bool,
+    /// Splice the contents of `@file.rsp` response files into the arguments
+    pub expand_response_files: bool,
+    /// Compiler executables whose invocations are converted (e.g. `cl.exe`)
+    pub compilers: Vec<String>,
+    /// Replace `%VAR%` references with the environment captured from the log
+    pub expand_env: bool,
+    /// Keep every diagnostic with its log line, for an error report
+    pub keep_occurrences: bool,
+    /// Resource compilers, MIDL and assemblers (e.g. `rc.exe`) whose
+    /// invocations are added as they were logged, one entry per source
+    pub extra_tools: Vec<String>,
+    /// Record each entry's MSBuild configuration and keep the entries of
+    /// different configurations for the same file apart
+    pub track_configurations: bool,
+    /// Regex of a decoration in front of every log line, removed before the
+    /// line is read (default: a `/clp:ShowTimestamp` time)
+    pub line_prefix: Option<String>,
+    /// Splits compiler command lines into arguments
+    pub tokenizer: Tokenizer,
+    /// Keep the precompiled header flags `/Yc`, `/Yu` and `/Fp`, which are
+    /// removed by default because clangd cannot use MSVC's PCH files
+    pub keep_pch_flags: bool,
+    /// Sources recorded by the file tracker, to resolve relative sources
+    /// that do not exist below the project directory
+    pub tlog_index: Option<Arc<TlogIndex>>,
+    /// How CL lines the log does not tie to a project are attributed
+    pub attribution: Attribution,
+    /// Drives of the build machine and the directories they stand for,
+    /// applied to sources before they are checked on disk
+    pub drive_map: DriveMap,
+    /// Resolvers of the embedding code, tried after the project directory
+    /// and the `/Fo` directories and before the tracker logs
+    pub source_resolvers: Vec<Arc<dyn SourceResolver>>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            source_extensions: DEFAULT_SOURCE_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            emit_output: false,
+            strict_ambiguity: false,
+            expand_response_files: true,
+            compilers: DEFAULT_COMPILERS.iter().map(|c| c.to_string()).collect(),
+            expand_env: false,
+            keep_occurrences: false,
+            extra_tools: Vec::new(),
+            track_configurations: false,
+            line_prefix: None,
+            tokenizer: Tokenizer::default(),
+            keep_pch_flags: false,
+            tlog_index: None,
+            attribution: Attribution::Strict,
+            drive_map: DriveMap::default(),
+            source_resolvers: Vec::new(),
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Resolvers tried in turn for each source: the project directory, the
+    /// `/Fo` directory and its ancestors, `source_resolvers`, then the
+    /// tracker logs
+    fn source_resolver(&self) -> ChainResolver {
+        let mut chain = ChainResolver::default();
+        chain.push(Arc::new(ProjectContextResolver));
+        chain.push(Arc::new(FoHeuristicResolver));
+        for resolver in &self.source_resolvers {
+            chain.push(Arc::clone(resolver));
+        }
+        if let Some(index) = &self.tlog_index {
+            chain.push(Arc::new(FileIndexResolver(Arc::clone(index))));
+        }
+        chain
+    }
+
+    /// Build options from a config file, falling back to defaults for unset values
+    pub fn from_config(config: &Config) -> Self {
+        let mut options = Self::default();
+        if let Some(extensions) = &config.extensions {
+            options.source_extensions = extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect();
+        }
+        if let Some(tokenizer) = &config.tokenizer {
+            options.tokenizer = tokenizer.clone();
+        }
+        options
+    }
+
+    /// Check if a token names one of the configured compilers, with or without
+    /// a directory and `.exe`
+    fn is_compiler(&self, token: &str) -> bool {
+        let path = compiler_stem(token.trim_matches('"'));
+        self.compilers.iter().map(|c| compiler_stem(c)).any(|stem| {
+            path.strip_suffix(stem.as_str())
+                .is_some_and(|parent| parent.is_empty() || parent.ends_with('\\'))
+        })
+    }
+
+    /// Check if a token is a source file with one of the configured extensions
+    pub fn is_source_file(&self, token: &str) -> bool {
+        // Remove quotes if present
+        let clean_token = token.trim_matches('"');
+        Path::new(clean_token)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| self.source_extensions.contains(&ext))
+    }
+}
+
+/// MSBuild global properties recorded for a project
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct ProjectProperties {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configuration: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform_toolset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub windows_target_platform_version: Option<String>,
+}
+
+impl ProjectProperties {
+    /// Record a property value; returns false for properties we don't track
+    fn set(&mut self, name: &str, value: &str) -> bool {
+        let slot = match name {
+            "Configuration" => &mut self.configuration,
+            "Platform" => &mut self.platform,
+            "PlatformToolset" => &mut self.platform_toolset,
+            "WindowsTargetPlatformVersion" => &mut self.windows_target_platform_version,
+            _ => return false,
+        };
+        *slot = Some(value.to_string());
+        true
+    }
+}
+
+/// Represents a single compilation command entry in compile_commands.json
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CompileCommand {
+    /// The working directory of the compilation
+    pub directory: String,
+    /// The compile command as a single string (empty when only `arguments` is set)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub command: String,
+    /// The compile command as a list of arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Vec<String>>,
+    /// The main translation unit source processed by this command
+    pub file: String,
+    /// The object file produced by this command
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Whether the CL task that ran this command failed (never serialized)
+    #[serde(skip)]
+    pub failed: bool,
+    /// MSBuild `Configuration` of the project that compiled the file, when
+    /// configurations are kept apart (never serialized)
+    #[serde(skip)]
+    pub configuration: Option<String>,
+}
+
+/// Which form of the command each database entry carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputStyle {
+    /// `arguments` list only
+    Arguments,
+    /// `command` string only
+    #[default]
+    Command,
+    /// Both `arguments` and `command`
+    Both,
+}
+
+impl CompileCommand {
+    /// The source file as a normalized absolute path
+    pub fn absolute_file(&self) -> String {
+        path_to_normalized_string(&Path::new(&self.directory).join(&self.file))
+    }
+
+    /// [`Self::absolute_file`] folded for lookups; the entry keeps its casing
+    pub fn file_key(&self) -> String {
+        path_key(&self.absolute_file())
+    }
+
+    /// Lowercase executable name of the compiler that produced the entry,
+    /// without `.exe`, e.g. `clang-cl`
+    pub fn compiler(&self) -> String {
+        let program = match &self.arguments {
+            Some(arguments) if self.command.is_empty() => arguments.first().cloned(),
+            _ => tokenize_command_line(&self.command).into_iter().next(),
+        };
+        compiler_name(program.unwrap_or_default().trim_matches('"'))
+    }
+
+    /// Check if the entry was produced by one of `compilers`, given like
+    /// `--compiler-executable` (e.g. `clang-cl.exe`)
+    pub fn uses_compiler(&self, compilers: &[String]) -> bool {
+        let compiler = self.compiler();
+        compilers.iter().any(|name| compiler_name(name) == compiler)
+    }
+
+    /// Fill in the command forms `style` asks for and drop the others.
+    /// Entries loaded from an existing database may carry either form.
+    pub fn apply_output_style(&mut self, style: OutputStyle) {
+        if self.command.is_empty()
+            && let Some(arguments) = &self.arguments
+        {
+            self.command = join_arguments(arguments);
+        }
+        if style != OutputStyle::Command && self.arguments.is_none() {
+            self.arguments = Some(split_arguments(&self.command));
+        }
+
+        match style {
+            OutputStyle::Arguments => self.command.clear(),
+            OutputStyle::Command => self.arguments = None,
+            OutputStyle::Both => {}
+        }
+    }
+
+    /// Write `file` relative to `base_dir` if it lies below it. Consumers
+    /// must resolve such entries against `base_dir`, not `directory`.
+    pub fn relativize_file(&mut self, base_dir: &Path) {
+        let base = path_to_normalized_string(base_dir);
+        let base = base.trim_end_matches(['\\', '/']);
+        let file = self.absolute_file();
+        let relative = file
+            .get(..base.len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case(base))
+            .and_then(|_| file[base.len()..].strip_prefix(['\\', '/']));
+        if let Some(relative) = relative {
+            self.file = relative.to_string();
+        }
+    }
+
+    /// Undo [`Self::relativize_file`] for an entry read back from a database
+    pub fn absolutize_file(&mut self, base_dir: &Path) {
+        if Path::new(&self.file).is_relative() {
+            self.file = path_to_normalized_string(&base_dir.join(&self.file));
+        }
+    }
+}
+
+/// State tracking for MSBuild log processing
+#[derive(Debug)]
+struct ProcessingState {
+    /// Maps output prefix (e.g., "7>") to the project being built
+    prefix_to_project: std::collections::HashMap<u32, ProjectContext>,
+    /// Current project context (for sequential builds or fallback)
+    current_project: Option<ProjectContext>,
+    /// Current output prefix being processed
+    current_prefix: Option<u32>,
+    /// Total number of compile commands found
+    command_count: usize,
+    /// Every project seen, in order of first appearance, with its global properties
+    projects: IndexMap<PathBuf, ProjectProperties>,
+    /// Indices of commands issued by the CL task still running on each output prefix
+    open_cl_tasks: std::collections::HashMap<Option<u32>, Vec<usize>>,
+    /// Context of the enclosing MSBuild invocations, innermost last
+    outer_scopes: Vec<InvocationScope>,
+    /// Problems found while scanning, reported once at the end
+    diagnostics: Diagnostics,
+    /// Working directory of the NMake/jom run active on each output prefix
+    nmake_dirs: std::collections::HashMap<Option<u32>, PathBuf>,
+    /// Environment logged for each output prefix; `None` is the whole build's
+    environments: std::collections::HashMap<Option<u32>, Environment>,
+    /// Output prefix of the environment block being read, if inside one
+    reading_environment: Option<Option<u32>>,
+    /// Project instances of the current invocation and which built which
+    graph: ProjectGraph,
+    /// C++ project of output prefixes only known through `graph`
+    graph_projects: std::collections::HashMap<u32, ProjectContext>,
+    /// Projects each project built, from all invocations that have ended
+    project_references: IndexMap<PathBuf, IndexSet<PathBuf>>,
+}
+
+/// Prefix tracking saved while a nested MSBuild invocation is being logged.
+/// Inner builds number their output prefixes from 1 again, so their mappings
+/// must not leak into the invocation that started them.
+#[derive(Debug, Default)]
+struct InvocationScope {
+    prefix_to_project: std::collections::HashMap<u32, ProjectContext>,
+    current_project: Option<ProjectContext>,
+    current_prefix: Option<u32>,
+    open_cl_tasks: std::collections::HashMap<Option<u32>, Vec<usize>>,
+    environments: std::collections::HashMap<Option<u32>, Environment>,
+    graph: ProjectGraph,
+    graph_projects: std::collections::HashMap<u32, ProjectContext>,
+}
+
+impl ProcessingState {
+    fn new() -> Self {
+        Self {
+            prefix_to_project: std::collections::HashMap::new(),
+            current_project: None,
+            current_prefix: None,
+            command_count: 0,
+            projects: IndexMap::new(),
+            open_cl_tasks: std::collections::HashMap::new(),
+            outer_scopes: Vec::new(),
+            diagnostics: Diagnostics::default(),
+            nmake_dirs: std::collections::HashMap::new(),
+            environments: std::collections::HashMap::new(),
+            reading_environment: None,
+            graph: ProjectGraph::default(),
+            graph_projects: std::collections::HashMap::new(),
+            project_references: IndexMap::new(),
+        }
+    }
+
+    /// Start tracking a new MSBuild invocation with empty prefix mappings
+    fn enter_invocation(&mut self) {
+        self.outer_scopes.push(InvocationScope {
+            prefix_to_project: take(&mut self.prefix_to_project),
+            current_project: self.current_project.take(),
+            current_prefix: self.current_prefix.take(),
+            open_cl_tasks: take(&mut self.open_cl_tasks),
+            environments: take(&mut self.environments),
+            graph: take(&mut self.graph),
+            graph_projects: take(&mut self.graph_projects),
+        });
+    }
+
+    /// Return to the invocation that started the current one, if any
+    fn leave_invocation(&mut self) {
+        self.keep_project_references();
+        let outer = self.outer_scopes.pop().unwrap_or_default();
+        self.prefix_to_project = outer.prefix_to_project;
+        self.current_project = outer.current_project;
+        self.current_prefix = outer.current_prefix;
+        self.open_cl_tasks = outer.open_cl_tasks;
+        self.environments = outer.environments;
+        self.graph = outer.graph;
+        self.graph_projects = outer.graph_projects;
+    }
+
+    /// Add the project references of the current invocation's graph to
+    /// those of the whole log
+    fn keep_project_references(&mut self) {
+        for (parent, children) in self.graph.references() {
+            self.project_references
+                .entry(parent)
+                .or_default()
+                .extend(children);
+        }
+    }
+
+    /// Add `edge` to the graph and update the project of every output
+    /// prefix whose C++ project it may have changed
+    fn add_project_edge(&mut self, edge: &ProjectEdge) {
+        self.graph.add(edge);
+        for prefix in self.graph.ancestors(edge.parent_prefix) {
+            match self.graph.build_project(prefix) {
+                Some(path) => {
+                    let ctx = ProjectContext::new(path.to_path_buf());
+                    self.graph_projects.insert(prefix, ctx);
+                }
+                None => {
+                    self.graph_projects.remove(&prefix);
+                }
+            }
+        }
+    }
+
+    /// Environment in effect for the current output prefix, if one was logged
+    fn environment(&self) -> Option<&Environment> {
+        self.environments
+            .get(&self.current_prefix)
+            .or_else(|| self.environments.get(&None))
+    }
+
+    /// Make `ctx` the current project and remember that it was seen
+    fn enter_project(&mut self, ctx: ProjectContext) {
+        self.projects.entry(ctx.project_path.clone()).or_default();
+        self.nmake_dirs.remove(&self.current_prefix);
+        self.current_project = Some(ctx);
+    }
+
+    /// Whether the active project is a fallback because the current output
+    /// prefix was never mapped to a project
+    fn active_project_is_inferred(&self) -> bool {
+        self.current_prefix.is_some_and(|prefix| {
+            !self.prefix_to_project.contains_key(&prefix)
+                && !self.graph_projects.contains_key(&prefix)
+        }) && self.current_project.is_some()
+    }
+
+    /// Record on `commands` the configuration `project_path` is built for
+    fn tag_configuration(&self, project_path: &Path, commands: &mut [CompileCommand]) {
+        let configuration = self
+            .projects
+            .get(project_path)
+            .and_then(|properties| properties.configuration.as_ref());
+        for command in commands {
+            command.configuration = configuration.cloned();
+        }
+    }
+
+    /// Project the current output prefix is known to build, without the
+    /// fallback to the project entered last
+    fn prefix_project(&self) -> Option<&ProjectContext> {
+        let prefix = self.current_prefix?;
+        self.prefix_to_project
+            .get(&prefix)
+            .or_else(|| self.graph_projects.get(&prefix))
+    }
+
+    /// Get the active project context based on current prefix or fallback
+    fn get_active_project(&self) -> Option<&ProjectContext> {
+        if let Some(prefix) = self.current_prefix {
+            // Try prefix-aware mapping first (parallel builds), then the
+            // project built below a prefix that is not a C++ project
+            self.prefix_to_project
+                .get(&prefix)
+                .or_else(|| self.graph_projects.get(&prefix))
+                .or(self.current_project.as_ref())
+        } else {
+            // Sequential build: use current_project
+            self.current_project.as_ref()
+        }
+    }
+}
+
+/// Result of scanning a build log
+#[derive(Debug, Default)]
+pub struct ScanResult {
+    /// Compile commands, one per translation unit, in log order
+    pub commands: Vec<CompileCommand>,
+    /// Number of earlier compilations of the same translation unit that were dropped
+    pub superseded: usize,
+    /// Problems found while scanning
+    pub diagnostics: Diagnostics,
+    /// Projects seen in the log with their global properties
+    pub projects: IndexMap<PathBuf, ProjectProperties>,
+    /// link.exe and lib.exe invocations, in log order
+    pub link_commands: Vec<LinkCommand>,
+    /// Environment logged for each project directory that compiled files
+    pub environments: IndexMap<String, Environment>,
+    /// Lines read from the logs
+    pub lines_read: usize,
+    /// Lines that could not be read and were skipped
+    pub lines_skipped: usize,
+    /// Projects each project built, from the `is building` lines
+    pub project_references: IndexMap<PathBuf, IndexSet<PathBuf>>,
+}
+
+impl ScanResult {
+    /// Add the results of a later log. Its entries replace earlier ones for
+    /// the same source file.
+    pub fn extend(&mut self, later: ScanResult) {
+        self.commands = merge_compile_commands(
+            take(&mut self.commands),
+            later.commands,
+            MergeStrategy::Update,
+        );
+        self.superseded += later.superseded;
+        self.projects.extend(later.projects);
+        self.diagnostics.extend(later.diagnostics);
+        self.link_commands.extend(later.link_commands);
+        self.environments.extend(later.environments);
+        self.lines_read += later.lines_read;
+        self.lines_skipped += later.lines_skipped;
+        for (parent, children) in later.project_references {
+            self.project_references
+                .entry(parent)
+                .or_default()
+                .extend(children);
+        }
+    }
+}
+
+/// Bundle of compiled regex patterns for log parsing
+pub struct LogPatterns {
+    line_prefix: Regex,
+    node_prefix: Regex,
+    project_property: Regex,
+    compile_command: Regex,
+    cl_task_done: Regex,
+    skipped_tool: Regex,
+    link_command: Regex,
+    extra_tool: Option<Regex>,
+    compiler_wrapper: Regex,
+    environment_start: Regex,
+    environment_variable: Regex,
+    set_compiler_options: Regex,
+    nmake_started: Regex,
+    exec_done: Regex,
+    build_started: Regex,
+    build_finished: Regex,
+}
+
+impl LogPatterns {
+    /// Patterns recognizing the default compiler (CL.exe)
+    pub fn new() -> Result<Self> {
+        Self::for_compilers(&ParseOptions::default().compilers)
+    }
+
+    /// Patterns recognizing the given compiler executables
+    pub fn for_compilers(compilers: &[String]) -> Result<Self> {
+        Self::for_tools(compilers, &[], None)
+    }
+
+    /// Patterns recognizing the compilers and extra tools of `options`
+    pub fn for_options(options: &ParseOptions) -> Result<Self> {
+        Self::for_tools(
+            &options.compilers,
+            &options.extra_tools,
+            options.line_prefix.as_deref(),
+        )
+    }
+
+    fn for_tools(
+        compilers: &[String],
+        extra_tools: &[String],
+        line_prefix: Option<&str>,
+    ) -> Result<Self> {
+        let converted = [compilers, extra_tools].concat();
+        Ok(Self {
+            line_prefix: line_prefix_pattern(line_prefix)?,
+            node_prefix: node_prefix_pattern()?,
+            project_property: project_property_pattern()?,
+            compile_command: compile_command_pattern(compilers)?,
+            cl_task_done: cl_task_done_pattern()?,
+            skipped_tool: skipped_tool_pattern(&converted)?,
+            link_command: link_command_pattern()?,
+            extra_tool: if extra_tools.is_empty() {
+                None
+            } else {
+                Some(extra_tool_pattern(extra_tools)?)
+            },
+            compiler_wrapper: compiler_wrapper_pattern()?,
+            environment_start: environment_start_pattern()?,
+            environment_variable: environment_variable_pattern()?,
+            set_compiler_options: set_compiler_options_pattern()?,
+            nmake_started: nmake_started_pattern()?,
+            exec_done: exec_done_pattern()?,
+            build_started: build_started_pattern()?,
+            build_finished: build_finished_pattern()?,
+        })
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Command Line Parsing
+// ----------------------------------------------------------------------------
+
+/// Tokenize a command line respecting quoted strings, as CL.exe logs it
+fn tokenize_command_line(line: &str) -> Vec<String> {
+    Tokenizer::default().tokenize(line)
+}
+
+/// Split a command line into arguments with quotes removed, the way
+/// CL.exe sees them
+fn split_arguments(command: &str) -> Vec<String> {
+    Tokenizer {
+        keep_quotes: false,
+        ..Tokenizer::default()
+    }
+    .tokenize(command)
+}
+
+/// Join arguments into a command line, quoting those that need it
+fn join_arguments(arguments: &[String]) -> String {
+    arguments
+        .iter()
+        .map(|argument| {
+            if argument.is_empty() || argument.contains([' ', '\t', '"']) {
+                // Backslashes only need doubling before a quote
+                let mut quoted = String::from("\"");
+                let mut backslashes = 0;
+                for ch in argument.chars() {
+                    match ch {
+                        '\\' => backslashes += 1,
+                        '"' => {
+                            quoted
+                                .extend(std::iter::repeat_n('\\', take(&mut backslashes) * 2 + 1));
+                            quoted.push('"');
+                        }
+                        _ => {
+                            quoted.extend(std::iter::repeat_n('\\', take(&mut backslashes)));
+                            quoted.push(ch);
+                        }
+                    }
+                }
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+                quoted.push('"');
+                quoted
+            } else {
+                argument.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// C++20 module flags whose value is the following token, e.g.
+/// `/reference Core=Core.ifc` or `/ifcOutput obj\`
+const MODULE_FLAGS_WITH_VALUE: &[&str] = &[
+    "reference",
+    "ifcOutput",
+    "ifcSearchDir",
+    "headerUnit",
+    "headerUnit:quote",
+    "headerUnit:angle",
+    "scanDependencies",
+    "sourceDependencies",
+];
+
+/// Check if a token is a module flag that consumes the next token
+fn module_flag_takes_value(flag: &str) -> bool {
+    flag.strip_prefix(['/', '-'])
+        .is_some_and(|name| MODULE_FLAGS_WITH_VALUE.contains(&name))
+}
+
+/// Split `/Tp<file>` and `/Tc<file>`, which compile a file as C++ or C
+/// whatever its extension, into the file and the `/TP` or `/TC` flag that
+/// does the same for a command with that single source. The file is empty
+/// when it is the next argument.
+fn explicit_source_flag(token: &str) -> Option<(&'static str, &str)> {
+    let flag = token.strip_prefix(['/', '-'])?;
+    if let Some(file) = flag.strip_prefix("Tp") {
+        Some(("/TP", file))
+    } else if let Some(file) = flag.strip_prefix("Tc") {
+        Some(("/TC", file))
+    } else {
+        None
+    }
+}
+
+/// Check if a flag should be filtered out (PCH-related)
+fn should_filter_flag(flag: &str) -> bool {
+    let flag_upper = flag.to_uppercase();
+    // Strip PCH flags: /Yc, /Yu, /Fp<path>
+    // Keep /FI (force include) - clangd supports this as -include
+    // Keep /fp:<model> (floating-point model) - has colon, different from /Fp (PCH)
+
+    if flag_upper.starts_with("/YC") || flag_upper.starts_with("/YU") {
+        return true;
+    }
+
+    // Check for /Fp (PCH file) but NOT /fp: (floating-point model)
+    // After uppercasing:
+    //   /fp:precise → /FP:PRECISE (floating-point, keep it)
+    //   /Fp"file.pch" → /FP"FILE.PCH" (PCH, filter it)
+    // The discriminator is the colon!
+    if flag_upper.starts_with("/FP") && !flag_upper.starts_with("/FP:") {
+        return true;
+    }
+
+    false
+}
+
+/// Normalize a path by rebuilding it from components
+/// This eliminates double backslashes, redundant separators, and other path anomalies,
+/// and resolves `.` and `..` without touching the filesystem
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // The parent of the root is the root
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                // Leading `..` of a relative path
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() && !path.as_os_str().is_empty() {
+        normalized.push(Component::CurDir);
+    }
+    normalized
+}
+
+/// Fold a path for lookups. Windows paths are case-insensitive, and logs
+/// spell the same file `C:\Src\a.cpp` in one place and `c:\src\A.cpp` in
+/// another; only the keys are folded, never the paths that are written out.
+fn path_key(path: &str) -> String {
+    path.to_lowercase()
+}
+
+/// Convert a PathBuf to a normalized string representation
+fn path_to_normalized_string(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    // clangd does not understand extended-length paths
+    let path = long_path::strip_verbatim(&path);
+    normalize_path(Path::new(path.as_ref()))
+        .display()
+        .to_string()
+}
+
+/// Clean include path by removing trailing backslashes that can cause
+/// quote-escaping issues in clangd's command-line parser
+///
+/// Examples:
+///   /I"C:\path\to\dir\\" -> /I"C:\path\to\dir"
+///   /I"C:\path\to\dir"   -> /I"C:\path\to\dir" (unchanged)
+///   /IC:\path\to\dir\    -> /IC:\path\to\dir (unquoted form)
+fn clean_include_path(flag: &str) -> String {
+    // Check if this is an include flag
+    let flag_upper = flag.to_uppercase();
+    if !flag_upper.starts_with("/I") {
+        return flag.to_string();
+    }
+
+    // Pattern: /I followed by optional quotes, path, optional trailing backslashes, optional closing quote
+    // Cases to handle:
+    //   /I"C:\path\\"    -> /I"C:\path"
+    //   /IC:\path\       -> /IC:\path
+    //   /I"C:\path"      -> /I"C:\path" (no change)
+
+    if flag.starts_with("/I\"") || flag.starts_with("/i\"") {
+        // Quoted path: /I"path\\"
+        if let Some(end_quote_pos) = flag.rfind('"')
+            && end_quote_pos > 3
+        {
+            // More than just /I"<quote>
+            let prefix = &flag[..3]; // /I"
+            let path = &flag[3..end_quote_pos]; // The actual path
+            let suffix = &flag[end_quote_pos..]; // Closing "
+
+            // Remove trailing backslashes from path
+            // But preserve at least one backslash if it's a root path like "C:\"
+            let cleaned_path = if path.len() == 3 && path.ends_with(":\\") {
+                // Root path like "C:\" - keep it
+                path
+            } else {
+                path.trim_end_matches('\\')
+            };
+
+            return format!("{}{}{}", prefix, cleaned_path, suffix);
+        }
+    } else if flag.len() > 2 {
+        // Unquoted path: /Ipath\
+        let prefix = &flag[..2]; // /I
+        let path = &flag[2..]; // The path
+
+        // Remove trailing backslashes
+        // But preserve at least one backslash if it's a root path like "C:\"
+        let cleaned_path = if path.len() == 3 && path.ends_with(":\\") {
+            // Root path like "C:\" - keep it
+            path
+        } else {
+            path.trim_end_matches('\\')
+        };
+
+        return format!("{}{}", prefix, cleaned_path);
+    }
+
+    // Fallback: return as-is if pattern doesn't match
+    flag.to_string()
+}
+
+/// Resolve source file path to absolute path
+fn resolve_source_file_path(source_file: &str, working_directory: &Path) -> PathBuf {
+    let file_path = PathBuf::from(source_file.trim_matches('"'));
+
+    if file_path.is_absolute() {
+        return file_path;
+    }
+
+    // Resolve relative to working directory
+    working_directory.join(&file_path)
+}
+
+/// Source files a compiler command line names, as logged
+fn named_sources(line: &str, options: &ParseOptions) -> Vec<String> {
+    options
+        .tokenizer
+        .tokenize(line)
+        .into_iter()
+        .skip(1)
+        .filter(|token| options.is_source_file(token))
+        .collect()
+}
+
+/// Extract the value of the last /Fo flag, without quotes
+fn object_file_flag(args: &[String]) -> Option<String> {
+    args.iter().rev().find_map(|arg| {
+        let value = arg
+            .strip_prefix("/Fo")
+            .or_else(|| arg.strip_prefix("-Fo"))?;
+        let value = value.strip_prefix(':').unwrap_or(value);
+        Some(value.trim_matches('"').to_string())
+    })
+}
+
+/// Derive the object file CL.exe writes for each source
+///
+/// A /Fo value ending in a separator names a directory and every source gets
+/// `<dir>\<stem>.obj`; without /Fo the objects land in the working directory.
+/// A file-style /Fo only applies when a single source is compiled. Sources in
+/// the same batch that map to the same object cannot be told apart, so they
+/// get no object path at all.
+fn object_file_paths(
+    fo: Option<&str>,
+    sources: &[PathBuf],
+    working_directory: &Path,
+    diagnostics: &mut Diagnostics,
+) -> Vec<Option<String>> {
+    let object_in = |dir: &Path, source: &Path| {
+        source
+            .file_stem()
+            .map(|stem| dir.join(Path::new(stem).with_extension("obj")))
+    };
+
+    let objects: Vec<Option<PathBuf>> = match fo {
+        Some(fo) if !fo.is_empty() && !fo.ends_with(['\\', '/']) => {
+            if sources.len() != 1 {
+                return vec![None; sources.len()];
+            }
+            let mut object = working_directory.join(fo);
+            if object.extension().is_none() {
+                object.set_extension("obj");
+            }
+            vec![Some(object)]
+        }
+        Some(fo) => {
+            let dir = working_directory.join(fo);
+            sources.iter().map(|s| object_in(&dir, s)).collect()
+        }
+        None => sources
+            .iter()
+            .map(|s| object_in(working_directory, s))
+            .collect(),
+    };
+
+    let normalized: Vec<Option<String>> = objects
+        .iter()
+        .map(|o| o.as_deref().map(path_to_normalized_string))
+        .collect();
+
+    normalized
+        .iter()
+        .map(|object| {
+            let object = object.as_ref()?;
+            let key = object.to_lowercase();
+            let collisions = normalized
+                .iter()
+                .flatten()
+                .filter(|other| other.to_lowercase() == key)
+                .count();
+            if collisions > 1 {
+                diagnostics.record(Category::AmbiguousObject, || object.clone());
+                return None;
+            }
+            Some(object.clone())
+        })
+        .collect()
+}
+
+/// Parse a CL.exe command line and extract compile commands
+/// Returns a vector of CompileCommand (one per source file)
+fn parse_cl_command(
+    line: &str,
+    project_ctx: &ProjectContext,
+    options: &ParseOptions,
+    diagnostics: &mut Diagnostics,
+    line_number: usize,
+) -> Result<Vec<CompileCommand>> {
+    // Extract the full CL.exe path using regex BEFORE tokenization
+    // This handles both quoted and unquoted paths with spaces:
+    //   Quoted: "C:\Program Files\...\CL.exe"
+    //   Unquoted: C:\Program Files\Microsoft Visual Studio\...\CL.exe
+    // Pattern matches from drive letter to CL.exe, handling spaces in between
+    // Bare `cl` invocations (NMake makefiles) rely on PATH and are kept as is
+    let compilers = compiler_alternation(&options.compilers);
+    let cl_exe_regex = regex::Regex::new(&format!(
+        r#"(?i)([A-Z]:[^\r\n]*?\\(?:{0})\.exe|"[^"]*\\(?:{0})\.exe"|^\s*(?:{0})(?:\.exe)?(?:\s|$))"#,
+        compilers
+    ))
+    .context("Failed to compile CL.exe regex")?;
+
+    let cl_exe_match = cl_exe_regex
+        .find(line)
+        .context("CL.exe not found in command line")?
+        .as_str()
+        .trim();
+
+    // An unquoted path with spaces is reassembled from several tokens
+    if !cl_exe_match.starts_with('"') && cl_exe_match.contains(' ') {
+        if options.strict_ambiguity {
+            return Err(AmbiguityError {
+                line_number,
+                line: line.to_string(),
+                reason: format!(
+                    "unquoted compiler path with spaces, guessed {}",
+                    cl_exe_match
+                ),
+            }
+            .into());
+        }
+        diagnostics.record(Category::GuessedCompilerPath, || {
+            format!("{} (line {})", cl_exe_match, line_number)
+        });
+    }
+
+    // Remove quotes if present
+    let cl_exe_path = cl_exe_match.trim_matches('"').to_string();
+
+    let tokens = options.tokenizer.tokenize(line);
+    // Where the compiler ran, on this machine
+    let project_dir = options.drive_map.translate_path(&project_ctx.project_dir);
+
+    // Find CL.exe position in tokens to know where arguments start
+    let cl_exe_pos = tokens
+        .iter()
+        .position(|t| options.is_compiler(t))
+        .context("CL.exe not found in command line")?;
+
+    // Separate source files, with the flag naming their language if they
+    // were given with /Tp or /Tc, from flags
+    let mut source_files: Vec<(String, Option<&str>)> = Vec::new();
+    let mut filtered_args = Vec::new();
+
+    // Arguments after CL.exe, with response files spliced in
+    let mut arguments = Vec::new();
+    for token in tokens.into_iter().skip(cl_exe_pos + 1) {
+        let rsp_path = options
+            .expand_response_files
+            .then(|| rsp::response_file_path(&token, &project_dir))
+            .flatten();
+        let Some(rsp_path) = rsp_path else {
+            arguments.push(token);
+            continue;
+        };
+        match rsp::read_arguments(&rsp_path) {
+            Ok(contents) => arguments.extend(contents),
+            Err(e) => {
+                debug!("{:#}", e);
+                diagnostics.record(Category::MissingResponseFile, || {
+                    format!("{} (line {})", rsp_path.display(), line_number)
+                });
+                arguments.push(token);
+            }
+        }
+    }
+
+    // Extract tokens (everything after CL.exe)
+    let mut takes_value = false;
+    let mut explicit_language = None;
+    for token in args::join_separated_flags(arguments) {
+        if let Some(language) = explicit_language.take() {
+            source_files.push((token, Some(language)));
+            continue;
+        }
+        // The value of a module flag is kept with it and never taken for a source
+        if take(&mut takes_value) {
+            filtered_args.push(token);
+            continue;
+        }
+        takes_value = module_flag_takes_value(&token);
+
+        if let Some((language, file)) = explicit_source_flag(&token) {
+            if file.is_empty() {
+                explicit_language = Some(language);
+            } else {
+                source_files.push((file.to_string(), Some(language)));
+            }
+        } else if options.is_source_file(&token) {
+            source_files.push((token, None));
+        } else if options.keep_pch_flags || !should_filter_flag(&token) {
+            // Clean include paths to remove trailing backslashes
+            let cleaned_token = clean_include_path(&token);
+            filtered_args.push(cleaned_token);
+        }
+    }
+
+    if source_files.is_empty() {
+        debug!(
+            "No source files found in CL.exe command at line {} for project {}",
+            line_number,
+            project_ctx.project_path.display()
+        );
+        diagnostics.record(Category::NoSourceFiles, || {
+            format!(
+                "line {} ({})",
+                line_number,
+                project_ctx.project_path.display()
+            )
+        });
+        return Ok(Vec::new());
+    }
+
+    // Create one CompileCommand per source file
+    let mut commands = Vec::new();
+    let object_flag = object_file_flag(&filtered_args);
+
+    // Build the base command string once (combines CL.exe path + filtered args)
+    let base_command = {
+        let cl_exe_token = if cl_exe_path.contains(' ') {
+            format!("\"{}\"", cl_exe_path)
+        } else {
+            cl_exe_path
+        };
+        let mut parts = vec![cl_exe_token];
+        parts.extend(filtered_args);
+        parts.join(" ")
+    };
+
+    // Resolve source files to absolute paths
+    let resolver = options.source_resolver();
+    let context = ResolveContext {
+        project_dir: &project_dir,
+        object_file: object_flag.as_deref(),
+    };
+    let absolute_paths: Vec<PathBuf> = source_files
+        .iter()
+        .map(|(source, _)| {
+            let source = options.drive_map.translate(source.trim_matches('"'));
+            let path = resolver
+                .resolve(&source, &context)
+                .unwrap_or_else(|| resolve_source_file_path(&source, &project_dir));
+            options.drive_map.translate_path(&path)
+        })
+        .collect();
+
+    for path in absolute_paths
+        .iter()
+        .filter(|p| !long_path::for_filesystem(p).exists())
+    {
+        diagnostics.record(Category::UnresolvedPath, || {
+            format!("{} (line {})", path.display(), line_number)
+        });
+    }
+
+    // An object file named by /Fo is always recorded; deriving one for every
+    // entry is opt-in
+    let names_object_file = object_flag
+        .as_deref()
+        .is_some_and(|fo| !fo.is_empty() && !fo.ends_with(['\\', '/']));
+    let object_files = if options.emit_output || names_object_file {
+        if object_flag.is_none() {
+            diagnostics.record(Category::MissingObjectFlag, || {
+                format!(
+                    "line {} ({})",
+                    line_number,
+                    project_ctx.project_path.display()
+                )
+            });
+        }
+        object_file_paths(
+            object_flag.as_deref(),
+            &absolute_paths,
+            &project_dir,
+            diagnostics,
+        )
+    } else {
+        vec![None; absolute_paths.len()]
+    };
+
+    let languages = source_files.iter().map(|(_, language)| *language);
+    for ((absolute_file_path, output), language) in
+        absolute_paths.into_iter().zip(object_files).zip(languages)
+    {
+        // Normalize paths to eliminate double backslashes and other anomalies
+        let normalized_file = path_to_normalized_string(&absolute_file_path);
+        let normalized_directory = path_to_normalized_string(&project_dir);
+
+        // Reconstruct command with base command + normalized absolute source file path
+        let command = match language {
+            Some(language) => format!("{} {} \"{}\"", base_command, language, normalized_file),
+            None => format!("{} \"{}\"", base_command, normalized_file),
+        };
+
+        commands.push(CompileCommand {
+            directory: normalized_directory,
+            command,
+            file: normalized_file,
+            output,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        });
+    }
+
+    trace!(
+        "Parsed {} compile command(s) from line {} for project {}",
+        commands.len(),
+        line_number,
+        project_ctx.project_path.display()
+    );
+
+    Ok(commands)
+}
+
+// ----------------------------------------------------------------------------
+// Regular Expression Patterns
+// ----------------------------------------------------------------------------
+
+/// Pattern to match node prefix (e.g., "7>" or "7:2>" at start of line)
+/// Used to track the current build node in parallel builds
+/// Handles both simple prefixes (7>) and multi-instance prefixes (7:2>)
+fn node_prefix_pattern() -> Result<Regex> {
+    let pattern = r"^\s*(\d+)(?::\d+)?>";
+    debug!("Compiling node prefix regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile node prefix regex")
+}
+
+/// Pattern to match MSBuild global property assignments we track per project
+/// Example (Initial/Global Properties sections): Configuration = Release
+/// Example (Message task output): 5>  Platform=x64
+/// Captures the PROPERTY NAME and VALUE
+fn project_property_pattern() -> Result<Regex> {
+    let pattern = r"^\s*(?:\d+(?::\d+)?>)?\s*(Configuration|Platform|PlatformToolset|WindowsTargetPlatformVersion)\s*=\s*(\S.*?)\s*$";
+    debug!("Compiling project-property regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile project-property regex")
+}
+
+/// Sources of the extra tools: resource scripts, IDL files and assembly
+const EXTRA_TOOL_SOURCE_EXTENSIONS: &[&str] = &["rc", "idl", "odl", "acf", "asm", "s"];
+
+/// Compilers whose invocations are reported as skipped unless configured
+const SKIPPED_TOOLS: &[&str] = &[
+    "clang-cl", "icl", "icx", "icx-cl", "nvcc", "ml", "ml64", "armasm", "armasm64",
+];
+
+/// Lowercase executable name without `.exe`. A compiler may also be given
+/// with the end of its path, e.g. `HostX64\x64\cl.exe`, to tell apart
+/// toolchains that use the same name; its separators become `\`.
+fn compiler_stem(name: &str) -> String {
+    let name = name.to_lowercase().replace('/', "\\");
+    name.strip_suffix(".exe").unwrap_or(&name).to_string()
+}
+
+/// Executable name of a compiler given as a path fragment
+fn compiler_name(name: &str) -> String {
+    let stem = compiler_stem(name);
+    stem.rsplit('\\').next().unwrap_or_default().to_string()
+}
+
+/// Regex alternation of the compiler stems, e.g. `cl|clang\-cl`; path
+/// fragments match either separator
+fn compiler_alternation(compilers: &[String]) -> String {
+    compilers
+        .iter()
+        .map(|name| regex::escape(&compiler_stem(name)).replace(r"\\", r"[\\/]"))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// Pattern to match compiler commands
+/// Matches lines containing one of `compilers` (e.g. CL.exe) followed by arguments
+fn compile_command_pattern(compilers: &[String]) -> Result<Regex> {
+    let pattern = format!(
+        r#"(?i)^\s+(?:.*?[\\/"\s])?(?:{})\.exe"?\s"#,
+        compiler_alternation(compilers)
+    );
+    debug!("Compiling CL.exe command regex: {}", pattern);
+    Regex::new(&pattern).context("Failed to compile CL.exe command regex")
+}
+
+/// Pattern to match the end of a CL task
+/// Example: Done executing task "CL".
+/// Example (compiler errors): 5>Done executing task "CL" -- FAILED.
+/// Captures " -- FAILED" when the task failed
+fn cl_task_done_pattern() -> Result<Regex> {
+    let pattern = r#"^\s*(?:\d+(?::\d+)?>)?\s*Done executing task "CL"( -- FAILED)?\."#;
+    debug!("Compiling CL task done regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile CL task done regex")
+}
+
+/// Pattern to match invocations of the `--extra-tools`
+/// Example: C:\Windows Kits\10\bin\x64\rc.exe /nologo /fo"obj\app.res" app.rc
+/// Captures the COMMAND and the TOOL NAME
+fn extra_tool_pattern(tools: &[String]) -> Result<Regex> {
+    let pattern = format!(
+        r#"(?i)^\s*(?:\d+(?::\d+)?>)?\s+((?:\S.*?[\\/"\s])?({})\.exe"?\s.*?)\s*$"#,
+        compiler_alternation(tools)
+    );
+    debug!("Compiling extra tool regex: {}", pattern);
+    Regex::new(&pattern).context("Failed to compile extra tool regex")
+}
+
+/// Pattern to match invocations of compilers that are not converted
+/// Example: C:\LLVM\bin\clang-cl.exe /c main.cpp
+/// Example: C:\CUDA\bin\nvcc.exe -c kernel.cu
+/// Captures the TOOL NAME. Tools listed in `compilers` are converted instead.
+fn skipped_tool_pattern(compilers: &[String]) -> Result<Regex> {
+    let tools: Vec<&str> = SKIPPED_TOOLS
+        .iter()
+        .copied()
+        .filter(|tool| !compilers.iter().any(|c| compiler_name(c) == *tool))
+        .collect();
+    let pattern = format!(r#"(?i)^\s+.*?[\\/"\s]({})\.exe[\s"]"#, tools.join("|"));
+    debug!("Compiling skipped-tool regex: {}", pattern);
+    Regex::new(&pattern).context("Failed to compile skipped-tool regex")
+}
+
+/// Pattern to match linker and librarian invocations
+/// Example: C:\VC\bin\HostX64\x64\link.exe /ERRORREPORT:QUEUE /OUT:"app.exe" main.obj
+/// Example: 5>  "C:\Program Files\VC\bin\lib.exe" /OUT:"core.lib" core.obj
+/// Captures the COMMAND (without output prefix) and the TOOL NAME
+fn link_command_pattern() -> Result<Regex> {
+    let pattern = r#"(?i)^\s*(?:\d+(?::\d+)?>)?\s+((?:\S.*?[\\/"\s])?(link|lib)\.exe"?\s.*?)\s*$"#;
+    debug!("Compiling link command regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile link command regex")
+}
+
+/// Compiler caches that take the compiler command line as their arguments
+const COMPILER_WRAPPERS: &[&str] = &["ccache", "sccache", "buildcache"];
+
+/// Pattern to match the decoration in front of each log line, by default
+/// the time `/clp:ShowTimestamp` adds
+/// Example: 17:49:27.305     5>  C:\VC\bin\CL.exe /c main.cpp
+fn line_prefix_pattern(custom: Option<&str>) -> Result<Regex> {
+    let pattern = custom.unwrap_or(r"^\d{1,2}:\d{2}:\d{2}(?:\.\d+)?\s");
+    debug!("Compiling line-prefix regex: {}", pattern);
+    Regex::new(pattern).with_context(|| format!("Invalid line prefix regex: {}", pattern))
+}
+
+/// Pattern to match a wrapper launching the compiler, up to the compiler
+/// Example: Tracker.exe /d FileTracker32.dll /i C:\obj\p.tlog /c C:\VC\bin\CL.exe /c main.cpp
+/// Example: 5>  C:\Tools\sccache.exe C:\VC\bin\cl.exe /c main.cpp
+/// Captures the LEADING whitespace and output prefix, which are kept
+fn compiler_wrapper_pattern() -> Result<Regex> {
+    let pattern = format!(
+        r#"(?i)^(\s*(?:\d+(?::\d+)?>)?\s*)(?:.*?[\\/"\s])?(?:tracker\.exe"?\s.*?\s/c|(?:{})(?:\.exe)?"?)\s+"#,
+        COMPILER_WRAPPERS.join("|")
+    );
+    debug!("Compiling compiler-wrapper regex: {}", pattern);
+    Regex::new(&pattern).context("Failed to compile compiler-wrapper regex")
+}
+
+/// Pattern to match the header of the environment block in diagnostic logs
+/// Example: Environment at start of build:
+fn environment_start_pattern() -> Result<Regex> {
+    let pattern = r"^\s*(?:\d+(?::\d+)?>)?\s*Environment at start of build:\s*$";
+    debug!("Compiling environment-start regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile environment-start regex")
+}
+
+/// Pattern to match one variable of the environment block
+/// Example: INCLUDE = C:\VC\include;C:\Windows Kits\10\include\ucrt
+/// Captures the NAME and the VALUE
+fn environment_variable_pattern() -> Result<Regex> {
+    let pattern = r"^\s*(?:\d+(?::\d+)?>)?\s*([A-Za-z_][\w().]*) = (.*?)\s*$";
+    debug!("Compiling environment-variable regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile environment-variable regex")
+}
+
+/// Pattern to match a `set` of the variables CL.exe reads options from
+/// Example: set CL=/DFOO /W4
+/// Captures the NAME (`CL` or `_CL_`) and the VALUE
+fn set_compiler_options_pattern() -> Result<Regex> {
+    let pattern = r#"(?i)^\s*(?:\d+(?::\d+)?>)?\s*set\s+"?(CL|_CL_)=(.*?)"?\s*$"#;
+    debug!("Compiling set-compiler-options regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile set-compiler-options regex")
+}
+
+/// Pattern to match the start of NMake or jom output
+/// Example: Microsoft (R) Program Maintenance Utility Version 14.44.35207.1
+/// Example: jom 1.1.4 - empower your cores
+/// Example (echoed command): nmake /nologo /f Makefile all
+fn nmake_started_pattern() -> Result<Regex> {
+    let pattern = r#"(?i)^\s*(?:\d+(?::\d+)?>)?\s*(?:Microsoft \(R\) Program Maintenance Utility|jom \d+\.\d+|(?:"[^"]*\\)?(?:nmake|jom)(?:\.exe)?"?(?:\s|$))"#;
+    debug!("Compiling NMake start regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile NMake start regex")
+}
+
+/// Pattern to match the end of an Exec task, which ends any NMake run it started
+/// Example: 5>Done executing task "Exec".
+fn exec_done_pattern() -> Result<Regex> {
+    let pattern = r#"^\s*(?:\d+(?::\d+)?>)?\s*Done executing task "Exec""#;
+    debug!("Compiling Exec done regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile Exec done regex")
+}
+
+/// Pattern to match the start of an MSBuild invocation
+/// Example: Build started 1/1/2025 10:00:00 AM.
+/// Example (msbuild run from an Exec task): 5>  Build started 1/1/2025 10:00:00 AM.
+fn build_started_pattern() -> Result<Regex> {
+    let pattern = r"^\s*(?:\d+(?::\d+)?>)?\s*Build started\b";
+    debug!("Compiling build-started regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile build-started regex")
+}
+
+/// Pattern to match the end of an MSBuild invocation
+/// Example: Build succeeded.
+/// Example: 5>  Build FAILED.
+fn build_finished_pattern() -> Result<Regex> {
+    let pattern = r"^\s*(?:\d+(?::\d+)?>)?\s*Build (?:succeeded|FAILED)\.";
+    debug!("Compiling build-finished regex: {}", pattern);
+    Regex::new(pattern).context("Failed to compile build-finished regex")
+}
+
+// ----------------------------------------------------------------------------
+// Log Processing Helper Functions
+// ----------------------------------------------------------------------------
+
+/// Setup and configure the progress bar for searching the `--tlog-dir`
+/// directories; its length grows as subdirectories are found
+fn setup_index_progress_bar(show_progress: bool, multi: &MultiProgress) -> Result<ProgressBar> {
+    if !show_progress {
+        return Ok(ProgressBar::hidden());
+    }
+
+    let pb = multi.add(ProgressBar::new(0));
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} directories (ETA {eta}) {msg}")?
+            .progress_chars("=> "),
+    );
+    pb.set_message("Searching for tracker logs...");
+    Ok(pb)
+}
+
+/// Setup and configure the progress bar for reading the build log
+fn setup_read_progress_bar(
+    show_progress: bool,
+    file_size: Option<u64>,
+    multi: &MultiProgress,
+) -> Result<ProgressBar> {
+    if !show_progress {
+        return Ok(ProgressBar::hidden());
+    }
+
+    let pb = match file_size {
+        Some(file_size) => {
+            let pb = multi.add(ProgressBar::new(file_size));
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template(
+                        "[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} {msg}",
+                    )?
+                    .progress_chars("=> "),
+            );
+            pb
+        }
+        // Piped input has no known size
+        None => {
+            let pb = multi.add(ProgressBar::new_spinner());
+            pb.set_style(
+                ProgressStyle::default_spinner()
+                    .template("[{elapsed_precise}] {spinner:.cyan} {bytes} {msg}")?
+                    .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "),
+            );
+            pb
+        }
+    };
+    pb.set_message("Processing build log...");
+    Ok(pb)
+}
+
+/// Finalize processing and log summary information
+fn finalize_processing(state: &ProcessingState, progress: &dyn ProgressSink, start_time: Instant) {
+    progress.finish_log();
+
+    let duration = start_time.elapsed();
+
+    debug!("Found {} projects", state.projects.len());
+
+    info!(
+        "Processing complete: {} compile commands found in {:.2}s",
+        state.command_count,
+        duration.as_secs_f64()
+    );
+
+    if state.projects.is_empty() {
+        warn!(
+            "No projects found in build log - ensure MSBuild was run with /v:detailed or /v:diagnostic"
+        );
+    }
+
+    if !state.projects.is_empty() && state.command_count == 0 {
+        warn!(
+            "Found {} projects but no compile commands - build log may be incomplete",
+            state.projects.len()
+        );
+    }
+}
+
+/// Remove the decoration matched at the start of `line`, if any
+fn strip_line_prefix(line: String, pattern: &Regex) -> String {
+    match pattern.find(&line) {
+        Some(prefix) if prefix.start() == 0 && !prefix.is_empty() => {
+            line[prefix.end()..].to_string()
+        }
+        _ => line,
+    }
+}
+
+/// Drop a Tracker.exe or compiler cache in front of the compiler, so the
+/// line reads as if the compiler had been run directly
+fn strip_compiler_wrapper(line: String, pattern: &Regex) -> String {
+    match pattern.replace(&line, "$1") {
+        Cow::Borrowed(_) => line,
+        Cow::Owned(stripped) => {
+            trace!("Stripped compiler wrapper: {}", stripped);
+            stripped
+        }
+    }
+}
+
+/// Handle node prefix pattern (e.g., "7>")
+fn handle_node_prefix(line: &str, pattern: &Regex, state: &mut ProcessingState) {
+    if let Some(caps) = pattern.captures(line)
+        && let Ok(prefix_num) = caps[1].parse::<u32>()
+    {
+        state.current_prefix = Some(prefix_num);
+    }
+}
+
+/// A command line wrapped onto the next log line ends with a continuation
+/// marker after a blank: `^` (cmd) or `\` (sh). Returns the line without it.
+fn strip_continuation(line: &str) -> Option<&str> {
+    let line = line.trim_end();
+    let rest = line.strip_suffix('^').or_else(|| line.strip_suffix('\\'))?;
+    rest.ends_with([' ', '\t']).then(|| rest.trim_end())
+}
+
+/// Whether `line` starts a command the scan converts. Only those are joined
+/// with the next line when they end in a continuation marker; other output
+/// can end in ` \` too.
+fn starts_command(line: &str, patterns: &LogPatterns, state: &ProcessingState) -> bool {
+    patterns.compile_command.is_match(line)
+        || patterns.link_command.is_match(line)
+        || patterns
+            .extra_tool
+            .as_ref()
+            .is_some_and(|pattern| pattern.is_match(line))
+        || state.nmake_dirs.contains_key(&state.current_prefix)
+}
+
+/// Handle the lines naming the project that the following lines belong to:
+/// "Project X on node N" and "Project X is building Y on node N" (parallel
+/// builds) and "from project X" (sequential builds)
+fn handle_project_line(line: &str, state: &mut ProcessingState, line_number: usize) {
+    let Some(project_line) = project_line::parse(line) else {
+        return;
+    };
+    if let ProjectLine::Building(edge) = &project_line {
+        handle_project_edge(edge, state, line_number);
+    }
+    let Some(project_path) = project_line.cpp_project() else {
+        return;
+    };
+    let ctx = ProjectContext::new(project_path.to_path_buf());
+
+    match &project_line {
+        ProjectLine::OnNode { prefix, .. } => {
+            trace!(
+                "Assigned project {} to output prefix {} at line {}",
+                ctx.project_path.display(),
+                prefix,
+                line_number
+            );
+            state.prefix_to_project.insert(*prefix, ctx.clone());
+        }
+        ProjectLine::Building(edge) => {
+            trace!(
+                "Assigned nested project {} to output prefix {} at line {}",
+                ctx.project_path.display(),
+                edge.child_prefix,
+                line_number
+            );
+            state
+                .prefix_to_project
+                .insert(edge.child_prefix, ctx.clone());
+        }
+        ProjectLine::FromProject(_) => {
+            trace!(
+                "Set current project to {} at line {}",
+                ctx.project_path.display(),
+                line_number
+            );
+        }
+    }
+    // Also the fallback for lines without an output prefix
+    state.enter_project(ctx);
+}
+
+/// Record a project building another in the project graph
+fn handle_project_edge(edge: &ProjectEdge, state: &mut ProcessingState, line_number: usize) {
+    trace!(
+        "Project {} ({}) builds {} ({}) at line {}",
+        edge.parent.display(),
+        edge.parent_prefix,
+        edge.child.display(),
+        edge.child_prefix,
+        line_number
+    );
+    state.add_project_edge(edge);
+}
+
+/// Handle global property assignments (Configuration, Platform, ...)
+fn handle_project_property(
+    line: &str,
+    pattern: &Regex,
+    state: &mut ProcessingState,
+    line_number: usize,
+) {
+    let Some(caps) = pattern.captures(line) else {
+        return;
+    };
+    let Some(project_path) = state.get_active_project().map(|p| p.project_path.clone()) else {
+        return;
+    };
+
+    if state
+        .projects
+        .entry(project_path.clone())
+        .or_default()
+        .set(&caps[1], &caps[2])
+    {
+        trace!(
+            "Recorded {}={} for project {} at line {}",
+            &caps[1],
+            &caps[2],
+            project_path.display(),
+            line_number
+        );
+    }
+}
+
+/// `--attribution heuristic`: the project whose directory holds the object
+/// file or the sources of a CL line
+fn guess_project_from_paths(
+    line: &str,
+    state: &ProcessingState,
+    options: &ParseOptions,
+) -> Option<ProjectContext> {
+    let args = options.tokenizer.tokenize(line);
+    let mut paths: Vec<String> = args
+        .iter()
+        .skip(1)
+        .filter(|token| options.is_source_file(token))
+        .cloned()
+        .collect();
+    paths.extend(object_file_flag(&args));
+    let project = attribution::guess_project(&paths, state.projects.keys())?;
+    trace!("Attributed CL line to {} by its paths", project.display());
+    Some(ProjectContext::new(project.clone()))
+}
+
+/// Handle CL.exe compilation command
+fn handle_cl_command(
+    line: &str,
+    pattern: &Regex,
+    state: &mut ProcessingState,
+    options: &ParseOptions,
+    line_number: usize,
+) -> Result<Vec<CompileCommand>> {
+    if !pattern.is_match(line) {
+        return Ok(Vec::new());
+    }
+
+    // Determine which project this command belongs to
+    let guessed =
+        if options.attribution == Attribution::Heuristic && state.prefix_project().is_none() {
+            guess_project_from_paths(line, state, options)
+        } else {
+            None
+        };
+    let is_guessed = guessed.is_some();
+    let project_ctx = guessed.or_else(|| state.get_active_project().cloned());
+
+    if let Some(proj_ctx) = &project_ctx
+        && !is_guessed
+        && state.active_project_is_inferred()
+    {
+        let reason = format!(
+            "output prefix {} has no project, guessed {}",
+            state.current_prefix.unwrap_or_default(),
+            proj_ctx.project_path.display()
+        );
+        if options.strict_ambiguity {
+            return Err(AmbiguityError {
+                line_number,
+                line: line.to_string(),
+                reason,
+            }
+            .into());
+        }
+        state.diagnostics.record(Category::InferredProject, || {
+            format!("line {}: {}", line_number, reason)
+        });
+    }
+
+    if let Some(proj_ctx) = project_ctx {
+        // Failures are reported with the log location by the caller
+        let mut commands = parse_cl_command(
+            line,
+            &proj_ctx,
+            options,
+            &mut state.diagnostics,
+            line_number,
+        )?;
+        if options.track_configurations {
+            state.tag_configuration(&proj_ctx.project_path, &mut commands);
+        }
+        Ok(commands)
+    } else {
+        debug!(
+            "Found CL.exe command at line {} but no project context available",
+            line_number
+        );
+        state.diagnostics.record(Category::NoProjectContext, || {
+            format!("line {}", line_number)
+        });
+        for file in named_sources(line, options) {
+            state
+                .diagnostics
+                .record_dropped(Category::NoProjectContext, &file, line_number);
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// Apply a `cd`/`chdir` argument to `current`, resolving `.` and `..` lexically
+fn change_directory(current: &Path, target: &str) -> PathBuf {
+    let target = target.trim().trim_matches('"');
+    let target = target
+        .strip_prefix("/d ")
+        .or_else(|| target.strip_prefix("/D "))
+        .unwrap_or(target)
+        .trim()
+        .trim_matches('"');
+
+    // Absolute targets (C:\x, \x) replace the directory outright
+    if Path::new(target).has_root() || target.as_bytes().get(1) == Some(&b':') {
+        return PathBuf::from(target);
+    }
+
+    // Split on both separators so Windows paths resolve the same on any host
+    let mut dir = current.to_path_buf();
+    for part in target.split(['\\', '/']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                dir.pop();
+            }
+            name => dir.push(name),
+        }
+    }
+    dir
+}
+
+/// Handle the start and end of NMake/jom output within a project
+fn handle_nmake_boundary(
+    line: &str,
+    patterns: &LogPatterns,
+    state: &mut ProcessingState,
+    line_number: usize,
+) {
+    if patterns.exec_done.is_match(line) {
+        if state.nmake_dirs.remove(&state.current_prefix).is_some() {
+            debug!("NMake output ended at line {}", line_number);
+        }
+    } else if patterns.nmake_started.is_match(line)
+        && !state.nmake_dirs.contains_key(&state.current_prefix)
+        && let Some(ctx) = state.get_active_project()
+    {
+        debug!(
+            "NMake output started at line {} for project {}",
+            line_number,
+            ctx.project_path.display()
+        );
+        let dir = ctx.project_dir.clone();
+        state.nmake_dirs.insert(state.current_prefix, dir);
+    }
+}
+
+/// Handle a line of NMake/jom output: track `cd` commands and parse compiler
+/// invocations against the tracked working directory. Commands chained with
+/// `&&` are processed left to right.
+fn handle_nmake_line(
+    line: &str,
+    state: &mut ProcessingState,
+    options: &ParseOptions,
+    line_number: usize,
+) -> Result<Vec<CompileCommand>> {
+    let Some(project) = state.get_active_project().cloned() else {
+        return Ok(Vec::new());
+    };
+    let Some(mut dir) = state.nmake_dirs.get(&state.current_prefix).cloned() else {
+        return Ok(Vec::new());
+    };
+
+    // Strip the node prefix MSBuild adds when output switches between nodes
+    let text = line.trim_start();
+    let text = match text.split_once('>') {
+        Some((prefix, rest)) if prefix.split(':').all(|p| p.parse::<u32>().is_ok()) => rest,
+        _ => text,
+    };
+
+    let mut commands = Vec::new();
+    for segment in text.split("&&") {
+        let segment = segment.trim();
+        let mut words = segment.splitn(2, char::is_whitespace);
+        let program = words.next().unwrap_or_default().to_lowercase();
+        let rest = words.next().unwrap_or_default();
+
+        if program == "cd" || program == "chdir" {
+            if !rest.trim().is_empty() {
+                dir = change_directory(&dir, rest);
+                trace!(
+                    "NMake working directory at line {}: {}",
+                    line_number,
+                    dir.display()
+                );
+            }
+            continue;
+        }
+
+        // An unquoted compiler path with spaces splits the program name
+        let lower = segment.to_lowercase();
+        let full_path_compiler = options
+            .compilers
+            .iter()
+            .any(|c| lower.contains(&format!("\\{}.exe ", compiler_stem(c))));
+        if options.is_compiler(&program) || full_path_compiler {
+            let ctx = ProjectContext {
+                project_path: project.project_path.clone(),
+                project_dir: dir.clone(),
+            };
+            commands.extend(parse_cl_command(
+                segment,
+                &ctx,
+                options,
+                &mut state.diagnostics,
+                line_number,
+            )?);
+        }
+    }
+
+    state.nmake_dirs.insert(state.current_prefix, dir);
+    if options.track_configurations {
+        state.tag_configuration(&project.project_path, &mut commands);
+    }
+    Ok(commands)
+}
+
+/// Handle invocations of compilers we do not convert. Returns true if the line
+/// was one, so it is not mistaken for a CL.exe command.
+fn handle_skipped_tool(
+    line: &str,
+    pattern: &Regex,
+    state: &mut ProcessingState,
+    line_number: usize,
+) -> bool {
+    let Some(caps) = pattern.captures(line) else {
+        return false;
+    };
+
+    let tool = caps[1].to_lowercase();
+    debug!("Skipping {}.exe invocation at line {}", tool, line_number);
+    state.diagnostics.record(Category::SkippedTool, || {
+        format!("{}.exe (line {})", tool, line_number)
+    });
+    true
+}
+
+/// Handle the "Environment at start of build:" block. Returns true for the
+/// header and each variable line, which are not looked at any further.
+fn handle_environment(line: &str, patterns: &LogPatterns, state: &mut ProcessingState) -> bool {
+    if patterns.environment_start.is_match(line) {
+        state.reading_environment = Some(state.current_prefix);
+        state.environments.remove(&state.current_prefix);
+        return true;
+    }
+
+    let Some(prefix) = state.reading_environment else {
+        return false;
+    };
+    let Some(caps) = patterns.environment_variable.captures(line) else {
+        // The block ends at the first line that is not a variable
+        state.reading_environment = None;
+        return false;
+    };
+    state
+        .environments
+        .entry(prefix)
+        .or_default()
+        .insert(caps[1].to_uppercase(), caps[2].to_string());
+    true
+}
+
+/// Handle `set CL=...` and `set _CL_=...` for the current output prefix; an
+/// empty value removes the variable. Returns true if the line was one.
+fn handle_set_compiler_options(line: &str, pattern: &Regex, state: &mut ProcessingState) -> bool {
+    let Some(caps) = pattern.captures(line) else {
+        return false;
+    };
+    let name = caps[1].to_uppercase();
+    let env = state.environments.entry(state.current_prefix).or_default();
+    if caps[2].trim().is_empty() {
+        env.shift_remove(&name);
+    } else {
+        env.insert(name, caps[2].trim().to_string());
+    }
+    true
+}
+
+/// Turn an invocation of one of the `--extra-tools` into an entry for its
+/// source. Returns `None` if the line is not such an invocation.
+fn handle_extra_tool(
+    line: &str,
+    pattern: &Regex,
+    state: &mut ProcessingState,
+    line_number: usize,
+) -> Option<Vec<CompileCommand>> {
+    let caps = pattern.captures(line)?;
+    let command = caps[1].to_string();
+    let tool = caps[2].to_lowercase();
+    trace!("Found {}.exe invocation at line {}", tool, line_number);
+
+    let Some(project_ctx) = state.get_active_project().cloned() else {
+        state.diagnostics.record(Category::NoProjectContext, || {
+            format!("line {}", line_number)
+        });
+        return Some(Vec::new());
+    };
+
+    // The source comes last, after the flags and their values
+    let source = split_arguments(&command)
+        .into_iter()
+        .skip(1)
+        .rev()
+        .find(|arg| {
+            !arg.starts_with(['/', '-'])
+                && Path::new(arg).extension().is_some_and(|ext| {
+                    let ext = ext.to_string_lossy().to_lowercase();
+                    EXTRA_TOOL_SOURCE_EXTENSIONS.contains(&ext.as_str())
+                })
+        });
+    let Some(source) = source else {
+        state.diagnostics.record(Category::NoSourceFiles, || {
+            format!("line {} ({}.exe)", line_number, tool)
+        });
+        return Some(Vec::new());
+    };
+
+    let path = resolve_source_file_path(&source, &project_ctx.project_dir);
+    if !long_path::for_filesystem(&path).exists() {
+        state.diagnostics.record(Category::UnresolvedPath, || {
+            format!("{} (line {})", path.display(), line_number)
+        });
+    }
+    Some(vec![CompileCommand {
+        directory: path_to_normalized_string(&project_ctx.project_dir),
+        command,
+        file: path_to_normalized_string(&path),
+        output: None,
+        arguments: None,
+        failed: false,
+        configuration: None,
+    }])
+}
+
+/// Handle link.exe and lib.exe invocations, attributed to the active project
+fn handle_link_command(
+    line: &str,
+    pattern: &Regex,
+    state: &ProcessingState,
+    line_number: usize,
+) -> Option<LinkCommand> {
+    let caps = pattern.captures(line)?;
+
+    let tool = caps[2].to_lowercase();
+    trace!("Found {}.exe invocation at line {}", tool, line_number);
+    let directory = state
+        .get_active_project()
+        .map(|ctx| path_to_normalized_string(&ctx.project_dir))
+        .unwrap_or_default();
+    Some(LinkCommand {
+        directory,
+        tool,
+        command: caps[1].to_string(),
+    })
+}
+
+/// Handle the start and end of (possibly nested) MSBuild invocations
+///
+/// Each "Build started" opens a new scope for output prefixes and each
+/// "Build succeeded."/"Build FAILED." returns to the enclosing one. This keeps
+/// the prefixes of recursive msbuild calls apart from the outer build's and
+/// resets the mappings between builds appended to the same log.
+fn handle_invocation_boundary(
+    line: &str,
+    patterns: &LogPatterns,
+    state: &mut ProcessingState,
+    line_number: usize,
+) {
+    if patterns.build_started.is_match(line) {
+        debug!(
+            "MSBuild invocation started at line {} (depth {})",
+            line_number,
+            state.outer_scopes.len() + 1
+        );
+        state.enter_invocation();
+    } else if patterns.build_finished.is_match(line) {
+        debug!(
+            "MSBuild invocation finished at line {} (depth {})",
+            line_number,
+            state.outer_scopes.len()
+        );
+        state.leave_invocation();
+    }
+}
+
+/// Handle the end of a CL task, marking its commands as failed if the task failed
+fn handle_cl_task_done(
+    line: &str,
+    pattern: &Regex,
+    state: &mut ProcessingState,
+    commands: &mut [CompileCommand],
+    line_number: usize,
+) {
+    let Some(caps) = pattern.captures(line) else {
+        return;
+    };
+
+    let indices = state
+        .open_cl_tasks
+        .remove(&state.current_prefix)
+        .unwrap_or_default();
+    if caps.get(1).is_some() {
+        debug!(
+            "CL task ending at line {} failed; {} command(s) marked as failed",
+            line_number,
+            indices.len()
+        );
+        for index in indices {
+            commands[index].failed = true;
+        }
+    }
+}
+
+/// Time of day of a ShowTimestamp prefix like `17:49:27.305 `
+fn parse_timestamp(prefix: &str) -> Option<Duration> {
+    let mut fields = prefix.trim().split(':');
+    let hours: u64 = fields.next()?.parse().ok()?;
+    let minutes: u64 = fields.next()?.parse().ok()?;
+    let seconds: f64 = fields.next()?.parse().ok()?;
+    if fields.next().is_some() || hours > 23 || minutes > 59 || seconds >= 60.0 {
+        return None;
+    }
+    Some(Duration::from_secs(hours * 3600 + minutes * 60) + Duration::from_secs_f64(seconds))
+}
+
+/// Time of day of the ShowTimestamp prefix of `line`, if it has one
+fn line_timestamp(line: &str, pattern: &Regex) -> Option<Duration> {
+    let prefix = pattern.find(line).filter(|prefix| prefix.start() == 0)?;
+    parse_timestamp(prefix.as_str())
+}
+
+/// ShowTimestamp times of a log, counted from the midnight before it started
+/// so that they keep growing when a build runs past midnight
+#[derive(Debug, Default)]
+struct LogClock {
+    last: Option<Duration>,
+    days: u32,
+}
+
+impl LogClock {
+    /// Parallel builds log slightly out of order; only a jump back by more
+    /// than this is a new day
+    const WRAP: Duration = Duration::from_secs(12 * 3600);
+    const DAY: Duration = Duration::from_secs(24 * 3600);
+
+    /// Time of a line stamped `time_of_day`
+    fn time(&mut self, time_of_day: Duration) -> Duration {
+        if let Some(last) = self.last
+            && time_of_day + Self::WRAP < last
+        {
+            self.days += 1;
+        }
+        self.last = Some(time_of_day);
+        time_of_day + Self::DAY * self.days
+    }
+}
+
+/// Collapse repeated compilations of the same translation unit within one log.
+///
+/// Entries are keyed by (file, directory, configuration), like the database
+/// merge, ignoring case. The latest command wins unless it failed and an
+/// earlier one succeeded, so retries after a broken edit do not replace the
+/// last working flags. `times` holds the ShowTimestamp time of each command,
+/// if the log has them; when both commands have one it decides which is the
+/// latest, else the log order does. The surviving entry keeps the position
+/// of the first compilation. Returns the number of commands that were
+/// discarded.
+fn keep_latest(
+    commands: Vec<CompileCommand>,
+    times: &[Option<Duration>],
+) -> (Vec<CompileCommand>, usize) {
+    let mut map: IndexMap<_, (CompileCommand, Option<Duration>)> =
+        IndexMap::with_capacity(commands.len());
+    let mut superseded = 0usize;
+
+    for (index, entry) in commands.into_iter().enumerate() {
+        let time = times.get(index).copied().flatten();
+        let key = MergeStrategy::Merge.key(&entry);
+        match map.get_mut(&key) {
+            Some((existing, existing_time)) => {
+                superseded += 1;
+                let is_later = match (time, *existing_time) {
+                    (Some(time), Some(existing_time)) => time >= existing_time,
+                    _ => true,
+                };
+                let replaces = if entry.failed == existing.failed {
+                    is_later
+                } else {
+                    existing.failed
+                };
+                if replaces {
+                    *existing = entry;
+                    *existing_time = time;
+                }
+            }
+            None => {
+                map.insert(key, (entry, time));
+            }
+        }
+    }
+
+    (
+        map.into_values().map(|(entry, _)| entry).collect(),
+        superseded,
+    )
+}
+
+/// Process the MSBuild log file. Tracks projects per output prefix for parallel
+/// builds and uses context markers for sequential builds
+pub fn process_msbuild_log(
+    input_file: &Path,
+    input_encoding: Option<&str>,
+    patterns: &LogPatterns,
+    options: &ParseOptions,
+    progress: &dyn ProgressSink,
+    cancel: &CancellationToken,
+) -> Result<ScanResult> {
+    let mut compile_commands = Vec::new();
+    let mut link_commands = Vec::new();
+    let mut environments = IndexMap::new();
+    let mut state = ProcessingState::new();
+    if options.keep_occurrences {
+        state.diagnostics.keep_occurrences();
+    }
+    state.diagnostics.set_log(input_file);
+    let mut lines_read = 0;
+    let mut lines_skipped = 0;
+    // Start of a wrapped command line, joined with the lines that follow
+    let mut continued: Option<String> = None;
+    // ShowTimestamp time of the line read last and of each compile command
+    let mut clock = LogClock::default();
+    let mut line_time = None;
+    let mut command_times: Vec<Option<Duration>> = Vec::new();
+
+    info!("Starting MSBuild log processing");
+    let start_time = Instant::now();
+
+    // Open the log (or stdin) and get its size for progress tracking
+    let (file, file_size) = input::open(input_file)?;
+
+    // Report the bytes read as the log is scanned
+    progress.start_log(input_file, file_size);
+    let mut input = BufReader::new(ProgressReader::new(file, progress));
+
+    // Unpack logs archived as .gz or .zst on the fly
+    let compression = input::detect_compression(
+        input
+            .fill_buf()
+            .with_context(|| format!("Failed to read input file: {}", input_file.display()))?,
+    );
+    if let Some(compression) = compression {
+        debug!("Decompressing {} input", compression);
+    }
+    let mut input = input::decompress(input, compression)
+        .with_context(|| format!("Failed to read input file: {}", input_file.display()))?;
+
+    // Make sure we were handed a text log before scanning it line by line
+    let head = input
+        .fill_buf()
+        .with_context(|| format!("Failed to read input file: {}", input_file.display()))?;
+    let kind = input::detect_input_kind(head);
+    debug!("Detected input kind: {}", kind);
+    input::ensure_text_log(kind, input_file)?;
+
+    // Transcode UTF-16 and legacy code pages to UTF-8 on the fly
+    let encoding = input::log_encoding(kind, head, input_encoding)?;
+    if let Some(encoding) = encoding {
+        debug!("Transcoding input from {}", encoding.name());
+    }
+    let input = input::decode_reader(input, encoding);
+
+    // Single-pass processing; the `None` after the last line flushes a
+    // wrapped command line the log ends in
+    let lines = input.lines().map(Some).chain(std::iter::once(None));
+    for (index, line_result) in lines.enumerate() {
+        cancel.check()?;
+        // The commands of the previous line were compiled at its time
+        command_times.resize(compile_commands.len(), line_time);
+        let line_number = if line_result.is_some() {
+            index + 1
+        } else {
+            index
+        };
+        let location = || LogLocation {
+            path: input_file.to_path_buf(),
+            line: line_number,
+        };
+
+        let line = match line_result {
+            Some(Ok(l)) => {
+                lines_read += 1;
+                if let Some(time_of_day) = line_timestamp(&l, &patterns.line_prefix) {
+                    line_time = Some(clock.time(time_of_day));
+                }
+                strip_line_prefix(l, &patterns.line_prefix)
+            }
+            Some(Err(e)) => {
+                lines_read += 1;
+                lines_skipped += 1;
+                warn!("Failed to read line {}: {:?}", location(), e);
+                continue;
+            }
+            None => match continued.take() {
+                Some(start) => {
+                    warn!("Log ends inside a wrapped command line at {}", location());
+                    start
+                }
+                None => break,
+            },
+        };
+
+        // Join command lines wrapped with `^` or `\`; the node prefix of
+        // the lines continuing one is dropped
+        let (line, pending) = match continued.take() {
+            Some(start) => {
+                let rest = patterns.node_prefix.replace(&line, "");
+                (format!("{} {}", start, rest.trim_start()), true)
+            }
+            None => (line, false),
+        };
+        if let Some(start) = strip_continuation(&line)
+            && (pending || starts_command(&line, patterns, &state))
+        {
+            continued = Some(start.to_string());
+            continue;
+        }
+
+        let line = strip_compiler_wrapper(line, &patterns.compiler_wrapper);
+
+        // Process each pattern type
+        handle_node_prefix(&line, &patterns.node_prefix, &mut state);
+        if state.diagnostics.keeps_occurrences() {
+            let project = state
+                .get_active_project()
+                .map(|ctx| ctx.project_path.display().to_string());
+            state.diagnostics.set_location(location(), project, &line);
+        }
+        handle_invocation_boundary(&line, patterns, &mut state, line_number);
+
+        if handle_environment(&line, patterns, &mut state)
+            || handle_set_compiler_options(&line, &patterns.set_compiler_options, &mut state)
+        {
+            continue;
+        }
+
+        handle_project_line(&line, &mut state, line_number);
+
+        handle_project_property(&line, &patterns.project_property, &mut state, line_number);
+
+        if let Some(pattern) = &patterns.extra_tool
+            && let Some(commands) = handle_extra_tool(&line, pattern, &mut state, line_number)
+        {
+            if !commands.is_empty() {
+                progress.commands_emitted(commands.len());
+            }
+            state.command_count += commands.len();
+            compile_commands.extend(commands);
+            continue;
+        }
+
+        if handle_skipped_tool(&line, &patterns.skipped_tool, &mut state, line_number) {
+            continue;
+        }
+
+        if let Some(link) = handle_link_command(&line, &patterns.link_command, &state, line_number)
+        {
+            link_commands.push(link);
+            continue;
+        }
+
+        handle_nmake_boundary(&line, patterns, &mut state, line_number);
+
+        let result = if state.nmake_dirs.contains_key(&state.current_prefix) {
+            handle_nmake_line(&line, &mut state, options, line_number)
+        } else {
+            handle_cl_command(
+                &line,
+                &patterns.compile_command,
+                &mut state,
+                options,
+                line_number,
+            )
+        };
+
+        match result {
+            Ok(mut commands) => {
+                if let Some(env) = state.environment() {
+                    for command in &mut commands {
+                        command.command = env::fold_compiler_options(&command.command, env);
+                        if options.expand_env {
+                            command.command = env::expand(&command.command, env);
+                        }
+                        environments
+                            .entry(command.directory.clone())
+                            .or_insert_with(|| env.clone());
+                    }
+                }
+                if !commands.is_empty() {
+                    progress.commands_emitted(commands.len());
+                    let first = compile_commands.len();
+                    state
+                        .open_cl_tasks
+                        .entry(state.current_prefix)
+                        .or_default()
+                        .extend(first..first + commands.len());
+                }
+                state.command_count += commands.len();
+                compile_commands.extend(commands);
+            }
+            Err(e) if e.is::<AmbiguityError>() => return Err(e.context(location())),
+            Err(e) => {
+                error!("Failed to handle CL command at {}: {:?}", location(), e);
+                state
+                    .diagnostics
+                    .record(Category::FailedLine, || format!("{}: {:#}", location(), e));
+                for file in named_sources(&line, options) {
+                    state
+                        .diagnostics
+                        .record_dropped(Category::FailedLine, &file, line_number);
+                }
+            }
+        }
+
+        handle_cl_task_done(
+            &line,
+            &patterns.cl_task_done,
+            &mut state,
+            &mut compile_commands,
+            line_number,
+        );
+    }
+
+    finalize_processing(&state, progress, start_time);
+    // A log cut off inside a nested invocation still has its outer graphs
+    while !state.outer_scopes.is_empty() {
+        state.leave_invocation();
+    }
+    state.keep_project_references();
+
+    command_times.resize(compile_commands.len(), line_time);
+    let (commands, superseded) = keep_latest(compile_commands, &command_times);
+    if superseded > 0 {
+        info!(
+            "Discarded {} superseded compile command(s) for rebuilt files",
+            superseded
+        );
+    }
+
+    Ok(ScanResult {
+        commands,
+        superseded,
+        projects: state.projects,
+        diagnostics: state.diagnostics,
+        link_commands,
+        environments,
+        lines_read,
+        lines_skipped,
+        project_references: state.project_references,
+    })
+}
+
+/// Scan `input_files` on up to `max_threads` threads (0: one per CPU) and
+/// combine the results in input order, so later logs still win for the same
+/// file. A single log is always scanned on one thread: output prefixes and
+/// nested builds map lines to projects across the whole log.
+pub fn scan_logs(
+    input_files: &[PathBuf],
+    input_encoding: Option<&str>,
+    patterns: &LogPatterns,
+    options: &ParseOptions,
+    progress: &dyn ProgressSink,
+    cancel: &CancellationToken,
+    max_threads: usize,
+) -> Result<ScanResult> {
+    let max_threads = match max_threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let threads = max_threads.min(input_files.len());
+    let scan = |input_file: &PathBuf| {
+        process_msbuild_log(
+            input_file,
+            input_encoding,
+            patterns,
+            options,
+            progress,
+            cancel,
+        )
+    };
+
+    let results: Vec<Result<ScanResult>> = if threads <= 1 {
+        input_files.iter().map(scan).collect()
+    } else {
+        debug!("Scanning {} logs on {} threads", input_files.len(), threads);
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new((0..input_files.len()).map(|_| None).collect::<Vec<_>>());
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    while !failed.load(Ordering::Relaxed) {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(input_file) = input_files.get(index) else {
+                            break;
+                        };
+                        let result = scan(input_file);
+                        if result.is_err() {
+                            failed.store(true, Ordering::Relaxed);
+                        }
+                        results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+                    }
+                });
+            }
+        });
+        // Logs skipped after a failure have no result; the failure is returned
+        results
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .into_iter()
+            .flatten()
+            .collect()
+    };
+
+    let mut combined = ScanResult::default();
+    for result in results {
+        combined.extend(result?);
+    }
+    Ok(combined)
+}
+
+/// Create a temporary file in the same directory as the output file.
+/// This validates that the output directory is writable before we begin parsing.
+/// The temp file auto-deletes on drop if not persisted.
+pub fn create_temp_output_file(output_path: &Path) -> Result<NamedTempFile> {
+    let parent = output_path.parent().unwrap_or(Path::new("."));
+    debug!("Creating temporary output file in: {}", parent.display());
+    NamedTempFile::new_in(parent)
+        .with_context(|| format!("Failed to create temporary file in: {}", parent.display()))
+}
+
+/// Load an existing compile_commands.json database for merging, written
+/// by `--format json` or `--format ndjson`.
+/// Returns an empty Vec if the file doesn't exist or can't be parsed.
+pub fn load_existing_database(path: &Path) -> Result<Vec<CompileCommand>> {
+    if !path.exists() {
+        debug!("No existing database at {}", path.display());
+        return Ok(Vec::new());
+    }
+
+    debug!("Loading existing database: {}", path.display());
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to open existing database: {}", path.display()))?;
+
+    match CompilationDatabase::from_json(&text) {
+        Ok(database) => Ok(database.into_entries()),
+        Err(e) => {
+            warn!(
+                "Failed to parse existing database ({}), starting fresh: {}",
+                path.display(),
+                e
+            );
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// How entries of an existing database are matched with new ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergeStrategy {
+    /// Match on (file, directory), ignoring case
+    #[default]
+    Merge,
+    /// Match on the normalized absolute file path alone (`--update`)
+    Update,
+    /// Keep every existing entry and add the new ones after them, skipping
+    /// only exact repeats of an entry (`--append`)
+    Append,
+}
+
+impl MergeStrategy {
+    fn key(self, entry: &CompileCommand) -> (String, String, Option<String>) {
+        let configuration = entry.configuration.clone();
+        match self {
+            MergeStrategy::Merge | MergeStrategy::Append => (
+                path_key(&entry.file),
+                path_key(&entry.directory),
+                configuration,
+            ),
+            MergeStrategy::Update => (entry.file_key(), String::new(), configuration),
+        }
+    }
+}
+
+/// Add `new_entries` after `existing`, skipping entries with the same file,
+/// directory and command as one already there
+fn append_compile_commands(
+    mut existing: Vec<CompileCommand>,
+    new_entries: Vec<CompileCommand>,
+) -> Vec<CompileCommand> {
+    let mut seen: IndexSet<_> = existing
+        .iter()
+        .map(|entry| (MergeStrategy::Append.key(entry), entry.command.clone()))
+        .collect();
+
+    let mut repeated_count = 0usize;
+    for entry in new_entries {
+        if seen.insert((MergeStrategy::Append.key(&entry), entry.command.clone())) {
+            existing.push(entry);
+        } else {
+            repeated_count += 1;
+        }
+    }
+
+    info!(
+        "Append result: {} already present, {} total",
+        repeated_count,
+        existing.len()
+    );
+    existing
+}
+
+/// Merge new compile commands into an existing database.
+/// Entries are keyed according to `strategy`. New entries replace existing ones with the
+/// same key; entries not present in the new set are preserved unchanged. With
+/// [`MergeStrategy::Append`] nothing is replaced.
+pub fn merge_compile_commands(
+    existing: Vec<CompileCommand>,
+    new_entries: Vec<CompileCommand>,
+    strategy: MergeStrategy,
+) -> Vec<CompileCommand> {
+    if strategy == MergeStrategy::Append {
+        return append_compile_commands(existing, new_entries);
+    }
+
+    let mut map: IndexMap<(String, String, Option<String>), CompileCommand> =
+        IndexMap::with_capacity(existing.len() + new_entries.len());
+
+    for entry in existing {
+        map.insert(strategy.key(&entry), entry);
+    }
+
+    let mut updated_count = 0usize;
+    let mut added_count = 0usize;
+
+    for entry in new_entries {
+        let key = strategy.key(&entry);
+        if map.contains_key(&key) {
+            updated_count += 1;
+        } else {
+            added_count += 1;
+        }
+        map.insert(key, entry);
+    }
+
+    info!(
+        "Merge result: {} updated, {} added, {} total",
+        updated_count,
+        added_count,
+        map.len()
+    );
+
+    map.into_values().collect()
+}
+
+// ----------------------------------------------------------------------------
+// Library entry point
+// ----------------------------------------------------------------------------
+
+/// What [`generate`] reads and how it shapes the entries. Built with
+/// [`GenerateOptions::builder`], so that new options are not breaking
+/// changes; the fields can still be read and changed.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct GenerateOptions {
+    /// MSBuild logs to read, in order; later logs win for the same file
+    pub input_files: Vec<PathBuf>,
+    /// Whether `input_files` are MSBuild logs or Ninja build directories
+    pub input_format: InputFormat,
+    /// Encoding label of the logs, e.g. `windows-1252` (default: detect UTF-16, else UTF-8)
+    pub input_encoding: Option<String>,
+    /// How compiler command lines are parsed
+    pub parse: ParseOptions,
+    /// CMake/Ninja build directories whose `ninja -t compdb` output is added
+    pub ninja_dirs: Vec<PathBuf>,
+    /// Add entries for headers, using the flags of a nearby source file
+    pub emit_header_entries: bool,
+    /// Built-in transformers to run on every entry, in order
+    pub transformers: Vec<String>,
+    /// Transformers of the embedding code, run after `transformers`
+    pub custom_transformers: Vec<SharedTransformer>,
+    /// Point generated sources at the file named by their first `#line`
+    /// directive, after the custom transformers
+    pub map_generated_sources: bool,
+    /// Source files pointed at the files they mirror, after that
+    pub remap: Remap,
+    /// Paths rewritten for clangd running elsewhere, by the last transformer
+    pub path_map: PathMap,
+    /// Shell command the entries are piped through as NDJSON after the
+    /// transformers; what it prints back is kept
+    pub filter_cmd: Option<String>,
+    /// Only keep the entries compiled for this MSBuild configuration
+    pub configuration: Option<String>,
+    /// Keep the configuration of each entry, e.g. to write one database per
+    /// configuration; otherwise it is cleared so that the entries match
+    /// those of databases written without one
+    pub keep_configurations: bool,
+    /// Only keep the entries of these compilers (default: all)
+    pub only_compilers: Vec<String>,
+    /// Drop the entries of these compilers
+    pub exclude_compilers: Vec<String>,
+    /// What the `directory` of each entry is set to
+    pub directory_mode: DirectoryMode,
+    /// Only keep source files matching one of these globs (default: all)
+    pub include: Vec<String>,
+    /// Drop source files matching one of these globs
+    pub exclude: Vec<String>,
+    /// What to do with several entries for the same source file
+    pub dedupe: Dedupe,
+    /// Form of the command in each entry
+    pub output_style: OutputStyle,
+    /// Number of logs scanned at once (0: one per CPU)
+    pub max_threads: usize,
+    /// Stops the conversion when cancelled from another thread
+    pub cancel: CancellationToken,
+}
+
+impl GenerateOptions {
+    /// Options built one setting at a time, starting from the defaults
+    pub fn builder() -> Ms2ccBuilder {
+        Ms2ccBuilder::default()
+    }
+}
+
+/// Fluent construction of [`GenerateOptions`]
+#[derive(Debug, Clone, Default)]
+pub struct Ms2ccBuilder {
+    options: GenerateOptions,
+}
+
+impl Ms2ccBuilder {
+    /// Read the log at `path`, after those added before
+    pub fn input_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.input_files.push(path.into());
+        self
+    }
+
+    /// Read each of `paths`, in order
+    pub fn input_files<P: Into<PathBuf>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+        self.options
+            .input_files
+            .extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// Whether the inputs are MSBuild logs or Ninja build directories
+    pub fn input_format(mut self, format: InputFormat) -> Self {
+        self.options.input_format = format;
+        self
+    }
+
+    /// Encoding label of the logs, e.g. `windows-1252`
+    pub fn input_encoding(mut self, label: impl Into<String>) -> Self {
+        self.options.input_encoding = Some(label.into());
+        self
+    }
+
+    /// How compiler command lines are parsed, replacing the settings made
+    /// so far with [`Self::compiler`] and the other parse setters
+    pub fn parse_options(mut self, options: ParseOptions) -> Self {
+        self.options.parse = options;
+        self
+    }
+
+    /// Also convert the invocations of `executable`, e.g. `clang-cl.exe`
+    pub fn compiler(mut self, executable: impl Into<String>) -> Self {
+        let executable = executable.into();
+        let compilers = &mut self.options.parse.compilers;
+        if !compilers
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(&executable))
+        {
+            compilers.push(executable);
+        }
+        self
+    }
+
+    /// Convert the invocations of `executables` only, replacing the
+    /// compilers added before
+    pub fn compilers<S: Into<String>>(mut self, executables: impl IntoIterator<Item = S>) -> Self {
+        self.options.parse.compilers = executables.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Also add the invocations of the resource compiler, MIDL or assembler
+    /// `executable` as they were logged
+    pub fn extra_tool(mut self, executable: impl Into<String>) -> Self {
+        self.options.parse.extra_tools.push(executable.into());
+        self
+    }
+
+    /// Source file extensions, without the leading dot, replacing the defaults
+    pub fn source_extensions<S: Into<String>>(
+        mut self,
+        extensions: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.options.parse.source_extensions = extensions
+            .into_iter()
+            .map(|ext| ext.into().trim_start_matches('.').to_lowercase())
+            .collect();
+        self
+    }
+
+    /// Record each entry's object file in the "output" field
+    pub fn emit_output(mut self, enabled: bool) -> Self {
+        self.options.parse.emit_output = enabled;
+        self
+    }
+
+    /// Fail instead of guessing when a line is ambiguous
+    pub fn strict_ambiguity(mut self, enabled: bool) -> Self {
+        self.options.parse.strict_ambiguity = enabled;
+        self
+    }
+
+    /// How CL lines the log does not tie to a project are attributed
+    pub fn attribution(mut self, attribution: Attribution) -> Self {
+        self.options.parse.attribution = attribution;
+        self
+    }
+
+    /// Keep the precompiled header flags `/Yc`, `/Yu` and `/Fp`
+    pub fn keep_pch_flags(mut self, enabled: bool) -> Self {
+        self.options.parse.keep_pch_flags = enabled;
+        self
+    }
+
+    /// Splice the contents of `@file.rsp` response files into the arguments
+    pub fn expand_response_files(mut self, enabled: bool) -> Self {
+        self.options.parse.expand_response_files = enabled;
+        self
+    }
+
+    /// Replace `%VAR%` references with the environment captured from the log
+    pub fn expand_env(mut self, enabled: bool) -> Self {
+        self.options.parse.expand_env = enabled;
+        self
+    }
+
+    /// Keep every diagnostic with its log line, for an error report
+    pub fn keep_occurrences(mut self, enabled: bool) -> Self {
+        self.options.parse.keep_occurrences = enabled;
+        self
+    }
+
+    /// Remove the decoration matched by the regex `pattern` from the start
+    /// of every log line
+    pub fn line_prefix(mut self, pattern: impl Into<String>) -> Self {
+        self.options.parse.line_prefix = Some(pattern.into());
+        self
+    }
+
+    /// How compiler command lines are split into arguments
+    pub fn tokenizer(mut self, tokenizer: Tokenizer) -> Self {
+        self.options.parse.tokenizer = tokenizer;
+        self
+    }
+
+    /// Drives of the build machine and the directories they stand for
+    pub fn drive_map(mut self, drive_map: DriveMap) -> Self {
+        self.options.parse.drive_map = drive_map;
+        self
+    }
+
+    /// Resolve sources missing below the project directory with the files
+    /// recorded by the tracker logs
+    pub fn tlog_index(mut self, index: Arc<TlogIndex>) -> Self {
+        self.options.parse.tlog_index = Some(index);
+        self
+    }
+
+    /// Try `resolver` for sources not found in the project directory or
+    /// next to the `/Fo` directory, before the tracker logs
+    pub fn resolver(mut self, resolver: impl SourceResolver + 'static) -> Self {
+        self.options.parse.source_resolvers.push(Arc::new(resolver));
+        self
+    }
+
+    /// Add the entries of a CMake/Ninja build directory
+    pub fn ninja_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.ninja_dirs.push(dir.into());
+        self
+    }
+
+    /// Add entries for headers, using the flags of a nearby source file
+    pub fn emit_header_entries(mut self, enabled: bool) -> Self {
+        self.options.emit_header_entries = enabled;
+        self
+    }
+
+    /// Run the built-in transformer `name`, after those added before
+    pub fn builtin_transformer(mut self, name: impl Into<String>) -> Self {
+        self.options.transformers.push(name.into());
+        self
+    }
+
+    /// Run `transformer` on every entry, after the built-in ones
+    pub fn transformer(
+        mut self,
+        name: impl Into<String>,
+        transformer: impl EntryTransformer + Send + Sync + 'static,
+    ) -> Self {
+        self.options
+            .custom_transformers
+            .push(SharedTransformer::new(name, transformer));
+        self
+    }
+
+    /// Point generated sources at the file named by their first `#line` directive
+    pub fn map_generated_sources(mut self, enabled: bool) -> Self {
+        self.options.map_generated_sources = enabled;
+        self
+    }
+
+    /// Point source files at the files they mirror
+    pub fn remap(mut self, remap: Remap) -> Self {
+        self.options.remap = remap;
+        self
+    }
+
+    /// Rewrite paths for clangd running elsewhere
+    pub fn path_map(mut self, path_map: PathMap) -> Self {
+        self.options.path_map = path_map;
+        self
+    }
+
+    /// Pipe the entries through the shell command `program`
+    pub fn filter_cmd(mut self, program: impl Into<String>) -> Self {
+        self.options.filter_cmd = Some(program.into());
+        self
+    }
+
+    /// Only keep the entries compiled for the MSBuild configuration `name`
+    pub fn configuration(mut self, name: impl Into<String>) -> Self {
+        self.options.configuration = Some(name.into());
+        self
+    }
+
+    /// Keep the configuration of each entry
+    pub fn keep_configurations(mut self, enabled: bool) -> Self {
+        self.options.keep_configurations = enabled;
+        self
+    }
+
+    /// Only keep the entries of `executable`, or of the other compilers kept
+    pub fn only_compiler(mut self, executable: impl Into<String>) -> Self {
+        self.options.only_compilers.push(executable.into());
+        self
+    }
+
+    /// Drop the entries of `executable`
+    pub fn exclude_compiler(mut self, executable: impl Into<String>) -> Self {
+        self.options.exclude_compilers.push(executable.into());
+        self
+    }
+
+    /// What the `directory` of each entry is set to
+    pub fn directory_mode(mut self, mode: DirectoryMode) -> Self {
+        self.options.directory_mode = mode;
+        self
+    }
+
+    /// Keep only the command line arguments `keep` accepts
+    pub fn flag_filter(self, keep: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.transformer("flag-filter", FlagFilter(keep))
+    }
+
+    /// Only keep source files matching `glob`, or one of the other included globs
+    pub fn include(mut self, glob: impl Into<String>) -> Self {
+        self.options.include.push(glob.into());
+        self
+    }
+
+    /// Drop source files matching `glob`
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.options.exclude.push(glob.into());
+        self
+    }
+
+    /// What to do with several entries for the same source file
+    pub fn dedupe(mut self, dedupe: Dedupe) -> Self {
+        self.options.dedupe = dedupe;
+        self
+    }
+
+    /// Form of the command in each entry
+    pub fn output_style(mut self, style: OutputStyle) -> Self {
+        self.options.output_style = style;
+        self
+    }
+
+    /// Number of logs scanned at once (0: one per CPU)
+    pub fn max_threads(mut self, threads: usize) -> Self {
+        self.options.max_threads = threads;
+        self
+    }
+
+    /// Stop the conversion when `cancel` is cancelled
+    pub fn cancel(mut self, cancel: CancellationToken) -> Self {
+        self.options.cancel = cancel;
+        self
+    }
+
+    /// The options
+    pub fn build(self) -> GenerateOptions {
+        self.options
+    }
+
+    /// Run [`generate`] with the options
+    pub fn generate(self) -> Result<Vec<CompileCommand>> {
+        generate(&self.options)
+    }
+}
+
+/// Convert MSBuild logs into compilation database entries.
+///
+/// This is the conversion the `ms2cc` binary runs, without the progress bars,
+/// the merge with an existing database and the output files.
+pub fn generate(options: &GenerateOptions) -> Result<Vec<CompileCommand>> {
+    generate_with_progress(options, &NoProgress)
+}
+
+/// [`generate`], reporting the progress of reading the logs to `progress`
+pub fn generate_with_progress(
+    options: &GenerateOptions,
+    progress: &dyn ProgressSink,
+) -> Result<Vec<CompileCommand>> {
+    let collected = collect(options, progress)?;
+    let (mut entries, _) = finish(options, collected.entries)?;
+    for entry in &mut entries {
+        entry.apply_output_style(options.output_style);
+    }
+    Ok(entries)
+}
+
+/// Entries found by [`collect`], and the scan of the logs they came from
+#[derive(Debug)]
+pub struct Collected {
+    /// Entries ready to be merged with an existing database
+    pub entries: Vec<CompileCommand>,
+    /// The scan of the logs, without its commands
+    pub scan: ScanResult,
+    /// Time taken by each stage, in order
+    pub stages: Vec<(&'static str, Duration)>,
+}
+
+/// First half of [`generate`]: read the inputs and shape the entries, up to
+/// the point where they can be merged with an existing database
+pub fn collect(options: &GenerateOptions, progress: &dyn ProgressSink) -> Result<Collected> {
+    // Fail on a bad glob before reading the logs
+    PathFilter::new(&options.include, &options.exclude)?;
+    let mut transformers = TransformerRegistry::default().chain(&options.transformers)?;
+    for transformer in &options.custom_transformers {
+        transformers.push(transformer.name(), Box::new(transformer.clone()));
+    }
+    if !options.parse.drive_map.is_empty() {
+        transformers.push("drive-map", Box::new(options.parse.drive_map.clone()));
+    }
+    if options.map_generated_sources {
+        transformers.push("map-generated-sources", Box::new(MapGeneratedSources));
+    }
+    if !options.remap.is_empty() {
+        transformers.push("remap", Box::new(options.remap.clone()));
+    }
+    if !options.path_map.is_empty() {
+        transformers.push("path-map", Box::new(options.path_map.clone()));
+    }
+    let mut parse = options.parse.clone();
+    parse.track_configurations |= options.keep_configurations || options.configuration.is_some();
+    let patterns = LogPatterns::for_options(&parse)?;
+    let mut stages = Vec::new();
+
+    let started = Instant::now();
+    let mut scan = match options.input_format {
+        InputFormat::Msbuild => scan_logs(
+            &options.input_files,
+            options.input_encoding.as_deref(),
+            &patterns,
+            &parse,
+            progress,
+            &options.cancel,
+            options.max_threads,
+        )?,
+        InputFormat::Ninja => ninja::scan_build_dirs(&options.input_files, &parse)?,
+    };
+    let mut entries = take(&mut scan.commands);
+    stages.push(("scan", started.elapsed()));
+
+    if let Some(name) = &options.configuration {
+        let before = entries.len();
+        entries.retain(|entry| {
+            entry
+                .configuration
+                .as_deref()
+                .is_some_and(|configuration| configuration.eq_ignore_ascii_case(name))
+        });
+        info!(
+            "Kept {} of {} entries built for configuration {}",
+            entries.len(),
+            before,
+            name
+        );
+    }
+    if !options.keep_configurations {
+        // The merge must match them with entries that never had one
+        for entry in &mut entries {
+            entry.configuration = None;
+        }
+    }
+
+    // Add entries from Ninja build directories of hybrid repositories
+    let started = Instant::now();
+    for build_dir in &options.ninja_dirs {
+        options.cancel.check()?;
+        entries.extend(ninja::compile_commands(build_dir, &parse)?);
+    }
+    options.cancel.check()?;
+
+    if !options.only_compilers.is_empty() || !options.exclude_compilers.is_empty() {
+        let before = entries.len();
+        entries.retain(|entry| {
+            (options.only_compilers.is_empty() || entry.uses_compiler(&options.only_compilers))
+                && !entry.uses_compiler(&options.exclude_compilers)
+        });
+        info!(
+            "Kept {} of {} entries after filtering by compiler",
+            entries.len(),
+            before
+        );
+    }
+
+    if options.emit_header_entries {
+        let headers = headers::synthesize(&entries);
+        entries.extend(headers);
+    }
+    stages.push(("extra entries", started.elapsed()));
+
+    let started = Instant::now();
+    if !transformers.is_empty() {
+        transformers.apply(&mut entries)?;
+    }
+    // Hand the entries to the filter program last, so it sees final values
+    if let Some(program) = &options.filter_cmd {
+        entries = filter_cmd::run(program, entries)?;
+    }
+    // Before the merge, so its keys match entries written the same way
+    compile_commands::apply_directory_mode(&mut entries, &options.directory_mode);
+    stages.push(("transform", started.elapsed()));
+
+    Ok(Collected {
+        entries,
+        scan,
+        stages,
+    })
+}
+
+/// Second half of [`generate`], after any merge: collapse the entries for
+/// the same file and keep the included paths. Returns the entries and the
+/// number of duplicates removed.
+pub fn finish(
+    options: &GenerateOptions,
+    entries: Vec<CompileCommand>,
+) -> Result<(Vec<CompileCommand>, usize)> {
+    let before = entries.len();
+    let mut entries = compile_commands::dedupe(entries, options.dedupe)?;
+    let duplicates = before - entries.len();
+
+    let path_filter = PathFilter::new(&options.include, &options.exclude)?;
+    if !path_filter.is_empty() {
+        let before = entries.len();
+        entries.retain(|entry| path_filter.is_match(&entry.absolute_file()));
+        info!("Excluded {} entries by path filter", before - entries.len());
+    }
+    Ok((entries, duplicates))
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn is_source_file(token: &str) -> bool {
+        ParseOptions::default().is_source_file(token)
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for regex patterns
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_strip_line_prefix() {
+        let strip = |line: &str, custom: Option<&str>| {
+            strip_line_prefix(line.to_string(), &line_prefix_pattern(custom).unwrap())
+        };
+
+        assert_eq!(
+            strip(r"17:49:27.305     5>  C:\VC\bin\CL.exe /c a.cpp", None),
+            r"    5>  C:\VC\bin\CL.exe /c a.cpp"
+        );
+        assert_eq!(
+            strip("Time Elapsed 00:00:05.12", None),
+            "Time Elapsed 00:00:05.12"
+        );
+        assert_eq!(
+            strip("##[info] 5>Project", Some(r"^##\[\w+\] ")),
+            "5>Project"
+        );
+        // Only a match at the start of the line is a prefix
+        assert_eq!(
+            strip("5>Project ##[info] ", Some(r"##\[\w+\] ")),
+            "5>Project ##[info] "
+        );
+    }
+
+    #[test]
+    fn test_strip_compiler_wrapper() {
+        let pattern = compiler_wrapper_pattern().unwrap();
+        let strip = |line: &str| strip_compiler_wrapper(line.to_string(), &pattern);
+
+        assert_eq!(
+            strip(
+                r#"  Tracker.exe /d "C:\Program Files\MSBuild\FileTracker32.dll" /i C:\obj\p.tlog /c "C:\Program Files\VC\bin\CL.exe" /c a.cpp"#
+            ),
+            r#"  "C:\Program Files\VC\bin\CL.exe" /c a.cpp"#
+        );
+        assert_eq!(
+            strip(r#"5>  "C:\MSBuild\Tracker.exe" /a /c C:\VC\bin\CL.exe /c b.cpp"#),
+            r#"5>  C:\VC\bin\CL.exe /c b.cpp"#
+        );
+        assert_eq!(
+            strip(r#"  C:\Tools\sccache.exe C:\VC\bin\cl.exe /c c.cpp"#),
+            r#"  C:\VC\bin\cl.exe /c c.cpp"#
+        );
+        let direct = r#"  C:\VC\bin\CL.exe /c /Fotracker.obj tracker.cpp"#;
+        assert_eq!(strip(direct), direct);
+    }
+
+    #[test]
+    fn test_node_prefix_pattern() {
+        let re = node_prefix_pattern().unwrap();
+
+        assert!(re.is_match("4>Project ..."));
+        assert!(re.is_match("  7>Something"));
+        assert!(re.is_match("123>Build"));
+        assert!(!re.is_match("Project without prefix"));
+
+        // Extract node number
+        let caps = re.captures("  4>Project").unwrap();
+        assert_eq!(&caps[1], "4");
+
+        // Test multi-instance prefix notation (e.g., "53:20>")
+        assert!(re.is_match("53:20>Project ..."));
+        assert!(re.is_match("  7:2>Something"));
+        let caps = re.captures("53:20>ClCompile").unwrap();
+        assert_eq!(&caps[1], "53"); // Should capture base number only
+    }
+
+    #[test]
+    fn test_project_property_pattern() {
+        let re = project_property_pattern().unwrap();
+
+        let caps = re.captures("  Configuration=Release").unwrap();
+        assert_eq!(&caps[1], "Configuration");
+        assert_eq!(&caps[2], "Release");
+
+        // Initial Properties format with spaces around '='
+        let caps = re.captures("PlatformToolset = v143").unwrap();
+        assert_eq!(&caps[1], "PlatformToolset");
+        assert_eq!(&caps[2], "v143");
+
+        // Parallel build prefix
+        let caps = re
+            .captures("    5>  WindowsTargetPlatformVersion=10.0.22621.0")
+            .unwrap();
+        assert_eq!(&caps[1], "WindowsTargetPlatformVersion");
+        assert_eq!(&caps[2], "10.0.22621.0");
+
+        assert!(!re.is_match("PlatformName=x64"));
+        assert!(!re.is_match(r#"Property reassignment: $(Platform)="x64""#));
+        assert!(!re.is_match("Configuration="));
+    }
+
+    #[test]
+    fn test_cl_exe_regex() {
+        let re = compile_command_pattern(&ParseOptions::default().compilers).unwrap();
+
+        assert!(re.is_match(r#"  CL.exe /c /I"include" main.cpp"#));
+        assert!(re.is_match(r#"    C:\Program Files\MSVC\bin\CL.exe /nologo"#));
+        assert!(!re.is_match(r#"CL.exe"#)); // No space after CL.exe
+        assert!(!re.is_match(r#"Link.exe /OUT:test.exe"#));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for argument tokenization and command parsing
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_tokenize_simple() {
+        let tokens = tokenize_command_line(r#"cl.exe /c main.cpp"#);
+        assert_eq!(tokens, vec!["cl.exe", "/c", "main.cpp"]);
+    }
+
+    #[test]
+    fn test_tokenize_quoted() {
+        let tokens = tokenize_command_line(r#"cl.exe /I"C:\Program Files\include" main.cpp"#);
+        assert_eq!(
+            tokens,
+            vec!["cl.exe", r#"/I"C:\Program Files\include""#, "main.cpp"]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_multiple_spaces() {
+        let tokens = tokenize_command_line(r#"cl.exe   /c    main.cpp"#);
+        assert_eq!(tokens, vec!["cl.exe", "/c", "main.cpp"]);
+    }
+
+    #[test]
+    fn test_is_source_file() {
+        assert!(is_source_file("main.cpp"));
+        assert!(is_source_file("test.c"));
+        assert!(is_source_file("code.cc"));
+        assert!(is_source_file("file.cxx"));
+        assert!(is_source_file("FILE.CPP")); // Case insensitive
+        assert!(!is_source_file("header.h"));
+        assert!(!is_source_file("lib.obj"));
+    }
+
+    #[test]
+    fn test_should_filter_flag() {
+        // Should filter PCH flags
+        assert!(should_filter_flag("/Yc"));
+        assert!(should_filter_flag("/YcStdAfx.h"));
+        assert!(should_filter_flag("/Yu"));
+        assert!(should_filter_flag("/YuPrecompiled.h"));
+        assert!(should_filter_flag("/Fp"));
+        assert!(should_filter_flag("/FpDebug/test.pch"));
+        assert!(should_filter_flag("/Fp\"C:\\path\\file.pch\""));
+
+        // Should NOT filter floating-point model flags (they have colons!)
+        assert!(!should_filter_flag("/fp:precise"));
+        assert!(!should_filter_flag("/fp:fast"));
+        assert!(!should_filter_flag("/fp:strict"));
+        assert!(!should_filter_flag("/Fp:precise")); // Alternative valid form
+        assert!(!should_filter_flag("/fp:contract"));
+        assert!(!should_filter_flag("/fp:except"));
+        assert!(!should_filter_flag("/fp:except-"));
+
+        // Should NOT filter force includes
+        assert!(!should_filter_flag("/FI"));
+        assert!(!should_filter_flag("/FIheader.h"));
+
+        // Case insensitive for PCH
+        assert!(should_filter_flag("/yc"));
+        assert!(should_filter_flag("/YC"));
+        assert!(should_filter_flag("/fp\"test.pch\"")); // PCH (no colon!)
+
+        // Should not filter other flags
+        assert!(!should_filter_flag("/c"));
+        assert!(!should_filter_flag("/Ox"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for normalize_path()
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_normalize_path_with_double_backslash() {
+        let path = PathBuf::from(r"C:\foo\bar\\baz\file.cpp");
+        let normalized = normalize_path(&path);
+        assert_eq!(normalized, PathBuf::from(r"C:\foo\bar\baz\file.cpp"));
+    }
+
+    #[test]
+    fn test_normalize_path_resolves_dot_segments() {
+        let path = PathBuf::from("/src/app/../Common/./shared.cpp");
+        assert_eq!(
+            normalize_path(&path),
+            PathBuf::from("/src/Common/shared.cpp")
+        );
+        assert_eq!(
+            normalize_path(Path::new("/../a.cpp")),
+            PathBuf::from("/a.cpp")
+        );
+        assert_eq!(
+            normalize_path(Path::new("../lib/./a/../b.cpp")),
+            PathBuf::from("../lib/b.cpp")
+        );
+        assert_eq!(normalize_path(Path::new("a/..")), PathBuf::from("."));
+    }
+
+    #[test]
+    fn test_normalize_path_normal() {
+        let path = PathBuf::from(r"C:\foo\bar\baz\file.cpp");
+        let normalized = normalize_path(&path);
+        assert_eq!(normalized, path);
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for path_to_normalized_string()
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_path_to_normalized_string() {
+        let path = PathBuf::from(r"S:\Acme\Project\src\project\obj\amd64\\bond\core\file.cpp");
+        let normalized = path_to_normalized_string(&path);
+        // Should not contain double backslashes
+        assert!(!normalized.contains(r"\\"));
+        // Should contain the components
+        assert!(normalized.contains("bond"));
+        assert!(normalized.contains("core"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for parse_cl_command()
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_parse_cl_command_single_file() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
+            project_dir: PathBuf::from(r"C:\project"),
+        };
+
+        // Test with UNQUOTED path (like real MSBuild logs)
+        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c /I"include" main.cpp"#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            200,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].directory, r"C:\project");
+        // File should now be absolute
+        assert_eq!(commands[0].file, r"C:\project\main.cpp");
+        assert!(commands[0]
+            .command
+            .contains(r#""C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe""#));
+        assert!(commands[0].command.contains(r#"/I"include""#));
+        assert!(commands[0].command.contains(r#"C:\project\main.cpp"#));
+    }
+
+    #[test]
+    fn test_parse_cl_command_multiple_files() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
+            project_dir: PathBuf::from(r"C:\project"),
+        };
+
+        // Test with UNQUOTED path (like real MSBuild logs)
+        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c /Ox main.cpp util.cpp helper.c"#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            200,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 3);
+        // Files should now be absolute
+        assert_eq!(commands[0].file, r"C:\project\main.cpp");
+        assert_eq!(commands[1].file, r"C:\project\util.cpp");
+        assert_eq!(commands[2].file, r"C:\project\helper.c");
+
+        // All should have same directory and flags
+        for cmd in &commands {
+            assert_eq!(cmd.directory, r"C:\project");
+            assert!(cmd.command.contains("/Ox"));
+            assert!(cmd
+                .command
+                .contains(r#""C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe""#));
+        }
+    }
+
+    #[test]
+    fn test_parse_cl_command_filters_pch() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
+            project_dir: PathBuf::from(r"C:\project"),
+        };
+
+        // Test with UNQUOTED path (like real MSBuild logs)
+        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c /YuStdafx.h /FpDebug/test.pch /FIcommon.h main.cpp"#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            200,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 1);
+
+        // Should filter /Yu and /Fp but keep /FI
+        assert!(!commands[0].command.contains("/Yu"));
+        assert!(!commands[0].command.contains("/Fp"));
+        assert!(commands[0].command.contains("/FIcommon.h"));
+    }
+
+    #[test]
+    fn test_parse_cl_command_preserves_fp_model() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
+            project_dir: PathBuf::from(r"C:\project"),
+        };
+
+        // Test that /fp:precise (floating-point model) is preserved while /Fp (PCH) is filtered
+        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c /fp:precise /YuStdafx.h /Fp"Debug\test.pch" /Od main.cpp"#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            200,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 1);
+
+        // Should keep /fp:precise (floating-point model)
+        assert!(
+            commands[0].command.contains("/fp:precise"),
+            "Command should contain /fp:precise but got: {}",
+            commands[0].command
+        );
+
+        // Should filter /Yu and /Fp"..." (PCH flags)
+        assert!(
+            !commands[0].command.contains("/Yu"),
+            "Command should not contain /Yu"
+        );
+        assert!(
+            !commands[0].command.contains("/Fp\""),
+            "Command should not contain /Fp with quotes"
+        );
+
+        // Should keep other flags
+        assert!(commands[0].command.contains("/Od"));
+    }
+
+    #[test]
+    fn test_parse_cl_command_quoted_file() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
+            project_dir: PathBuf::from(r"C:\project"),
+        };
+
+        // Test with UNQUOTED path (like real MSBuild logs)
+        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c "path with spaces\main.cpp""#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            200,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 1);
+        // File field should be absolute with no quotes
+        assert_eq!(commands[0].file, r"C:\project\path with spaces\main.cpp");
+        // Command should have absolute path with quotes
+        assert!(
+            commands[0]
+                .command
+                .contains(r#"C:\project\path with spaces\main.cpp"#)
+        );
+    }
+
+    #[test]
+    fn test_parse_cl_command_full_path_with_spaces() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
+            project_dir: PathBuf::from(r"C:\project"),
+        };
+
+        // Test with QUOTED CL.exe path (ensure backward compatibility)
+        let line = r#"  "C:\Program Files\MSVC\bin\HostX64\x64\CL.exe" /c main.cpp"#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            200,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 1);
+        // Should preserve full path with quotes due to spaces
+        assert!(
+            commands[0]
+                .command
+                .contains(r#""C:\Program Files\MSVC\bin\HostX64\x64\CL.exe""#)
+        );
+        assert!(commands[0].command.contains(r"C:\project\main.cpp"));
+    }
+
+    #[test]
+    fn test_parse_cl_command_unquoted_path_with_spaces() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
+            project_dir: PathBuf::from(r"C:\project"),
+        };
+
+        // Test with UNQUOTED CL.exe path with spaces (real MSBuild logs)
+        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c main.cpp"#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            200,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 1);
+        // Should quote the path with spaces
+        assert!(commands[0]
+            .command
+            .contains(r#""C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe""#));
+        assert!(commands[0].command.contains(r"C:\project\main.cpp"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for object file mapping
+    // ----------------------------------------------------------------------------
+
+    fn object_paths(fo: Option<&str>, sources: &[&str]) -> Vec<Option<PathBuf>> {
+        let sources: Vec<PathBuf> = sources.iter().map(PathBuf::from).collect();
+        object_file_paths(fo, &sources, Path::new("proj"), &mut Diagnostics::default())
+            .into_iter()
+            .map(|o| o.map(PathBuf::from))
+            .collect()
+    }
+
+    #[test]
+    fn test_object_file_flag() {
+        let args = vec!["/c".to_string(), r#"/Fo"obj\x64\\""#.to_string()];
+        assert_eq!(object_file_flag(&args), Some(r"obj\x64\\".to_string()));
+        assert_eq!(
+            object_file_flag(&["/Fo:out.obj".to_string()]),
+            Some("out.obj".to_string())
+        );
+        assert_eq!(object_file_flag(&["/c".to_string()]), None);
+    }
+
+    #[test]
+    fn test_object_file_paths_directory_fo() {
+        let objects = object_paths(Some("obj/"), &["src/a.cpp", "src/b.c"]);
+        assert_eq!(
+            objects,
+            vec![
+                Some(PathBuf::from("proj/obj/a.obj")),
+                Some(PathBuf::from("proj/obj/b.obj"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_object_file_paths_default_and_file_fo() {
+        assert_eq!(
+            object_paths(None, &["src/a.cpp"]),
+            vec![Some(PathBuf::from("proj/a.obj"))]
+        );
+        assert_eq!(
+            object_paths(Some("out/custom"), &["src/a.cpp"]),
+            vec![Some(PathBuf::from("proj/out/custom.obj"))]
+        );
+        // A file-style /Fo cannot apply to several sources
+        assert_eq!(
+            object_paths(Some("out/custom.obj"), &["a.cpp", "b.cpp"]),
+            vec![None, None]
+        );
+    }
+
+    #[test]
+    fn test_object_file_paths_collisions_are_dropped() {
+        let objects = object_paths(Some("obj/"), &["a/util.cpp", "b/util.cpp", "main.cpp"]);
+        assert_eq!(
+            objects,
+            vec![None, None, Some(PathBuf::from("proj/obj/main.obj"))]
+        );
+    }
+
+    #[test]
+    fn test_parse_cl_command_emit_output() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from("proj/test.vcxproj"),
+            project_dir: PathBuf::from("proj"),
+        };
+        let options = ParseOptions {
+            emit_output: true,
+            ..Default::default()
+        };
+
+        let line = r#"C:\VC\bin\CL.exe /c /Foobj/ main.cpp util.cpp"#;
+        let commands =
+            parse_cl_command(line, &project_ctx, &options, &mut Diagnostics::default(), 1).unwrap();
+        assert_eq!(
+            commands[0].output.as_deref().map(Path::new),
+            Some(Path::new("proj/obj/main.obj"))
+        );
+        assert_eq!(
+            commands[1].output.as_deref().map(Path::new),
+            Some(Path::new("proj/obj/util.obj"))
+        );
+
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            1,
+        )
+        .unwrap();
+        assert!(commands[0].output.is_none());
+        let json = serde_json::to_string(&commands[0]).unwrap();
+        assert!(!json.contains("output"));
+    }
+
+    #[test]
+    fn test_parse_cl_command_explicit_object_file() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from("proj/test.vcxproj"),
+            project_dir: PathBuf::from("proj"),
+        };
+        let parse = |line: &str| {
+            parse_cl_command(
+                line,
+                &project_ctx,
+                &ParseOptions::default(),
+                &mut Diagnostics::default(),
+                1,
+            )
+            .unwrap()
+        };
+
+        // Without --emit-output only an explicit object file is recorded
+        let commands = parse(r#"C:\VC\bin\CL.exe /c /Fo"obj/main.o" main.cpp"#);
+        assert_eq!(
+            commands[0].output.as_deref().map(Path::new),
+            Some(Path::new("proj/obj/main.o"))
+        );
+        let json = serde_json::to_string(&commands[0]).unwrap();
+        assert!(json.contains(r#""output":"#));
+
+        let commands = parse(r#"C:\VC\bin\CL.exe /c /Fo"obj/main.o" main.cpp util.cpp"#);
+        assert!(commands.iter().all(|c| c.output.is_none()));
+    }
+
+    #[test]
+    fn test_parse_cl_command_module_interface() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from("proj/test.vcxproj"),
+            project_dir: PathBuf::from("proj"),
+        };
+
+        let line = r#"C:\VC\bin\CL.exe /c /std:c++20 /interface /reference Base=obj\Base.ifc /headerUnit vec.h=vec.h.ifc /ifcOutput obj\ core.ixx"#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].file.ends_with("core.ixx"));
+        assert!(commands[0].command.contains(
+            r"/interface /reference Base=obj\Base.ifc /headerUnit vec.h=vec.h.ifc /ifcOutput obj\ "
+        ));
+    }
+
+    #[test]
+    fn test_parse_cl_command_keep_pch_flags() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from("proj/test.vcxproj"),
+            project_dir: PathBuf::from("proj"),
+        };
+        let line = r#"cl.exe /c /Yupch.h /Fpx64\app.pch /FIpch.h main.cpp"#;
+        let parse = |options: &ParseOptions| {
+            parse_cl_command(line, &project_ctx, options, &mut Diagnostics::default(), 1)
+                .unwrap()
+                .remove(0)
+                .command
+        };
+
+        let command = parse(&ParseOptions::default());
+        assert!(command.starts_with("cl.exe /c /FIpch.h "));
+
+        let options = ParseOptions {
+            keep_pch_flags: true,
+            ..Default::default()
+        };
+        let command = parse(&options);
+        assert!(command.starts_with(r"cl.exe /c /Yupch.h /Fpx64\app.pch /FIpch.h "));
+    }
+
+    #[test]
+    fn test_parse_cl_command_explicit_source_type() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from("proj/test.vcxproj"),
+            project_dir: PathBuf::from("proj"),
+        };
+
+        let line = r#"C:\VC\bin\CL.exe /c /W4 /Tpgen.inl /Tc legacy.src main.cpp"#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 3);
+        assert!(commands[0].file.ends_with("gen.inl"));
+        assert!(commands[0].command.contains(r#"/c /W4 /TP ""#));
+        assert!(commands[1].file.ends_with("legacy.src"));
+        assert!(commands[1].command.contains("/W4 /TC "));
+        assert!(!commands[2].command.contains("/T"));
+    }
+
+    #[test]
+    fn test_parse_cl_command_joins_external_include() {
+        let project_ctx = ProjectContext {
+            project_path: PathBuf::from("proj/test.vcxproj"),
+            project_dir: PathBuf::from("proj"),
+        };
+
+        let line = r#"C:\VC\bin\CL.exe /c /external:I "C:\SDK\include" /external:env:EXTERNAL_INCLUDE /external:W0 main.cpp"#;
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut Diagnostics::default(),
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].command.contains(
+            r#"/c /external:I"C:\SDK\include" /external:env:EXTERNAL_INCLUDE /external:W0 "#
+        ));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for clean_include_path()
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_clean_include_path_quoted_with_trailing_backslash() {
+        assert_eq!(
+            clean_include_path(r#"/I"C:\path\to\dir\\""#),
+            r#"/I"C:\path\to\dir""#
+        );
+    }
+
+    #[test]
+    fn test_clean_include_path_quoted_no_trailing() {
+        assert_eq!(
+            clean_include_path(r#"/I"C:\path\to\dir""#),
+            r#"/I"C:\path\to\dir""#
+        );
+    }
+
+    #[test]
+    fn test_clean_include_path_unquoted_with_trailing() {
+        assert_eq!(
+            clean_include_path(r#"/IC:\path\to\dir\"#),
+            r#"/IC:\path\to\dir"#
+        );
+    }
+
+    #[test]
+    fn test_clean_include_path_multiple_trailing() {
+        assert_eq!(
+            clean_include_path(r#"/I"C:\path\to\dir\\\\\""#),
+            r#"/I"C:\path\to\dir""#
+        );
+    }
+
+    #[test]
+    fn test_clean_include_path_not_include_flag() {
+        assert_eq!(clean_include_path(r#"/Od"#), r#"/Od"#);
+    }
+
+    #[test]
+    fn test_clean_include_path_root_drive() {
+        // Root path like C:\ should be preserved
+        assert_eq!(clean_include_path(r#"/I"C:\""#), r#"/I"C:\""#);
+    }
+
+    #[test]
+    fn test_clean_include_path_lowercase_flag() {
+        // Should work with lowercase /i as well
+        assert_eq!(
+            clean_include_path(r#"/i"C:\path\to\dir\\""#),
+            r#"/i"C:\path\to\dir""#
+        );
+    }
+
+    #[test]
+    fn test_clean_include_path_real_world_example() {
+        // Real example from the XStore build
+        assert_eq!(
+            clean_include_path(
+                r#"/I"S:\Azure\Storage-XStore\src\XAggregator\XsdMacroGen\obj\amd64\\""#
+            ),
+            r#"/I"S:\Azure\Storage-XStore\src\XAggregator\XsdMacroGen\obj\amd64""#
+        );
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for resolve_source_file_path()
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_resolve_source_file_path_relative() {
+        let working_dir = PathBuf::from(r"C:\project");
+        let source = "src\\main.cpp";
+        let resolved = resolve_source_file_path(source, &working_dir);
+        assert_eq!(resolved, PathBuf::from(r"C:\project\src\main.cpp"));
+    }
+
+    #[test]
+    fn test_resolve_source_file_path_parent_directory() {
+        let working_dir = PathBuf::from(r"C:\project\SubDir");
+        let source = r"..\Common\shared.cpp";
+        let resolved = resolve_source_file_path(source, &working_dir);
+        assert_eq!(
+            resolved,
+            PathBuf::from(r"C:\project\SubDir\..\Common\shared.cpp")
+        );
+    }
+
+    #[test]
+    fn test_resolve_source_file_path_already_absolute() {
+        let working_dir = PathBuf::from(r"C:\project");
+        let source = r"D:\external\library\file.cpp";
+        let resolved = resolve_source_file_path(source, &working_dir);
+        assert_eq!(resolved, PathBuf::from(r"D:\external\library\file.cpp"));
+    }
+
+    #[test]
+    fn test_resolve_source_file_path_quoted() {
+        let working_dir = PathBuf::from(r"C:\project");
+        let source = r#""src\main.cpp""#;
+        let resolved = resolve_source_file_path(source, &working_dir);
+        assert_eq!(resolved, PathBuf::from(r"C:\project\src\main.cpp"));
+    }
+
+    #[test]
+    fn test_resolve_source_file_path_current_directory() {
+        let working_dir = PathBuf::from(r"C:\project");
+        let source = r".\main.cpp";
+        let resolved = resolve_source_file_path(source, &working_dir);
+        assert_eq!(resolved, PathBuf::from(r"C:\project\.\main.cpp"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for normalize_path()
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_normalize_path_triple_backslash() {
+        let path = PathBuf::from(r"C:\foo\bar\\\baz\file.cpp");
+        let normalized = normalize_path(&path);
+        // Should eliminate all redundant backslashes
+        assert_eq!(normalized, PathBuf::from(r"C:\foo\bar\baz\file.cpp"));
+    }
+
+    #[test]
+    fn test_normalize_path_mixed_separators() {
+        // On Windows, PathBuf handles / and \ differently depending on the input
+        let path = PathBuf::from(r"C:\foo/bar\baz/file.cpp");
+        let normalized = normalize_path(&path);
+        // The path has 5 meaningful components, but the mixed separator might create more
+        // Just verify normalization happened
+        let normalized_str = normalized.display().to_string();
+        assert!(normalized_str.contains("foo"));
+        assert!(normalized_str.contains("bar"));
+        assert!(normalized_str.contains("baz"));
+        assert!(normalized_str.contains("file.cpp"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for tokenize_command_line()
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_tokenize_empty_string() {
+        let tokens = tokenize_command_line("");
+        assert_eq!(tokens.len(), 0);
+    }
+
+    #[test]
+    fn test_tokenize_only_whitespace() {
+        let tokens = tokenize_command_line("   \t  ");
+        assert_eq!(tokens.len(), 0);
+    }
+
+    #[test]
+    fn test_tokenize_unclosed_quote() {
+        // Unclosed quote - should still tokenize (quote becomes part of token)
+        let tokens = tokenize_command_line(r#"cl.exe /I"C:\Program Files"#);
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0], "cl.exe");
+        assert_eq!(tokens[1], r#"/I"C:\Program Files"#);
+    }
+
+    #[test]
+    fn test_tokenize_adjacent_quotes() {
+        let tokens = tokenize_command_line(r#"cl.exe ""file.cpp"""#);
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0], "cl.exe");
+        assert_eq!(tokens[1], r#"""file.cpp"""#);
+    }
+
+    #[test]
+    fn test_tokenize_tabs() {
+        let tokens = tokenize_command_line("cl.exe\t/c\tmain.cpp");
+        assert_eq!(tokens, vec!["cl.exe", "/c", "main.cpp"]);
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for is_source_file()
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_is_source_file_uppercase_extensions() {
+        assert!(is_source_file("MAIN.CPP"));
+        assert!(is_source_file("FILE.C"));
+        assert!(is_source_file("CODE.CXX"));
+        assert!(is_source_file("TEST.CC"));
+    }
+
+    #[test]
+    fn test_is_source_file_mixed_case_extensions() {
+        assert!(is_source_file("main.CpP"));
+        assert!(is_source_file("file.Cpp"));
+    }
+
+    #[test]
+    fn test_is_source_file_quoted_paths() {
+        assert!(is_source_file(r#""path\to\file.cpp""#));
+        assert!(is_source_file(r#""test.c""#));
+    }
+
+    #[test]
+    fn test_is_source_file_with_path() {
+        assert!(is_source_file(r"C:\project\src\main.cpp"));
+        assert!(is_source_file(r"relative\path\file.c"));
+    }
+
+    #[test]
+    fn test_is_source_file_not_source() {
+        assert!(!is_source_file("header.h"));
+        assert!(!is_source_file("library.lib"));
+        assert!(!is_source_file("object.obj"));
+        assert!(!is_source_file("executable.exe"));
+        assert!(!is_source_file("archive.a"));
+        assert!(!is_source_file("README.md"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for handler functions
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_handle_node_prefix_valid() {
+        let mut state = ProcessingState::new();
+        let pattern = node_prefix_pattern().unwrap();
+
+        handle_node_prefix("  7>Project ...", &pattern, &mut state);
+
+        assert_eq!(state.current_prefix, Some(7));
+    }
+
+    #[test]
+    fn test_handle_node_prefix_no_match() {
+        let mut state = ProcessingState::new();
+        let pattern = node_prefix_pattern().unwrap();
+
+        handle_node_prefix("Project without prefix", &pattern, &mut state);
+
+        assert_eq!(state.current_prefix, None);
+    }
+
+    #[test]
+    fn test_handle_project_on_node_parallel_build() {
+        let mut state = ProcessingState::new();
+        let line = r#"4>Project "C:\path\to\project.vcxproj" on node 3 (Build target(s))."#;
+
+        handle_project_line(line, &mut state, 100);
+
+        assert_eq!(state.prefix_to_project.len(), 1);
+        assert!(state.prefix_to_project.contains_key(&4));
+        assert!(state.current_project.is_some());
+        assert_eq!(
+            state.prefix_to_project.get(&4).unwrap().project_path,
+            PathBuf::from(r"C:\path\to\project.vcxproj")
+        );
+    }
+
+    #[test]
+    fn test_handle_nested_project_pattern() {
+        let mut state = ProcessingState::new();
+        let line = r#"    44>Project "S:\Acme\corp\src\foo\baz.proj" (44) is building "S:\Acme\corp\src\foo\bar.vcxproj" (54) on node 13 (default targets)."#;
+
+        handle_project_line(line, &mut state, 100);
+
+        assert_eq!(state.prefix_to_project.len(), 1);
+        assert!(state.prefix_to_project.contains_key(&54));
+        assert_eq!(
+            state.prefix_to_project.get(&54).unwrap().project_path,
+            PathBuf::from(r"S:\Acme\corp\src\foo\bar.vcxproj")
+        );
+    }
+
+    #[test]
+    fn test_handle_from_project_sequential_build() {
+        let mut state = ProcessingState::new();
+        let line = r#"Target "ClCompile" from project "C:\path\to\project.vcxproj""#;
+
+        handle_project_line(line, &mut state, 100);
+
+        assert!(state.current_project.is_some());
+        assert_eq!(
+            state.current_project.as_ref().unwrap().project_path,
+            PathBuf::from(r"C:\path\to\project.vcxproj")
+        );
+    }
+
+    #[test]
+    fn test_handle_project_property_records_active_project() {
+        let mut state = ProcessingState::new();
+        let pattern = project_property_pattern().unwrap();
+
+        // No project yet: property is ignored
+        handle_project_property("  Configuration=Debug", &pattern, &mut state, 1);
+        assert!(state.projects.is_empty());
+
+        handle_project_line(
+            r#"Target "Build" from project "C:\p\p.vcxproj""#,
+            &mut state,
+            2,
+        );
+        handle_project_property("  Configuration=Release", &pattern, &mut state, 3);
+        handle_project_property("  Platform=x64", &pattern, &mut state, 4);
+
+        let properties = &state.projects[&PathBuf::from(r"C:\p\p.vcxproj")];
+        assert_eq!(properties.configuration.as_deref(), Some("Release"));
+        assert_eq!(properties.platform.as_deref(), Some("x64"));
+        assert_eq!(properties.platform_toolset, None);
+    }
+
+    #[test]
+    fn test_handle_project_property_parallel_prefix() {
+        let mut state = ProcessingState::new();
+        let node_pattern = node_prefix_pattern().unwrap();
+        let pattern = project_property_pattern().unwrap();
+
+        for line in [
+            r#"4>Project "C:\a\a.vcxproj" on node 1 (Build target(s))."#,
+            r#"5>Project "C:\b\b.vcxproj" on node 2 (Build target(s))."#,
+            "4>  Platform=Win32",
+            "5>  Platform=x64",
+        ] {
+            handle_node_prefix(line, &node_pattern, &mut state);
+            handle_project_line(line, &mut state, 1);
+            handle_project_property(line, &pattern, &mut state, 1);
+        }
+
+        assert_eq!(
+            state.projects[&PathBuf::from(r"C:\a\a.vcxproj")]
+                .platform
+                .as_deref(),
+            Some("Win32")
+        );
+        assert_eq!(
+            state.projects[&PathBuf::from(r"C:\b\b.vcxproj")]
+                .platform
+                .as_deref(),
+            Some("x64")
+        );
+    }
+
+    #[test]
+    fn test_handle_cl_command_with_context() {
+        let mut state = ProcessingState::new();
+        state.current_project = Some(ProjectContext {
+            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
+            project_dir: PathBuf::from(r"C:\project"),
+        });
+
+        let pattern = compile_command_pattern(&ParseOptions::default().compilers).unwrap();
+        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c main.cpp"#;
+
+        let result = handle_cl_command(line, &pattern, &mut state, &ParseOptions::default(), 100);
+
+        assert!(result.is_ok());
+        let commands = result.unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].file, r"C:\project\main.cpp");
+    }
+
+    #[test]
+    fn test_handle_cl_command_no_context() {
+        let mut state = ProcessingState::new();
+        let pattern = compile_command_pattern(&ParseOptions::default().compilers).unwrap();
+        let line = r#"  CL.exe /c main.cpp"#;
+
+        let result = handle_cl_command(line, &pattern, &mut state, &ParseOptions::default(), 100);
+
+        assert!(result.is_ok());
+        let commands = result.unwrap();
+        assert_eq!(commands.len(), 0); // No commands when no context
+
+        let summaries = state.diagnostics.summaries();
+        assert_eq!(summaries[0].category, Category::NoProjectContext);
+        assert_eq!(summaries[0].examples, vec!["line 100"]);
+
+        let dropped = state.diagnostics.dropped();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].file, "main.cpp");
+        assert_eq!(dropped[0].location.line, 100);
+    }
+
+    #[test]
+    fn test_handle_cl_command_not_cl_command() {
+        let mut state = ProcessingState::new();
+        let pattern = compile_command_pattern(&ParseOptions::default().compilers).unwrap();
+        let line = r#"This is not a CL.exe command"#;
+
+        let result = handle_cl_command(line, &pattern, &mut state, &ParseOptions::default(), 100);
+
+        assert!(result.is_ok());
+        let commands = result.unwrap();
+        assert_eq!(commands.len(), 0);
+    }
+
+    #[test]
+    fn test_processing_state_get_active_project_with_prefix() {
+        let mut state = ProcessingState::new();
+        let ctx = ProjectContext {
+            project_path: PathBuf::from(r"C:\prefix\project.vcxproj"),
+            project_dir: PathBuf::from(r"C:\prefix"),
+        };
+        state.prefix_to_project.insert(7, ctx.clone());
+        state.current_prefix = Some(7);
+
+        let active = state.get_active_project();
+
+        assert!(active.is_some());
+        assert_eq!(
+            active.unwrap().project_path,
+            PathBuf::from(r"C:\prefix\project.vcxproj")
+        );
+    }
+
+    #[test]
+    fn test_processing_state_get_active_project_fallback() {
+        let mut state = ProcessingState::new();
+        state.current_project = Some(ProjectContext {
+            project_path: PathBuf::from(r"C:\fallback\project.vcxproj"),
+            project_dir: PathBuf::from(r"C:\fallback"),
+        });
+        state.current_prefix = Some(99); // Prefix not in map
+
+        let active = state.get_active_project();
+
+        assert!(active.is_some());
+        assert_eq!(
+            active.unwrap().project_path,
+            PathBuf::from(r"C:\fallback\project.vcxproj")
+        );
+    }
+
+    #[test]
+    fn test_processing_state_get_active_project_no_prefix() {
+        let mut state = ProcessingState::new();
+        state.current_project = Some(ProjectContext {
+            project_path: PathBuf::from(r"C:\sequential\project.vcxproj"),
+            project_dir: PathBuf::from(r"C:\sequential"),
+        });
+
+        let active = state.get_active_project();
+
+        assert!(active.is_some());
+        assert_eq!(
+            active.unwrap().project_path,
+            PathBuf::from(r"C:\sequential\project.vcxproj")
+        );
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for progress bar setup functions
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_setup_read_progress_bar_enabled() {
+        let multi = MultiProgress::new();
+        let pb = setup_read_progress_bar(true, Some(1000), &multi).unwrap();
+        // Should create a visible progress bar (not hidden)
+        // We can't directly test visibility, but we can verify it doesn't panic
+        pb.finish_and_clear();
+    }
+
+    #[test]
+    fn test_setup_read_progress_bar_unknown_size() {
+        let multi = MultiProgress::new();
+        // Reading from stdin: no size, so a spinner counting bytes
+        let pb = setup_read_progress_bar(true, None, &multi).unwrap();
+        assert_eq!(pb.length(), None);
+        pb.finish_and_clear();
+    }
+
+    #[test]
+    fn test_setup_read_progress_bar_disabled() {
+        let multi = MultiProgress::new();
+        let pb = setup_read_progress_bar(false, Some(1000), &multi).unwrap();
+        // Should create a hidden progress bar
+        pb.finish_and_clear();
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for merge_compile_commands
+    // ----------------------------------------------------------------------------
+
+    fn make_entry(file: &str, directory: &str, command: &str) -> CompileCommand {
+        CompileCommand {
+            file: file.to_string(),
+            directory: directory.to_string(),
+            command: command.to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_empty_existing_returns_new() {
+        let existing = vec![];
+        let new_entries = vec![
+            make_entry("a.cpp", "C:\\proj", "cl /c a.cpp"),
+            make_entry("b.cpp", "C:\\proj", "cl /c b.cpp"),
+        ];
+        let result = merge_compile_commands(existing, new_entries, MergeStrategy::Merge);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].file, "a.cpp");
+        assert_eq!(result[1].file, "b.cpp");
+    }
+
+    #[test]
+    fn test_merge_empty_new_returns_existing() {
+        let existing = vec![
+            make_entry("a.cpp", "C:\\proj", "cl /c a.cpp"),
+            make_entry("b.cpp", "C:\\proj", "cl /c b.cpp"),
+        ];
+        let new_entries = vec![];
+        let result = merge_compile_commands(existing, new_entries, MergeStrategy::Merge);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].file, "a.cpp");
+        assert_eq!(result[1].file, "b.cpp");
+    }
+
+    #[test]
+    fn test_merge_updates_matching_entries() {
+        let existing = vec![
+            make_entry("a.cpp", "C:\\proj", "cl /c /O1 a.cpp"),
+            make_entry("b.cpp", "C:\\proj", "cl /c /O1 b.cpp"),
+        ];
+        let new_entries = vec![make_entry("a.cpp", "C:\\proj", "cl /c /O2 a.cpp")];
+        let result = merge_compile_commands(existing, new_entries, MergeStrategy::Merge);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].command, "cl /c /O2 a.cpp");
+        assert_eq!(result[1].command, "cl /c /O1 b.cpp");
+    }
+
+    #[test]
+    fn test_append_keeps_matching_entries() {
+        let existing = vec![
+            make_entry("a.cpp", "C:\\proj", "cl /c /O1 a.cpp"),
+            make_entry("b.cpp", "C:\\proj", "cl /c b.cpp"),
+        ];
+        let new_entries = vec![
+            make_entry("a.cpp", "C:\\proj", "cl /c /O2 a.cpp"),
+            make_entry("B.cpp", "c:\\proj", "cl /c b.cpp"),
+        ];
+        let result = merge_compile_commands(existing, new_entries, MergeStrategy::Append);
+        let commands: Vec<&str> = result.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            ["cl /c /O1 a.cpp", "cl /c b.cpp", "cl /c /O2 a.cpp"]
+        );
+    }
+
+    #[test]
+    fn test_merge_appends_new_entries() {
+        let existing = vec![make_entry("a.cpp", "C:\\proj", "cl /c a.cpp")];
+        let new_entries = vec![make_entry("b.cpp", "C:\\proj", "cl /c b.cpp")];
+        let result = merge_compile_commands(existing, new_entries, MergeStrategy::Merge);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].file, "a.cpp");
+        assert_eq!(result[1].file, "b.cpp");
+    }
+
+    #[test]
+    fn test_merge_preserves_same_file_different_directory() {
+        let existing = vec![
+            make_entry("crc.cpp", "C:\\lib", "cl /c /DUSER crc.cpp"),
+            make_entry("crc.cpp", "C:\\klib", "cl /c /DKERNEL crc.cpp"),
+        ];
+        let new_entries = vec![make_entry("crc.cpp", "C:\\lib", "cl /c /DUSER /O2 crc.cpp")];
+        let result = merge_compile_commands(existing, new_entries, MergeStrategy::Merge);
+        assert_eq!(result.len(), 2);
+        // The userspace entry should be updated
+        assert_eq!(result[0].command, "cl /c /DUSER /O2 crc.cpp");
+        assert_eq!(result[0].directory, "C:\\lib");
+        // The kernel entry should be untouched
+        assert_eq!(result[1].command, "cl /c /DKERNEL crc.cpp");
+        assert_eq!(result[1].directory, "C:\\klib");
+    }
+
+    #[test]
+    fn test_merge_mixed_update_and_add() {
+        let existing = vec![
+            make_entry("a.cpp", "C:\\proj", "cl /c a.cpp"),
+            make_entry("b.cpp", "C:\\proj", "cl /c b.cpp"),
+            make_entry("c.cpp", "C:\\proj", "cl /c c.cpp"),
+        ];
+        let new_entries = vec![
+            make_entry("b.cpp", "C:\\proj", "cl /c /O2 b.cpp"),
+            make_entry("d.cpp", "C:\\proj", "cl /c d.cpp"),
+        ];
+        let result = merge_compile_commands(existing, new_entries, MergeStrategy::Merge);
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].file, "a.cpp");
+        assert_eq!(result[1].file, "b.cpp");
+        assert_eq!(result[1].command, "cl /c /O2 b.cpp");
+        assert_eq!(result[2].file, "c.cpp");
+        assert_eq!(result[3].file, "d.cpp");
+    }
+
+    #[test]
+    fn test_merge_update_matches_by_file() {
+        // The same source, once relative to the project and once absolute
+        let existing = vec![
+            make_entry("src/a.cpp", "/proj", "cl /c a.cpp"),
+            make_entry("b.cpp", "/proj", "cl /c b.cpp"),
+        ];
+        let new_entries = vec![make_entry("/proj/src/a.cpp", "/build", "cl /c /O2 a.cpp")];
+
+        let result =
+            merge_compile_commands(existing.clone(), new_entries.clone(), MergeStrategy::Update);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].command, "cl /c /O2 a.cpp");
+        assert_eq!(result[0].directory, "/build");
+        assert_eq!(result[1].file, "b.cpp");
+
+        let result = merge_compile_commands(existing, new_entries, MergeStrategy::Merge);
+        assert_eq!(result.len(), 3);
+    }
+
+    #[test]
+    fn test_merge_ignores_case_keeps_new_casing() {
+        let existing = vec![make_entry("Src\\A.cpp", "C:\\Proj", "cl /c A.cpp")];
+        let new_entries = vec![make_entry("src\\a.cpp", "c:\\proj", "cl /c /O2 a.cpp")];
+
+        let result = merge_compile_commands(existing, new_entries, MergeStrategy::Merge);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].file, "src\\a.cpp");
+        assert_eq!(result[0].directory, "c:\\proj");
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for keep-latest handling of rebuilt files
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_generate() {
+        let mut log = NamedTempFile::new().unwrap();
+        log.write_all(
+            br#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c /DDEBUG main.cpp
+  C:\VC\bin\CL.exe /c /DDEBUG gen/parser.cpp
+"#,
+        )
+        .unwrap();
+
+        let entries = generate(&GenerateOptions {
+            input_files: vec![log.path().to_path_buf()],
+            exclude: vec!["**/gen/**".to_string()],
+            output_style: OutputStyle::Arguments,
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].file.ends_with("main.cpp"));
+        assert!(entries[0].command.is_empty());
+        assert!(
+            entries[0]
+                .arguments
+                .as_ref()
+                .unwrap()
+                .contains(&"/DDEBUG".to_string())
+        );
+    }
+
+    #[test]
+    fn test_generate_with_builder() {
+        let mut log = NamedTempFile::new().unwrap();
+        log.write_all(
+            br#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c /analyze /DDEBUG main.cpp
+  C:\LLVM\bin\clang-cl.exe /c /analyze:only util.cpp
+"#,
+        )
+        .unwrap();
+
+        struct Tag;
+        impl EntryTransformer for Tag {
+            fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+                entry.command.push_str(" /DTAGGED");
+                Ok(())
+            }
+        }
+
+        let entries = GenerateOptions::builder()
+            .input_file(log.path())
+            .compiler("CLANG-CL.exe")
+            .flag_filter(|flag| !flag.starts_with("/analyze"))
+            .transformer("tag", Tag)
+            .generate()
+            .unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].command.contains("/DDEBUG"));
+        assert!(entries[0].command.ends_with("/DTAGGED"));
+        assert!(entries.iter().all(|e| !e.command.contains("/analyze")));
+        assert!(entries[1].command.contains("clang-cl.exe"));
+    }
+
+    #[test]
+    fn test_generate_filters_like_the_binary() {
+        let mut log = NamedTempFile::new().unwrap();
+        log.write_all(
+            br#"Target "ClCompile" from project "/p/p.vcxproj":
+  Configuration=Debug
+  C:\VC\bin\CL.exe /c /Od main.cpp
+  C:\LLVM\bin\clang-cl.exe /c /Od util.cpp
+Target "ClCompile" from project "/p/p.vcxproj":
+  Configuration=Release
+  C:\VC\bin\CL.exe /c /O2 main.cpp
+"#,
+        )
+        .unwrap();
+
+        let entries = GenerateOptions::builder()
+            .input_file(log.path())
+            .compiler("clang-cl.exe")
+            .configuration("debug")
+            .exclude_compiler("clang-cl.exe")
+            .directory_mode(DirectoryMode::Fixed(PathBuf::from("/p/build")))
+            .generate()
+            .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].command.contains("/Od"));
+        assert_eq!(entries[0].configuration, None);
+        assert_eq!(entries[0].directory, "/p/build");
+    }
+
+    #[test]
+    fn test_builder_parse_setters() {
+        let mut log = NamedTempFile::new().unwrap();
+        log.write_all(
+            br#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c /Yupch.h main.cpp gen.inl
+"#,
+        )
+        .unwrap();
+
+        let entries = GenerateOptions::builder()
+            .input_file(log.path())
+            .source_extensions([".CPP", "inl"])
+            .keep_pch_flags(true)
+            .emit_output(true)
+            .generate()
+            .unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].command.contains("/Yupch.h"));
+        assert!(entries[1].file.ends_with("gen.inl"));
+        assert!(entries[0].output.as_deref().unwrap().ends_with("main.obj"));
+    }
+
+    #[test]
+    fn test_generate_with_source_resolver() {
+        #[derive(Debug)]
+        struct Generated;
+        impl SourceResolver for Generated {
+            fn resolve(&self, source: &str, _context: &ResolveContext) -> Option<PathBuf> {
+                source
+                    .starts_with("gen")
+                    .then(|| PathBuf::from("/out").join(source))
+            }
+        }
+
+        let mut log = NamedTempFile::new().unwrap();
+        log.write_all(
+            br#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c gen_parser.cpp main.cpp
+"#,
+        )
+        .unwrap();
+        let entries = GenerateOptions::builder()
+            .input_file(log.path())
+            .resolver(Generated)
+            .generate()
+            .unwrap();
+
+        assert_eq!(entries[0].file, "/out/gen_parser.cpp");
+        assert!(entries[1].file.ends_with("main.cpp"));
+        assert!(!entries[1].file.starts_with("/out"));
+    }
+
+    #[test]
+    fn test_generate_reports_progress() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Recorder {
+            events: Mutex<Vec<String>>,
+        }
+        impl ProgressSink for Recorder {
+            fn start_log(&self, _path: &Path, size: Option<u64>) {
+                self.events
+                    .lock()
+                    .unwrap()
+                    .push(format!("start {:?}", size));
+            }
+            fn commands_emitted(&self, count: usize) {
+                self.events
+                    .lock()
+                    .unwrap()
+                    .push(format!("commands {}", count));
+            }
+            fn finish_log(&self) {
+                self.events.lock().unwrap().push("finish".to_string());
+            }
+        }
+
+        let contents = "Target \"ClCompile\" from project \"/p/p.vcxproj\":\n  C:\\VC\\bin\\CL.exe /c a.cpp b.cpp\n";
+        let mut log = NamedTempFile::new().unwrap();
+        log.write_all(contents.as_bytes()).unwrap();
+
+        let recorder = Recorder::default();
+        let options = GenerateOptions {
+            input_files: vec![log.path().to_path_buf()],
+            ..Default::default()
+        };
+        generate_with_progress(&options, &recorder).unwrap();
+
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                format!("start {:?}", Some(contents.len() as u64)),
+                "commands 2".to_string(),
+                "finish".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_cancelled() {
+        let mut log = NamedTempFile::new().unwrap();
+        log.write_all(b"Build started.\n").unwrap();
+        let options = GenerateOptions {
+            input_files: vec![log.path().to_path_buf()],
+            ..Default::default()
+        };
+
+        let token = options.cancel.clone();
+        token.cancel();
+        let err = generate(&options).unwrap_err();
+        assert!(err.is::<cancel::Cancelled>());
+    }
+
+    fn scan_log_with(contents: &str, options: &ParseOptions) -> Result<ScanResult> {
+        let mut log = NamedTempFile::new().unwrap();
+        log.write_all(contents.as_bytes()).unwrap();
+        process_msbuild_log(
+            log.path(),
+            None,
+            &LogPatterns::for_options(options).unwrap(),
+            options,
+            &NoProgress,
+            &CancellationToken::default(),
+        )
+    }
+
+    fn scan_log(contents: &str) -> ScanResult {
+        scan_log_with(contents, &ParseOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_cl_task_done_pattern() {
+        let pattern = cl_task_done_pattern().unwrap();
+        let caps = pattern.captures(r#"Done executing task "CL"."#).unwrap();
+        assert!(caps.get(1).is_none());
+        let caps = pattern
+            .captures(r#"5>Done executing task "CL" -- FAILED."#)
+            .unwrap();
+        assert!(caps.get(1).is_some());
+        assert!(!pattern.is_match(r#"Done executing task "Message"."#));
+    }
+
+    #[test]
+    fn test_handle_cl_task_done_marks_failed_prefix_only() {
+        let mut state = ProcessingState::new();
+        let pattern = cl_task_done_pattern().unwrap();
+        let mut commands = vec![
+            make_entry("a.cpp", "C:\\proj", "cl /c a.cpp"),
+            make_entry("b.cpp", "C:\\proj", "cl /c b.cpp"),
+        ];
+        state.open_cl_tasks.insert(Some(4), vec![0]);
+        state.open_cl_tasks.insert(Some(5), vec![1]);
+
+        state.current_prefix = Some(5);
+        handle_cl_task_done(
+            r#"5>Done executing task "CL" -- FAILED."#,
+            &pattern,
+            &mut state,
+            &mut commands,
+            10,
+        );
+        state.current_prefix = Some(4);
+        handle_cl_task_done(
+            r#"4>Done executing task "CL"."#,
+            &pattern,
+            &mut state,
+            &mut commands,
+            11,
+        );
+
+        assert!(!commands[0].failed);
+        assert!(commands[1].failed);
+        assert!(state.open_cl_tasks.is_empty());
+    }
+
+    #[test]
+    fn test_keep_latest_prefers_last_successful() {
+        let mut failed = make_entry("a.cpp", "C:\\proj", "cl /c /DBROKEN a.cpp");
+        failed.failed = true;
+        let commands = vec![
+            make_entry("a.cpp", "C:\\proj", "cl /c /DOLD a.cpp"),
+            make_entry("b.cpp", "C:\\proj", "cl /c b.cpp"),
+            make_entry("a.cpp", "C:\\proj", "cl /c /DNEW a.cpp"),
+            failed,
+        ];
+
+        let (result, superseded) = keep_latest(commands, &[]);
+        assert_eq!(superseded, 2);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].command, "cl /c /DNEW a.cpp");
+        assert_eq!(result[1].file, "b.cpp");
+    }
+
+    #[test]
+    fn test_keep_latest_all_failed_keeps_last() {
+        let mut first = make_entry("a.cpp", "C:\\proj", "cl /c /DONE a.cpp");
+        first.failed = true;
+        let mut second = make_entry("a.cpp", "C:\\proj", "cl /c /DTWO a.cpp");
+        second.failed = true;
+
+        let (result, superseded) = keep_latest(vec![first, second], &[]);
+        assert_eq!(superseded, 1);
+        assert_eq!(result[0].command, "cl /c /DTWO a.cpp");
+    }
+
+    #[test]
+    fn test_keep_latest_uses_timestamps() {
+        let seconds = |s| Some(Duration::from_secs(s));
+        let commands = vec![
+            make_entry("a.cpp", "C:\\proj", "cl /c /DNEW a.cpp"),
+            make_entry("a.cpp", "C:\\proj", "cl /c /DOLD a.cpp"),
+        ];
+        // Logged out of order by a parallel build
+        let (result, _) = keep_latest(commands.clone(), &[seconds(20), seconds(10)]);
+        assert_eq!(result[0].command, "cl /c /DNEW a.cpp");
+        // Without times, the log order decides
+        let (result, _) = keep_latest(commands, &[seconds(20), None]);
+        assert_eq!(result[0].command, "cl /c /DOLD a.cpp");
+
+        let pattern = line_prefix_pattern(None).unwrap();
+        assert_eq!(
+            line_timestamp("17:49:27.5 5>  cl.exe", &pattern),
+            Some(Duration::from_secs_f64(17.0 * 3600.0 + 49.0 * 60.0 + 27.5))
+        );
+        assert_eq!(line_timestamp("5>  cl.exe", &pattern), None);
+
+        let mut clock = LogClock::default();
+        let time = clock.time(Duration::from_secs(23 * 3600));
+        assert!(clock.time(Duration::from_secs(22 * 3600)) < time);
+        assert!(clock.time(Duration::from_secs(60)) > time);
+    }
+
+    #[test]
+    fn test_process_log_keeps_last_successful_compile() {
+        let scan = scan_log(
+            r#"Target "ClCompile" from project "C:\p\p.vcxproj":
+  C:\VC\bin\CL.exe /c /DGOOD main.cpp
+Done executing task "CL".
+  C:\VC\bin\CL.exe /c /DBROKEN main.cpp
+Done executing task "CL" -- FAILED.
+"#,
+        );
+
+        assert_eq!(scan.superseded, 1);
+        assert_eq!(scan.commands.len(), 1);
+        assert!(scan.commands[0].command.contains("/DGOOD"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for nested and repeated MSBuild invocations
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_build_boundary_patterns() {
+        let started = build_started_pattern().unwrap();
+        assert!(started.is_match("Build started 1/1/2025 10:00:00 AM."));
+        assert!(started.is_match("5>  Build started 1/1/2025 10:00:00 AM."));
+        assert!(!started.is_match("  Task \"Message\": Build started"));
+
+        let finished = build_finished_pattern().unwrap();
+        assert!(finished.is_match("Build succeeded."));
+        assert!(finished.is_match("5>      Build FAILED."));
+        assert!(!finished.is_match("Build succeeded with warnings"));
+    }
+
+    #[test]
+    fn test_nested_invocation_does_not_clobber_outer_prefixes() {
+        let scan = scan_log(
+            r#"Build started 1/1/2025 10:00:00 AM.
+     1>Project "/outer/outer.vcxproj" on node 1 (Build target(s)).
+     1>Exec:
+         msbuild.exe /m /fl /v:detailed /outer/pack/pack.sln
+         Build started 1/1/2025 10:00:05 AM.
+         1>Project "/outer/pack/pack.vcxproj" on node 1 (Build target(s)).
+         1>ClCompile:
+             C:\VC\bin\CL.exe /c pack.cpp
+         Build succeeded.
+     1>ClCompile:
+         C:\VC\bin\CL.exe /c main.cpp
+Build succeeded.
+"#,
+        );
+
+        assert_eq!(scan.commands.len(), 2);
+        assert_eq!(
+            Path::new(&scan.commands[0].directory),
+            Path::new("/outer/pack")
+        );
+        assert_eq!(Path::new(&scan.commands[1].directory), Path::new("/outer"));
+        assert_eq!(scan.projects.len(), 2);
+    }
+
+    #[test]
+    fn test_appended_builds_start_with_fresh_prefixes() {
+        let scan = scan_log(
+            r#"Build started 1/1/2025 10:00:00 AM.
+     2>Project "/first/first.vcxproj" on node 1 (Build target(s)).
+     2>ClCompile:
+         C:\VC\bin\CL.exe /c a.cpp
+Build succeeded.
+Build started 1/1/2025 11:00:00 AM.
+     2>ClCompile:
+         C:\VC\bin\CL.exe /c orphan.cpp
+     2>Project "/second/second.vcxproj" on node 1 (Build target(s)).
+     2>ClCompile:
+         C:\VC\bin\CL.exe /c b.cpp
+Build succeeded.
+"#,
+        );
+
+        // orphan.cpp must not be attributed to the first build's project
+        let directories: Vec<_> = scan.commands.iter().map(|c| c.directory.as_str()).collect();
+        assert_eq!(directories.len(), 2);
+        assert_eq!(Path::new(directories[0]), Path::new("/first"));
+        assert_eq!(Path::new(directories[1]), Path::new("/second"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for grouped diagnostics
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_skipped_tool_pattern() {
+        let pattern = skipped_tool_pattern(&ParseOptions::default().compilers).unwrap();
+        let caps = pattern
+            .captures(r#"         C:\LLVM\bin\clang-cl.exe /c main.cpp"#)
+            .unwrap();
+        assert_eq!(&caps[1], "clang-cl");
+        assert!(pattern.is_match(r#"    "C:\CUDA\v12\bin\nvcc.exe" -c kernel.cu"#));
+        assert!(pattern.is_match("    ml64.exe /c /Fo x.obj x.asm"));
+        assert!(!pattern.is_match(r#"         C:\VC\bin\CL.exe /c main.cpp"#));
+        assert!(!pattern.is_match(r#"         C:\VC\bin\xml.exe in.xml"#));
+    }
+
+    #[test]
+    fn test_link_command_pattern() {
+        let pattern = link_command_pattern().unwrap();
+        let caps = pattern
+            .captures(r#"5>  "C:\Program Files\VC\bin\link.exe" /LTCG /OUT:"app.exe" main.obj "#)
+            .unwrap();
+        assert_eq!(
+            &caps[1],
+            r#""C:\Program Files\VC\bin\link.exe" /LTCG /OUT:"app.exe" main.obj"#
+        );
+        assert_eq!(&caps[2], "link");
+        assert!(pattern.is_match(r#"    C:\Program Files\VC\bin\Lib.exe /OUT:core.lib a.obj"#));
+        assert!(pattern.is_match("    lib.exe /OUT:core.lib a.obj"));
+        assert!(!pattern.is_match(r#"    C:\VC\bin\CL.exe /c main.cpp"#));
+        assert!(!pattern.is_match(r#"    C:\VC\bin\zlib.exe in.txt"#));
+        assert!(!pattern.is_match("Link:"));
+    }
+
+    #[test]
+    fn test_scan_collects_link_commands() {
+        let scan = scan_log(
+            r#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c main.cpp
+  C:\VC\bin\link.exe /DEBUG /OUT:app.exe main.obj
+"#,
+        );
+
+        assert_eq!(scan.commands.len(), 1);
+        assert_eq!(scan.link_commands.len(), 1);
+        assert_eq!(scan.link_commands[0].tool, "link");
+        assert_eq!(Path::new(&scan.link_commands[0].directory), Path::new("/p"));
+        assert!(
+            scan.link_commands[0]
+                .command
+                .ends_with("/DEBUG /OUT:app.exe main.obj")
+        );
+    }
+
+    #[test]
+    fn test_scan_extra_tools() {
+        let log = r#"Target "ResourceCompile" from project "/p/p.vcxproj":
+  C:\Kits\bin\rc.exe /nologo /fo"obj\app.res" res\app.rc
+  C:\VC\bin\ml64.exe /c /Foobj\fast.obj /Ta fast.asm
+"#;
+        // Without --extra-tools ml64 is reported as skipped and rc ignored
+        let scan = scan_log(log);
+        assert!(scan.commands.is_empty());
+        assert_eq!(scan.diagnostics.count(Category::SkippedTool), 1);
+
+        let options = ParseOptions {
+            extra_tools: vec!["rc.exe".to_string(), "ml64.exe".to_string()],
+            ..ParseOptions::default()
+        };
+        let scan = scan_log_with(log, &options).unwrap();
+        assert_eq!(scan.diagnostics.count(Category::SkippedTool), 0);
+        let files: Vec<&Path> = scan.commands.iter().map(|c| Path::new(&c.file)).collect();
+        assert_eq!(
+            files,
+            vec![Path::new("/p/res\\app.rc"), Path::new("/p/fast.asm")]
+        );
+        assert!(
+            scan.commands[0]
+                .command
+                .starts_with(r"C:\Kits\bin\rc.exe /nologo")
+        );
+    }
+
+    #[test]
+    fn test_scan_captures_environment() {
+        let log = r#"Build started 1/1/2025 10:00:00 AM.
+Environment at start of build:
+INCLUDE = C:\VC\include
+Platform = x64
+VCToolsDir = C:\VC
+Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c /I%VCToolsDir%\atlmfc main.cpp
+"#;
+        let scan = scan_log(log);
+        assert!(scan.commands[0].command.contains("%VCToolsDir%"));
+        let env = &scan.environments[&scan.commands[0].directory];
+        assert_eq!(env["INCLUDE"], r"C:\VC\include");
+
+        let options = ParseOptions {
+            expand_env: true,
+            ..Default::default()
+        };
+        let scan = scan_log_with(log, &options).unwrap();
+        assert!(scan.commands[0].command.contains(r"/IC:\VC\atlmfc"));
+    }
+
+    #[test]
+    fn test_scan_folds_cl_environment_options() {
+        let log = r#"Environment at start of build:
+_CL_ = /DLAST
+Target "ClCompile" from project "/p/p.vcxproj":
+  set CL=/DFOO /W4
+  C:\VC\bin\CL.exe /c main.cpp
+  set CL=
+  C:\VC\bin\CL.exe /c other.cpp
+"#;
+        let scan = scan_log(log);
+        assert_eq!(
+            scan.commands[0].command,
+            r#"C:\VC\bin\CL.exe /DFOO /W4 /c "/p/main.cpp" /DLAST"#
+        );
+        assert!(!scan.commands[1].command.contains("/DFOO"));
+    }
+
+    #[test]
+    fn test_scan_groups_diagnostics() {
+        let scan = scan_log(
+            r#"  C:\VC\bin\CL.exe /c early.cpp
+Target "ClCompile" from project "/p/p.vcxproj":
+  C:\LLVM\bin\clang-cl.exe /c fast.cpp
+  C:\VC\bin\CL.exe /c /nologo
+  C:\VC\bin\CL.exe /c missing-on-disk.cpp
+"#,
+        );
+
+        assert_eq!(scan.commands.len(), 1);
+        let categories: Vec<_> = scan
+            .diagnostics
+            .summaries()
+            .iter()
+            .map(|s| s.category)
+            .collect();
+        assert_eq!(
+            categories,
+            vec![
+                Category::NoProjectContext,
+                Category::SkippedTool,
+                Category::NoSourceFiles,
+                Category::UnresolvedPath
+            ]
+        );
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for --strict-ambiguity
+    // ----------------------------------------------------------------------------
+
+    const UNQUOTED_COMPILER_LOG: &str = r#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\Program Files\VC\bin\CL.exe /c main.cpp
+"#;
+
+    const UNMAPPED_PREFIX_LOG: &str = r#"  1>Project "/a/a.vcxproj" on node 1 (Build target(s)).
+  2>ClCompile:
+      C:\VC\bin\CL.exe /c main.cpp
+"#;
+
+    fn strict() -> ParseOptions {
+        ParseOptions {
+            strict_ambiguity: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_guesses_are_diagnostics_by_default() {
+        let scan = scan_log(UNQUOTED_COMPILER_LOG);
+        assert_eq!(scan.commands.len(), 1);
+        assert!(
+            scan.diagnostics
+                .summaries()
+                .iter()
+                .any(|s| s.category == Category::GuessedCompilerPath)
+        );
+
+        let scan = scan_log(UNMAPPED_PREFIX_LOG);
+        assert_eq!(scan.commands.len(), 1);
+        assert!(
+            scan.diagnostics
+                .summaries()
+                .iter()
+                .any(|s| s.category == Category::InferredProject)
+        );
+    }
+
+    #[test]
+    fn test_strict_ambiguity_rejects_unquoted_compiler_path() {
+        let err = scan_log_with(UNQUOTED_COMPILER_LOG, &strict()).unwrap_err();
+        // The log location comes first, like a compiler message
+        assert!(err.to_string().ends_with(":2"));
+        let message = format!("{:#}", err);
+        assert!(message.contains("line 2"));
+        assert!(message.contains("unquoted compiler path"));
+    }
+
+    #[test]
+    fn test_strict_ambiguity_rejects_inferred_project() {
+        let err = scan_log_with(UNMAPPED_PREFIX_LOG, &strict()).unwrap_err();
+        assert!(err.is::<AmbiguityError>());
+        assert!(format!("{:#}", err).contains("output prefix 2 has no project"));
+    }
+
+    #[test]
+    fn test_strict_ambiguity_accepts_unambiguous_log() {
+        let scan = scan_log_with(
+            r#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c main.cpp
+"#,
+            &strict(),
+        )
+        .unwrap();
+        assert_eq!(scan.commands.len(), 1);
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for NMake/jom output
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_nmake_started_pattern() {
+        let pattern = nmake_started_pattern().unwrap();
+        assert!(
+            pattern.is_match("  Microsoft (R) Program Maintenance Utility Version 14.44.35207.1")
+        );
+        assert!(pattern.is_match("  jom 1.1.4 - empower your cores"));
+        assert!(pattern.is_match("  nmake /nologo /f Makefile all"));
+        assert!(pattern.is_match(r#"5>  "C:\VC\bin\nmake.exe" /nologo"#));
+        assert!(!pattern.is_match("  Creating directory nmake_out"));
+    }
+
+    #[test]
+    fn test_change_directory() {
+        let base = Path::new("/p");
+        assert_eq!(change_directory(base, "src"), PathBuf::from("/p/src"));
+        assert_eq!(
+            change_directory(base, "src/../lib/."),
+            PathBuf::from("/p/lib")
+        );
+        assert_eq!(
+            change_directory(base, "\"sub dir\""),
+            PathBuf::from("/p/sub dir")
+        );
+        assert_eq!(change_directory(base, "/d /other"), PathBuf::from("/other"));
+    }
+
+    #[test]
+    fn test_scan_nmake_project_tracks_directories() {
+        let scan = scan_log(
+            r#"Target "Build" from project "/p/p.vcxproj":
+  Task "Exec"
+    nmake /nologo /f Makefile
+    Microsoft (R) Program Maintenance Utility Version 14.44.35207.1
+    cd src
+    cl /c /nologo /I..\inc main.cpp util.cpp
+    cd ..\lib
+    cd .. && cd lib && cl.exe /c /DLIB lib.c
+    cd ..
+  Done executing task "Exec".
+  C:\VC\bin\CL.exe /c after.cpp
+"#,
+        );
+
+        let entries: Vec<_> = scan
+            .commands
+            .iter()
+            .map(|c| (Path::new(&c.directory).to_path_buf(), c.command.clone()))
+            .collect();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].0, PathBuf::from("/p/src"));
+        assert!(entries[0].1.starts_with("cl /c /nologo"));
+        assert_eq!(entries[1].0, PathBuf::from("/p/src"));
+        assert_eq!(entries[2].0, PathBuf::from("/p/lib"));
+        assert!(entries[2].1.starts_with("cl.exe /c /DLIB"));
+        // Back to MSBuild: the project directory applies again
+        assert_eq!(entries[3].0, PathBuf::from("/p"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for --output-style
+    // ----------------------------------------------------------------------------
+
+    fn make_styled_entry(command: &str) -> CompileCommand {
+        CompileCommand {
+            directory: r"C:\src".to_string(),
+            command: command.to_string(),
+            arguments: None,
+            file: r"C:\src\main.cpp".to_string(),
+            output: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_split_arguments_removes_quotes() {
+        assert_eq!(
+            split_arguments(r#"cl.exe /I"C:\a b" /DNAME=\"x\" /Fo"obj\\" "C:\src\main.cpp""#),
+            vec![
+                "cl.exe",
+                r"/IC:\a b",
+                r#"/DNAME="x""#,
+                r"/Foobj\",
+                r"C:\src\main.cpp"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join_arguments_round_trips() {
+        let arguments = split_arguments(r#"cl.exe /I"C:\a b" /Fo"obj\\" /c main.cpp"#);
+        let command = join_arguments(&arguments);
+        assert_eq!(command, r#"cl.exe "/IC:\a b" /Foobj\ /c main.cpp"#);
+        assert_eq!(split_arguments(&command), arguments);
+    }
+
+    #[test]
+    fn test_apply_output_style() {
+        let mut entry = make_styled_entry(r#"cl.exe /c "C:\src\main.cpp""#);
+        entry.apply_output_style(OutputStyle::Arguments);
+        let json = serde_json::to_value(&entry).unwrap();
+        assert!(json.get("command").is_none());
+        assert_eq!(json["arguments"][2], r"C:\src\main.cpp");
+
+        // An arguments-only entry from an existing database regains its command
+        let mut loaded: CompileCommand = serde_json::from_value(json).unwrap();
+        loaded.apply_output_style(OutputStyle::Both);
+        let json = serde_json::to_value(&loaded).unwrap();
+        assert_eq!(json["command"], r"cl.exe /c C:\src\main.cpp");
+        assert_eq!(json["arguments"][0], "cl.exe");
+
+        loaded.apply_output_style(OutputStyle::Command);
+        assert!(loaded.arguments.is_none());
+    }
+
+    #[test]
+    fn test_relativize_file_round_trips() {
+        let base = Path::new("/repo");
+        let mut entry = CompileCommand {
+            directory: "/repo/proj".to_string(),
+            command: "cl.exe /c src/a.cpp".to_string(),
+            file: "src/a.cpp".to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        };
+
+        entry.relativize_file(base);
+        assert_eq!(Path::new(&entry.file), Path::new("proj/src/a.cpp"));
+        entry.absolutize_file(base);
+        assert_eq!(Path::new(&entry.file), Path::new("/repo/proj/src/a.cpp"));
+
+        // Files outside the base directory keep their absolute path
+        entry.relativize_file(Path::new("/repository"));
+        assert_eq!(Path::new(&entry.file), Path::new("/repo/proj/src/a.cpp"));
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for response file expansion
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_parse_cl_command_expands_response_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("cl.rsp"), "/c /DFROM_RSP\r\nmain.cpp\r\n").unwrap();
+        let project_ctx = ProjectContext {
+            project_path: dir.path().join("p.vcxproj"),
+            project_dir: dir.path().to_path_buf(),
+        };
+        let line = r"C:\VC\bin\CL.exe /nologo @cl.rsp @missing.rsp";
+
+        let mut diagnostics = Diagnostics::default();
+        let commands = parse_cl_command(
+            line,
+            &project_ctx,
+            &ParseOptions::default(),
+            &mut diagnostics,
+            1,
+        )
+        .unwrap();
+        assert_eq!(commands.len(), 1);
+        assert!(commands[0].file.ends_with("main.cpp"));
+        assert!(
+            commands[0]
+                .command
+                .contains("/nologo /c /DFROM_RSP @missing.rsp")
+        );
+        assert_eq!(
+            diagnostics.summaries()[0].category,
+            Category::MissingResponseFile
+        );
+
+        let options = ParseOptions {
+            expand_response_files: false,
+            ..Default::default()
+        };
+        let commands =
+            parse_cl_command(line, &project_ctx, &options, &mut Diagnostics::default(), 1).unwrap();
+        assert!(commands.is_empty());
+    }
+
+    // ----------------------------------------------------------------------------
+    #[test]
+    fn test_entry_compiler() {
+        let mut entry = CompileCommand {
+            directory: "/src".to_string(),
+            command: r#""C:\LLVM\bin\clang-cl.exe" /c a.cpp"#.to_string(),
+            file: "a.cpp".to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        };
+        assert_eq!(entry.compiler(), "clang-cl");
+        assert!(entry.uses_compiler(&["CLANG-CL.EXE".to_string()]));
+        assert!(!entry.uses_compiler(&["cl.exe".to_string()]));
+
+        entry.command.clear();
+        entry.arguments = Some(vec!["CL.exe".to_string(), "a.cpp".to_string()]);
+        assert_eq!(entry.compiler(), "cl");
+    }
+
+    // Tests for --compiler-executable
+    // ----------------------------------------------------------------------------
+
+    fn clang_cl_options() -> ParseOptions {
+        ParseOptions {
+            compilers: vec!["cl.exe".to_string(), "clang-cl.exe".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_compiler() {
+        let options = clang_cl_options();
+        assert!(options.is_compiler(r#""C:\LLVM\bin\clang-cl.exe""#));
+        assert!(options.is_compiler("CL"));
+        assert!(options.is_compiler(r"C:\VC\bin\CL.exe"));
+        assert!(!options.is_compiler("link.exe"));
+        assert!(!ParseOptions::default().is_compiler("clang-cl.exe"));
+        assert!(!options.is_compiler(r"C:\VC\bin\mycl.exe"));
+    }
+
+    #[test]
+    fn test_scan_matches_compiler_path_fragment() {
+        let options = ParseOptions {
+            compilers: vec![r"HostX64\x64\cl.exe".to_string()],
+            ..Default::default()
+        };
+        assert!(options.is_compiler(r"C:\VC\bin\HostX64\x64\CL.exe"));
+        assert!(!options.is_compiler(r"C:\VC\bin\HostX64\arm64\cl.exe"));
+
+        let log = r#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\HostX64\arm64\cl.exe /c /DARM64 arm.cpp
+  C:\VC\bin\HostX64\x64\CL.exe /c /DX64 x64.cpp
+"#;
+        let scan = scan_log_with(log, &options).unwrap();
+        assert_eq!(scan.commands.len(), 1);
+        assert!(scan.commands[0].command.contains("/DX64"));
+    }
+
+    #[test]
+    fn test_scan_joins_continuation_lines() {
+        let log = r#"Target "ClCompile" from project "/p/p.vcxproj":
+  cl.exe /c /DA ^
+    /DB \
+    main.cpp
+  cl.exe /c /DOTHER other.cpp
+"#;
+        let scan = scan_log_with(log, &ParseOptions::default()).unwrap();
+        assert_eq!(scan.commands.len(), 2);
+        assert!(scan.commands[0].command.contains("/c /DA /DB "));
+        assert!(scan.commands[0].file.ends_with("main.cpp"));
+        assert!(scan.commands[1].command.contains("/DOTHER"));
+    }
+
+    #[test]
+    fn test_scan_only_joins_commands() {
+        let log = r#"Target "ClCompile" from project "/p/p.vcxproj":
+  Copying generated headers to out \
+  cl.exe /c /DA main.cpp
+"#;
+        let scan = scan_log_with(log, &ParseOptions::default()).unwrap();
+        assert_eq!(scan.commands.len(), 1);
+        assert!(scan.commands[0].command.starts_with("cl.exe /c /DA"));
+        assert!(scan.commands[0].file.ends_with("main.cpp"));
+    }
+
+    #[test]
+    fn test_scan_joins_prefixed_continuation_lines() {
+        let log = r#"3>Project "/p/p.vcxproj" on node 1 (default targets).
+3>ClCompile:
+     cl.exe /c /DA ^
+3>   /DB ^
+3>   main.cpp
+     cl.exe /c /DLAST ^
+3>   last.cpp ^
+"#;
+        let scan = scan_log_with(log, &ParseOptions::default()).unwrap();
+        assert_eq!(scan.commands.len(), 2);
+        assert!(scan.commands[0].command.contains("/c /DA /DB "));
+        assert!(!scan.commands[0].command.contains('>'));
+        assert!(scan.commands[0].file.ends_with("main.cpp"));
+        // Still wrapped when the log ends
+        assert!(scan.commands[1].file.ends_with("last.cpp"));
+    }
+
+    #[test]
+    fn test_scan_applies_drive_map_to_project_directory() {
+        let log = r#"Target "ClCompile" from project "S:/app/app.vcxproj":
+  cl.exe /c /Foobj/ main.cpp
+"#;
+        let options = ParseOptions {
+            drive_map: DriveMap::new(&["S:=/w".to_string()]).unwrap(),
+            emit_output: true,
+            ..Default::default()
+        };
+        let scan = scan_log_with(log, &options).unwrap();
+        assert_eq!(scan.commands[0].directory, "/w/app");
+        assert_eq!(scan.commands[0].file, "/w/app/main.cpp");
+        assert_eq!(
+            scan.commands[0].output.as_deref(),
+            Some("/w/app/obj/main.obj")
+        );
+    }
+
+    #[test]
+    fn test_scan_resolves_sources_next_to_object_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let project_dir = dir.path().join("app");
+        let object_dir = project_dir.join("x64").join("Debug");
+        std::fs::create_dir_all(&object_dir).unwrap();
+        std::fs::write(dir.path().join("shared.cpp"), "").unwrap();
+
+        let log = format!(
+            "Target \"ClCompile\" from project \"{}\":\n  cl.exe /c /Fo{}/ shared.cpp\n",
+            project_dir.join("app.vcxproj").display(),
+            object_dir.display()
+        );
+        let scan = scan_log_with(&log, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            scan.commands[0].file,
+            path_to_normalized_string(&dir.path().join("shared.cpp"))
+        );
+        assert_eq!(scan.diagnostics.count(Category::UnresolvedPath), 0);
+    }
+
+    #[test]
+    fn test_scan_attributes_parent_prefix_to_only_child_project() {
+        let log = r#"1>Project "/w/app.sln" (1) is building "/w/app/app.vcxproj" (2) on node 1 (default targets).
+5>Project "/w/lib/lib.vcxproj" on node 2 (default targets).
+1>ClCompile:
+     cl.exe /c main.cpp
+"#;
+        let scan = scan_log_with(log, &ParseOptions::default()).unwrap();
+        assert_eq!(scan.commands.len(), 1);
+        assert_eq!(scan.commands[0].directory, "/w/app");
+        assert_eq!(scan.diagnostics.count(Category::InferredProject), 0);
+        assert!(
+            scan.project_references[Path::new("/w/app.sln")]
+                .contains(Path::new("/w/app/app.vcxproj"))
+        );
+    }
+
+    #[test]
+    fn test_scan_heuristic_attribution_uses_object_directory() {
+        let log = r#"Target "ClCompile" from project "/w/a/a.vcxproj":
+Target "ClCompile" from project "/w/b/b.vcxproj":
+  cl.exe /c /Fo/w/a/obj/ main.cpp
+"#;
+        let scan = scan_log_with(log, &ParseOptions::default()).unwrap();
+        assert_eq!(scan.commands[0].directory, "/w/b");
+
+        let options = ParseOptions {
+            attribution: Attribution::Heuristic,
+            ..Default::default()
+        };
+        let scan = scan_log_with(log, &options).unwrap();
+        assert_eq!(scan.commands[0].directory, "/w/a");
+    }
+
+    #[test]
+    fn test_scan_keeps_configurations_apart() {
+        let log = r#"Target "ClCompile" from project "/p/p.vcxproj":
+  Configuration=Debug
+  cl.exe /c /Od main.cpp
+Target "ClCompile" from project "/p/p.vcxproj":
+  Configuration=Release
+  cl.exe /c /O2 main.cpp
+"#;
+        let scan = scan_log_with(log, &ParseOptions::default()).unwrap();
+        assert_eq!(scan.commands.len(), 1);
+        assert_eq!(scan.commands[0].configuration, None);
+
+        let options = ParseOptions {
+            track_configurations: true,
+            ..Default::default()
+        };
+        let scan = scan_log_with(log, &options).unwrap();
+        let configurations: Vec<_> = scan
+            .commands
+            .iter()
+            .map(|entry| entry.configuration.as_deref())
+            .collect();
+        assert_eq!(configurations, [Some("Debug"), Some("Release")]);
+        assert!(scan.commands[1].command.contains("/O2"));
+    }
+
+    #[test]
+    fn test_scan_converts_configured_compilers() {
+        let log = r#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\LLVM\bin\clang-cl.exe /c fast.cpp
+  C:\VC\bin\CL.exe /c slow.cpp
+"#;
+        let scan = scan_log_with(log, &clang_cl_options()).unwrap();
+        assert_eq!(scan.commands.len(), 2);
+        assert!(
+            scan.commands[0]
+                .command
+                .starts_with(r"C:\LLVM\bin\clang-cl.exe /c")
+        );
+        assert!(scan.commands[1].command.starts_with(r"C:\VC\bin\CL.exe /c"));
+
+        // With the default compiler clang-cl is still skipped
+        let scan = scan_log(log);
+        assert_eq!(scan.commands.len(), 1);
+    }
+
+    // ----------------------------------------------------------------------------
+    // Tests for multiple input logs
+    // ----------------------------------------------------------------------------
+
+    #[test]
+    fn test_later_log_overrides_earlier() {
+        let mut scan = scan_log(
+            r#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c /DDEBUG a.cpp b.cpp
+"#,
+        );
+        scan.extend(scan_log(
+            r#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c /DRELEASE a.cpp
+  C:\LLVM\bin\clang-cl.exe /c c.cpp
+"#,
+        ));
+
+        assert_eq!(scan.commands.len(), 2);
+        assert!(scan.commands[0].file.ends_with("a.cpp"));
+        assert!(scan.commands[0].command.contains("/DRELEASE"));
+        assert!(scan.commands[1].file.ends_with("b.cpp"));
+        assert_eq!(scan.projects.len(), 1);
+        assert!(
+            scan.diagnostics
+                .summaries()
+                .iter()
+                .any(|s| s.category == Category::SkippedTool)
+        );
+    }
+
+    #[test]
+    fn test_scan_logs_in_parallel_keeps_input_order() {
+        let logs: Vec<NamedTempFile> = ["/DFIRST a.cpp b.cpp", "/DSECOND a.cpp", "/DTHIRD b.cpp"]
+            .iter()
+            .map(|args| {
+                let mut log = NamedTempFile::new().unwrap();
+                write!(
+                    log,
+                    "Target \"ClCompile\" from project \"/p/p.vcxproj\":\n  C:\\VC\\bin\\CL.exe /c {}\n",
+                    args
+                )
+                .unwrap();
+                log
+            })
+            .collect();
+        let paths: Vec<PathBuf> = logs.iter().map(|log| log.path().to_path_buf()).collect();
+
+        let options = ParseOptions::default();
+        let scan = scan_logs(
+            &paths,
+            None,
+            &LogPatterns::for_options(&options).unwrap(),
+            &options,
+            &NoProgress,
+            &CancellationToken::default(),
+            3,
+        )
+        .unwrap();
+
+        assert_eq!(scan.commands.len(), 2);
+        assert!(scan.commands[0].command.contains("/DSECOND"));
+        assert!(scan.commands[1].command.contains("/DTHIRD"));
+        assert_eq!(scan.lines_read, 6);
+    }
+}
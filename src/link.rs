@@ -0,0 +1,71 @@
+//! `--link-commands`: `link.exe` and `lib.exe` invocations from the log,
+//! written next to the compilation database.
+
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// One linker or librarian invocation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkCommand {
+    /// Directory of the project that ran the tool
+    pub directory: String,
+    /// `link` or `lib`
+    pub tool: String,
+    /// The command line as logged
+    pub command: String,
+}
+
+/// Write the invocations to `path` as a JSON array
+pub fn write(path: &Path, commands: &[LinkCommand], pretty_print: bool) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create link commands file: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    if pretty_print {
+        serde_json::to_writer_pretty(&mut writer, commands)
+    } else {
+        serde_json::to_writer(&mut writer, commands)
+    }
+    .with_context(|| format!("Failed to write link commands file: {}", path.display()))?;
+    writer
+        .flush()
+        .with_context(|| format!("Failed to write link commands file: {}", path.display()))?;
+
+    info!(
+        "Wrote {} link command(s) to {}",
+        commands.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_write_round_trips() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("link_commands.json");
+        let commands = vec![LinkCommand {
+            directory: "/p".to_string(),
+            tool: "link".to_string(),
+            command: "link.exe /LTCG /OUT:app.exe main.obj".to_string(),
+        }];
+
+        write(&path, &commands, false).unwrap();
+        let read: Vec<LinkCommand> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(read, commands);
+    }
+}
@@ -0,0 +1,98 @@
+//! Extended-length (`\\?\`) and UNC paths.
+//!
+//! Windows only opens paths longer than `MAX_PATH` when they carry the
+//! `\\?\` prefix, and logs of large repositories sometimes spell paths that
+//! way. Entries are written without the prefix, which clangd does not
+//! understand, and it is added back only to look at files on disk.
+
+use std::{borrow::Cow, path::Path};
+
+const VERBATIM: &str = r"\\?\";
+const VERBATIM_UNC: &str = r"\\?\UNC\";
+const UNC: &str = r"\\";
+
+/// Longest path Windows opens without the `\\?\` prefix, counting the NUL
+const MAX_PATH: usize = 260;
+
+/// `path` without its `\\?\` prefix: `\\?\C:\src` becomes `C:\src` and
+/// `\\?\UNC\server\share` becomes `\\server\share`
+pub fn strip_verbatim(path: &str) -> Cow<'_, str> {
+    if let Some(share) = path.strip_prefix(VERBATIM_UNC) {
+        Cow::Owned(format!("{}{}", UNC, share))
+    } else if let Some(rest) = path.strip_prefix(VERBATIM) {
+        Cow::Borrowed(rest)
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// `path` with the `\\?\` prefix if it is an absolute Windows path too long
+/// to open without one
+fn extended(path: &str) -> Option<String> {
+    if path.len() < MAX_PATH || path.starts_with(VERBATIM) {
+        return None;
+    }
+    // The prefix turns off all parsing, including `/` as a separator
+    let path = path.replace('/', r"\");
+    if let Some(share) = path.strip_prefix(UNC) {
+        return Some(format!("{}{}", VERBATIM_UNC, share));
+    }
+    let bytes = path.as_bytes();
+    let has_drive =
+        bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\';
+    has_drive.then(|| format!("{}{}", VERBATIM, path))
+}
+
+/// `path` as it must be passed to the filesystem: with the `\\?\` prefix
+/// if it is too long on Windows, unchanged otherwise
+pub fn for_filesystem(path: &Path) -> Cow<'_, Path> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(path);
+    }
+    match path.to_str().and_then(extended) {
+        Some(extended) => Cow::Owned(extended.into()),
+        None => Cow::Borrowed(path),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_verbatim() {
+        assert_eq!(strip_verbatim(r"\\?\C:\src\a.cpp"), r"C:\src\a.cpp");
+        assert_eq!(
+            strip_verbatim(r"\\?\UNC\server\share\a.cpp"),
+            r"\\server\share\a.cpp"
+        );
+        assert_eq!(
+            strip_verbatim(r"\\server\share\a.cpp"),
+            r"\\server\share\a.cpp"
+        );
+        assert_eq!(strip_verbatim("/src/a.cpp"), "/src/a.cpp");
+    }
+
+    #[test]
+    fn test_extended() {
+        let long = format!(r"C:\src\{}\a.cpp", "d".repeat(MAX_PATH));
+        assert_eq!(extended(&long), Some(format!(r"\\?\{}", long)));
+        assert_eq!(
+            extended(&format!(r"\\server\share\{}", "d".repeat(MAX_PATH))),
+            Some(format!(r"\\?\UNC\server\share\{}", "d".repeat(MAX_PATH)))
+        );
+        assert_eq!(
+            extended(&format!("C:/src/{}", "d".repeat(MAX_PATH))),
+            Some(format!(r"\\?\C:\src\{}", "d".repeat(MAX_PATH)))
+        );
+
+        // Short, relative and already prefixed paths are left alone
+        assert_eq!(extended(r"C:\src\a.cpp"), None);
+        assert_eq!(extended(&format!(r"src\{}", "d".repeat(MAX_PATH))), None);
+        assert_eq!(extended(&format!(r"\\?\C:\{}", "d".repeat(MAX_PATH))), None);
+    }
+}
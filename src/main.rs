@@ -1,19 +1,45 @@
 use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
-use indexmap::IndexMap;
+use clap::{
+    ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, parser::ValueSource,
+};
+use clap_complete::Shell;
+use indexmap::{IndexMap, IndexSet};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use log::{LevelFilter, debug, error, info, trace, warn};
-use regex::Regex;
+use log::{LevelFilter, debug, error, info, warn};
+use ms2cc::{
+    CompileCommand, GenerateOptions, MergeStrategy, OutputStyle, ParseOptions,
+    attribution::Attribution,
+    clangd,
+    compile_commands::{self, Dedupe, DirectoryMode},
+    compile_flags::{self, FlagSet, OutputFormat},
+    config::{self, Config},
+    create_temp_output_file,
+    database::{CompilationDatabase, SortOrder, WriteOptions},
+    diagnostics::{Category, CategorySummary},
+    env,
+    input::{self, InputFormat},
+    link, load_existing_database, merge_compile_commands, p1689,
+    path_map::{DriveMap, PathMap, PathStyle, Remap},
+    progress::ProgressBars,
+    report::RunReport,
+    tlog::TlogIndex,
+    transform::{AddFlag, ReplaceCompiler, ReplacePrefix, StripFlag},
+};
 use simplelog::*;
-use std::mem::take;
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, BufWriter},
+    ffi::OsString,
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
+    sync::Arc,
     time::{Duration, Instant},
 };
 use tempfile::NamedTempFile;
 
+mod subcommands;
+mod watch;
+
+use watch::Watch;
+
 // ----------------------------------------------------------------------------
 // Logging
 // ----------------------------------------------------------------------------
@@ -48,19 +74,44 @@ impl From<LogLevel> for LevelFilter {
 const PACKAGE_DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 const PACKAGE_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Dropped source files named in the warning; --dropped-sources lists all
+const MAX_LISTED_DROPPED_SOURCES: usize = 10;
+
+/// Entries printed by --dry-run
+const DRY_RUN_SAMPLE_ENTRIES: usize = 3;
+
 #[derive(Parser)]
 #[command(version, about=PACKAGE_DESCRIPTION)]
 struct Args {
-    /// Full path to msbuild.log file
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Full path to msbuild.log file, or - for stdin (repeatable; later logs win for the same file)
     #[arg(short = 'i', long, default_value = "msbuild.log")]
-    input_file: PathBuf,
+    input_file: Vec<PathBuf>,
+
+    /// Whether --input-file names MSBuild logs or Ninja build directories (default input: .)
+    #[arg(long, value_enum, default_value = "msbuild")]
+    input_format: InputFormat,
+
+    /// Read every *.log file in this directory, in name order, instead of --input-file
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["input_file", "input_format"])]
+    input_dir: Option<PathBuf>,
+
+    /// Number of logs to scan at once (0: one per CPU)
+    #[arg(long, value_name = "N", default_value = "0")]
+    max_threads: usize,
 
     /// Path to output compile_commands.json file
     #[arg(short = 'o', long, default_value = "compile_commands.json")]
     output_file: PathBuf,
 
+    /// Encoding of the log, e.g. utf-16le or windows-1252 (default: detect UTF-16, else UTF-8)
+    #[arg(long, value_name = "LABEL")]
+    input_encoding: Option<String>,
+
     /// Logging level
-    #[arg(short = 'l', long, value_enum, default_value = "info")]
+    #[arg(short = 'l', long, value_enum, default_value = "info", global = true)]
     log_level: LogLevel,
 
     /// Pretty-print JSON output
@@ -74,779 +125,425 @@ struct Args {
     /// Overwrite the output file instead of merging with existing entries
     #[arg(long, default_value = "false")]
     overwrite: bool,
-}
 
-// ----------------------------------------------------------------------------
-// Data Structures
-// ----------------------------------------------------------------------------
-
-/// Context for the current project being processed
-#[derive(Debug, Clone)]
-struct ProjectContext {
-    /// Full path to the project file
-    project_path: PathBuf,
-    /// Directory containing the project file (for resolving relative paths)
-    project_dir: PathBuf,
-}
+    /// Drop entries whose source file no longer exists, existing entries too
+    #[arg(long, default_value = "false")]
+    prune: bool,
 
-/// Represents a single compilation command entry in compile_commands.json
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-struct CompileCommand {
-    /// The working directory of the compilation
-    directory: String,
-    /// The compile command as a single string
-    command: String,
-    /// The main translation unit source processed by this command
-    file: String,
-}
+    /// Write the source files named in the log that got no entry, and why, as JSON
+    #[arg(long, value_name = "FILE")]
+    dropped_sources: Option<PathBuf>,
 
-/// State tracking for MSBuild log processing
-#[derive(Debug)]
-struct ProcessingState {
-    /// Maps output prefix (e.g., "7>") to the project being built
-    prefix_to_project: std::collections::HashMap<u32, ProjectContext>,
-    /// Current project context (for sequential builds or fallback)
-    current_project: Option<ProjectContext>,
-    /// Current output prefix being processed
-    current_prefix: Option<u32>,
-    /// Total number of compile commands found
-    command_count: usize,
-}
+    /// Write one database per MSBuild configuration, e.g. compile_commands.Debug.json
+    #[arg(long, default_value = "false")]
+    split_by_config: bool,
 
-impl ProcessingState {
-    fn new() -> Self {
-        Self {
-            prefix_to_project: std::collections::HashMap::new(),
-            current_project: None,
-            current_prefix: None,
-            command_count: 0,
-        }
-    }
+    /// Only keep the entries compiled for this MSBuild configuration, e.g. Debug
+    #[arg(long, value_name = "NAME")]
+    configuration: Option<String>,
 
-    /// Get the active project context based on current prefix or fallback
-    fn get_active_project(&self) -> Option<&ProjectContext> {
-        if let Some(prefix) = self.current_prefix {
-            // Try prefix-aware mapping first (parallel builds)
-            self.prefix_to_project
-                .get(&prefix)
-                .or(self.current_project.as_ref())
-        } else {
-            // Sequential build: use current_project
-            self.current_project.as_ref()
-        }
-    }
-}
+    /// Only keep the entries produced by this compiler, e.g. clang-cl.exe (repeatable)
+    #[arg(long, value_name = "EXE")]
+    only_compiler: Vec<String>,
 
-/// Bundle of compiled regex patterns for log parsing
-struct LogPatterns {
-    node_prefix: Regex,
-    project_on_node: Regex,
-    nested_project: Regex,
-    from_project: Regex,
-    compile_command: Regex,
-}
+    /// Drop the entries produced by this compiler, e.g. cl.exe (repeatable)
+    #[arg(long, value_name = "EXE")]
+    exclude_compiler: Vec<String>,
 
-impl LogPatterns {
-    fn new() -> Result<Self> {
-        Ok(Self {
-            node_prefix: node_prefix_pattern()?,
-            project_on_node: project_on_node_pattern()?,
-            nested_project: nested_project_pattern()?,
-            from_project: from_project_pattern()?,
-            compile_command: compile_command_pattern()?,
-        })
-    }
-}
+    /// Keep the previous output file as <OUTPUT>.bak before replacing it
+    #[arg(long, default_value = "false")]
+    backup: bool,
 
-// ----------------------------------------------------------------------------
-// Command Line Parsing
-// ----------------------------------------------------------------------------
+    /// Replace existing entries by source file alone, even if their directory changed
+    #[arg(long, default_value = "false", conflicts_with = "overwrite")]
+    update: bool,
 
-/// Tokenize a command line respecting quoted strings
-/// Implements state machine: NORMAL -> IN_QUOTE -> NORMAL
-fn tokenize_command_line(line: &str) -> Vec<String> {
-    let mut tokens = Vec::new();
-    let mut current_token = String::new();
-    let mut in_quotes = false;
-
-    for ch in line.chars() {
-        match ch {
-            '"' => {
-                in_quotes = !in_quotes;
-                current_token.push(ch);
-            }
-            ' ' | '\t' if !in_quotes => {
-                if !current_token.is_empty() {
-                    tokens.push(take(&mut current_token));
-                }
-            }
-            _ => {
-                current_token.push(ch);
-            }
-        }
-    }
+    /// Add the new entries after the existing ones without replacing any, e.g. to build one database from several project logs
+    #[arg(long, default_value = "false", conflicts_with_all = ["overwrite", "update"])]
+    append: bool,
 
-    if !current_token.is_empty() {
-        tokens.push(current_token);
-    }
+    /// Path to a .ms2cc.toml config file (default: search upward from the current directory)
+    #[arg(long, conflicts_with = "no_config")]
+    config: Option<PathBuf>,
 
-    tokens
-}
+    /// Ignore any .ms2cc.toml config file
+    #[arg(long, default_value = "false")]
+    no_config: bool,
 
-/// Check if a flag should be filtered out (PCH-related)
-fn should_filter_flag(flag: &str) -> bool {
-    let flag_upper = flag.to_uppercase();
-    // Strip PCH flags: /Yc, /Yu, /Fp<path>
-    // Keep /FI (force include) - clangd supports this as -include
-    // Keep /fp:<model> (floating-point model) - has colon, different from /Fp (PCH)
+    /// Write a JSON report about the run (projects, configurations) to this file
+    #[arg(long)]
+    stats_file: Option<PathBuf>,
 
-    if flag_upper.starts_with("/YC") || flag_upper.starts_with("/YU") {
-        return true;
-    }
+    /// Fail instead of guessing when the log is ambiguous
+    #[arg(long, default_value = "false")]
+    strict_ambiguity: bool,
 
-    // Check for /Fp (PCH file) but NOT /fp: (floating-point model)
-    // After uppercasing:
-    //   /fp:precise → /FP:PRECISE (floating-point, keep it)
-    //   /Fp"file.pch" → /FP"FILE.PCH" (PCH, filter it)
-    // The discriminator is the colon!
-    if flag_upper.starts_with("/FP") && !flag_upper.starts_with("/FP:") {
-        return true;
-    }
+    /// How CL lines the log does not tie to a project are attributed, e.g. /m builds without node prefixes
+    #[arg(long, value_enum, default_value = "strict")]
+    attribution: Attribution,
 
-    false
-}
+    /// Keep the precompiled header flags /Yc, /Yu and /Fp, e.g. for tools other than clangd
+    #[arg(long, default_value = "false")]
+    keep_pch_flags: bool,
 
-/// Check if a token is a source file (.c, .cpp, .cc, .cxx)
-fn is_source_file(token: &str) -> bool {
-    // Remove quotes if present
-    let clean_token = token.trim_matches('"');
-    let token_lower = clean_token.to_lowercase();
-    token_lower.ends_with(".c")
-        || token_lower.ends_with(".cpp")
-        || token_lower.ends_with(".cc")
-        || token_lower.ends_with(".cxx")
-}
+    /// Fail without writing the database if any compiler command could not be converted completely
+    #[arg(long, default_value = "false")]
+    strict: bool,
 
-/// Normalize a path by rebuilding it from components
-/// This eliminates double backslashes, redundant separators, and other path anomalies
-fn normalize_path(path: &Path) -> PathBuf {
-    path.components().collect()
-}
+    /// Run everything but write no files; print the entry count, the first entries and the problems found
+    #[arg(long, default_value = "false", conflicts_with = "watch")]
+    dry_run: bool,
 
-/// Convert a PathBuf to a normalized string representation
-fn path_to_normalized_string(path: &Path) -> String {
-    normalize_path(path).display().to_string()
-}
+    /// Write the database even if no compile commands were found, replacing any existing one
+    #[arg(long, default_value = "false")]
+    allow_empty: bool,
 
-/// Clean include path by removing trailing backslashes that can cause
-/// quote-escaping issues in clangd's command-line parser
-///
-/// Examples:
-///   /I"C:\path\to\dir\\" -> /I"C:\path\to\dir"
-///   /I"C:\path\to\dir"   -> /I"C:\path\to\dir" (unchanged)
-///   /IC:\path\to\dir\    -> /IC:\path\to\dir (unquoted form)
-fn clean_include_path(flag: &str) -> String {
-    // Check if this is an include flag
-    let flag_upper = flag.to_uppercase();
-    if !flag_upper.starts_with("/I") {
-        return flag.to_string();
-    }
-
-    // Pattern: /I followed by optional quotes, path, optional trailing backslashes, optional closing quote
-    // Cases to handle:
-    //   /I"C:\path\\"    -> /I"C:\path"
-    //   /IC:\path\       -> /IC:\path
-    //   /I"C:\path"      -> /I"C:\path" (no change)
-
-    if flag.starts_with("/I\"") || flag.starts_with("/i\"") {
-        // Quoted path: /I"path\\"
-        if let Some(end_quote_pos) = flag.rfind('"')
-            && end_quote_pos > 3
-        {
-            // More than just /I"<quote>
-            let prefix = &flag[..3]; // /I"
-            let path = &flag[3..end_quote_pos]; // The actual path
-            let suffix = &flag[end_quote_pos..]; // Closing "
-
-            // Remove trailing backslashes from path
-            // But preserve at least one backslash if it's a root path like "C:\"
-            let cleaned_path = if path.len() == 3 && path.ends_with(":\\") {
-                // Root path like "C:\" - keep it
-                path
-            } else {
-                path.trim_end_matches('\\')
-            };
+    /// Profile from .ms2cc.toml selecting the entry transformers to run (default: "default")
+    #[arg(long)]
+    profile: Option<String>,
 
-            return format!("{}{}{}", prefix, cleaned_path, suffix);
-        }
-    } else if flag.len() > 2 {
-        // Unquoted path: /Ipath\
-        let prefix = &flag[..2]; // /I
-        let path = &flag[2..]; // The path
-
-        // Remove trailing backslashes
-        // But preserve at least one backslash if it's a root path like "C:\"
-        let cleaned_path = if path.len() == 3 && path.ends_with(":\\") {
-            // Root path like "C:\" - keep it
-            path
-        } else {
-            path.trim_end_matches('\\')
-        };
+    /// Only keep entries whose source file matches this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include_path: Vec<String>,
 
-        return format!("{}{}", prefix, cleaned_path);
-    }
+    /// Drop entries whose source file matches this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude_path: Vec<String>,
 
-    // Fallback: return as-is if pattern doesn't match
-    flag.to_string()
-}
+    /// Also include `ninja -t compdb` output from this CMake/Ninja build directory (repeatable)
+    #[arg(long, value_name = "DIR")]
+    ninja_dir: Vec<PathBuf>,
 
-/// Resolve source file path to absolute path
-fn resolve_source_file_path(source_file: &str, working_directory: &Path) -> PathBuf {
-    let file_path = PathBuf::from(source_file.trim_matches('"'));
+    /// Resolve relative sources missing below the project directory with the
+    /// CL.read.*.tlog files below this directory, e.g. the intermediate directory (repeatable)
+    #[arg(long, value_name = "DIR")]
+    tlog_dir: Vec<PathBuf>,
 
-    if file_path.is_absolute() {
-        return file_path;
-    }
+    /// Remove this argument from every command; a trailing `*` matches a prefix
+    #[arg(long, value_name = "FLAG", allow_hyphen_values = true)]
+    strip_flag: Vec<String>,
 
-    // Resolve relative to working directory
-    working_directory.join(&file_path)
-}
+    /// Add this argument to every command, right after the compiler
+    #[arg(long, value_name = "FLAG", allow_hyphen_values = true)]
+    add_flag: Vec<String>,
 
-/// Parse a CL.exe command line and extract compile commands
-/// Returns a vector of CompileCommand (one per source file)
-fn parse_cl_command(
-    line: &str,
-    project_ctx: &ProjectContext,
-    line_number: usize,
-) -> Result<Vec<CompileCommand>> {
-    // Extract the full CL.exe path using regex BEFORE tokenization
-    // This handles both quoted and unquoted paths with spaces:
-    //   Quoted: "C:\Program Files\...\CL.exe"
-    //   Unquoted: C:\Program Files\Microsoft Visual Studio\...\CL.exe
-    // Pattern matches from drive letter to CL.exe, handling spaces in between
-    let cl_exe_regex = regex::Regex::new(r#"(?i)([A-Z]:[^\r\n]*?\\CL\.exe|"[^"]*\\CL\.exe")"#)
-        .context("Failed to compile CL.exe regex")?;
-
-    let cl_exe_match = cl_exe_regex
-        .find(line)
-        .context("CL.exe not found in command line")?
-        .as_str();
-
-    // Remove quotes if present
-    let cl_exe_path = cl_exe_match.trim_matches('"').to_string();
-
-    let tokens = tokenize_command_line(line);
-
-    // Find CL.exe position in tokens to know where arguments start
-    let cl_exe_pos = tokens
-        .iter()
-        .position(|t| t.to_uppercase().contains("CL.EXE"))
-        .context("CL.exe not found in command line")?;
-
-    // Separate source files from flags
-    let mut source_files = Vec::new();
-    let mut filtered_args = Vec::new();
-
-    // Extract tokens (everything after CL.exe)
-    for token in tokens.into_iter().skip(cl_exe_pos + 1) {
-        if is_source_file(&token) {
-            source_files.push(token);
-        } else if !should_filter_flag(&token) {
-            // Clean include paths to remove trailing backslashes
-            let cleaned_token = clean_include_path(&token);
-            filtered_args.push(cleaned_token);
-        }
-    }
+    /// Replace the compiler of every command with this one, e.g. an installed clang-cl.exe
+    #[arg(long, value_name = "PATH")]
+    replace_compiler: Option<String>,
 
-    if source_files.is_empty() {
-        warn!(
-            "No source files found in CL.exe command at line {} for project {}",
-            line_number,
-            project_ctx.project_path.display()
-        );
-        return Ok(Vec::new());
-    }
+    /// Rewrite arguments starting with FROM to start with TO instead
+    #[arg(long, value_name = "FROM=TO", allow_hyphen_values = true)]
+    replace_prefix: Vec<String>,
 
-    // Create one CompileCommand per source file
-    let mut commands = Vec::new();
+    /// Rewrite paths on a subst or network drive to the directory it stands for, e.g. "S:=C:\src" (repeatable)
+    #[arg(long, value_name = "DRIVE:=DIR")]
+    drive_map: Vec<String>,
 
-    // Build the base command string once (combines CL.exe path + filtered args)
-    let base_command = {
-        let cl_exe_token = if cl_exe_path.contains(' ') {
-            format!("\"{}\"", cl_exe_path)
-        } else {
-            cl_exe_path
-        };
-        let mut parts = vec![cl_exe_token];
-        parts.extend(filtered_args);
-        parts.join(" ")
-    };
+    /// Set "file" of generated sources to the file named by their first #line directive; the command and "output" keep the generated one
+    #[arg(long)]
+    map_generated_sources: bool,
 
-    for source_file in source_files {
-        // Resolve source file to absolute path
-        let absolute_file_path = resolve_source_file_path(&source_file, &project_ctx.project_dir);
+    /// Point source files below FROM at the same path below TO, keeping the original as "output" (repeatable)
+    #[arg(long, value_name = "FROM=TO")]
+    remap: Vec<String>,
 
-        // Normalize paths to eliminate double backslashes and other anomalies
-        let normalized_file = path_to_normalized_string(&absolute_file_path);
-        let normalized_directory = path_to_normalized_string(&project_ctx.project_dir);
+    /// Rewrite paths starting with FROM to start with TO, e.g. "C:\src=>/mnt/c/src" (repeatable)
+    #[arg(long, value_name = "FROM=>TO")]
+    path_map: Vec<String>,
 
-        // Reconstruct command with base command + normalized absolute source file path
-        let command = format!("{} \"{}\"", base_command, normalized_file);
+    /// Write "file" paths relative to this directory; "directory" stays absolute
+    #[arg(long, value_name = "DIR", conflicts_with = "directory_mode")]
+    base_dir: Option<PathBuf>,
 
-        commands.push(CompileCommand {
-            directory: normalized_directory,
-            command,
-            file: normalized_file,
-        });
-    }
+    /// "directory" of each entry: project, source-root or fixed:<path>; with the
+    /// last two, "file" is written relative to it
+    #[arg(long, value_name = "MODE")]
+    directory_mode: Option<String>,
 
-    trace!(
-        "Parsed {} compile command(s) from line {} for project {}",
-        commands.len(),
-        line_number,
-        project_ctx.project_path.display()
-    );
+    /// Separator to use in the paths of every entry
+    #[arg(long, value_enum)]
+    path_style: Option<PathStyle>,
 
-    Ok(commands)
-}
+    /// Write every problem found in the log, with its line number, project and raw line, to this JSON file
+    #[arg(long, value_name = "FILE")]
+    error_report: Option<PathBuf>,
 
-// ----------------------------------------------------------------------------
-// Regular Expression Patterns
-// ----------------------------------------------------------------------------
+    /// Pipe entries as NDJSON through this shell command and keep what it prints back
+    #[arg(long, value_name = "PROGRAM")]
+    filter_cmd: Option<String>,
 
-/// Pattern to match node prefix (e.g., "7>" or "7:2>" at start of line)
-/// Used to track the current build node in parallel builds
-/// Handles both simple prefixes (7>) and multi-instance prefixes (7:2>)
-fn node_prefix_pattern() -> Result<Regex> {
-    let pattern = r"^\s*(\d+)(?::\d+)?>";
-    debug!("Compiling node prefix regex: {}", pattern);
-    Regex::new(pattern).context("Failed to compile node prefix regex")
-}
+    /// Compiler executables to convert, comma-separated (e.g. cl.exe,clang-cl.exe); a path end like HostX64\x64\cl.exe picks one toolchain
+    #[arg(long, value_delimiter = ',', default_value = "cl.exe")]
+    compiler_executable: Vec<String>,
 
-/// Pattern to match "Project X on node N" (parallel builds)
-/// Example: 5>Project "S:\Acme\...\Project.vcxproj" on node 4 (Build target(s)).
-/// Also handles multi-instance prefixes like 5:2>Project ...
-/// Captures the OUTPUT PREFIX (5 or 5:2) and PROJECT PATH, not the physical node number
-fn project_on_node_pattern() -> Result<Regex> {
-    let pattern = r#"^\s*(\d+)(?::\d+)?>Project "([^"]+\.vcxproj)" on node \d+"#;
-    debug!("Compiling project-on-node regex: {}", pattern);
-    Regex::new(pattern).context("Failed to compile project-on-node regex")
-}
+    /// Also add entries for these tools' sources, comma-separated (e.g. rc.exe,midl.exe,ml64.exe)
+    #[arg(long, value_delimiter = ',', value_name = "TOOLS")]
+    extra_tools: Vec<String>,
 
-/// Pattern to match nested "Project X is building Y on node N" (parallel builds with dependencies)
-/// Example: 44>Project "Parent.proj" (44) is building "Child.vcxproj" (54) on node 13 (default targets).
-/// Also handles multi-instance notation: 44:2>Project "..." (44:2) is building "..." (54:3) on node 13
-/// Captures the CHILD PROJECT PATH and CHILD OUTPUT PREFIX (base number only, e.g., 54 from 54:3)
-fn nested_project_pattern() -> Result<Regex> {
-    let pattern = r#"^\s*\d+(?::\d+)?>Project "[^"]*" \([^\)]+\) is building "([^"]+\.vcxproj)" \((\d+)(?::\d+)?\) on node \d+"#;
-    debug!("Compiling nested-project regex: {}", pattern);
-    Regex::new(pattern).context("Failed to compile nested-project regex")
-}
+    /// Regex of a decoration in front of every log line to remove (default: a ShowTimestamp time)
+    #[arg(long, value_name = "REGEX")]
+    line_prefix_regex: Option<String>,
 
-/// Pattern to match "from project X" (sequential builds)
-/// Example: Target "ClCompile" ... from project "C:\...\Project.vcxproj"
-fn from_project_pattern() -> Result<Regex> {
-    let pattern = r#"from project "([^"]+\.vcxproj)""#;
-    debug!("Compiling from-project regex: {}", pattern);
-    Regex::new(pattern).context("Failed to compile from-project regex")
-}
+    /// Keep `@file.rsp` arguments as they are instead of reading the response files
+    #[arg(long, default_value = "false")]
+    no_rsp_expansion: bool,
 
-/// Pattern to match CL.exe compilation commands
-/// Matches lines containing CL.exe followed by arguments
-fn compile_command_pattern() -> Result<Regex> {
-    let pattern = r"(?i)^\s+.*CL\.exe\s";
-    debug!("Compiling CL.exe command regex: {}", pattern);
-    Regex::new(pattern).context("Failed to compile CL.exe command regex")
-}
+    /// Also write the link.exe and lib.exe invocations in the log to this JSON file
+    #[arg(long, value_name = "FILE")]
+    link_commands: Option<PathBuf>,
 
-// ----------------------------------------------------------------------------
-// Log Processing Helper Functions
-// ----------------------------------------------------------------------------
+    /// Replace %VAR% references in commands with the environment logged at the start of the build
+    #[arg(long, default_value = "false")]
+    expand_env: bool,
 
-/// Setup and configure the progress bar for reading the build log
-fn setup_read_progress_bar(
-    show_progress: bool,
-    file_size: u64,
-    multi: &MultiProgress,
-) -> Result<ProgressBar> {
-    if show_progress {
-        let pb = multi.add(ProgressBar::new(file_size));
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} {msg}")?
-                .progress_chars("=> "),
-        );
-        pb.set_message("Processing build log...");
-        Ok(pb)
-    } else {
-        Ok(ProgressBar::hidden())
-    }
-}
+    /// Write the environment logged for each project directory to this JSON file
+    #[arg(long, value_name = "FILE")]
+    environment_file: Option<PathBuf>,
 
-/// Setup and configure the spinner progress bar for writing output
-fn setup_write_progress_bar(show_progress: bool, multi: &MultiProgress) -> Result<ProgressBar> {
-    if show_progress {
-        let pb = multi.add(ProgressBar::new_spinner());
-        pb.set_style(
-            ProgressStyle::default_spinner()
-                .template("[{elapsed_precise}] {spinner:.cyan} {bytes} {msg}")?
-                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "),
-        );
-        pb.set_message("Writing output...");
-        pb.enable_steady_tick(Duration::from_millis(100));
-        Ok(pb)
-    } else {
-        Ok(ProgressBar::hidden())
-    }
-}
+    /// Add every entry's object file as the "output" field, also when /Fo names a directory or is missing
+    #[arg(long, default_value = "false")]
+    emit_output: bool,
 
-/// Finalize processing and log summary information
-fn finalize_processing(state: &ProcessingState, pb: ProgressBar, start_time: Instant) {
-    pb.finish_and_clear();
-
-    let duration = start_time.elapsed();
-
-    debug!(
-        "Found {} projects across {} output prefixes",
-        state.prefix_to_project.len()
-            + if state.current_project.is_some() {
-                1
-            } else {
-                0
-            },
-        state.prefix_to_project.len()
-    );
+    /// Add entries for headers below the project directories, using the flags of a nearby source file
+    #[arg(long, default_value = "false")]
+    emit_header_entries: bool,
 
-    info!(
-        "Processing complete: {} compile commands found in {:.2}s",
-        state.command_count,
-        duration.as_secs_f64()
-    );
+    /// What to do with several entries for the same source file
+    #[arg(long, value_enum, default_value = "all")]
+    dedupe: Dedupe,
 
-    if state.prefix_to_project.is_empty() && state.current_project.is_none() {
-        warn!(
-            "No projects found in build log - ensure MSBuild was run with /v:detailed or /v:diagnostic"
-        );
-    }
+    /// Form of the command in each entry
+    #[arg(long, value_enum, default_value = "command")]
+    output_style: OutputStyle,
 
-    if !state.prefix_to_project.is_empty() && state.command_count == 0 {
-        warn!(
-            "Found {} output prefixes with projects but no compile commands - build log may be incomplete",
-            state.prefix_to_project.len()
-        );
-    }
-}
+    /// Write a compilation database, or a single compile_flags.txt (default output name changes to match)
+    #[arg(long, value_enum, default_value = "json")]
+    format: OutputFormat,
 
-/// Handle node prefix pattern (e.g., "7>")
-fn handle_node_prefix(line: &str, pattern: &Regex, state: &mut ProcessingState) {
-    if let Some(caps) = pattern.captures(line)
-        && let Ok(prefix_num) = caps[1].parse::<u32>()
-    {
-        state.current_prefix = Some(prefix_num);
-    }
-}
+    /// Order of the entries written, so that the same logs give the same file
+    #[arg(long, value_enum, default_value = "file")]
+    sort: SortOrder,
 
-/// Handle "Project X on node N" pattern (parallel builds)
-fn handle_project_on_node(
-    line: &str,
-    pattern: &Regex,
-    state: &mut ProcessingState,
-    line_number: usize,
-) -> Result<()> {
-    if let Some(caps) = pattern.captures(line) {
-        let prefix_num = caps[1]
-            .parse::<u32>()
-            .context("Failed to parse output prefix")?;
-        let project_path = PathBuf::from(&caps[2]);
-        let project_dir = project_path
-            .parent()
-            .map(|p| p.to_path_buf())
-            .unwrap_or_else(|| PathBuf::from("."));
-
-        let ctx = ProjectContext {
-            project_path: project_path.clone(),
-            project_dir,
-        };
+    /// With --format compile-flags, keep the flags of every entry or only those all entries share
+    #[arg(long, value_enum, default_value = "intersection")]
+    flag_set: FlagSet,
 
-        trace!(
-            "Assigned project {} to output prefix {} at line {}",
-            project_path.display(),
-            prefix_num,
-            line_number
-        );
+    /// Write C++20 module dependencies (P1689 format) for module entries to this file
+    #[arg(long, value_name = "FILE")]
+    p1689_file: Option<PathBuf>,
 
-        state.prefix_to_project.insert(prefix_num, ctx.clone());
-        // Also update current_project as fallback for sequential builds
-        state.current_project = Some(ctx);
-    }
-    Ok(())
-}
+    /// Write a .clangd fragment with the target and MSVC version of the CL.exe in the log
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = ".clangd")]
+    emit_clangd_config: Option<PathBuf>,
 
-/// Handle nested "Project X is building Y on node N" pattern
-fn handle_nested_project(
-    line: &str,
-    pattern: &Regex,
-    state: &mut ProcessingState,
-    line_number: usize,
-) -> Result<()> {
-    if let Some(caps) = pattern.captures(line) {
-        let project_path = PathBuf::from(&caps[1]);
-        let prefix_num = caps[2]
-            .parse::<u32>()
-            .context("Failed to parse nested project output prefix")?;
-        let project_dir = project_path
-            .parent()
-            .map(|p| p.to_path_buf())
-            .unwrap_or_else(|| PathBuf::from("."));
-
-        let ctx = ProjectContext {
-            project_path: project_path.clone(),
-            project_dir,
-        };
+    /// Keep running and regenerate the output whenever an input log changes
+    #[arg(long, default_value = "false")]
+    watch: bool,
 
-        trace!(
-            "Assigned nested project {} to output prefix {} at line {}",
-            project_path.display(),
-            prefix_num,
-            line_number
-        );
+    /// With --watch, also regenerate when files below this directory change (repeatable)
+    #[arg(long, value_name = "DIR", requires = "watch")]
+    watch_dir: Vec<PathBuf>,
 
-        state.prefix_to_project.insert(prefix_num, ctx.clone());
-        // Also update current_project as fallback
-        state.current_project = Some(ctx);
-    }
-    Ok(())
+    /// Run clang-scan-deps for module entries without dependency files in the log
+    #[arg(long, default_value = "false", requires = "p1689_file")]
+    scan_deps: bool,
 }
 
-/// Handle "from project X" pattern (sequential builds)
-fn handle_from_project(
-    line: &str,
-    pattern: &Regex,
-    state: &mut ProcessingState,
-    line_number: usize,
-) {
-    if let Some(caps) = pattern.captures(line) {
-        let project_path = PathBuf::from(&caps[1]);
-        let project_dir = project_path
-            .parent()
-            .map(|p| p.to_path_buf())
-            .unwrap_or_else(|| PathBuf::from("."));
-
-        let ctx = ProjectContext {
-            project_path: project_path.clone(),
-            project_dir,
-        };
-
-        trace!(
-            "Set current project to {} at line {}",
-            project_path.display(),
-            line_number
-        );
-
-        state.current_project = Some(ctx);
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Inspect the build log and environment and report likely problems
+    Doctor {
+        /// Full path to msbuild.log file
+        #[arg(short = 'i', long, default_value = "msbuild.log")]
+        input_file: PathBuf,
+
+        /// Path to output compile_commands.json file
+        #[arg(short = 'o', long, default_value = "compile_commands.json")]
+        output_file: PathBuf,
+    },
+
+    /// Fail if the database is missing files that a baseline database covered
+    Guard {
+        /// Previously accepted compile_commands.json to compare against
+        #[arg(long, value_name = "FILE")]
+        baseline: PathBuf,
+
+        /// Newly generated compile_commands.json to check
+        #[arg(short = 'o', long, default_value = "compile_commands.json")]
+        output_file: PathBuf,
+    },
+
+    /// Compare two databases: files added and removed, and flag changes per file
+    Diff {
+        /// Database before the change
+        old: PathBuf,
+
+        /// Database after the change
+        new: PathBuf,
+
+        /// Fail if the databases differ
+        #[arg(long, default_value = "false")]
+        exit_code: bool,
+    },
+
+    /// Print the entries of a database for a source file
+    Query {
+        /// Source file to look up; a similar path or the same file name is
+        /// matched if no entry has exactly this one
+        #[arg(long)]
+        file: String,
+
+        /// compile_commands.json to search
+        #[arg(long, default_value = "compile_commands.json")]
+        database: PathBuf,
+    },
+
+    /// Check a database for format errors, missing files and flags clangd cannot use
+    Validate {
+        /// compile_commands.json to check
+        #[arg(default_value = "compile_commands.json")]
+        database: PathBuf,
+
+        /// Do not check that the source files exist, e.g. for a database from another machine
+        #[arg(long, default_value = "false")]
+        no_file_check: bool,
+    },
+
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
+    /// Print the man page (roff format) to stdout
+    Man,
 }
 
-/// Handle CL.exe compilation command
-fn handle_cl_command(
-    line: &str,
-    pattern: &Regex,
-    state: &ProcessingState,
-    line_number: usize,
-) -> Result<Vec<CompileCommand>> {
-    if !pattern.is_match(line) {
-        return Ok(Vec::new());
-    }
-
-    // Determine which project this command belongs to
-    let project_ctx = state.get_active_project();
-
-    if let Some(proj_ctx) = project_ctx {
-        match parse_cl_command(line, proj_ctx, line_number) {
-            Ok(commands) => Ok(commands),
-            Err(e) => {
-                error!(
-                    "Failed to parse CL.exe command at line {}: {:?}",
-                    line_number, e
-                );
-                Ok(Vec::new())
-            }
-        }
-    } else {
-        warn!(
-            "Found CL.exe command at line {} but no project context available",
-            line_number
-        );
-        Ok(Vec::new())
-    }
+/// Write a completion script for `shell` to stdout
+fn print_completions(shell: Shell) {
+    let mut command = Args::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
 }
 
-/// Process the MSBuild log file. Tracks projects per output prefix for parallel
-/// builds and uses context markers for sequential builds
-fn process_msbuild_log(
-    input_file: &Path,
-    patterns: LogPatterns,
-    show_progress: bool,
-    multi: &MultiProgress,
-) -> Result<Vec<CompileCommand>> {
-    let mut compile_commands = Vec::new();
-    let mut state = ProcessingState::new();
-
-    info!("Starting MSBuild log processing");
-    let start_time = Instant::now();
-
-    // Open file and get size for progress tracking
-    let file = File::open(input_file)
-        .with_context(|| format!("Failed to open input file: {}", input_file.display()))?;
-    let file_size = file.metadata()?.len();
-
-    // Create progress bar
-    let pb = setup_read_progress_bar(show_progress, file_size, multi)?;
-
-    // Wrap file with progress tracking
-    let progress_reader = pb.wrap_read(file);
-    let input = BufReader::new(progress_reader);
-
-    // Single-pass processing
-    for (index, line_result) in input.lines().enumerate() {
-        let line_number = index + 1;
-
-        let line = match line_result {
-            Ok(l) => l,
-            Err(e) => {
-                warn!("Failed to read line {}: {:?}", line_number, e);
-                continue;
-            }
-        };
-
-        // Process each pattern type
-        handle_node_prefix(&line, &patterns.node_prefix, &mut state);
-
-        if let Err(e) =
-            handle_project_on_node(&line, &patterns.project_on_node, &mut state, line_number)
-        {
-            error!(
-                "Failed to process project-on-node at line {}: {:?}",
-                line_number, e
-            );
-        }
-
-        if let Err(e) =
-            handle_nested_project(&line, &patterns.nested_project, &mut state, line_number)
-        {
-            error!(
-                "Failed to process nested project at line {}: {:?}",
-                line_number, e
-            );
-        }
+/// Render the man page to stdout
+fn print_man_page() -> Result<()> {
+    clap_mangen::Man::new(Args::command())
+        .render(&mut std::io::stdout())
+        .context("Failed to render man page")
+}
 
-        handle_from_project(&line, &patterns.from_project, &mut state, line_number);
+/// The logs to read: the `--input-file` values, or the `*.log` files in
+/// `--input-dir` sorted by name
+fn input_files(args: &Args) -> Result<Vec<PathBuf>> {
+    let Some(dir) = &args.input_dir else {
+        return Ok(args.input_file.clone());
+    };
 
-        match handle_cl_command(&line, &patterns.compile_command, &state, line_number) {
-            Ok(commands) => {
-                state.command_count += commands.len();
-                compile_commands.extend(commands);
-            }
-            Err(e) => {
-                error!(
-                    "Failed to handle CL command at line {}: {:?}",
-                    line_number, e
-                );
-            }
+    let mut logs = Vec::new();
+    for entry in std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read input directory: {}", dir.display()))?
+    {
+        let path = entry?.path();
+        let is_log = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("log"));
+        if is_log && path.is_file() {
+            logs.push(path);
         }
     }
+    logs.sort();
 
-    finalize_processing(&state, pb, start_time);
-
-    Ok(compile_commands)
-}
-
-/// Create a temporary file in the same directory as the output file.
-/// This validates that the output directory is writable before we begin parsing.
-/// The temp file auto-deletes on drop if not persisted.
-fn create_temp_output_file(output_path: &Path) -> Result<NamedTempFile> {
-    let parent = output_path.parent().unwrap_or(Path::new("."));
-    debug!("Creating temporary output file in: {}", parent.display());
-    NamedTempFile::new_in(parent)
-        .with_context(|| format!("Failed to create temporary file in: {}", parent.display()))
+    if logs.is_empty() {
+        anyhow::bail!("No *.log files found in {}", dir.display());
+    }
+    info!("Reading {} log(s) from {}", logs.len(), dir.display());
+    Ok(logs)
 }
 
-/// Load an existing compile_commands.json database for merging.
-/// Returns an empty Vec if the file doesn't exist or can't be parsed.
-fn load_existing_database(path: &Path) -> Result<Vec<CompileCommand>> {
-    if !path.exists() {
-        debug!("No existing database at {}", path.display());
-        return Ok(Vec::new());
+/// Locate and load the .ms2cc.toml config file, honoring --config and --no-config
+fn load_config(args: &Args) -> Result<Config> {
+    if args.no_config {
+        debug!("Config file discovery disabled");
+        return Ok(Config::default());
     }
 
-    debug!("Loading existing database: {}", path.display());
-    let file = File::open(path)
-        .with_context(|| format!("Failed to open existing database: {}", path.display()))?;
-    let reader = BufReader::new(file);
+    let path = match &args.config {
+        Some(path) => Some(path.clone()),
+        None => config::discover(
+            &std::env::current_dir().context("Failed to determine current directory")?,
+        ),
+    };
 
-    match serde_json::from_reader(reader) {
-        Ok(commands) => Ok(commands),
-        Err(e) => {
-            warn!(
-                "Failed to parse existing database ({}), starting fresh: {}",
-                path.display(),
-                e
-            );
-            Ok(Vec::new())
+    match path {
+        Some(path) => {
+            info!("Using configuration from {}", path.display());
+            config::load(&path)
         }
+        None => Ok(Config::default()),
     }
 }
 
-/// Merge new compile commands into an existing database.
-/// Entries are keyed by (file, directory). New entries replace existing ones with the
-/// same key; entries not present in the new set are preserved unchanged.
-fn merge_compile_commands(
-    existing: Vec<CompileCommand>,
-    new_entries: Vec<CompileCommand>,
-) -> Vec<CompileCommand> {
-    let mut map: IndexMap<(String, String), CompileCommand> =
-        IndexMap::with_capacity(existing.len() + new_entries.len());
+/// Check whether an argument was given explicitly on the command line
+fn is_explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
 
-    for entry in existing {
-        let key = (entry.file.clone(), entry.directory.clone());
-        map.insert(key, entry);
+/// Fill in arguments that were left at their defaults from the config file
+fn apply_config(args: &mut Args, config: &Config, matches: &ArgMatches) {
+    if let Some(input_file) = &config.input_file
+        && !is_explicit(matches, "input_file")
+    {
+        args.input_file = vec![input_file.clone()];
     }
-
-    let mut updated_count = 0usize;
-    let mut added_count = 0usize;
-
-    for entry in new_entries {
-        let key = (entry.file.clone(), entry.directory.clone());
-        if map.contains_key(&key) {
-            updated_count += 1;
-        } else {
-            added_count += 1;
-        }
-        map.insert(key, entry);
+    if let Some(output_file) = &config.output_file
+        && !is_explicit(matches, "output_file")
+    {
+        args.output_file = output_file.clone();
+    }
+    if let Some(pretty_print) = config.pretty_print
+        && !is_explicit(matches, "pretty_print")
+    {
+        args.pretty_print = pretty_print;
+    }
+    if let Some(overwrite) = config.overwrite
+        && !is_explicit(matches, "overwrite")
+    {
+        args.overwrite = overwrite;
+    }
+    if let Some(no_progress) = config.no_progress
+        && !is_explicit(matches, "no_progress")
+    {
+        args.no_progress = no_progress;
+    }
+    if !is_explicit(matches, "include_path") {
+        args.include_path = config.include.clone();
+    }
+    if !is_explicit(matches, "exclude_path") {
+        args.exclude_path = config.exclude.clone();
     }
-
-    info!(
-        "Merge result: {} updated, {} added, {} total",
-        updated_count,
-        added_count,
-        map.len()
-    );
-
-    map.into_values().collect()
 }
 
 fn run() -> Result<()> {
-    let args = Args::parse();
-
-    // Determine if progress bar should be shown
-    // Disable only if --no-progress flag is set or output is not a TTY
-    let show_progress = !args.no_progress && atty::is(atty::Stream::Stderr);
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // Generators write to stdout and must not be mixed with log output
+    match args.command {
+        Some(Command::Completions { shell }) => {
+            print_completions(shell);
+            return Ok(());
+        }
+        Some(Command::Man) => return print_man_page(),
+        _ => {}
+    }
 
     // Create MultiProgress for coordinating progress bars and logging
     let multi = MultiProgress::new();
 
     // Initialize logging with indicatif-log-bridge
-    let config = ConfigBuilder::new()
+    let log_config = ConfigBuilder::new()
         .set_target_level(LevelFilter::Off)
         .set_thread_level(LevelFilter::Off)
         .build();
@@ -855,7 +552,7 @@ fn run() -> Result<()> {
 
     let logger = TermLogger::new(
         log_level_filter,
-        config,
+        log_config,
         TerminalMode::Mixed,
         ColorChoice::Auto,
     );
@@ -866,881 +563,605 @@ fn run() -> Result<()> {
 
     info!("ms2cc v{} - {}", PACKAGE_VERSION, PACKAGE_DESCRIPTION);
 
-    // Create a temp file in the output directory to validate writability before parsing.
-    // The temp file auto-deletes on drop if we don't persist it.
-    let temp_file = create_temp_output_file(&args.output_file)?;
-
-    // Load existing database for merging (unless --overwrite is set)
-    let existing = if args.overwrite {
-        info!("Overwrite mode: existing database will be replaced");
-        Vec::new()
-    } else {
-        let loaded = load_existing_database(&args.output_file)?;
-        if !loaded.is_empty() {
-            info!(
-                "Loaded {} existing entries from {}",
-                loaded.len(),
-                args.output_file.display()
-            );
-        }
-        loaded
-    };
-
-    // Process the MSBuild log file
-    let patterns = LogPatterns::new()?;
-    let new_commands = process_msbuild_log(&args.input_file, patterns, show_progress, &multi)?;
-
-    // Merge or replace
-    let compile_commands = if existing.is_empty() {
-        new_commands
-    } else {
-        merge_compile_commands(existing, new_commands)
-    };
-
-    // Write JSON output to the temp file
-    info!(
-        "Writing {} commands to {}",
-        compile_commands.len(),
-        args.output_file.display()
-    );
+    if let Some(Command::Doctor {
+        input_file,
+        output_file,
+    }) = &args.command
+    {
+        return subcommands::doctor(input_file, output_file);
+    }
 
-    // Create progress spinner for write operation if enabled
-    let write_pb = setup_write_progress_bar(show_progress, &multi)?;
+    if let Some(Command::Guard {
+        baseline,
+        output_file,
+    }) = &args.command
+    {
+        return subcommands::guard(baseline, output_file);
+    }
 
-    let output = BufWriter::new(temp_file.as_file());
-    let progress_writer = write_pb.wrap_write(output);
+    if let Some(Command::Diff {
+        old,
+        new,
+        exit_code,
+    }) = &args.command
+    {
+        return subcommands::diff(old, new, *exit_code);
+    }
 
-    if args.pretty_print {
-        serde_json::to_writer_pretty(progress_writer, &compile_commands)
-            .context("Failed to write JSON output")?;
-    } else {
-        serde_json::to_writer(progress_writer, &compile_commands)
-            .context("Failed to write JSON output")?;
+    if let Some(Command::Query { file, database }) = &args.command {
+        return subcommands::query(database, file);
     }
 
-    write_pb.finish_and_clear();
+    if let Some(Command::Validate {
+        database,
+        no_file_check,
+    }) = &args.command
+    {
+        return subcommands::validate(database, !no_file_check);
+    }
 
-    // Atomically replace the output file now that writing succeeded
-    temp_file.persist(&args.output_file).with_context(|| {
-        format!(
-            "Failed to persist output file: {}",
-            args.output_file.display()
-        )
-    })?;
+    // Apply repository-local defaults; explicit command-line values win
+    let config = load_config(&args)?;
+    apply_config(&mut args, &config, &matches);
+    if args.format == OutputFormat::CompileFlags
+        && config.output_file.is_none()
+        && !is_explicit(&matches, "output_file")
+    {
+        args.output_file = PathBuf::from(compile_flags::FILE_NAME);
+    }
+    if args.input_format == InputFormat::Ninja
+        && config.input_file.is_none()
+        && !is_explicit(&matches, "input_file")
+    {
+        args.input_file = vec![PathBuf::from(".")];
+    }
 
-    info!("Finished");
+    if !args.watch {
+        return generate(&args, &config, &multi);
+    }
 
-    Ok(())
+    // Regenerate on every change to the logs or the --watch-dir sources
+    let mut watch = Watch::default();
+    match &args.input_dir {
+        Some(dir) => watch.dir(dir, false)?,
+        None => {
+            for input_file in &args.input_file {
+                if input::is_stdin(input_file) {
+                    anyhow::bail!("--watch cannot read the log from stdin");
+                }
+                match args.input_format {
+                    InputFormat::Msbuild => watch.file(input_file)?,
+                    InputFormat::Ninja => watch.file(&input_file.join("build.ninja"))?,
+                }
+            }
+        }
+    }
+    for dir in &args.watch_dir {
+        watch.dir(dir, true)?;
+    }
+    for written in [
+        Some(&args.output_file),
+        args.stats_file.as_ref(),
+        args.p1689_file.as_ref(),
+        args.link_commands.as_ref(),
+        args.environment_file.as_ref(),
+        args.error_report.as_ref(),
+        args.dropped_sources.as_ref(),
+        args.emit_clangd_config.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        watch.ignore(written)?;
+    }
+    if args.backup {
+        watch.ignore(&backup_path(&args.output_file))?;
+    }
+    watch.run(|| generate(&args, &config, &multi))
 }
 
-// ----------------------------------------------------------------------------
-// Main entry point
-// ----------------------------------------------------------------------------
+/// Where `--backup` keeps the previous output: the same name plus `.bak`
+fn backup_path(output_file: &Path) -> PathBuf {
+    let mut backup = OsString::from(output_file.as_os_str());
+    backup.push(".bak");
+    PathBuf::from(backup)
+}
 
-fn main() -> Result<()> {
-    if let Err(e) = run() {
-        error!("Application error: {:?}", e);
-        std::process::exit(1);
+/// Output file of one configuration with `--split-by-config`, e.g.
+/// `compile_commands.Debug.json`; entries without one go to `output_file`
+fn configuration_output_path(output_file: &Path, configuration: Option<&str>) -> PathBuf {
+    let Some(configuration) = configuration else {
+        return output_file.to_path_buf();
     };
+    let mut name = output_file.file_stem().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(configuration);
+    if let Some(extension) = output_file.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    output_file.with_file_name(name)
+}
 
-    Ok(())
+/// Load the database at `path` to merge new entries into, with its files
+/// made absolute again if `--base-dir` made them relative
+fn load_existing(path: &Path, base_dir: Option<&Path>) -> Result<Vec<CompileCommand>> {
+    let mut loaded = load_existing_database(path)?;
+    if let Some(base_dir) = base_dir {
+        for entry in &mut loaded {
+            entry.absolutize_file(base_dir);
+        }
+    }
+    if !loaded.is_empty() {
+        info!(
+            "Loaded {} existing entries from {}",
+            loaded.len(),
+            path.display()
+        );
+    }
+    Ok(loaded)
 }
 
-// ----------------------------------------------------------------------------
-// Tests
-// ----------------------------------------------------------------------------
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    // ----------------------------------------------------------------------------
-    // Tests for regex patterns
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_node_prefix_pattern() {
-        let re = node_prefix_pattern().unwrap();
-
-        assert!(re.is_match("4>Project ..."));
-        assert!(re.is_match("  7>Something"));
-        assert!(re.is_match("123>Build"));
-        assert!(!re.is_match("Project without prefix"));
-
-        // Extract node number
-        let caps = re.captures("  4>Project").unwrap();
-        assert_eq!(&caps[1], "4");
-
-        // Test multi-instance prefix notation (e.g., "53:20>")
-        assert!(re.is_match("53:20>Project ..."));
-        assert!(re.is_match("  7:2>Something"));
-        let caps = re.captures("53:20>ClCompile").unwrap();
-        assert_eq!(&caps[1], "53"); // Should capture base number only
-    }
-
-    #[test]
-    fn test_project_on_node_pattern() {
-        let re = project_on_node_pattern().unwrap();
-
-        let line1 = r#"4>Project "C:\path\to\project.vcxproj" on node 3 (Build target(s))."#;
-        let caps = re.captures(line1).expect("Should match");
-        assert_eq!(&caps[1], "4"); // Output prefix
-        assert_eq!(&caps[2], r#"C:\path\to\project.vcxproj"#); // Project path
-
-        let line2 = r#"  7>Project "S:\My Project\test.vcxproj" on node 12 (default targets)."#;
-        let caps = re.captures(line2).expect("Should match path with spaces");
-        assert_eq!(&caps[1], "7"); // Output prefix
-        assert_eq!(&caps[2], r#"S:\My Project\test.vcxproj"#); // Project path
-
-        // Test multi-instance prefix notation
-        let line3 = r#"  53:20>Project "S:\Azure\test.vcxproj" on node 30 (default targets)."#;
-        let caps = re
-            .captures(line3)
-            .expect("Should match multi-instance prefix");
-        assert_eq!(&caps[1], "53"); // Base prefix number
-        assert_eq!(&caps[2], r#"S:\Azure\test.vcxproj"#); // Project path
-    }
-
-    #[test]
-    fn test_nested_project_pattern() {
-        let re = nested_project_pattern().unwrap();
-
-        let line1 = r#"    44>Project "S:\Acme\corp\src\foo\baz.proj" (44) is building "S:\Acme\corp\src\foo\bar.vcxproj" (54) on node 13 (default targets)."#;
-        let caps = re
-            .captures(line1)
-            .expect("Should match nested project pattern");
-        assert_eq!(&caps[1], r#"S:\Acme\corp\src\foo\bar.vcxproj"#); // Child project path
-        assert_eq!(&caps[2], "54"); // Child output prefix
-
-        // Another example with spaces
-        let line2 = r#"  10>Project "C:\Parent.proj" (10) is building "C:\My Projects\Child.vcxproj" (25) on node 5 (Build target(s))."#;
-        let caps = re.captures(line2).expect("Should match nested with spaces");
-        assert_eq!(&caps[1], r#"C:\My Projects\Child.vcxproj"#); // Child project path
-        assert_eq!(&caps[2], "25"); // Child output prefix
-
-        // Test multi-instance prefix notation
-        let line3 = r#" 53:20>Project "S:\Azure\Parent.csproj" (53:20) is building "S:\Azure\XStoreUlsNative.vcxproj" (246) on node 30 (default targets)."#;
-        let caps = re
-            .captures(line3)
-            .expect("Should match multi-instance parent prefix");
-        assert_eq!(&caps[1], r#"S:\Azure\XStoreUlsNative.vcxproj"#); // Child project path
-        assert_eq!(&caps[2], "246"); // Child output prefix (base number)
-
-        // Test multi-instance for both parent and child
-        let line4 = r#" 264:105>Project "S:\Azure\Parent.vcxproj" (264:105) is building "S:\Azure\Child.vcxproj" (266:5) on node 28 (BuiltProjectOutputGroup target(s))."#;
-        let caps = re
-            .captures(line4)
-            .expect("Should match multi-instance both prefixes");
-        assert_eq!(&caps[1], r#"S:\Azure\Child.vcxproj"#); // Child project path
-        assert_eq!(&caps[2], "266"); // Child output prefix (base number only)
-    }
-
-    #[test]
-    fn test_from_project_pattern() {
-        let re = from_project_pattern().unwrap();
-
-        let line1 = r#"Target "ClCompile" from project "C:\path\to\project.vcxproj""#;
-        let caps = re.captures(line1).expect("Should match");
-        assert_eq!(&caps[1], r#"C:\path\to\project.vcxproj"#);
-
-        let line2 = r#"  Some text from project "D:\My Projects\test.vcxproj" more text"#;
-        let caps = re.captures(line2).expect("Should match path with spaces");
-        assert_eq!(&caps[1], r#"D:\My Projects\test.vcxproj"#);
-    }
-
-    #[test]
-    fn test_cl_exe_regex() {
-        let re = compile_command_pattern().unwrap();
-
-        assert!(re.is_match(r#"  CL.exe /c /I"include" main.cpp"#));
-        assert!(re.is_match(r#"    C:\Program Files\MSVC\bin\CL.exe /nologo"#));
-        assert!(!re.is_match(r#"CL.exe"#)); // No space after CL.exe
-        assert!(!re.is_match(r#"Link.exe /OUT:test.exe"#));
-    }
-
-    // ----------------------------------------------------------------------------
-    // Tests for argument tokenization and command parsing
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_tokenize_simple() {
-        let tokens = tokenize_command_line(r#"cl.exe /c main.cpp"#);
-        assert_eq!(tokens, vec!["cl.exe", "/c", "main.cpp"]);
-    }
-
-    #[test]
-    fn test_tokenize_quoted() {
-        let tokens = tokenize_command_line(r#"cl.exe /I"C:\Program Files\include" main.cpp"#);
-        assert_eq!(
-            tokens,
-            vec!["cl.exe", r#"/I"C:\Program Files\include""#, "main.cpp"]
+/// Setup and configure the spinner progress bar for writing output
+fn setup_write_progress_bar(show_progress: bool, multi: &MultiProgress) -> Result<ProgressBar> {
+    if show_progress {
+        let pb = multi.add(ProgressBar::new_spinner());
+        pb.set_style(
+            ProgressStyle::default_spinner()
+                .template("[{elapsed_precise}] {spinner:.cyan} {bytes} {msg}")?
+                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ "),
         );
+        pb.set_message("Writing output...");
+        pb.enable_steady_tick(Duration::from_millis(100));
+        Ok(pb)
+    } else {
+        Ok(ProgressBar::hidden())
     }
+}
 
-    #[test]
-    fn test_tokenize_multiple_spaces() {
-        let tokens = tokenize_command_line(r#"cl.exe   /c    main.cpp"#);
-        assert_eq!(tokens, vec!["cl.exe", "/c", "main.cpp"]);
-    }
-
-    #[test]
-    fn test_is_source_file() {
-        assert!(is_source_file("main.cpp"));
-        assert!(is_source_file("test.c"));
-        assert!(is_source_file("code.cc"));
-        assert!(is_source_file("file.cxx"));
-        assert!(is_source_file("FILE.CPP")); // Case insensitive
-        assert!(!is_source_file("header.h"));
-        assert!(!is_source_file("lib.obj"));
-    }
-
-    #[test]
-    fn test_should_filter_flag() {
-        // Should filter PCH flags
-        assert!(should_filter_flag("/Yc"));
-        assert!(should_filter_flag("/YcStdAfx.h"));
-        assert!(should_filter_flag("/Yu"));
-        assert!(should_filter_flag("/YuPrecompiled.h"));
-        assert!(should_filter_flag("/Fp"));
-        assert!(should_filter_flag("/FpDebug/test.pch"));
-        assert!(should_filter_flag("/Fp\"C:\\path\\file.pch\""));
-
-        // Should NOT filter floating-point model flags (they have colons!)
-        assert!(!should_filter_flag("/fp:precise"));
-        assert!(!should_filter_flag("/fp:fast"));
-        assert!(!should_filter_flag("/fp:strict"));
-        assert!(!should_filter_flag("/Fp:precise")); // Alternative valid form
-        assert!(!should_filter_flag("/fp:contract"));
-        assert!(!should_filter_flag("/fp:except"));
-        assert!(!should_filter_flag("/fp:except-"));
-
-        // Should NOT filter force includes
-        assert!(!should_filter_flag("/FI"));
-        assert!(!should_filter_flag("/FIheader.h"));
-
-        // Case insensitive for PCH
-        assert!(should_filter_flag("/yc"));
-        assert!(should_filter_flag("/YC"));
-        assert!(should_filter_flag("/fp\"test.pch\"")); // PCH (no colon!)
-
-        // Should not filter other flags
-        assert!(!should_filter_flag("/c"));
-        assert!(!should_filter_flag("/Ox"));
-    }
-
-    // ----------------------------------------------------------------------------
-    // Tests for normalize_path()
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_normalize_path_with_double_backslash() {
-        let path = PathBuf::from(r"C:\foo\bar\\baz\file.cpp");
-        let normalized = normalize_path(&path);
-        assert_eq!(normalized, PathBuf::from(r"C:\foo\bar\baz\file.cpp"));
-    }
-
-    #[test]
-    fn test_normalize_path_normal() {
-        let path = PathBuf::from(r"C:\foo\bar\baz\file.cpp");
-        let normalized = normalize_path(&path);
-        assert_eq!(normalized, path);
-    }
-
-    // ----------------------------------------------------------------------------
-    // Tests for path_to_normalized_string()
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_path_to_normalized_string() {
-        let path = PathBuf::from(r"S:\Acme\Project\src\project\obj\amd64\\bond\core\file.cpp");
-        let normalized = path_to_normalized_string(&path);
-        // Should not contain double backslashes
-        assert!(!normalized.contains(r"\\"));
-        // Should contain the components
-        assert!(normalized.contains("bond"));
-        assert!(normalized.contains("core"));
-    }
-
-    // ----------------------------------------------------------------------------
-    // Tests for parse_cl_command()
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_parse_cl_command_single_file() {
-        let project_ctx = ProjectContext {
-            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
-            project_dir: PathBuf::from(r"C:\project"),
-        };
-
-        // Test with UNQUOTED path (like real MSBuild logs)
-        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c /I"include" main.cpp"#;
-        let commands = parse_cl_command(line, &project_ctx, 200).unwrap();
+/// Write `database` to `temp_file` and move it over `path`
+fn write_output(
+    args: &Args,
+    path: &Path,
+    temp_file: NamedTempFile,
+    database: &CompilationDatabase,
+    show_progress: bool,
+    multi: &MultiProgress,
+) -> Result<()> {
+    info!("Writing {} commands to {}", database.len(), path.display());
 
-        assert_eq!(commands.len(), 1);
-        assert_eq!(commands[0].directory, r"C:\project");
-        // File should now be absolute
-        assert_eq!(commands[0].file, r"C:\project\main.cpp");
-        assert!(commands[0]
-            .command
-            .contains(r#""C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe""#));
-        assert!(commands[0].command.contains(r#"/I"include""#));
-        assert!(commands[0].command.contains(r#"C:\project\main.cpp"#));
-    }
+    // Create progress spinner for write operation if enabled
+    let write_pb = setup_write_progress_bar(show_progress, multi)?;
 
-    #[test]
-    fn test_parse_cl_command_multiple_files() {
-        let project_ctx = ProjectContext {
-            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
-            project_dir: PathBuf::from(r"C:\project"),
-        };
+    let output = BufWriter::new(temp_file.as_file());
+    let mut progress_writer = write_pb.wrap_write(output);
 
-        // Test with UNQUOTED path (like real MSBuild logs)
-        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c /Ox main.cpp util.cpp helper.c"#;
-        let commands = parse_cl_command(line, &project_ctx, 200).unwrap();
-
-        assert_eq!(commands.len(), 3);
-        // Files should now be absolute
-        assert_eq!(commands[0].file, r"C:\project\main.cpp");
-        assert_eq!(commands[1].file, r"C:\project\util.cpp");
-        assert_eq!(commands[2].file, r"C:\project\helper.c");
-
-        // All should have same directory and flags
-        for cmd in &commands {
-            assert_eq!(cmd.directory, r"C:\project");
-            assert!(cmd.command.contains("/Ox"));
-            assert!(cmd
-                .command
-                .contains(r#""C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe""#));
+    match args.format {
+        OutputFormat::CompileFlags => {
+            compile_flags::write(&mut progress_writer, database.entries(), args.flag_set)?;
+        }
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let options = WriteOptions {
+                pretty: args.pretty_print,
+                json_lines: args.format == OutputFormat::Ndjson,
+                sort: args.sort,
+            };
+            database.to_writer(&mut progress_writer, &options)?;
         }
     }
 
-    #[test]
-    fn test_parse_cl_command_filters_pch() {
-        let project_ctx = ProjectContext {
-            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
-            project_dir: PathBuf::from(r"C:\project"),
-        };
-
-        // Test with UNQUOTED path (like real MSBuild logs)
-        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c /YuStdafx.h /FpDebug/test.pch /FIcommon.h main.cpp"#;
-        let commands = parse_cl_command(line, &project_ctx, 200).unwrap();
+    // Dropping the writer would ignore a failed flush, e.g. on a full disk,
+    // and the truncated file would replace the good one
+    progress_writer
+        .flush()
+        .context("Failed to write output file")?;
+    drop(progress_writer);
+    temp_file
+        .as_file()
+        .sync_all()
+        .context("Failed to write output file")?;
 
-        assert_eq!(commands.len(), 1);
+    write_pb.finish_and_clear();
 
-        // Should filter /Yu and /Fp but keep /FI
-        assert!(!commands[0].command.contains("/Yu"));
-        assert!(!commands[0].command.contains("/Fp"));
-        assert!(commands[0].command.contains("/FIcommon.h"));
+    if args.backup && path.exists() {
+        let backup = backup_path(path);
+        std::fs::copy(path, &backup)
+            .with_context(|| format!("Failed to back up {}", path.display()))?;
+        debug!("Kept the previous output as {}", backup.display());
     }
 
-    #[test]
-    fn test_parse_cl_command_preserves_fp_model() {
-        let project_ctx = ProjectContext {
-            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
-            project_dir: PathBuf::from(r"C:\project"),
-        };
-
-        // Test that /fp:precise (floating-point model) is preserved while /Fp (PCH) is filtered
-        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c /fp:precise /YuStdafx.h /Fp"Debug\test.pch" /Od main.cpp"#;
-        let commands = parse_cl_command(line, &project_ctx, 200).unwrap();
-
-        assert_eq!(commands.len(), 1);
+    // Atomically replace the output file now that writing succeeded
+    temp_file
+        .persist(path)
+        .with_context(|| format!("Failed to persist output file: {}", path.display()))?;
+    Ok(())
+}
 
-        // Should keep /fp:precise (floating-point model)
-        assert!(
-            commands[0].command.contains("/fp:precise"),
-            "Command should contain /fp:precise but got: {}",
-            commands[0].command
-        );
+/// Print what `--dry-run` would have written: the entry count of each
+/// database, the first entries in written order and the problems found
+fn print_dry_run(
+    args: &Args,
+    compile_commands: Vec<CompileCommand>,
+    problems: &[CategorySummary],
+    missing_sources: usize,
+) -> Result<()> {
+    println!("ms2cc dry run: nothing was written");
 
-        // Should filter /Yu and /Fp"..." (PCH flags)
-        assert!(
-            !commands[0].command.contains("/Yu"),
-            "Command should not contain /Yu"
-        );
-        assert!(
-            !commands[0].command.contains("/Fp\""),
-            "Command should not contain /Fp with quotes"
+    if args.split_by_config {
+        let mut counts: IndexMap<Option<String>, usize> = IndexMap::new();
+        for entry in &compile_commands {
+            *counts.entry(entry.configuration.clone()).or_default() += 1;
+        }
+        for (configuration, count) in counts {
+            let path = configuration_output_path(&args.output_file, configuration.as_deref());
+            println!("Would write {} entries to {}", count, path.display());
+        }
+    } else {
+        println!(
+            "Would write {} entries to {}",
+            compile_commands.len(),
+            args.output_file.display()
         );
-
-        // Should keep other flags
-        assert!(commands[0].command.contains("/Od"));
     }
 
-    #[test]
-    fn test_parse_cl_command_quoted_file() {
-        let project_ctx = ProjectContext {
-            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
-            project_dir: PathBuf::from(r"C:\project"),
-        };
-
-        // Test with UNQUOTED path (like real MSBuild logs)
-        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c "path with spaces\main.cpp""#;
-        let commands = parse_cl_command(line, &project_ctx, 200).unwrap();
-
-        assert_eq!(commands.len(), 1);
-        // File field should be absolute with no quotes
-        assert_eq!(commands[0].file, r"C:\project\path with spaces\main.cpp");
-        // Command should have absolute path with quotes
-        assert!(
-            commands[0]
-                .command
-                .contains(r#"C:\project\path with spaces\main.cpp"#)
+    let mut database = CompilationDatabase::new(compile_commands);
+    database.sort(args.sort);
+    let sample = &database.entries()[..database.len().min(DRY_RUN_SAMPLE_ENTRIES)];
+    if !sample.is_empty() {
+        println!("First {} of {} entries:", sample.len(), database.len());
+        println!(
+            "{}",
+            serde_json::to_string_pretty(sample).context("Failed to format entries")?
         );
     }
 
-    #[test]
-    fn test_parse_cl_command_full_path_with_spaces() {
-        let project_ctx = ProjectContext {
-            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
-            project_dir: PathBuf::from(r"C:\project"),
-        };
-
-        // Test with QUOTED CL.exe path (ensure backward compatibility)
-        let line = r#"  "C:\Program Files\MSVC\bin\HostX64\x64\CL.exe" /c main.cpp"#;
-        let commands = parse_cl_command(line, &project_ctx, 200).unwrap();
-
-        assert_eq!(commands.len(), 1);
-        // Should preserve full path with quotes due to spaces
-        assert!(
-            commands[0]
-                .command
-                .contains(r#""C:\Program Files\MSVC\bin\HostX64\x64\CL.exe""#)
-        );
-        assert!(commands[0].command.contains(r"C:\project\main.cpp"));
+    if problems.is_empty() && missing_sources == 0 {
+        println!("No problems found.");
+    } else {
+        println!("Problems:");
     }
-
-    #[test]
-    fn test_parse_cl_command_unquoted_path_with_spaces() {
-        let project_ctx = ProjectContext {
-            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
-            project_dir: PathBuf::from(r"C:\project"),
-        };
-
-        // Test with UNQUOTED CL.exe path with spaces (real MSBuild logs)
-        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c main.cpp"#;
-        let commands = parse_cl_command(line, &project_ctx, 200).unwrap();
-
-        assert_eq!(commands.len(), 1);
-        // Should quote the path with spaces
-        assert!(commands[0]
-            .command
-            .contains(r#""C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe""#));
-        assert!(commands[0].command.contains(r"C:\project\main.cpp"));
+    for problem in problems {
+        println!("  {} {}", problem.count, problem.category);
+        println!("     fix: {}", problem.category.advice());
     }
-
-    // ----------------------------------------------------------------------------
-    // Tests for clean_include_path()
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_clean_include_path_quoted_with_trailing_backslash() {
-        assert_eq!(
-            clean_include_path(r#"/I"C:\path\to\dir\\""#),
-            r#"/I"C:\path\to\dir""#
+    if missing_sources > 0 {
+        println!(
+            "  {} source file(s) named in the log have no entry",
+            missing_sources
         );
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_clean_include_path_quoted_no_trailing() {
-        assert_eq!(
-            clean_include_path(r#"/I"C:\path\to\dir""#),
-            r#"/I"C:\path\to\dir""#
+/// Fail if there is nothing to write, unless `--allow-empty`. An empty
+/// database is usually a log without compiler lines or a run that failed
+/// before them; the existing one is kept.
+fn ensure_not_empty(args: &Args, compile_commands: &[CompileCommand]) -> Result<()> {
+    if compile_commands.is_empty() && !args.allow_empty {
+        anyhow::bail!(
+            "No compile commands found; not writing {} (use --allow-empty to write an empty database)",
+            args.output_file.display()
         );
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_clean_include_path_unquoted_with_trailing() {
-        assert_eq!(
-            clean_include_path(r#"/IC:\path\to\dir\"#),
-            r#"/IC:\path\to\dir"#
-        );
-    }
+/// Read the logs and write the database, as configured by `args`
+fn generate(args: &Args, config: &Config, multi: &MultiProgress) -> Result<()> {
+    // Determine if progress bar should be shown
+    // Disable only if --no-progress flag is set or output is not a TTY
+    let show_progress = !args.no_progress && atty::is(atty::Stream::Stderr);
+    let progress = ProgressBars::new(show_progress, multi);
 
-    #[test]
-    fn test_clean_include_path_multiple_trailing() {
-        assert_eq!(
-            clean_include_path(r#"/I"C:\path\to\dir\\\\\""#),
-            r#"/I"C:\path\to\dir""#
-        );
+    let mut builder = GenerateOptions::builder()
+        .input_files(input_files(args)?)
+        .input_format(args.input_format)
+        .parse_options(ParseOptions::from_config(config))
+        .emit_output(args.emit_output)
+        .strict_ambiguity(args.strict_ambiguity)
+        .attribution(args.attribution)
+        .keep_pch_flags(args.keep_pch_flags)
+        .expand_response_files(!args.no_rsp_expansion)
+        .compilers(&args.compiler_executable)
+        .expand_env(args.expand_env)
+        .keep_occurrences(args.error_report.is_some())
+        .drive_map(DriveMap::new(&args.drive_map)?)
+        .emit_header_entries(args.emit_header_entries)
+        .map_generated_sources(args.map_generated_sources)
+        .remap(Remap::new(&args.remap)?)
+        .path_map(PathMap::new(&args.path_map, args.path_style)?)
+        .keep_configurations(args.split_by_config)
+        .dedupe(args.dedupe)
+        .output_style(args.output_style)
+        .max_threads(args.max_threads);
+    if let Some(label) = &args.input_encoding {
+        builder = builder.input_encoding(label);
     }
-
-    #[test]
-    fn test_clean_include_path_not_include_flag() {
-        assert_eq!(clean_include_path(r#"/Od"#), r#"/Od"#);
+    for tool in &args.extra_tools {
+        builder = builder.extra_tool(tool);
     }
-
-    #[test]
-    fn test_clean_include_path_root_drive() {
-        // Root path like C:\ should be preserved
-        assert_eq!(clean_include_path(r#"/I"C:\""#), r#"/I"C:\""#);
+    if let Some(pattern) = &args.line_prefix_regex {
+        builder = builder.line_prefix(pattern);
     }
-
-    #[test]
-    fn test_clean_include_path_lowercase_flag() {
-        // Should work with lowercase /i as well
-        assert_eq!(
-            clean_include_path(r#"/i"C:\path\to\dir\\""#),
-            r#"/i"C:\path\to\dir""#
-        );
+    if !args.tlog_dir.is_empty() {
+        builder = builder.tlog_index(Arc::new(TlogIndex::read_dirs_with_progress(
+            &args.tlog_dir,
+            &progress,
+        )?));
     }
-
-    #[test]
-    fn test_clean_include_path_real_world_example() {
-        // Real example from the XStore build
-        assert_eq!(
-            clean_include_path(
-                r#"/I"S:\Azure\Storage-XStore\src\XAggregator\XsdMacroGen\obj\amd64\\""#
-            ),
-            r#"/I"S:\Azure\Storage-XStore\src\XAggregator\XsdMacroGen\obj\amd64""#
-        );
+    for name in config.profile_transformers(args.profile.as_deref())? {
+        builder = builder.builtin_transformer(name);
     }
-
-    // ----------------------------------------------------------------------------
-    // Tests for resolve_source_file_path()
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_resolve_source_file_path_relative() {
-        let working_dir = PathBuf::from(r"C:\project");
-        let source = "src\\main.cpp";
-        let resolved = resolve_source_file_path(source, &working_dir);
-        assert_eq!(resolved, PathBuf::from(r"C:\project\src\main.cpp"));
+    for flag in &args.strip_flag {
+        builder = builder.transformer("strip-flag", StripFlag(flag.clone()));
     }
-
-    #[test]
-    fn test_resolve_source_file_path_parent_directory() {
-        let working_dir = PathBuf::from(r"C:\project\SubDir");
-        let source = r"..\Common\shared.cpp";
-        let resolved = resolve_source_file_path(source, &working_dir);
-        assert_eq!(
-            resolved,
-            PathBuf::from(r"C:\project\SubDir\..\Common\shared.cpp")
-        );
+    for spec in &args.replace_prefix {
+        builder = builder.transformer("replace-prefix", ReplacePrefix::parse(spec)?);
     }
-
-    #[test]
-    fn test_resolve_source_file_path_already_absolute() {
-        let working_dir = PathBuf::from(r"C:\project");
-        let source = r"D:\external\library\file.cpp";
-        let resolved = resolve_source_file_path(source, &working_dir);
-        assert_eq!(resolved, PathBuf::from(r"D:\external\library\file.cpp"));
+    for flag in &args.add_flag {
+        builder = builder.transformer("add-flag", AddFlag(flag.clone()));
     }
-
-    #[test]
-    fn test_resolve_source_file_path_quoted() {
-        let working_dir = PathBuf::from(r"C:\project");
-        let source = r#""src\main.cpp""#;
-        let resolved = resolve_source_file_path(source, &working_dir);
-        assert_eq!(resolved, PathBuf::from(r"C:\project\src\main.cpp"));
+    if let Some(compiler) = &args.replace_compiler {
+        builder = builder.transformer("replace-compiler", ReplaceCompiler(compiler.clone()));
     }
-
-    #[test]
-    fn test_resolve_source_file_path_current_directory() {
-        let working_dir = PathBuf::from(r"C:\project");
-        let source = r".\main.cpp";
-        let resolved = resolve_source_file_path(source, &working_dir);
-        assert_eq!(resolved, PathBuf::from(r"C:\project\.\main.cpp"));
+    if let Some(program) = &args.filter_cmd {
+        builder = builder.filter_cmd(program);
     }
-
-    // ----------------------------------------------------------------------------
-    // Tests for normalize_path()
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_normalize_path_triple_backslash() {
-        let path = PathBuf::from(r"C:\foo\bar\\\baz\file.cpp");
-        let normalized = normalize_path(&path);
-        // Should eliminate all redundant backslashes
-        assert_eq!(normalized, PathBuf::from(r"C:\foo\bar\baz\file.cpp"));
+    if let Some(name) = &args.configuration {
+        builder = builder.configuration(name);
     }
-
-    #[test]
-    fn test_normalize_path_mixed_separators() {
-        // On Windows, PathBuf handles / and \ differently depending on the input
-        let path = PathBuf::from(r"C:\foo/bar\baz/file.cpp");
-        let normalized = normalize_path(&path);
-        // The path has 5 meaningful components, but the mixed separator might create more
-        // Just verify normalization happened
-        let normalized_str = normalized.display().to_string();
-        assert!(normalized_str.contains("foo"));
-        assert!(normalized_str.contains("bar"));
-        assert!(normalized_str.contains("baz"));
-        assert!(normalized_str.contains("file.cpp"));
+    for compiler in &args.only_compiler {
+        builder = builder.only_compiler(compiler);
     }
-
-    // ----------------------------------------------------------------------------
-    // Tests for tokenize_command_line()
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_tokenize_empty_string() {
-        let tokens = tokenize_command_line("");
-        assert_eq!(tokens.len(), 0);
+    for compiler in &args.exclude_compiler {
+        builder = builder.exclude_compiler(compiler);
     }
-
-    #[test]
-    fn test_tokenize_only_whitespace() {
-        let tokens = tokenize_command_line("   \t  ");
-        assert_eq!(tokens.len(), 0);
+    for glob in &args.include_path {
+        builder = builder.include(glob);
     }
-
-    #[test]
-    fn test_tokenize_unclosed_quote() {
-        // Unclosed quote - should still tokenize (quote becomes part of token)
-        let tokens = tokenize_command_line(r#"cl.exe /I"C:\Program Files"#);
-        assert_eq!(tokens.len(), 2);
-        assert_eq!(tokens[0], "cl.exe");
-        assert_eq!(tokens[1], r#"/I"C:\Program Files"#);
-    }
-
-    #[test]
-    fn test_tokenize_adjacent_quotes() {
-        let tokens = tokenize_command_line(r#"cl.exe ""file.cpp"""#);
-        assert_eq!(tokens.len(), 2);
-        assert_eq!(tokens[0], "cl.exe");
-        assert_eq!(tokens[1], r#"""file.cpp"""#);
+    for glob in &args.exclude_path {
+        builder = builder.exclude(glob);
     }
-
-    #[test]
-    fn test_tokenize_tabs() {
-        let tokens = tokenize_command_line("cl.exe\t/c\tmain.cpp");
-        assert_eq!(tokens, vec!["cl.exe", "/c", "main.cpp"]);
+    if let Some(mode) = &args.directory_mode {
+        builder = builder.directory_mode(DirectoryMode::parse(mode)?);
     }
-
-    // ----------------------------------------------------------------------------
-    // Tests for is_source_file()
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_is_source_file_uppercase_extensions() {
-        assert!(is_source_file("MAIN.CPP"));
-        assert!(is_source_file("FILE.C"));
-        assert!(is_source_file("CODE.CXX"));
-        assert!(is_source_file("TEST.CC"));
+    for build_dir in &args.ninja_dir {
+        builder = builder.ninja_dir(build_dir);
     }
+    let options = builder.build();
 
-    #[test]
-    fn test_is_source_file_mixed_case_extensions() {
-        assert!(is_source_file("main.CpP"));
-        assert!(is_source_file("file.Cpp"));
-    }
+    // Create a temp file in the output directory to validate writability before parsing.
+    // The temp file auto-deletes on drop if we don't persist it.
+    let temp_file = if args.dry_run {
+        None
+    } else {
+        Some(create_temp_output_file(&args.output_file)?)
+    };
 
-    #[test]
-    fn test_is_source_file_quoted_paths() {
-        assert!(is_source_file(r#""path\to\file.cpp""#));
-        assert!(is_source_file(r#""test.c""#));
-    }
+    // Load existing database for merging (unless --overwrite is set)
+    let base_dir = args
+        .base_dir
+        .as_deref()
+        .map(std::path::absolute)
+        .transpose()
+        .context("Failed to resolve --base-dir")?;
+    let mut existing = if args.overwrite {
+        info!("Overwrite mode: existing database will be replaced");
+        Vec::new()
+    } else if args.format == OutputFormat::CompileFlags {
+        // compile_flags.txt is always written from scratch
+        Vec::new()
+    } else if args.split_by_config {
+        // Loaded once the configurations in the log are known
+        Vec::new()
+    } else {
+        load_existing(&args.output_file, base_dir.as_deref())?
+    };
 
-    #[test]
-    fn test_is_source_file_with_path() {
-        assert!(is_source_file(r"C:\project\src\main.cpp"));
-        assert!(is_source_file(r"relative\path\file.c"));
+    // Read the inputs and shape the entries as the library does
+    let collected = ms2cc::collect(&options, &progress)?;
+    let scan = collected.scan;
+    let new_commands = collected.entries;
+
+    scan.diagnostics.log_summary();
+    let incomplete = scan.diagnostics.incomplete_count();
+    if args.strict && incomplete > 0 {
+        // The report explains what is missing
+        if let Some(error_report) = &args.error_report
+            && !args.dry_run
+        {
+            scan.diagnostics.write_report(error_report)?;
+        }
+        anyhow::bail!(
+            "{} problem(s) left the database incomplete (see the warnings above); not writing {}",
+            incomplete,
+            args.output_file.display()
+        );
     }
 
-    #[test]
-    fn test_is_source_file_not_source() {
-        assert!(!is_source_file("header.h"));
-        assert!(!is_source_file("library.lib"));
-        assert!(!is_source_file("object.obj"));
-        assert!(!is_source_file("executable.exe"));
-        assert!(!is_source_file("archive.a"));
-        assert!(!is_source_file("README.md"));
+    let mut report = RunReport {
+        superseded_commands: scan.superseded,
+        unresolved_sources: scan.diagnostics.count(Category::UnresolvedPath),
+        lines_read: scan.lines_read,
+        lines_skipped: scan.lines_skipped,
+        diagnostics: scan.diagnostics.summaries(),
+        ..Default::default()
+    };
+    report.add_projects(&scan.projects);
+    for (stage, elapsed) in collected.stages {
+        report.add_stage(stage, elapsed);
+    }
+
+    // With --split-by-config, merge with the databases of the configurations built
+    if args.split_by_config && !args.overwrite && args.format != OutputFormat::CompileFlags {
+        let configurations: IndexSet<Option<String>> = new_commands
+            .iter()
+            .map(|entry| entry.configuration.clone())
+            .collect();
+        for configuration in configurations {
+            let path = configuration_output_path(&args.output_file, configuration.as_deref());
+            for mut entry in load_existing(&path, base_dir.as_deref())? {
+                entry.configuration = configuration.clone();
+                existing.push(entry);
+            }
+        }
     }
 
-    // ----------------------------------------------------------------------------
-    // Tests for handler functions
-    // ----------------------------------------------------------------------------
-
-    #[test]
-    fn test_handle_node_prefix_valid() {
-        let mut state = ProcessingState::new();
-        let pattern = node_prefix_pattern().unwrap();
+    // Merge or replace
+    let started = Instant::now();
+    let compile_commands = if existing.is_empty() {
+        new_commands
+    } else {
+        let strategy = if args.update {
+            MergeStrategy::Update
+        } else if args.append {
+            MergeStrategy::Append
+        } else {
+            MergeStrategy::Merge
+        };
+        merge_compile_commands(existing, new_commands, strategy)
+    };
 
-        handle_node_prefix("  7>Project ...", &pattern, &mut state);
+    // Dedupe and restrict to the included paths, existing entries too
+    let (mut compile_commands, duplicates_removed) = ms2cc::finish(&options, compile_commands)?;
+    report.duplicates_removed = duplicates_removed;
 
-        assert_eq!(state.current_prefix, Some(7));
+    if args.prune {
+        compile_commands = compile_commands::prune_missing(compile_commands);
     }
 
-    #[test]
-    fn test_handle_node_prefix_no_match() {
-        let mut state = ProcessingState::new();
-        let pattern = node_prefix_pattern().unwrap();
-
-        handle_node_prefix("Project without prefix", &pattern, &mut state);
-
-        assert_eq!(state.current_prefix, None);
+    // The entries are final; an empty result writes no file at all
+    if !args.dry_run {
+        ensure_not_empty(args, &compile_commands)?;
     }
 
-    #[test]
-    fn test_handle_project_on_node_parallel_build() {
-        let mut state = ProcessingState::new();
-        let pattern = project_on_node_pattern().unwrap();
-        let line = r#"4>Project "C:\path\to\project.vcxproj" on node 3 (Build target(s))."#;
-
-        let result = handle_project_on_node(line, &pattern, &mut state, 100);
-
-        assert!(result.is_ok());
-        assert_eq!(state.prefix_to_project.len(), 1);
-        assert!(state.prefix_to_project.contains_key(&4));
-        assert!(state.current_project.is_some());
-        assert_eq!(
-            state.prefix_to_project.get(&4).unwrap().project_path,
-            PathBuf::from(r"C:\path\to\project.vcxproj")
-        );
+    if let Some(link_file) = &args.link_commands
+        && !args.dry_run
+    {
+        link::write(link_file, &scan.link_commands, args.pretty_print)?;
     }
-
-    #[test]
-    fn test_handle_nested_project_pattern() {
-        let mut state = ProcessingState::new();
-        let pattern = nested_project_pattern().unwrap();
-        let line = r#"    44>Project "S:\Acme\corp\src\foo\baz.proj" (44) is building "S:\Acme\corp\src\foo\bar.vcxproj" (54) on node 13 (default targets)."#;
-
-        let result = handle_nested_project(line, &pattern, &mut state, 100);
-
-        assert!(result.is_ok());
-        assert_eq!(state.prefix_to_project.len(), 1);
-        assert!(state.prefix_to_project.contains_key(&54));
-        assert_eq!(
-            state.prefix_to_project.get(&54).unwrap().project_path,
-            PathBuf::from(r"S:\Acme\corp\src\foo\bar.vcxproj")
-        );
+    if let Some(environment_file) = &args.environment_file
+        && !args.dry_run
+    {
+        if scan.environments.is_empty() {
+            warn!("The log has no environment block; build with /v:diagnostic to include one");
+        }
+        env::write(environment_file, &scan.environments, args.pretty_print)?;
+    }
+    if let Some(error_report) = &args.error_report
+        && !args.dry_run
+    {
+        scan.diagnostics.write_report(error_report)?;
     }
 
-    #[test]
-    fn test_handle_from_project_sequential_build() {
-        let mut state = ProcessingState::new();
-        let pattern = from_project_pattern().unwrap();
-        let line = r#"Target "ClCompile" from project "C:\path\to\project.vcxproj""#;
-
-        handle_from_project(line, &pattern, &mut state, 100);
-
-        assert!(state.current_project.is_some());
-        assert_eq!(
-            state.current_project.as_ref().unwrap().project_path,
-            PathBuf::from(r"C:\path\to\project.vcxproj")
+    // Sources the log names that no entry was made for, from any line
+    let missing = compile_commands::missing_sources(scan.diagnostics.dropped(), &compile_commands);
+    if !missing.is_empty() {
+        warn!(
+            "{} source file(s) named in the log have no entry in the database",
+            missing.len()
         );
+        for source in missing.iter().take(MAX_LISTED_DROPPED_SOURCES) {
+            warn!(
+                "    {} ({}: {:?})",
+                source.file, source.location, source.reason
+            );
+        }
+    }
+    if let Some(path) = &args.dropped_sources
+        && !args.dry_run
+    {
+        compile_commands::write_missing_sources(path, &missing)?;
     }
 
-    #[test]
-    fn test_handle_cl_command_with_context() {
-        let mut state = ProcessingState::new();
-        state.current_project = Some(ProjectContext {
-            project_path: PathBuf::from(r"C:\project\test.vcxproj"),
-            project_dir: PathBuf::from(r"C:\project"),
-        });
-
-        let pattern = compile_command_pattern().unwrap();
-        let line = r#"  C:\Program Files\Microsoft Visual Studio\2022\Enterprise\VC\Tools\MSVC\14.44.35207\bin\HostX64\x64\CL.exe /c main.cpp"#;
-
-        let result = handle_cl_command(line, &pattern, &state, 100);
+    if let Some(p1689_file) = &args.p1689_file
+        && !args.dry_run
+    {
+        p1689::write(p1689_file, &compile_commands, args.scan_deps)?;
+    }
+    if let Some(clangd_file) = &args.emit_clangd_config
+        && !args.dry_run
+    {
+        clangd::write(clangd_file, &compile_commands)?;
+    }
 
-        assert!(result.is_ok());
-        let commands = result.unwrap();
-        assert_eq!(commands.len(), 1);
-        assert_eq!(commands[0].file, r"C:\project\main.cpp");
+    for entry in &mut compile_commands {
+        entry.apply_output_style(args.output_style);
+        if let Some(base_dir) = &base_dir {
+            entry.relativize_file(base_dir);
+        }
     }
 
-    #[test]
-    fn test_handle_cl_command_no_context() {
-        let state = ProcessingState::new();
-        let pattern = compile_command_pattern().unwrap();
-        let line = r#"  CL.exe /c main.cpp"#;
+    report.add_stage("merge", started.elapsed());
 
-        let result = handle_cl_command(line, &pattern, &state, 100);
+    let Some(temp_file) = temp_file else {
+        return print_dry_run(args, compile_commands, &report.diagnostics, missing.len());
+    };
 
-        assert!(result.is_ok());
-        let commands = result.unwrap();
-        assert_eq!(commands.len(), 0); // No commands when no context
+    // Write the output to the temp file
+    let commands_written = compile_commands.len();
+    let started = Instant::now();
+    if args.split_by_config {
+        // The temp file only checked that the directory is writable
+        drop(temp_file);
+        let mut by_configuration: IndexMap<Option<String>, CompilationDatabase> = IndexMap::new();
+        for entry in compile_commands.iter().cloned() {
+            by_configuration
+                .entry(entry.configuration.clone())
+                .or_default()
+                .entries_mut()
+                .push(entry);
+        }
+        for (configuration, database) in &by_configuration {
+            let path = configuration_output_path(&args.output_file, configuration.as_deref());
+            let temp_file = create_temp_output_file(&path)?;
+            write_output(args, &path, temp_file, database, show_progress, multi)?;
+        }
+    } else {
+        write_output(
+            args,
+            &args.output_file,
+            temp_file,
+            &CompilationDatabase::new(std::mem::take(&mut compile_commands)),
+            show_progress,
+            multi,
+        )?;
     }
 
-    #[test]
-    fn test_handle_cl_command_not_cl_command() {
-        let state = ProcessingState::new();
-        let pattern = compile_command_pattern().unwrap();
-        let line = r#"This is not a CL.exe command"#;
-
-        let result = handle_cl_command(line, &pattern, &state, 100);
+    report.add_stage("write", started.elapsed());
+    report.commands_written = commands_written;
+    report.log_summary();
 
-        assert!(result.is_ok());
-        let commands = result.unwrap();
-        assert_eq!(commands.len(), 0);
+    if let Some(stats_file) = &args.stats_file {
+        report.write(stats_file)?;
+        info!("Wrote run report to {}", stats_file.display());
     }
 
-    #[test]
-    fn test_processing_state_get_active_project_with_prefix() {
-        let mut state = ProcessingState::new();
-        let ctx = ProjectContext {
-            project_path: PathBuf::from(r"C:\prefix\project.vcxproj"),
-            project_dir: PathBuf::from(r"C:\prefix"),
-        };
-        state.prefix_to_project.insert(7, ctx.clone());
-        state.current_prefix = Some(7);
-
-        let active = state.get_active_project();
+    info!("Finished");
 
-        assert!(active.is_some());
-        assert_eq!(
-            active.unwrap().project_path,
-            PathBuf::from(r"C:\prefix\project.vcxproj")
-        );
-    }
+    Ok(())
+}
 
-    #[test]
-    fn test_processing_state_get_active_project_fallback() {
-        let mut state = ProcessingState::new();
-        state.current_project = Some(ProjectContext {
-            project_path: PathBuf::from(r"C:\fallback\project.vcxproj"),
-            project_dir: PathBuf::from(r"C:\fallback"),
-        });
-        state.current_prefix = Some(99); // Prefix not in map
-
-        let active = state.get_active_project();
-
-        assert!(active.is_some());
-        assert_eq!(
-            active.unwrap().project_path,
-            PathBuf::from(r"C:\fallback\project.vcxproj")
-        );
-    }
+// ----------------------------------------------------------------------------
+// Main entry point
+// ----------------------------------------------------------------------------
 
-    #[test]
-    fn test_processing_state_get_active_project_no_prefix() {
-        let mut state = ProcessingState::new();
-        state.current_project = Some(ProjectContext {
-            project_path: PathBuf::from(r"C:\sequential\project.vcxproj"),
-            project_dir: PathBuf::from(r"C:\sequential"),
-        });
-
-        let active = state.get_active_project();
-
-        assert!(active.is_some());
-        assert_eq!(
-            active.unwrap().project_path,
-            PathBuf::from(r"C:\sequential\project.vcxproj")
-        );
-    }
+fn main() -> Result<()> {
+    if let Err(e) = run() {
+        error!("Application error: {:?}", e);
+        std::process::exit(1);
+    };
 
-    // ----------------------------------------------------------------------------
-    // Tests for progress bar setup functions
-    // ----------------------------------------------------------------------------
+    Ok(())
+}
 
-    #[test]
-    fn test_setup_read_progress_bar_enabled() {
-        let multi = MultiProgress::new();
-        let pb = setup_read_progress_bar(true, 1000, &multi).unwrap();
-        // Should create a visible progress bar (not hidden)
-        // We can't directly test visibility, but we can verify it doesn't panic
-        pb.finish_and_clear();
-    }
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
 
-    #[test]
-    fn test_setup_read_progress_bar_disabled() {
-        let multi = MultiProgress::new();
-        let pb = setup_read_progress_bar(false, 1000, &multi).unwrap();
-        // Should create a hidden progress bar
-        pb.finish_and_clear();
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
     #[test]
     fn test_setup_write_progress_bar_enabled() {
@@ -1759,100 +1180,107 @@ mod tests {
     }
 
     // ----------------------------------------------------------------------------
-    // Tests for merge_compile_commands
+    // Tests for command-line definition
     // ----------------------------------------------------------------------------
 
-    fn make_entry(file: &str, directory: &str, command: &str) -> CompileCommand {
-        CompileCommand {
-            file: file.to_string(),
-            directory: directory.to_string(),
-            command: command.to_string(),
-        }
-    }
-
     #[test]
-    fn test_merge_empty_existing_returns_new() {
-        let existing = vec![];
-        let new_entries = vec![
-            make_entry("a.cpp", "C:\\proj", "cl /c a.cpp"),
-            make_entry("b.cpp", "C:\\proj", "cl /c b.cpp"),
-        ];
-        let result = merge_compile_commands(existing, new_entries);
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0].file, "a.cpp");
-        assert_eq!(result[1].file, "b.cpp");
+    fn test_args_definition() {
+        Args::command().debug_assert();
     }
 
     #[test]
-    fn test_merge_empty_new_returns_existing() {
-        let existing = vec![
-            make_entry("a.cpp", "C:\\proj", "cl /c a.cpp"),
-            make_entry("b.cpp", "C:\\proj", "cl /c b.cpp"),
-        ];
-        let new_entries = vec![];
-        let result = merge_compile_commands(existing, new_entries);
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0].file, "a.cpp");
-        assert_eq!(result[1].file, "b.cpp");
+    fn test_completions_generate_for_all_shells() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::PowerShell, Shell::Fish] {
+            let mut buffer = Vec::new();
+            clap_complete::generate(shell, &mut Args::command(), "ms2cc", &mut buffer);
+            let script = String::from_utf8(buffer).unwrap();
+            assert!(script.contains("input-file"), "{shell} completions");
+            assert!(script.contains("doctor"), "{shell} completions");
+        }
     }
 
     #[test]
-    fn test_merge_updates_matching_entries() {
-        let existing = vec![
-            make_entry("a.cpp", "C:\\proj", "cl /c /O1 a.cpp"),
-            make_entry("b.cpp", "C:\\proj", "cl /c /O1 b.cpp"),
+    fn test_empty_result_needs_allow_empty() {
+        let args = Args::try_parse_from(["ms2cc", "-o", "out.json"]).unwrap();
+        let error = ensure_not_empty(&args, &[]).unwrap_err();
+        assert!(error.to_string().contains("not writing out.json"));
+
+        let entry = CompileCommand {
+            directory: "/src".to_string(),
+            command: "cl.exe /c a.cpp".to_string(),
+            file: "a.cpp".to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        };
+        assert!(ensure_not_empty(&args, &[entry]).is_ok());
+
+        let args = Args::try_parse_from(["ms2cc", "--allow-empty"]).unwrap();
+        assert!(ensure_not_empty(&args, &[]).is_ok());
+
+        // A log without compiler lines leaves no side file behind either
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("msbuild.log");
+        std::fs::write(&log, "Build started.\nBuild succeeded.\n").unwrap();
+        let side_files = [
+            "--link-commands",
+            "--environment-file",
+            "--error-report",
+            "--dropped-sources",
+            "--p1689-file",
+            "--emit-clangd-config",
         ];
-        let new_entries = vec![make_entry("a.cpp", "C:\\proj", "cl /c /O2 a.cpp")];
-        let result = merge_compile_commands(existing, new_entries);
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0].command, "cl /c /O2 a.cpp");
-        assert_eq!(result[1].command, "cl /c /O1 b.cpp");
-    }
+        let mut argv: Vec<OsString> = vec!["ms2cc".into(), "--no-progress".into(), "-i".into()];
+        argv.push(log.into());
+        argv.push("-o".into());
+        argv.push(dir.path().join("compile_commands.json").into());
+        for (index, option) in side_files.iter().enumerate() {
+            argv.push(option.into());
+            argv.push(dir.path().join(format!("side{}", index)).into());
+        }
+        let args = Args::try_parse_from(argv).unwrap();
 
-    #[test]
-    fn test_merge_appends_new_entries() {
-        let existing = vec![make_entry("a.cpp", "C:\\proj", "cl /c a.cpp")];
-        let new_entries = vec![make_entry("b.cpp", "C:\\proj", "cl /c b.cpp")];
-        let result = merge_compile_commands(existing, new_entries);
-        assert_eq!(result.len(), 2);
-        assert_eq!(result[0].file, "a.cpp");
-        assert_eq!(result[1].file, "b.cpp");
+        let error = generate(&args, &Config::default(), &MultiProgress::new()).unwrap_err();
+        assert!(error.to_string().contains("No compile commands found"));
+        let written: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(written, ["msbuild.log"]);
     }
 
     #[test]
-    fn test_merge_preserves_same_file_different_directory() {
-        let existing = vec![
-            make_entry("crc.cpp", "C:\\lib", "cl /c /DUSER crc.cpp"),
-            make_entry("crc.cpp", "C:\\klib", "cl /c /DKERNEL crc.cpp"),
-        ];
-        let new_entries = vec![make_entry("crc.cpp", "C:\\lib", "cl /c /DUSER /O2 crc.cpp")];
-        let result = merge_compile_commands(existing, new_entries);
-        assert_eq!(result.len(), 2);
-        // The userspace entry should be updated
-        assert_eq!(result[0].command, "cl /c /DUSER /O2 crc.cpp");
-        assert_eq!(result[0].directory, "C:\\lib");
-        // The kernel entry should be untouched
-        assert_eq!(result[1].command, "cl /c /DKERNEL crc.cpp");
-        assert_eq!(result[1].directory, "C:\\klib");
+    fn test_command_line_globs_replace_config_globs() {
+        let config = Config {
+            include: vec!["src/**".to_string()],
+            exclude: vec!["third_party/**".to_string()],
+            ..Default::default()
+        };
+        let parse = |argv: &[&str]| {
+            let matches = Args::command().try_get_matches_from(argv).unwrap();
+            let mut args = Args::from_arg_matches(&matches).unwrap();
+            apply_config(&mut args, &config, &matches);
+            args
+        };
+
+        let args = parse(&["ms2cc"]);
+        assert_eq!(args.include_path, ["src/**"]);
+        assert_eq!(args.exclude_path, ["third_party/**"]);
+
+        let args = parse(&["ms2cc", "--include-path", "lib/**"]);
+        assert_eq!(args.include_path, ["lib/**"]);
+        assert_eq!(args.exclude_path, ["third_party/**"]);
     }
 
     #[test]
-    fn test_merge_mixed_update_and_add() {
-        let existing = vec![
-            make_entry("a.cpp", "C:\\proj", "cl /c a.cpp"),
-            make_entry("b.cpp", "C:\\proj", "cl /c b.cpp"),
-            make_entry("c.cpp", "C:\\proj", "cl /c c.cpp"),
-        ];
-        let new_entries = vec![
-            make_entry("b.cpp", "C:\\proj", "cl /c /O2 b.cpp"),
-            make_entry("d.cpp", "C:\\proj", "cl /c d.cpp"),
-        ];
-        let result = merge_compile_commands(existing, new_entries);
-        assert_eq!(result.len(), 4);
-        assert_eq!(result[0].file, "a.cpp");
-        assert_eq!(result[1].file, "b.cpp");
-        assert_eq!(result[1].command, "cl /c /O2 b.cpp");
-        assert_eq!(result[2].file, "c.cpp");
-        assert_eq!(result[3].file, "d.cpp");
+    fn test_man_page_renders() {
+        let mut buffer = Vec::new();
+        clap_mangen::Man::new(Args::command())
+            .render(&mut buffer)
+            .unwrap();
+        let page = String::from_utf8(buffer).unwrap();
+        assert!(page.contains(".TH ms2cc"));
+        assert!(page.contains("output\\-file"));
     }
 }
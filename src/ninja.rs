@@ -0,0 +1,551 @@
+//! Compile commands from Ninja build directories (CMake "Open Folder" projects).
+//!
+//! `--ninja-dir` asks `ninja -t compdb` for the commands. `--input-format
+//! ninja` reads `build.ninja` itself, so it also works where ninja is not on
+//! PATH.
+
+use crate::{
+    CompileCommand, MergeStrategy, ParseOptions, ScanResult, compile_commands,
+    compiler_wrapper_pattern, merge_compile_commands, path_to_normalized_string,
+    strip_compiler_wrapper, tokenize_command_line,
+};
+use anyhow::{Context, Result, bail};
+use log::{debug, info};
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// One entry as printed by `ninja -t compdb`
+#[derive(Debug, Deserialize)]
+struct NinjaEntry {
+    directory: String,
+    command: String,
+    file: String,
+    #[serde(default)]
+    output: Option<String>,
+}
+
+/// Run `ninja -t compdb` in `build_dir` and convert its output
+pub fn compile_commands(build_dir: &Path, options: &ParseOptions) -> Result<Vec<CompileCommand>> {
+    debug!("Running ninja -t compdb in {}", build_dir.display());
+
+    let output = Command::new("ninja")
+        .arg("-C")
+        .arg(build_dir)
+        .args(["-t", "compdb"])
+        .output()
+        .context(
+            "Failed to run ninja; make sure it is on PATH (Visual Studio ships it in Common7\\IDE\\CommonExtensions\\Microsoft\\CMake\\Ninja)",
+        )?;
+
+    if !output.status.success() {
+        bail!(
+            "ninja -t compdb failed in {} ({}): {}",
+            build_dir.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let commands = parse_compdb(&output.stdout, options)
+        .with_context(|| format!("Failed to read ninja output for {}", build_dir.display()))?;
+    info!(
+        "Found {} compile commands in Ninja build directory {}",
+        commands.len(),
+        build_dir.display()
+    );
+    Ok(commands)
+}
+
+/// Convert `ninja -t compdb` JSON into entries, keeping only compilations of
+/// recognized sources (compdb also lists link and custom steps)
+fn parse_compdb(json: &[u8], options: &ParseOptions) -> Result<Vec<CompileCommand>> {
+    let entries: Vec<NinjaEntry> = serde_json::from_slice(json)?;
+    let wrapper = compiler_wrapper_pattern()?;
+
+    let mut commands = Vec::new();
+    for entry in entries
+        .into_iter()
+        .filter(|entry| options.is_source_file(&entry.file))
+    {
+        let directory = Path::new(&entry.directory);
+        let output = entry.output.map(|output| directory.join(output));
+        commands.extend(
+            convert(
+                &entry.command,
+                directory,
+                output.as_deref(),
+                &wrapper,
+                options,
+            )?
+            .into_iter()
+            .flatten(),
+        );
+    }
+    Ok(commands)
+}
+
+/// Build the entries of one Ninja compilation the way a CL.exe line of a
+/// build log is built, so both get the same response file expansion, flag
+/// cleanup and drive mapping. `None` if the command does not run one of the
+/// configured compilers.
+fn convert(
+    command: &str,
+    directory: &Path,
+    output: Option<&Path>,
+    wrapper: &Regex,
+    options: &ParseOptions,
+) -> Result<Option<Vec<CompileCommand>>> {
+    let command = strip_compiler_wrapper(command.trim().to_string(), wrapper);
+    let compiler = tokenize_command_line(&command).into_iter().next();
+    if !compiler.is_some_and(|compiler| options.is_compiler(&compiler)) {
+        debug!("Skipping Ninja command without a compiler: {}", command);
+        return Ok(None);
+    }
+
+    let mut commands =
+        compile_commands::from_command_line_with_options(&command, directory, options)?;
+    // Ninja knows the object file even when the command does not name it
+    for entry in &mut commands {
+        if entry.output.is_none() {
+            entry.output = output
+                .map(|output| path_to_normalized_string(&options.drive_map.translate_path(output)));
+        }
+    }
+    Ok(Some(commands))
+}
+
+// ----------------------------------------------------------------------------
+// build.ninja
+// ----------------------------------------------------------------------------
+
+/// A `build` statement with its paths and variables already evaluated
+#[derive(Debug)]
+struct Build {
+    outputs: Vec<String>,
+    rule: String,
+    /// Explicit inputs, the ones `$in` expands to
+    inputs: Vec<String>,
+    vars: HashMap<String, String>,
+}
+
+/// Declarations of a build.ninja and the files it includes
+#[derive(Debug, Default)]
+struct Manifest {
+    vars: HashMap<String, String>,
+    /// Unevaluated variables of each rule
+    rules: HashMap<String, HashMap<String, String>>,
+    builds: Vec<Build>,
+}
+
+/// Expand `$var`, `${var}` and the `$$`, `$ `, `$:` escapes of a ninja value
+fn evaluate(value: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '$' {
+            result.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('{') => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                result.push_str(&lookup(&name).unwrap_or_default());
+            }
+            Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-' => {
+                let mut name = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                result.push_str(&lookup(&name).unwrap_or_default());
+            }
+            Some(c) => result.push(c),
+            None => {}
+        }
+    }
+    result
+}
+
+/// Split a path list at unescaped spaces, keeping escapes for [`evaluate`]
+fn split_paths(list: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut current = String::new();
+    let mut chars = list.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '$' => {
+                current.push(ch);
+                current.extend(chars.next());
+            }
+            ' ' | '\t' => {
+                if !current.is_empty() {
+                    paths.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(ch),
+        }
+    }
+    if !current.is_empty() {
+        paths.push(current);
+    }
+    paths
+}
+
+/// Byte offset of the first `:` that is not escaped as `$:`
+fn find_unescaped_colon(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (index, ch) in text.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '$' => escaped = true,
+            ':' => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Join lines ending in an unescaped `$` with the next one
+fn logical_lines(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut pending: Option<String> = None;
+    for line in text.lines() {
+        let line = match pending.take() {
+            Some(mut joined) => {
+                joined.push_str(line.trim_start());
+                joined
+            }
+            None => line.to_string(),
+        };
+        let dollars = line.len() - line.trim_end_matches('$').len();
+        if dollars % 2 == 1 {
+            pending = Some(line[..line.len() - 1].to_string());
+        } else {
+            lines.push(line);
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+/// Split `name = value`
+fn binding(line: &str) -> Option<(String, &str)> {
+    let (name, value) = line.split_once('=')?;
+    Some((name.trim().to_string(), value.trim_start()))
+}
+
+impl Manifest {
+    /// Read `path` and the files it includes. Relative paths are resolved
+    /// against `build_dir`, the directory ninja runs in.
+    fn read(&mut self, path: &Path, build_dir: &Path) -> Result<()> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let lines = logical_lines(&text);
+        let mut lines = lines.iter().peekable();
+
+        while let Some(line) = lines.next() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            // Indented bindings belonging to this declaration
+            let mut scoped = Vec::new();
+            while let Some(next) = lines.next_if(|next| next.starts_with([' ', '\t'])) {
+                if !next.trim().is_empty() && !next.trim_start().starts_with('#') {
+                    scoped.push(next.trim_start());
+                }
+            }
+
+            let file_vars = |name: &str| self.vars.get(name).cloned();
+            if let Some(name) = line.strip_prefix("rule ") {
+                let vars = scoped
+                    .iter()
+                    .filter_map(|line| binding(line))
+                    .map(|(name, value)| (name, value.to_string()))
+                    .collect();
+                self.rules.insert(name.trim().to_string(), vars);
+            } else if let Some(statement) = line.strip_prefix("build ") {
+                let colon = find_unescaped_colon(statement)
+                    .with_context(|| format!("Missing ':' in build statement: {}", line))?;
+                let outputs_part = statement[..colon].split(" | ").next().unwrap_or_default();
+                let mut inputs = split_paths(&statement[colon + 1..]).into_iter();
+                let rule = inputs.next().unwrap_or_default();
+
+                let build = Build {
+                    outputs: split_paths(outputs_part)
+                        .iter()
+                        .map(|path| evaluate(path, &file_vars))
+                        .collect(),
+                    rule,
+                    inputs: inputs
+                        .take_while(|path| !path.starts_with('|'))
+                        .map(|path| evaluate(&path, &file_vars))
+                        .collect(),
+                    vars: scoped
+                        .iter()
+                        .filter_map(|line| binding(line))
+                        .map(|(name, value)| (name, evaluate(value, &file_vars)))
+                        .collect(),
+                };
+                self.builds.push(build);
+            } else if let Some(file) = line
+                .strip_prefix("include ")
+                .or_else(|| line.strip_prefix("subninja "))
+            {
+                let file = evaluate(file.trim(), &file_vars);
+                self.read(&build_dir.join(file), build_dir)?;
+            } else if line.starts_with("default ") || line.starts_with("pool ") {
+                // Neither affects commands
+            } else if let Some((name, value)) = binding(line) {
+                let value = evaluate(value, &file_vars);
+                self.vars.insert(name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// The command of `build`, with the rule's variables looked up in the
+    /// build, then the rule, then the file
+    fn command(&self, build: &Build) -> Option<String> {
+        let rule = self.rules.get(&build.rule)?;
+        let quote = |paths: &[String]| {
+            paths
+                .iter()
+                .map(|path| {
+                    if path.contains(' ') {
+                        format!("\"{}\"", path)
+                    } else {
+                        path.clone()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        fn lookup(
+            name: &str,
+            manifest: &Manifest,
+            build: &Build,
+            rule: &HashMap<String, String>,
+            quote: &dyn Fn(&[String]) -> String,
+            depth: usize,
+        ) -> Option<String> {
+            match name {
+                "in" => Some(quote(&build.inputs)),
+                "out" => Some(quote(&build.outputs)),
+                _ => build
+                    .vars
+                    .get(name)
+                    .cloned()
+                    // Rule variables may refer to each other; stop on cycles
+                    .or_else(|| {
+                        let value = rule.get(name).filter(|_| depth < 8)?;
+                        Some(evaluate(value, &|name| {
+                            lookup(name, manifest, build, rule, quote, depth + 1)
+                        }))
+                    })
+                    .or_else(|| manifest.vars.get(name).cloned()),
+            }
+        }
+
+        let command = rule.get("command")?;
+        Some(evaluate(command, &|name| {
+            lookup(name, self, build, rule, &quote, 0)
+        }))
+    }
+}
+
+/// Read `build_dir/build.ninja` and convert the compilations it declares.
+/// Only builds whose command runs one of the configured compilers on a
+/// recognized source become entries.
+pub fn read_build_dir(build_dir: &Path, options: &ParseOptions) -> Result<Vec<CompileCommand>> {
+    let mut manifest = Manifest::default();
+    manifest
+        .read(&build_dir.join("build.ninja"), build_dir)
+        .with_context(|| {
+            format!(
+                "Failed to read Ninja build directory {}",
+                build_dir.display()
+            )
+        })?;
+    debug!(
+        "Read {} rules and {} build statements from {}",
+        manifest.rules.len(),
+        manifest.builds.len(),
+        build_dir.display()
+    );
+
+    let directory: PathBuf = build_dir.components().collect();
+    let wrapper = compiler_wrapper_pattern()?;
+    let mut commands = Vec::new();
+    for build in &manifest.builds {
+        if !build
+            .inputs
+            .first()
+            .is_some_and(|source| options.is_source_file(source))
+        {
+            continue;
+        }
+        let Some(command) = manifest.command(build) else {
+            continue;
+        };
+        let output = build.outputs.first().map(|output| directory.join(output));
+        commands.extend(
+            convert(&command, &directory, output.as_deref(), &wrapper, options)?
+                .into_iter()
+                .flatten(),
+        );
+    }
+
+    info!(
+        "Found {} compile commands in {}",
+        commands.len(),
+        build_dir.join("build.ninja").display()
+    );
+    Ok(commands)
+}
+
+/// Read the build directories in order, like [`crate::scan_logs`] reads
+/// logs: later directories win for the same source file
+pub fn scan_build_dirs(build_dirs: &[PathBuf], options: &ParseOptions) -> Result<ScanResult> {
+    let mut commands = Vec::new();
+    for build_dir in build_dirs {
+        let later = read_build_dir(build_dir, options)?;
+        commands = merge_compile_commands(commands, later, MergeStrategy::Update);
+    }
+    Ok(ScanResult {
+        commands,
+        ..Default::default()
+    })
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_parse_compdb_keeps_sources_and_resolves_paths() {
+        let json = br#"[
+  {
+    "directory": "/src/out/build/x64-Debug",
+    "command": "cl.exe /nologo /c ../../../main.cpp",
+    "file": "../../../main.cpp",
+    "output": "CMakeFiles/app.dir/main.cpp.obj"
+  },
+  {
+    "directory": "/src/out/build/x64-Debug",
+    "command": "link.exe /nologo CMakeFiles/app.dir/main.cpp.obj",
+    "file": "CMakeFiles/app.dir/main.cpp.obj",
+    "output": "app.exe"
+  }
+]"#;
+
+        let commands = parse_compdb(json, &ParseOptions::default()).unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].command, r#"cl.exe /nologo /c "/src/main.cpp""#);
+        assert_eq!(
+            Path::new(&commands[0].directory),
+            Path::new("/src/out/build/x64-Debug")
+        );
+        // `..` segments are resolved
+        assert_eq!(Path::new(&commands[0].file), Path::new("/src/main.cpp"));
+        assert!(
+            commands[0]
+                .output
+                .as_deref()
+                .unwrap()
+                .ends_with("main.cpp.obj")
+        );
+    }
+
+    #[test]
+    fn test_read_build_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("CMakeFiles")).unwrap();
+        fs::write(
+            dir.path().join("CMakeFiles").join("rules.ninja"),
+            "rule CXX_COMPILER__app_Debug\n  deps = msvc\n  command = ${LAUNCHER}C:\\VC\\bin\\cl.exe  /nologo /TP $DEFINES $FLAGS /Fo$out -c $in\n\nrule CXX_EXECUTABLE_LINKER__app_Debug\n  command = link.exe $in /out:$out\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("build.ninja"),
+            "# Generated by CMake\ncmake_ninja_workdir = /src/out/\ninclude CMakeFiles/rules.ninja\n\nbuild CMakeFiles/app.dir/main.cpp.obj: CXX_COMPILER__app_Debug /src/my$ app/main.cpp || cmake_object_order_depends_target_app\n  DEFINES = -DNAME=$$HOME\n  FLAGS = /Zi $\n      /Od\n\nbuild app.exe: CXX_EXECUTABLE_LINKER__app_Debug CMakeFiles/app.dir/main.cpp.obj\n",
+        )
+        .unwrap();
+
+        let commands = read_build_dir(dir.path(), &ParseOptions::default()).unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(
+            commands[0].command,
+            r#"C:\VC\bin\cl.exe /nologo /TP -DNAME=$HOME /Zi /Od /FoCMakeFiles/app.dir/main.cpp.obj -c "/src/my app/main.cpp""#
+        );
+        assert_eq!(
+            Path::new(&commands[0].file),
+            Path::new("/src/my app/main.cpp")
+        );
+        assert!(
+            commands[0]
+                .output
+                .as_deref()
+                .unwrap()
+                .ends_with("main.cpp.obj")
+        );
+    }
+
+    #[test]
+    fn test_read_build_dir_matches_msbuild() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("flags.rsp"), "/DFOO /Iinc\\").unwrap();
+        fs::write(
+            dir.path().join("build.ninja"),
+            "rule CXX_COMPILER\n  command = sccache C:\\VC\\bin\\cl.exe /nologo /Yupch.h /Fppch.pch @flags.rsp /Fo$out -c $in\n\nbuild main.cpp.obj: CXX_COMPILER main.cpp\n",
+        )
+        .unwrap();
+        let ninja = read_build_dir(dir.path(), &ParseOptions::default()).unwrap();
+
+        let mut log = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            log,
+            "1>Project \"{}\" on node 1 (Build target(s)).\n1>ClCompile:\n     C:\\VC\\bin\\cl.exe /nologo /Yupch.h /Fppch.pch @flags.rsp /Fomain.cpp.obj -c main.cpp\n",
+            dir.path().join("app.vcxproj").display()
+        )
+        .unwrap();
+        let options = ParseOptions::default();
+        let msbuild = crate::process_msbuild_log(
+            log.path(),
+            None,
+            &crate::LogPatterns::for_options(&options).unwrap(),
+            &options,
+            &crate::NoProgress,
+            &crate::CancellationToken::default(),
+        )
+        .unwrap()
+        .commands;
+
+        assert_eq!(ninja.len(), 1);
+        assert!(!ninja[0].command.contains("/Yu"));
+        assert!(ninja[0].command.contains("/DFOO /Iinc "));
+        assert_eq!(
+            serde_json::to_value(&ninja).unwrap(),
+            serde_json::to_value(&msbuild).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_compdb_rejects_invalid_json() {
+        assert!(parse_compdb(b"ninja: error", &ParseOptions::default()).is_err());
+    }
+}
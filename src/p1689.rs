@@ -0,0 +1,283 @@
+//! `--p1689-file`: C++20 module dependencies in the P1689 format.
+//!
+//! Dependencies come from the JSON files MSVC writes for `/scanDependencies`
+//! (already P1689) or `/sourceDependencies` when the logged command lines
+//! used them. With `--scan-deps` the remaining module units are scanned with
+//! `clang-scan-deps`.
+
+use crate::{CompileCommand, tokenize_command_line};
+use anyhow::{Context, Result, bail};
+use log::{debug, info, warn};
+use serde_json::{Value, json};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tempfile::NamedTempFile;
+
+/// Extensions of module interface units
+const MODULE_EXTENSIONS: &[&str] = &["ixx", "cppm"];
+
+/// Flags marking a command as part of a modules build
+const MODULE_FLAGS: &[&str] = &[
+    "interface",
+    "internalPartition",
+    "reference",
+    "headerUnit",
+    "headerUnit:quote",
+    "headerUnit:angle",
+    "exportHeader",
+];
+
+/// Dependency output MSVC was asked to write
+#[derive(Debug, PartialEq)]
+enum DependencyFile {
+    /// `/scanDependencies`: P1689 already
+    Scan(PathBuf),
+    /// `/sourceDependencies`: MSVC's own format
+    Source(PathBuf),
+}
+
+/// Flag name without its leading `/` or `-`
+fn flag_name(token: &str) -> Option<&str> {
+    token.strip_prefix(['/', '-'])
+}
+
+/// Check if an entry compiles a module unit or consumes modules
+fn is_module_entry(entry: &CompileCommand) -> bool {
+    let is_module_source = Path::new(&entry.file)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| MODULE_EXTENSIONS.contains(&ext.as_str()));
+
+    is_module_source
+        || tokenize_command_line(&entry.command)
+            .iter()
+            .filter_map(|token| flag_name(token))
+            .any(|name| MODULE_FLAGS.contains(&name))
+}
+
+/// Find the dependency file requested on the entry's command line.
+/// A value ending in a separator is a directory holding `<source>.json`.
+fn dependency_file(entry: &CompileCommand) -> Option<DependencyFile> {
+    let tokens = tokenize_command_line(&entry.command);
+    let (index, is_scan) =
+        tokens
+            .iter()
+            .enumerate()
+            .find_map(|(i, token)| match flag_name(token)? {
+                "scanDependencies" => Some((i, true)),
+                "sourceDependencies" => Some((i, false)),
+                _ => None,
+            })?;
+
+    let value = tokens.get(index + 1)?.trim_matches('"');
+    let mut path = Path::new(&entry.directory).join(value);
+    if value.ends_with(['\\', '/']) {
+        let name = Path::new(&entry.file).file_name()?.to_string_lossy();
+        path.push(format!("{}.json", name));
+    }
+    Some(if is_scan {
+        DependencyFile::Scan(path)
+    } else {
+        DependencyFile::Source(path)
+    })
+}
+
+/// Convert a `/sourceDependencies` file into a P1689 rule
+fn rule_from_source_dependencies(json: &Value, entry: &CompileCommand) -> Value {
+    let data = &json["Data"];
+    let mut rule = json!({});
+
+    if let Some(output) = &entry.output {
+        rule["primary-output"] = json!(output);
+    }
+    if let Some(name) = data["ProvidedModule"].as_str().filter(|n| !n.is_empty()) {
+        rule["provides"] = json!([{
+            "logical-name": name,
+            "source-path": entry.file,
+            "is-interface": true,
+        }]);
+    }
+
+    // Older versions list plain names, newer ones objects with a BMI path
+    let requires: Vec<Value> = data["ImportedModules"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|import| import.as_str().or_else(|| import["Name"].as_str()))
+        .map(|name| json!({ "logical-name": name }))
+        .chain(
+            data["ImportedHeaderUnits"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|unit| unit.as_str().or_else(|| unit["Header"].as_str()))
+                .map(|header| {
+                    json!({
+                        "logical-name": header,
+                        "source-path": header,
+                        "lookup-method": "include-quote",
+                    })
+                }),
+        )
+        .collect();
+    if !requires.is_empty() {
+        rule["requires"] = json!(requires);
+    }
+    rule
+}
+
+/// Read the rules MSVC wrote for an entry, if the file is still there
+fn rules_from_log(entry: &CompileCommand) -> Result<Option<Vec<Value>>> {
+    let Some(file) = dependency_file(entry) else {
+        return Ok(None);
+    };
+    let path = match &file {
+        DependencyFile::Scan(path) | DependencyFile::Source(path) => path,
+    };
+    if !path.exists() {
+        debug!("Dependency file not found: {}", path.display());
+        return Ok(None);
+    }
+
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read dependency file: {}", path.display()))?;
+    let json: Value = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse dependency file: {}", path.display()))?;
+
+    Ok(Some(match file {
+        DependencyFile::Scan(_) => json["rules"].as_array().cloned().unwrap_or_default(),
+        DependencyFile::Source(_) => vec![rule_from_source_dependencies(&json, entry)],
+    }))
+}
+
+/// Scan entries with `clang-scan-deps -format=p1689`
+fn rules_from_clang_scan_deps(entries: &[&CompileCommand]) -> Result<Vec<Value>> {
+    let database = NamedTempFile::new().context("Failed to create temporary database")?;
+    let mut writer = BufWriter::new(database.as_file());
+    serde_json::to_writer(&mut writer, entries).context("Failed to write temporary database")?;
+    writer
+        .flush()
+        .context("Failed to write temporary database")?;
+
+    let output = Command::new("clang-scan-deps")
+        .arg("-format=p1689")
+        .arg("-compilation-database")
+        .arg(database.path())
+        .output()
+        .context("Failed to run clang-scan-deps; make sure it is on PATH (it ships with LLVM)")?;
+    if !output.status.success() {
+        bail!(
+            "clang-scan-deps failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let json: Value =
+        serde_json::from_slice(&output.stdout).context("Failed to parse clang-scan-deps output")?;
+    Ok(json["rules"].as_array().cloned().unwrap_or_default())
+}
+
+/// Collect dependency rules for the module entries and write them to `path`
+pub fn write(path: &Path, entries: &[CompileCommand], scan_deps: bool) -> Result<()> {
+    let mut rules = Vec::new();
+    let mut unscanned = Vec::new();
+
+    for entry in entries.iter().filter(|entry| is_module_entry(entry)) {
+        match rules_from_log(entry)? {
+            Some(found) => rules.extend(found),
+            None => unscanned.push(entry),
+        }
+    }
+
+    if !unscanned.is_empty() {
+        if scan_deps {
+            rules.extend(rules_from_clang_scan_deps(&unscanned)?);
+        } else {
+            warn!(
+                "{} module unit(s) have no dependency information; build with /scanDependencies or use --scan-deps",
+                unscanned.len()
+            );
+        }
+    }
+
+    let document = json!({ "version": 1, "revision": 0, "rules": rules });
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create P1689 file: {}", path.display()))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &document)
+        .with_context(|| format!("Failed to write P1689 file: {}", path.display()))?;
+
+    info!(
+        "Wrote {} module dependency rule(s) to {}",
+        rules.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(directory: &str, file: &str, flags: &str) -> CompileCommand {
+        CompileCommand {
+            directory: directory.to_string(),
+            command: format!("cl.exe /c {} {}", flags, file),
+            file: file.to_string(),
+            output: Some("core.obj".to_string()),
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_is_module_entry() {
+        assert!(is_module_entry(&entry("/src", "core.ixx", "")));
+        assert!(is_module_entry(&entry(
+            "/src",
+            "main.cpp",
+            "/reference Core=Core.ifc"
+        )));
+        assert!(!is_module_entry(&entry("/src", "main.cpp", "/std:c++20")));
+    }
+
+    #[test]
+    fn test_dependency_file_in_directory() {
+        let e = entry("/src", "core.ixx", "/sourceDependencies obj/");
+        assert_eq!(
+            dependency_file(&e),
+            Some(DependencyFile::Source(
+                Path::new("/src").join("obj/").join("core.ixx.json")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_source_dependencies_become_rule() {
+        let dir = tempdir().unwrap();
+        let directory = dir.path().display().to_string();
+        fs::write(
+            dir.path().join("core.json"),
+            r#"{"Version":"1.2","Data":{"Source":"core.ixx","ProvidedModule":"Core",
+                "ImportedModules":[{"Name":"Base","BMI":"Base.ifc"}],"ImportedHeaderUnits":[]}}"#,
+        )
+        .unwrap();
+
+        let e = entry(&directory, "core.ixx", "/sourceDependencies core.json");
+        let rules = rules_from_log(&e).unwrap().unwrap();
+
+        assert_eq!(rules[0]["primary-output"], "core.obj");
+        assert_eq!(rules[0]["provides"][0]["logical-name"], "Core");
+        assert_eq!(rules[0]["requires"][0]["logical-name"], "Base");
+    }
+}
@@ -0,0 +1,502 @@
+//! `--path-map` and `--path-style`: Windows paths rewritten for clangd
+//! running somewhere else, e.g. inside WSL or a Linux container.
+//!
+//! The translation covers `file`, `directory`, `output`, the source file on
+//! the command line and the values of the path-bearing flags in
+//! [`PATH_FLAGS`]. It runs as the last of the entry transformers, before the
+//! merge, so an existing translated database matches the new entries.
+//!
+//! `--drive-map` undoes `subst` and `net use` drives of the build machine.
+//! It runs before the other path transformers, and the parser applies it to
+//! the paths it checks on disk and looks up in the tracker logs.
+//!
+//! `--remap` is narrower: it points entries for generated sources, e.g.
+//! `obj\x64\Release\gen\foo.cpp`, at the checked-in file they mirror, and
+//! only touches the source file.
+//!
+//! `--map-generated-sources` only changes `file`, to the source named by
+//! the `#line` directive of a generated one; the command still compiles
+//! the generated source.
+
+use crate::{CompileCommand, long_path, path_key, transform::EntryTransformer};
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use log::debug;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// Separator written between the components of translated paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PathStyle {
+    /// Backslashes
+    Windows,
+    /// Forward slashes
+    Posix,
+}
+
+/// Flags followed directly by a path, e.g. `/I"C:\src\inc"`
+const PATH_FLAGS: &[&str] = &["external:I", "FI", "Fo", "I"];
+
+/// `path` with the FROM of the first mapping starting it (ignoring case, at
+/// a separator) replaced by its TO
+fn replace_prefix(mappings: &[(String, String)], path: &str) -> Option<String> {
+    mappings.iter().find_map(|(from, to)| {
+        let head = path.get(..from.len())?;
+        let rest = &path[from.len()..];
+        let at_separator = rest.is_empty() || rest.starts_with(['\\', '/']);
+        (head.eq_ignore_ascii_case(from) && at_separator).then(|| format!("{}{}", to, rest))
+    })
+}
+
+/// Split `FROM<separator>TO`, without trailing separators on either side
+fn parse_mapping(spec: &str, separator: &str) -> Result<(String, String)> {
+    let Some((from, to)) = spec.split_once(separator) else {
+        bail!(
+            "Invalid path mapping '{}' (expected FROM{}TO)",
+            spec,
+            separator
+        );
+    };
+    let from = from.trim_end_matches(['\\', '/']);
+    if from.is_empty() {
+        bail!("Invalid path mapping '{}' (FROM is empty)", spec);
+    }
+    Ok((
+        from.to_string(),
+        to.trim_end_matches(['\\', '/']).to_string(),
+    ))
+}
+
+/// Prefix replacements and separator style applied to every path
+#[derive(Debug, Clone, Default)]
+pub struct PathMap {
+    mappings: Vec<(String, String)>,
+    style: Option<PathStyle>,
+}
+
+impl PathMap {
+    /// Parse `FROM=>TO` mappings, tried in order
+    pub fn new(specs: &[String], style: Option<PathStyle>) -> Result<Self> {
+        let mappings = specs
+            .iter()
+            .map(|spec| parse_mapping(spec, "=>"))
+            .collect::<Result<_>>()?;
+        Ok(Self { mappings, style })
+    }
+
+    /// Whether translating would leave every path unchanged
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty() && self.style.is_none()
+    }
+
+    /// Translate one path: the first mapping whose FROM starts it (ignoring
+    /// case, at a separator) is replaced, then separators follow the style
+    pub fn translate(&self, path: &str) -> String {
+        let mapped = replace_prefix(&self.mappings, path).unwrap_or_else(|| path.to_string());
+
+        match self.style {
+            Some(PathStyle::Posix) => mapped.replace('\\', "/"),
+            Some(PathStyle::Windows) => mapped.replace('/', "\\"),
+            None => mapped,
+        }
+    }
+
+    /// Translate a path that may be wrapped in quotes
+    fn translate_quoted(&self, value: &str) -> String {
+        match value
+            .strip_prefix('"')
+            .and_then(|inner| inner.strip_suffix('"'))
+        {
+            Some(inner) => format!("\"{}\"", self.translate(inner)),
+            None => self.translate(value),
+        }
+    }
+
+    /// Translate a command line argument if it is the source file or a
+    /// path-bearing flag; anything else is kept
+    fn translate_argument(&self, argument: &str, sources: &[&str]) -> String {
+        if sources.contains(&argument.trim_matches('"')) {
+            return self.translate_quoted(argument);
+        }
+        let Some(name) = argument.strip_prefix(['/', '-']) else {
+            return argument.to_string();
+        };
+        for flag in PATH_FLAGS {
+            if let Some(value) = name.strip_prefix(flag)
+                && !value.is_empty()
+            {
+                let prefix = &argument[..argument.len() - value.len()];
+                return format!("{}{}", prefix, self.translate_quoted(value));
+            }
+        }
+        argument.to_string()
+    }
+}
+
+impl EntryTransformer for PathMap {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let absolute = entry.absolute_file();
+        let sources = [entry.file.as_str(), absolute.as_str()];
+
+        entry.command = crate::tokenize_command_line(&entry.command)
+            .iter()
+            .map(|token| self.translate_argument(token, &sources))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Some(arguments) = &mut entry.arguments {
+            for argument in arguments.iter_mut() {
+                *argument = self.translate_argument(argument, &sources);
+            }
+        }
+
+        entry.file = self.translate(&entry.file);
+        entry.directory = self.translate(&entry.directory);
+        if let Some(output) = &mut entry.output {
+            *output = self.translate(output);
+        }
+        Ok(())
+    }
+}
+
+/// `--drive-map S:=C:\src`: paths on a substituted or network drive
+/// rewritten to the directory it stands for
+#[derive(Debug, Clone, Default)]
+pub struct DriveMap(PathMap);
+
+impl DriveMap {
+    /// Parse `DRIVE:=DIR` mappings
+    pub fn new(specs: &[String]) -> Result<Self> {
+        let mut mappings = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let (drive, dir) = parse_mapping(spec, "=")?;
+            let is_drive = drive.len() == 2
+                && drive.ends_with(':')
+                && drive.starts_with(|c: char| c.is_ascii_alphabetic());
+            if !is_drive {
+                bail!(
+                    "Invalid drive mapping '{}' (expected e.g. S:=C:\\src)",
+                    spec
+                );
+            }
+            mappings.push((drive, dir));
+        }
+        Ok(Self(PathMap {
+            mappings,
+            style: None,
+        }))
+    }
+
+    /// Whether there are no mappings
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// `path` on the directory its drive stands for
+    pub fn translate(&self, path: &str) -> String {
+        self.0.translate(path)
+    }
+
+    /// [`Self::translate`] for a [`Path`]
+    pub fn translate_path(&self, path: &Path) -> PathBuf {
+        if self.is_empty() {
+            return path.to_path_buf();
+        }
+        PathBuf::from(self.translate(&path.to_string_lossy()))
+    }
+}
+
+impl EntryTransformer for DriveMap {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        self.0.transform(entry)
+    }
+}
+
+/// `--remap FROM=TO`: source files below FROM are replaced by the same
+/// path below TO. The original path is kept as `output` unless the entry
+/// already names its object file there.
+#[derive(Debug, Clone, Default)]
+pub struct Remap {
+    mappings: Vec<(String, String)>,
+}
+
+impl Remap {
+    /// Parse `FROM=TO` mappings, tried in order
+    pub fn new(specs: &[String]) -> Result<Self> {
+        let mappings = specs
+            .iter()
+            .map(|spec| parse_mapping(spec, "="))
+            .collect::<Result<_>>()?;
+        Ok(Self { mappings })
+    }
+
+    /// Whether there are no mappings
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+}
+
+impl EntryTransformer for Remap {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let absolute = entry.absolute_file();
+        let Some(remapped) = replace_prefix(&self.mappings, &absolute) else {
+            return Ok(());
+        };
+        let sources = [entry.file.as_str(), absolute.as_str()];
+        let replace = |argument: &str| {
+            if !sources.contains(&argument.trim_matches('"')) {
+                argument.to_string()
+            } else if argument.starts_with('"') {
+                format!("\"{}\"", remapped)
+            } else {
+                remapped.clone()
+            }
+        };
+
+        entry.command = crate::tokenize_command_line(&entry.command)
+            .iter()
+            .map(|token| replace(token))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Some(arguments) = &mut entry.arguments {
+            for argument in arguments.iter_mut() {
+                *argument = replace(argument);
+            }
+        }
+        entry.output.get_or_insert(absolute);
+        entry.file = remapped;
+        Ok(())
+    }
+}
+
+/// File named by a `#line 12 "file"` (or `# 12 "file"`) directive, with the
+/// escapes of the string undone
+fn line_directive_file(line: &str) -> Option<String> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let directive = directive.strip_prefix("line").unwrap_or(directive);
+    let rest = directive.trim_start();
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 || digits == rest.len() || !rest[digits..].starts_with([' ', '\t']) {
+        return None;
+    }
+    let quoted = rest[digits..].trim_start().strip_prefix('"')?;
+
+    let mut file = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return (!file.is_empty()).then_some(file),
+            '\\' => file.push(chars.next()?),
+            _ => file.push(c),
+        }
+    }
+    None
+}
+
+/// Lines of a generated source searched for a `#line` directive; generators
+/// put it after their banner comment
+const LINE_DIRECTIVE_SEARCH_LINES: usize = 64;
+
+/// `--map-generated-sources`: the `file` of entries for generated sources,
+/// e.g. a parser written by bison, is the file named by the first `#line`
+/// directive of the generated source. The command still compiles the
+/// generated source, which is kept as `output` unless the entry already
+/// names its object file there.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MapGeneratedSources;
+
+impl MapGeneratedSources {
+    /// The existing file named by the first `#line` directive near the top
+    /// of the file at `path`. A relative name is looked up next to the
+    /// generated file, then in `directory`, where the compiler ran.
+    fn original_source(path: &Path, directory: &Path) -> Option<PathBuf> {
+        let file = File::open(long_path::for_filesystem(path)).ok()?;
+        let original = BufReader::new(file)
+            .lines()
+            .take(LINE_DIRECTIVE_SEARCH_LINES)
+            .map_while(|line| line.ok())
+            .find_map(|line| line_directive_file(&line))?;
+
+        let original = PathBuf::from(original);
+        if original.is_absolute() {
+            return long_path::for_filesystem(&original)
+                .is_file()
+                .then_some(original);
+        }
+        [path.parent(), Some(directory)]
+            .into_iter()
+            .flatten()
+            .map(|dir| dir.join(&original))
+            .find(|candidate| long_path::for_filesystem(candidate).is_file())
+    }
+}
+
+impl EntryTransformer for MapGeneratedSources {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let absolute = entry.absolute_file();
+        let Some(original) =
+            Self::original_source(Path::new(&absolute), Path::new(&entry.directory))
+        else {
+            return Ok(());
+        };
+        let original = crate::path_to_normalized_string(&original);
+        if path_key(&original) != path_key(&absolute) {
+            debug!("{} was generated from {}", absolute, original);
+            entry.output.get_or_insert(absolute);
+            entry.file = original;
+        }
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        let map = PathMap::new(
+            &[r"C:\src\=>/mnt/c/src".to_string()],
+            Some(PathStyle::Posix),
+        )
+        .unwrap();
+
+        assert_eq!(map.translate(r"c:\Src\lib\a.cpp"), "/mnt/c/src/lib/a.cpp");
+        assert_eq!(map.translate(r"C:\src"), "/mnt/c/src");
+        // Only whole components match
+        assert_eq!(map.translate(r"C:\srcx\a.cpp"), "C:/srcx/a.cpp");
+        assert!(PathMap::new(&[r"C:\src=/mnt".to_string()], None).is_err());
+    }
+
+    #[test]
+    fn test_translate_entry() {
+        let map = PathMap::new(&[r"C:\src=>/w".to_string()], Some(PathStyle::Posix)).unwrap();
+        let mut entry = CompileCommand {
+            directory: r"C:\src\app".to_string(),
+            command: r#"cl.exe /c /I"C:\src\inc" /FIC:\src\pch.h /FoC:\src\obj\ /DX=C:\src "C:\src\app\main.cpp""#
+                .to_string(),
+            file: r"C:\src\app\main.cpp".to_string(),
+            output: Some(r"C:\src\obj\main.obj".to_string()),
+            arguments: None,
+            failed: false,
+            configuration: None,
+        };
+        map.transform(&mut entry).unwrap();
+
+        assert_eq!(
+            entry.command,
+            r#"cl.exe /c /I"/w/inc" /FI/w/pch.h /Fo/w/obj/ /DX=C:\src "/w/app/main.cpp""#
+        );
+        assert_eq!(entry.file, "/w/app/main.cpp");
+        assert_eq!(entry.directory, "/w/app");
+        assert_eq!(entry.output.as_deref(), Some("/w/obj/main.obj"));
+    }
+
+    #[test]
+    fn test_drive_map() {
+        let map = DriveMap::new(&[r"s:\=C:\src".to_string()]).unwrap();
+        assert_eq!(map.translate(r"S:\app\main.cpp"), r"C:\src\app\main.cpp");
+        assert_eq!(map.translate("S:"), r"C:\src");
+        assert_eq!(map.translate(r"T:\app\main.cpp"), r"T:\app\main.cpp");
+        assert_eq!(
+            map.translate_path(Path::new(r"S:\inc")),
+            PathBuf::from(r"C:\src\inc")
+        );
+        assert!(DriveMap::new(&[r"C:\src=D:\src".to_string()]).is_err());
+        assert!(DriveMap::new(&[r"S:".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_remap_source_file() {
+        let remap = Remap::new(&["/src/obj/gen=/src/templates".to_string()]).unwrap();
+        let mut entry = CompileCommand {
+            directory: "/src/obj".to_string(),
+            command: r#"cl.exe /c /I/src/obj/gen "gen/foo.cpp""#.to_string(),
+            file: "gen/foo.cpp".to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        };
+        remap.transform(&mut entry).unwrap();
+        assert_eq!(entry.file, "/src/templates/foo.cpp");
+        assert_eq!(
+            entry.command,
+            r#"cl.exe /c /I/src/obj/gen "/src/templates/foo.cpp""#
+        );
+        assert_eq!(entry.output.as_deref(), Some("/src/obj/gen/foo.cpp"));
+
+        // Entries outside FROM are left alone
+        let mut other = entry.clone();
+        other.file = "/src/main.cpp".to_string();
+        remap.transform(&mut other).unwrap();
+        assert_eq!(other.file, "/src/main.cpp");
+        assert_eq!(other.command, entry.command);
+        assert!(Remap::new(&["/src".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_line_directive_file() {
+        assert_eq!(
+            line_directive_file(r#"#line 1 "C:\\src\\parser.y""#).as_deref(),
+            Some(r"C:\src\parser.y")
+        );
+        assert_eq!(
+            line_directive_file(r#"  # 12 "lexer.l" 2"#).as_deref(),
+            Some("lexer.l")
+        );
+        assert_eq!(line_directive_file("#line 12"), None);
+        assert_eq!(line_directive_file(r#"#include "a.h""#), None);
+        assert_eq!(line_directive_file(r#"#line 1 """#), None);
+    }
+
+    #[test]
+    fn test_map_generated_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let gen_dir = dir.path().join("obj").join("gen");
+        std::fs::create_dir_all(&gen_dir).unwrap();
+        std::fs::create_dir_all(dir.path().join("grammar")).unwrap();
+        std::fs::write(dir.path().join("grammar").join("parser.y"), "").unwrap();
+        std::fs::write(
+            gen_dir.join("parser.cpp"),
+            "// Generated by bison\n#line 1 \"../../grammar/parser.y\"\nint x;\n",
+        )
+        .unwrap();
+        std::fs::write(gen_dir.join("plain.cpp"), "int y;\n").unwrap();
+
+        let mut entry = CompileCommand {
+            directory: crate::path_to_normalized_string(&dir.path().join("obj")),
+            command: "cl.exe /c gen/parser.cpp".to_string(),
+            file: "gen/parser.cpp".to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        };
+        let generated = entry.absolute_file();
+        MapGeneratedSources.transform(&mut entry).unwrap();
+        let original =
+            crate::path_to_normalized_string(&dir.path().join("grammar").join("parser.y"));
+        assert_eq!(entry.file, original);
+        // The command still compiles the generated source
+        assert_eq!(entry.command, "cl.exe /c gen/parser.cpp");
+        assert_eq!(entry.output, Some(generated));
+
+        // Sources without a directive are left alone
+        let mut plain = CompileCommand {
+            command: "cl.exe /c gen/plain.cpp".to_string(),
+            file: "gen/plain.cpp".to_string(),
+            output: None,
+            ..entry
+        };
+        MapGeneratedSources.transform(&mut plain).unwrap();
+        assert_eq!(plain.file, "gen/plain.cpp");
+        assert_eq!(plain.output, None);
+    }
+}
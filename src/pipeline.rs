@@ -0,0 +1,4 @@
+//! Ways of running the conversion other than the blocking [`crate::generate`].
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
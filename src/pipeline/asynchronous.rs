@@ -0,0 +1,117 @@
+//! The conversion for async callers, behind the `async` feature.
+//!
+//! Reading a log is blocking work, so it runs on tokio's blocking thread
+//! pool and leaves the runtime's workers free. Entries are only known once
+//! every log is read: a later log can still replace the entry for a file,
+//! and deduplication and the path filters need all of them.
+
+use crate::{
+    CompileCommand, GenerateOptions, cancel::CancellationToken, generate_with_progress,
+    progress::ProgressSink,
+};
+use anyhow::{Context, Result};
+use tokio::sync::mpsc::{self, Sender};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Entries queued in the channel before sending waits for the reader
+const STREAM_BUFFER: usize = 256;
+
+/// [`crate::generate`] without blocking the runtime
+pub async fn generate(options: GenerateOptions) -> Result<Vec<CompileCommand>> {
+    tokio::task::spawn_blocking(move || crate::generate(&options))
+        .await
+        .context("Conversion task failed")?
+}
+
+/// Cancels the conversion once nobody reads its stream anymore
+struct CancelWhenClosed<'a> {
+    sender: &'a Sender<Result<CompileCommand>>,
+    cancel: &'a CancellationToken,
+}
+
+impl ProgressSink for CancelWhenClosed<'_> {
+    fn bytes_read(&self, _bytes: u64) {
+        if self.sender.is_closed() {
+            self.cancel.cancel();
+        }
+    }
+}
+
+/// Run [`crate::generate`] and hand its entries over as a stream. Nothing is
+/// sent until the whole conversion is done; the entries are then fed through
+/// a bounded channel as the reader takes them. A failed conversion ends the
+/// stream with its error; dropping the stream cancels the conversion.
+/// Must be called from within a tokio runtime.
+pub fn generate_buffered(options: GenerateOptions) -> ReceiverStream<Result<CompileCommand>> {
+    let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+    tokio::task::spawn_blocking(move || {
+        let sink = CancelWhenClosed {
+            sender: &sender,
+            cancel: &options.cancel,
+        };
+        match generate_with_progress(&options, &sink) {
+            Ok(entries) => {
+                for entry in entries {
+                    if sender.blocking_send(Ok(entry)).is_err() {
+                        break;
+                    }
+                }
+            }
+            Err(e) => {
+                // The reader is gone if this fails; nobody is left to tell
+                let _ = sender.blocking_send(Err(e));
+            }
+        }
+    });
+    ReceiverStream::new(receiver)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn test_generate_buffered_and_generate() {
+        let mut log = NamedTempFile::new().unwrap();
+        log.write_all(
+            br#"Target "ClCompile" from project "/p/p.vcxproj":
+  C:\VC\bin\CL.exe /c main.cpp util.cpp
+"#,
+        )
+        .unwrap();
+        let options = GenerateOptions {
+            input_files: vec![log.path().to_path_buf()],
+            ..Default::default()
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        let files: Vec<String> = runtime.block_on(async {
+            generate_buffered(options.clone())
+                .map(|entry| entry.unwrap().file)
+                .collect()
+                .await
+        });
+        assert_eq!(files.len(), 2);
+        assert!(files[1].ends_with("util.cpp"));
+
+        let entries = runtime.block_on(generate(options)).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let missing = GenerateOptions {
+            input_files: vec!["missing.log".into()],
+            ..Default::default()
+        };
+        let results: Vec<_> =
+            runtime.block_on(async { generate_buffered(missing).collect().await });
+        assert!(results.len() == 1 && results[0].is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Progress reporting from the log scanner and the `--tlog-dir` search.
+//!
+//! The scanner reports through a [`ProgressSink`]; the binary draws indicatif
+//! progress bars with [`ProgressBars`], library callers can pass their own.
+//! Logs may be scanned on several threads at once, so calls for different
+//! logs can interleave; each thread reads one log at a time.
+
+use crate::{setup_index_progress_bar, setup_read_progress_bar};
+use indicatif::{MultiProgress, ProgressBar};
+use log::warn;
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    path::Path,
+    sync::Mutex,
+    thread::{self, ThreadId},
+};
+
+/// Receives progress while logs are scanned. Every method does nothing by
+/// default, so implementations only override what they show.
+pub trait ProgressSink: Sync {
+    /// A log is about to be read; `size` is unknown for stdin
+    fn start_log(&self, _path: &Path, _size: Option<u64>) {}
+
+    /// Another `bytes` of the current log were read
+    fn bytes_read(&self, _bytes: u64) {}
+
+    /// A compiler invocation produced `count` database entries
+    fn commands_emitted(&self, _count: usize) {}
+
+    /// The current log was read to the end
+    fn finish_log(&self) {}
+
+    /// Directories searched for tracker logs: `processed` of the
+    /// `discovered` so far, which grows as subdirectories are found
+    fn index_progress(&self, _processed: usize, _discovered: usize) {}
+
+    /// Every directory was searched
+    fn finish_index(&self) {}
+}
+
+/// Ignores all progress
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {}
+
+/// One progress bar per log, counting bytes and the entries found so far
+#[derive(Debug)]
+pub struct ProgressBars {
+    show: bool,
+    multi: MultiProgress,
+    /// Bar of the log each thread is reading and the entries found in it
+    bars: Mutex<HashMap<ThreadId, (ProgressBar, usize)>>,
+    /// Bar of the directories searched for tracker logs
+    index_bar: Mutex<Option<ProgressBar>>,
+}
+
+impl ProgressBars {
+    /// Draw bars in `multi`, or nothing if `show` is false
+    pub fn new(show: bool, multi: &MultiProgress) -> Self {
+        Self {
+            show,
+            multi: multi.clone(),
+            bars: Mutex::new(HashMap::new()),
+            index_bar: Mutex::new(None),
+        }
+    }
+
+    /// Run `f` on the bar of the log the current thread is reading
+    fn with_bar(&self, f: impl FnOnce(&ProgressBar, &mut usize)) {
+        let mut bars = self.bars.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((bar, commands)) = bars.get_mut(&thread::current().id()) {
+            f(bar, commands);
+        }
+    }
+}
+
+impl ProgressSink for ProgressBars {
+    fn start_log(&self, _path: &Path, size: Option<u64>) {
+        let bar = setup_read_progress_bar(self.show, size, &self.multi).unwrap_or_else(|e| {
+            warn!("Failed to set up progress bar: {:?}", e);
+            ProgressBar::hidden()
+        });
+        self.bars
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(thread::current().id(), (bar, 0));
+    }
+
+    fn bytes_read(&self, bytes: u64) {
+        self.with_bar(|bar, _| bar.inc(bytes));
+    }
+
+    fn commands_emitted(&self, count: usize) {
+        self.with_bar(|bar, commands| {
+            *commands += count;
+            bar.set_message(format!("Processing build log... {} commands", commands));
+        });
+    }
+
+    fn finish_log(&self) {
+        let bar = self
+            .bars
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&thread::current().id());
+        if let Some((bar, _)) = bar {
+            bar.finish_and_clear();
+        }
+    }
+
+    fn index_progress(&self, processed: usize, discovered: usize) {
+        let mut index_bar = self.index_bar.lock().unwrap_or_else(|e| e.into_inner());
+        let bar = index_bar.get_or_insert_with(|| {
+            setup_index_progress_bar(self.show, &self.multi).unwrap_or_else(|e| {
+                warn!("Failed to set up progress bar: {:?}", e);
+                ProgressBar::hidden()
+            })
+        });
+        bar.set_length(discovered as u64);
+        bar.set_position(processed as u64);
+    }
+
+    fn finish_index(&self) {
+        let bar = self
+            .index_bar
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(bar) = bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// Reader that reports the bytes passing through it to a sink
+pub(crate) struct ProgressReader<'a, R> {
+    inner: R,
+    sink: &'a dyn ProgressSink,
+}
+
+impl<'a, R> ProgressReader<'a, R> {
+    pub(crate) fn new(inner: R, sink: &'a dyn ProgressSink) -> Self {
+        Self { inner, sink }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            self.sink.bytes_read(read as u64);
+        }
+        Ok(read)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Recorder {
+        bytes: AtomicUsize,
+    }
+
+    impl ProgressSink for Recorder {
+        fn bytes_read(&self, bytes: u64) {
+            self.bytes.fetch_add(bytes as usize, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_progress_reader_counts_bytes() {
+        let recorder = Recorder::default();
+        let mut reader = ProgressReader::new(&b"Build started."[..], &recorder);
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+
+        assert_eq!(recorder.bytes.load(Ordering::Relaxed), 14);
+    }
+}
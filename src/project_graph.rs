@@ -0,0 +1,183 @@
+//! Which project built which, from the `Project "A" (N) is building "B" (M)`
+//! lines of parallel builds.
+//!
+//! MSBuild numbers each project instance it builds and prefixes its output
+//! with that number. Only C++ projects compile files, but CL lines are
+//! sometimes logged under the prefix of the solution or traversal project
+//! that started one. The graph tells which C++ project such a prefix stands
+//! for: the only one built below it.
+
+use indexmap::{IndexMap, IndexSet};
+use std::path::{Path, PathBuf};
+
+/// One `Project "A" (N) is building "B" (M)` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectEdge {
+    pub parent: PathBuf,
+    /// Output prefix of the parent instance (base number, e.g. 53 of 53:20)
+    pub parent_prefix: u32,
+    pub child: PathBuf,
+    /// Output prefix of the child instance
+    pub child_prefix: u32,
+}
+
+/// Check if `path` is a project that compiles C++ files
+pub(crate) fn is_cpp_project(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("vcxproj"))
+}
+
+/// Project instances of one MSBuild invocation, by output prefix, and the
+/// instances each one built
+#[derive(Debug, Clone, Default)]
+pub struct ProjectGraph {
+    projects: IndexMap<u32, PathBuf>,
+    children: IndexMap<u32, IndexSet<u32>>,
+    parents: IndexMap<u32, u32>,
+}
+
+impl ProjectGraph {
+    /// Add both instances of `edge` and the edge between them
+    pub fn add(&mut self, edge: &ProjectEdge) {
+        self.projects
+            .insert(edge.parent_prefix, edge.parent.clone());
+        self.projects.insert(edge.child_prefix, edge.child.clone());
+        if edge.parent_prefix != edge.child_prefix {
+            self.children
+                .entry(edge.parent_prefix)
+                .or_default()
+                .insert(edge.child_prefix);
+            self.parents.insert(edge.child_prefix, edge.parent_prefix);
+        }
+    }
+
+    /// Whether no edges were added
+    pub fn is_empty(&self) -> bool {
+        self.projects.is_empty()
+    }
+
+    /// Project built by the instance with output prefix `prefix`
+    pub fn project(&self, prefix: u32) -> Option<&Path> {
+        self.projects.get(&prefix).map(PathBuf::as_path)
+    }
+
+    /// Output prefix of the instance that started `prefix`
+    pub fn parent(&self, prefix: u32) -> Option<u32> {
+        self.parents.get(&prefix).copied()
+    }
+
+    /// Output prefixes of the instances `prefix` started, in log order
+    pub fn children(&self, prefix: u32) -> impl Iterator<Item = u32> + '_ {
+        self.children.get(&prefix).into_iter().flatten().copied()
+    }
+
+    /// `prefix` and the instances that started it, innermost first
+    pub fn ancestors(&self, prefix: u32) -> Vec<u32> {
+        let mut chain = vec![prefix];
+        while let Some(parent) = self.parent(*chain.last().unwrap_or(&prefix)) {
+            // A log reusing prefixes could close a cycle
+            if chain.contains(&parent) {
+                break;
+            }
+            chain.push(parent);
+        }
+        chain
+    }
+
+    /// C++ project that CL lines logged under `prefix` belong to: its own
+    /// project, or the only C++ project built below it
+    pub fn build_project(&self, prefix: u32) -> Option<&Path> {
+        let project = self.project(prefix)?;
+        if is_cpp_project(project) {
+            return Some(project);
+        }
+        let mut found: IndexSet<&Path> = IndexSet::new();
+        let mut visited: IndexSet<u32> = IndexSet::from([prefix]);
+        let mut pending: Vec<u32> = self.children(prefix).collect();
+        while let Some(next) = pending.pop() {
+            if !visited.insert(next) {
+                continue;
+            }
+            match self.project(next) {
+                Some(path) if is_cpp_project(path) => {
+                    found.insert(path);
+                }
+                _ => pending.extend(self.children(next)),
+            }
+        }
+        match found.len() {
+            1 => found.pop(),
+            _ => None,
+        }
+    }
+
+    /// Projects each project built, by path
+    pub fn references(&self) -> IndexMap<PathBuf, IndexSet<PathBuf>> {
+        let mut references: IndexMap<PathBuf, IndexSet<PathBuf>> = IndexMap::new();
+        for (parent, children) in &self.children {
+            let Some(parent) = self.projects.get(parent) else {
+                continue;
+            };
+            let children = children
+                .iter()
+                .filter_map(|child| self.projects.get(child))
+                .filter(|child| *child != parent)
+                .cloned();
+            references
+                .entry(parent.clone())
+                .or_default()
+                .extend(children);
+        }
+        references
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project_line::{self, ProjectLine};
+
+    fn graph(lines: &[&str]) -> ProjectGraph {
+        let mut graph = ProjectGraph::default();
+        for line in lines {
+            let Some(ProjectLine::Building(edge)) = project_line::parse(line) else {
+                panic!("not an edge: {}", line);
+            };
+            graph.add(&edge);
+        }
+        graph
+    }
+
+    #[test]
+    fn test_build_project_follows_single_cpp_child() {
+        let graph = graph(&[
+            r#"1>Project "C:\App.sln" (1) is building "C:\App.metaproj" (2) on node 1 (default targets)."#,
+            r#"2>Project "C:\App.metaproj" (2) is building "C:\App\App.vcxproj" (3) on node 1 (default targets)."#,
+            r#"3>Project "C:\App\App.vcxproj" (3) is building "C:\Lib\Lib.vcxproj" (4) on node 2 (default targets)."#,
+        ]);
+        let app = Path::new(r"C:\App\App.vcxproj");
+        assert_eq!(graph.build_project(1), Some(app));
+        assert_eq!(graph.build_project(3), Some(app));
+        assert_eq!(
+            graph.build_project(4),
+            Some(Path::new(r"C:\Lib\Lib.vcxproj"))
+        );
+        assert_eq!(graph.ancestors(4), [4, 3, 2, 1]);
+        assert_eq!(graph.references()[Path::new(r"C:\App.sln")].len(), 1);
+    }
+
+    #[test]
+    fn test_build_project_is_ambiguous_with_several_children() {
+        let graph = graph(&[
+            r#"1>Project "C:\All.proj" (1) is building "C:\A\A.vcxproj" (2) on node 1 (default targets)."#,
+            r#"1>Project "C:\All.proj" (1) is building "C:\B\B.vcxproj" (3) on node 2 (default targets)."#,
+        ]);
+        assert_eq!(graph.build_project(1), None);
+        assert_eq!(graph.children(1).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(graph.parent(3), Some(1));
+    }
+}
@@ -0,0 +1,235 @@
+//! The lines MSBuild logs when it starts building a project, read without
+//! regexes.
+//!
+//! Project paths are always quoted and Windows paths cannot contain `"`, so
+//! everything between the quotes is the path: parentheses, `#`, accented
+//! letters and CJK included. Only the text around the quotes is matched.
+
+use crate::project_graph::{ProjectEdge, is_cpp_project};
+use std::path::{Path, PathBuf};
+
+/// A line naming the project that the lines after it belong to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectLine {
+    /// `5>Project "C:\App\App.vcxproj" on node 4 (Build target(s)).`
+    OnNode { prefix: u32, project: PathBuf },
+    /// `44>Project "C:\All.proj" (44) is building "C:\App\App.vcxproj" (54) on node 13 (default targets).`
+    Building(ProjectEdge),
+    /// `Target "ClCompile" in file "..." from project "C:\App\App.vcxproj"`
+    FromProject(PathBuf),
+}
+
+impl ProjectLine {
+    /// The project the line enters: the child of a project building another
+    pub fn project(&self) -> &Path {
+        match self {
+            Self::OnNode { project, .. } | Self::FromProject(project) => project,
+            Self::Building(edge) => &edge.child,
+        }
+    }
+
+    /// [`Self::project`], if it compiles C++ files
+    pub fn cpp_project(&self) -> Option<&Path> {
+        Some(self.project()).filter(|project| is_cpp_project(project))
+    }
+}
+
+/// Base number of an instance number like `53` or `53:20`
+fn instance_number(text: &str) -> Option<u32> {
+    let base = match text.split_once(':') {
+        Some((base, instance)) if is_number(instance) => base,
+        Some(_) => return None,
+        None => text,
+    };
+    if is_number(base) {
+        base.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Check if `text` is a non-empty run of ASCII digits
+fn is_number(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Output prefix of `line`, e.g. 53 of ` 53:20>`, and the text after it
+fn split_prefix(line: &str) -> Option<(u32, &str)> {
+    let (prefix, rest) = line.trim_start().split_once('>')?;
+    Some((instance_number(prefix)?, rest))
+}
+
+/// Path of a quoted `"path"` at the start of `text`, and the text after it
+fn split_quoted(text: &str) -> Option<(PathBuf, &str)> {
+    let (path, rest) = text.strip_prefix('"')?.split_once('"')?;
+    (!path.is_empty()).then(|| (PathBuf::from(path), rest))
+}
+
+/// Instance number of a ` (53:20)` at the start of `text`, and the text
+/// after it
+fn split_instance(text: &str) -> Option<(u32, &str)> {
+    let (number, rest) = text.strip_prefix(" (")?.split_once(')')?;
+    Some((instance_number(number)?, rest))
+}
+
+/// Check if `text` starts with ` on node N`
+fn is_on_node(text: &str) -> bool {
+    text.strip_prefix(" on node ")
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// `Project "A" on node N` or `Project "A" (N) is building "B" (M) on node
+/// K` after the output prefix
+fn parse_project(line: &str) -> Option<ProjectLine> {
+    let (prefix, rest) = split_prefix(line)?;
+    let (project, rest) = split_quoted(rest.strip_prefix("Project ")?)?;
+    if is_on_node(rest) {
+        return Some(ProjectLine::OnNode { prefix, project });
+    }
+
+    let (parent_prefix, rest) = split_instance(rest)?;
+    let (child, rest) = split_quoted(rest.strip_prefix(" is building ")?)?;
+    let (child_prefix, rest) = split_instance(rest)?;
+    is_on_node(rest).then_some(ProjectLine::Building(ProjectEdge {
+        parent: project,
+        parent_prefix,
+        child,
+        child_prefix,
+    }))
+}
+
+/// The project line `line` is, if any
+pub fn parse(line: &str) -> Option<ProjectLine> {
+    const FROM_PROJECT: &str = "from project ";
+
+    if let Some(project_line) = parse_project(line) {
+        return Some(project_line);
+    }
+    let start = line.find(FROM_PROJECT)? + FROM_PROJECT.len();
+    let (project, _) = split_quoted(&line[start..])?;
+    Some(ProjectLine::FromProject(project))
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn on_node(prefix: u32, project: &str) -> Option<ProjectLine> {
+        Some(ProjectLine::OnNode {
+            prefix,
+            project: PathBuf::from(project),
+        })
+    }
+
+    #[test]
+    fn test_parse_project_on_node() {
+        assert_eq!(
+            parse(r#"4>Project "C:\path\to\project.vcxproj" on node 3 (Build target(s))."#),
+            on_node(4, r"C:\path\to\project.vcxproj")
+        );
+        assert_eq!(
+            parse(r#"  7>Project "S:\My Project\test.vcxproj" on node 12 (default targets)."#),
+            on_node(7, r"S:\My Project\test.vcxproj")
+        );
+        // Multi-instance prefix: the base number
+        assert_eq!(
+            parse(r#"  53:20>Project "S:\Azure\test.vcxproj" on node 30 (default targets)."#),
+            on_node(53, r"S:\Azure\test.vcxproj")
+        );
+        assert_eq!(
+            parse(r#"Project "C:\a.vcxproj" on node 3 (Build target(s))."#),
+            None
+        );
+        assert_eq!(parse(r#"4>Project "C:\a.vcxproj" on node x"#), None);
+    }
+
+    #[test]
+    fn test_parse_project_building_project() {
+        let line = r#" 264:105>Project "S:\Azure\Parent.vcxproj" (264:105) is building "S:\Azure\Child.vcxproj" (266:5) on node 28 (BuiltProjectOutputGroup target(s))."#;
+        assert_eq!(
+            parse(line),
+            Some(ProjectLine::Building(ProjectEdge {
+                parent: PathBuf::from(r"S:\Azure\Parent.vcxproj"),
+                parent_prefix: 264,
+                child: PathBuf::from(r"S:\Azure\Child.vcxproj"),
+                child_prefix: 266,
+            }))
+        );
+        let line = r#"    44>Project "S:\Acme\corp\src\foo\baz.proj" (44) is building "S:\Acme\corp\src\foo\bar.vcxproj" (54) on node 13 (default targets)."#;
+        assert_eq!(
+            parse(line).unwrap().cpp_project(),
+            Some(Path::new(r"S:\Acme\corp\src\foo\bar.vcxproj"))
+        );
+        let line = r#"10>Project "C:\App.sln" (10) is building "C:\App\App.csproj" (25) on node 5 (Build target(s))."#;
+        let parsed = parse(line).unwrap();
+        assert_eq!(parsed.project(), Path::new(r"C:\App\App.csproj"));
+        assert_eq!(parsed.cpp_project(), None);
+    }
+
+    #[test]
+    fn test_parse_from_project() {
+        assert_eq!(
+            parse(r#"Target "ClCompile" from project "C:\path\to\project.vcxproj""#),
+            Some(ProjectLine::FromProject(PathBuf::from(
+                r"C:\path\to\project.vcxproj"
+            )))
+        );
+        assert_eq!(
+            parse(r#"  Some text from project "D:\My Projects\test.vcxproj" more text"#)
+                .unwrap()
+                .project(),
+            Path::new(r"D:\My Projects\test.vcxproj")
+        );
+        assert_eq!(parse(r#"from project """#), None);
+    }
+
+    #[test]
+    fn test_parse_paths_with_parentheses() {
+        assert_eq!(
+            parse(
+                r#"3>Project "C:\Program Files (x86)\App (v2)\App.vcxproj" on node 1 (Build target(s))."#
+            ),
+            on_node(3, r"C:\Program Files (x86)\App (v2)\App.vcxproj")
+        );
+        let line = r#"1>Project "C:\src (copy)\All.sln" (1) is building "C:\src (copy)\a) b (\Lib.vcxproj" (2:4) on node 1 (default targets)."#;
+        assert_eq!(
+            parse(line).unwrap().cpp_project(),
+            Some(Path::new(r"C:\src (copy)\a) b (\Lib.vcxproj"))
+        );
+    }
+
+    #[test]
+    fn test_parse_cjk_paths() {
+        assert_eq!(
+            parse(
+                r#"5>Project "D:\開発\ゲーム (テスト)\描画.vcxproj" on node 2 (Build target(s))."#
+            ),
+            on_node(5, r"D:\開発\ゲーム (テスト)\描画.vcxproj")
+        );
+        let line = r#"2>Project "C:\项目\全部.sln" (2) is building "C:\项目\库\库.vcxproj" (7) on node 3 (default targets)."#;
+        assert_eq!(
+            parse(line).unwrap().project(),
+            Path::new(r"C:\项目\库\库.vcxproj")
+        );
+    }
+
+    #[test]
+    fn test_parse_accented_paths() {
+        assert_eq!(
+            parse(
+                r#"Target "ClCompile" in file "C:\x.targets" from project "C:\Développement\Éditeur\Moteur.vcxproj" (target "Build" depends on it):"#
+            ),
+            Some(ProjectLine::FromProject(PathBuf::from(
+                r"C:\Développement\Éditeur\Moteur.vcxproj"
+            )))
+        );
+        assert_eq!(
+            parse(r#"12:3>Project "C:\Bücher\Straße\Ñandú.vcxproj" on node 4 (Build target(s))."#),
+            on_node(12, r"C:\Bücher\Straße\Ñandú.vcxproj")
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! `ms2cc query`: print the entries a database has for a file, to see which
+//! flags clangd gets for it.
+
+use crate::{CompileCommand, path_key, path_to_normalized_string};
+use std::path::Path;
+
+/// How an entry was matched to the queried file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Match {
+    /// Same absolute path
+    Exact,
+    /// The entry's path ends with the queried one, e.g. `src\foo.cpp`
+    Suffix,
+    /// Only the file name is the same
+    FileName,
+}
+
+/// Whether `file` ends with the components of `query`
+pub(crate) fn ends_with_components(file: &str, query: &str) -> bool {
+    let split = |path: &str| -> Vec<String> {
+        path.split(['\\', '/'])
+            .filter(|part| !part.is_empty() && *part != ".")
+            .map(path_key)
+            .collect()
+    };
+    let file = split(file);
+    let query = split(query);
+    !query.is_empty() && file.ends_with(&query)
+}
+
+/// Entries for `query`, by the closest kind of match any entry has. A
+/// relative `query` is resolved against `cwd` for the exact match.
+pub fn find<'a>(
+    entries: &'a [CompileCommand],
+    query: &str,
+    cwd: &Path,
+) -> (Option<Match>, Vec<&'a CompileCommand>) {
+    let absolute = path_key(&path_to_normalized_string(&cwd.join(query)));
+    let name = Path::new(query)
+        .file_name()
+        .map(|name| path_key(&name.to_string_lossy()));
+
+    let kinds = [Match::Exact, Match::Suffix, Match::FileName];
+    for kind in kinds {
+        let matches: Vec<&CompileCommand> = entries
+            .iter()
+            .filter(|entry| match kind {
+                Match::Exact => entry.file_key() == absolute,
+                Match::Suffix => ends_with_components(&entry.absolute_file(), query),
+                Match::FileName => {
+                    let file = entry.absolute_file();
+                    let file_name = file.rsplit(['\\', '/']).next().map(path_key);
+                    name.is_some() && file_name == name
+                }
+            })
+            .collect();
+        if !matches.is_empty() {
+            return (Some(kind), matches);
+        }
+    }
+    (None, Vec::new())
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(directory: &str, file: &str) -> CompileCommand {
+        CompileCommand {
+            directory: directory.to_string(),
+            command: format!("cl /c {}", file),
+            file: file.to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_find_prefers_closest_match() {
+        let entries = vec![
+            entry("/src/app", "util.cpp"),
+            entry("/src/lib", "/src/lib/util.cpp"),
+            entry("/src/lib", "Main.cpp"),
+        ];
+        let cwd = Path::new("/src");
+
+        let (kind, matches) = find(&entries, "lib/util.cpp", cwd);
+        assert_eq!(kind, Some(Match::Exact));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].directory, "/src/lib");
+
+        let (kind, matches) = find(&entries, "app/util.cpp", Path::new("/elsewhere"));
+        assert_eq!(kind, Some(Match::Suffix));
+        assert_eq!(matches[0].directory, "/src/app");
+
+        let (kind, matches) = find(&entries, "other/util.cpp", cwd);
+        assert_eq!(kind, Some(Match::FileName));
+        assert_eq!(matches.len(), 2);
+
+        assert_eq!(find(&entries, "main.cpp", cwd).0, Some(Match::Suffix));
+        assert_eq!(find(&entries, "missing.cpp", cwd).0, None);
+    }
+}
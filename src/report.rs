@@ -0,0 +1,129 @@
+//! Machine-readable summary of a conversion run (`--stats-file`).
+
+use crate::{ProjectProperties, diagnostics::CategorySummary};
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use log::{debug, info};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Per-project information gathered from the build log
+#[derive(Debug, Serialize)]
+pub struct ProjectReport {
+    /// Full path to the project file
+    pub project: String,
+    #[serde(flatten)]
+    pub properties: ProjectProperties,
+}
+
+/// Everything ms2cc learned about a run, written as JSON with `--stats-file`
+#[derive(Debug, Default, Serialize)]
+pub struct RunReport {
+    /// Entries written to the output
+    pub commands_written: usize,
+    /// Projects seen in the log, in order of first appearance
+    pub projects: Vec<ProjectReport>,
+    /// Compilations dropped because the same file was compiled again later
+    pub superseded_commands: usize,
+    /// Entries removed by `--dedupe`
+    pub duplicates_removed: usize,
+    /// Source files that do not exist on this machine
+    pub unresolved_sources: usize,
+    /// Lines read from the logs
+    pub lines_read: usize,
+    /// Lines that could not be read and were skipped
+    pub lines_skipped: usize,
+    /// Wall-clock seconds spent in each stage, in pipeline order
+    pub stage_seconds: IndexMap<String, f64>,
+    /// Problems found while scanning, grouped by category
+    pub diagnostics: Vec<CategorySummary>,
+}
+
+impl RunReport {
+    /// Record the projects and their global properties
+    pub fn add_projects(&mut self, projects: &IndexMap<PathBuf, ProjectProperties>) {
+        self.projects
+            .extend(projects.iter().map(|(path, properties)| ProjectReport {
+                project: path.display().to_string(),
+                properties: properties.clone(),
+            }));
+    }
+
+    /// Record the time spent in `stage`
+    pub fn add_stage(&mut self, stage: &str, elapsed: Duration) {
+        *self.stage_seconds.entry(stage.to_string()).or_default() += elapsed.as_secs_f64();
+    }
+
+    /// Log the counts and timings
+    pub fn log_summary(&self) {
+        info!(
+            "Summary: {} commands written from {} project(s)",
+            self.commands_written,
+            self.projects.len()
+        );
+        info!(
+            "  {} superseded and {} duplicate command(s) collapsed, {} unresolved source(s)",
+            self.superseded_commands, self.duplicates_removed, self.unresolved_sources
+        );
+        info!(
+            "  {} line(s) read, {} skipped",
+            self.lines_read, self.lines_skipped
+        );
+        let stages: Vec<String> = self
+            .stage_seconds
+            .iter()
+            .map(|(stage, seconds)| format!("{} {:.2}s", stage, seconds))
+            .collect();
+        info!("  {}", stages.join(", "));
+    }
+
+    /// Write the report as pretty-printed JSON
+    pub fn write(&self, path: &Path) -> Result<()> {
+        debug!("Writing run report: {}", path.display());
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create stats file: {}", path.display()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .with_context(|| format!("Failed to write stats file: {}", path.display()))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_serializes_project_properties() {
+        let mut projects = IndexMap::new();
+        projects.insert(
+            PathBuf::from("p.vcxproj"),
+            ProjectProperties {
+                configuration: Some("Release".to_string()),
+                platform: Some("x64".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let mut report = RunReport::default();
+        report.add_projects(&projects);
+
+        report.add_stage("scan", Duration::from_millis(500));
+        report.add_stage("scan", Duration::from_millis(250));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["stage_seconds"]["scan"], 0.75);
+        let project = &json["projects"][0];
+        assert_eq!(project["project"], "p.vcxproj");
+        assert_eq!(project["configuration"], "Release");
+        assert_eq!(project["platform"], "x64");
+        assert!(project["platform_toolset"].is_null());
+    }
+}
@@ -0,0 +1,148 @@
+//! Where the source files named on a compiler command line are on disk.
+//!
+//! CL.exe opens relative sources from the directory it runs in, which the
+//! log only implies: usually the project directory, but builds that move
+//! sources or run from elsewhere leave a relative name that does not exist
+//! there. Resolvers are tried in order and the first one that finds the
+//! file wins; when none does, the source is taken relative to the project
+//! directory and reported as unresolved. Embedding code can add its own,
+//! e.g. one asking a build graph service.
+
+use crate::{long_path, tlog::TlogIndex};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// What is known about the command line a source was named on
+#[derive(Debug, Clone, Copy)]
+pub struct ResolveContext<'a> {
+    /// Directory of the project the command was logged for
+    pub project_dir: &'a Path,
+    /// Value of the `/Fo` flag, if any
+    pub object_file: Option<&'a str>,
+}
+
+/// Finds the file a source named on a command line refers to
+pub trait SourceResolver: fmt::Debug + Send + Sync {
+    /// Path of `source`, unquoted, if this resolver can tell where it is
+    fn resolve(&self, source: &str, context: &ResolveContext) -> Option<PathBuf>;
+}
+
+/// Check if `path` exists on this machine
+fn exists(path: &Path) -> bool {
+    long_path::for_filesystem(path).exists()
+}
+
+/// The source relative to the project directory, if it exists there
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProjectContextResolver;
+
+impl SourceResolver for ProjectContextResolver {
+    fn resolve(&self, source: &str, context: &ResolveContext) -> Option<PathBuf> {
+        let path = context.project_dir.join(source);
+        exists(&path).then_some(path)
+    }
+}
+
+/// The source recorded by the file tracker logs (`--tlog-dir`) with the
+/// same trailing components
+#[derive(Debug, Clone)]
+pub struct FileIndexResolver(pub Arc<TlogIndex>);
+
+impl SourceResolver for FileIndexResolver {
+    fn resolve(&self, source: &str, _context: &ResolveContext) -> Option<PathBuf> {
+        self.0.resolve(source)
+    }
+}
+
+/// The source below the `/Fo` directory or one of its ancestors, nearest
+/// first. Intermediate directories like `C:\src\app\x64\Debug\` usually sit
+/// below the directory CL.exe ran in.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FoHeuristicResolver;
+
+impl SourceResolver for FoHeuristicResolver {
+    fn resolve(&self, source: &str, context: &ResolveContext) -> Option<PathBuf> {
+        let object_file = Path::new(context.object_file?);
+        if !object_file.is_absolute() {
+            return None;
+        }
+        // A value ending in a separator is already a directory
+        let dir = if context.object_file?.ends_with(['\\', '/']) {
+            object_file
+        } else {
+            object_file.parent()?
+        };
+        dir.ancestors()
+            .map(|ancestor| ancestor.join(source))
+            .find(|path| exists(path))
+    }
+}
+
+/// Resolvers tried in order
+#[derive(Debug, Clone, Default)]
+pub struct ChainResolver(Vec<Arc<dyn SourceResolver>>);
+
+impl ChainResolver {
+    /// Try `resolver` after the ones already in the chain
+    pub fn push(&mut self, resolver: Arc<dyn SourceResolver>) {
+        self.0.push(resolver);
+    }
+}
+
+impl SourceResolver for ChainResolver {
+    fn resolve(&self, source: &str, context: &ResolveContext) -> Option<PathBuf> {
+        self.0
+            .iter()
+            .find_map(|resolver| resolver.resolve(source, context))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_chain_tries_resolvers_in_order() {
+        let dir = tempdir().unwrap();
+        let project_dir = dir.path().join("app");
+        let object_dir = project_dir.join("x64").join("Debug");
+        fs::create_dir_all(&object_dir).unwrap();
+        fs::write(dir.path().join("shared.cpp"), "").unwrap();
+        fs::write(project_dir.join("main.cpp"), "").unwrap();
+
+        let object_file = format!("{}/", object_dir.display());
+        let context = ResolveContext {
+            project_dir: &project_dir,
+            object_file: Some(&object_file),
+        };
+        let mut chain = ChainResolver::default();
+        chain.push(Arc::new(ProjectContextResolver));
+        chain.push(Arc::new(FoHeuristicResolver));
+
+        assert_eq!(
+            chain.resolve("main.cpp", &context),
+            Some(project_dir.join("main.cpp"))
+        );
+        // Not in the project directory, but next to one of the /Fo ancestors
+        assert_eq!(
+            chain.resolve("shared.cpp", &context),
+            Some(dir.path().join("shared.cpp"))
+        );
+        assert_eq!(chain.resolve("missing.cpp", &context), None);
+
+        let relative = ResolveContext {
+            object_file: Some(r"x64\Debug\"),
+            ..context
+        };
+        assert_eq!(FoHeuristicResolver.resolve("shared.cpp", &relative), None);
+    }
+}
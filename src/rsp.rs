@@ -0,0 +1,84 @@
+//! Expansion of `@file.rsp` response files on compiler command lines.
+
+use crate::tokenize_command_line;
+use anyhow::{Context, Result};
+use log::trace;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+
+/// Path named by a `@path` token, resolved against `working_directory`
+pub fn response_file_path(token: &str, working_directory: &Path) -> Option<PathBuf> {
+    let path = token.strip_prefix('@')?.trim_matches('"');
+    (!path.is_empty()).then(|| working_directory.join(path))
+}
+
+/// Decode a response file; MSBuild writes them as UTF-16LE with a BOM
+fn decode(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(UTF16_LE_BOM) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Read and tokenize a response file. Line breaks separate arguments like spaces.
+pub fn read_arguments(path: &Path) -> Result<Vec<String>> {
+    let bytes = fs::read(path)
+        .with_context(|| format!("Failed to read response file: {}", path.display()))?;
+    let arguments: Vec<String> = decode(&bytes)
+        .lines()
+        .flat_map(tokenize_command_line)
+        .collect();
+    trace!(
+        "Read {} argument(s) from response file {}",
+        arguments.len(),
+        path.display()
+    );
+    Ok(arguments)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_response_file_path() {
+        let dir = Path::new("/proj");
+        assert_eq!(
+            response_file_path(r#"@"obj/cl 1.rsp""#, dir),
+            Some(dir.join("obj/cl 1.rsp"))
+        );
+        assert_eq!(response_file_path("/DNAME", dir), None);
+        assert_eq!(response_file_path("@", dir), None);
+    }
+
+    #[test]
+    fn test_read_arguments_utf16() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cl.rsp");
+        let mut bytes = UTF16_LE_BOM.to_vec();
+        for unit in "/c /I\"C:\\a b\"\r\n/DX main.cpp".encode_utf16() {
+            bytes.extend(unit.to_le_bytes());
+        }
+        fs::write(&path, bytes).unwrap();
+
+        assert_eq!(
+            read_arguments(&path).unwrap(),
+            vec!["/c", "/I\"C:\\a b\"", "/DX", "main.cpp"]
+        );
+    }
+}
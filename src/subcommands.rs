@@ -0,0 +1,218 @@
+//! The subcommands that inspect databases and logs, printing what the
+//! library's [`doctor`], [`guard`], [`diff`], [`query`] and [`validation`]
+//! modules find.
+
+use anyhow::{Context, Result, bail};
+use log::{error, info};
+use ms2cc::{
+    CompileCommand, OutputStyle,
+    database::CompilationDatabase,
+    diff, doctor,
+    doctor::Severity,
+    guard,
+    query::{self, Match},
+    validation,
+};
+use serde_json::Value;
+use std::{fs, path::Path};
+
+/// Read a compilation database, failing if it is missing or malformed
+fn load_database(path: &Path) -> Result<Vec<CompileCommand>> {
+    CompilationDatabase::load(path).map(CompilationDatabase::into_entries)
+}
+
+/// `ms2cc doctor`: print the ranked findings
+pub fn doctor(input_file: &Path, output_file: &Path) -> Result<()> {
+    let findings = doctor::diagnose(input_file, output_file)?;
+
+    println!("ms2cc doctor: {}", input_file.display());
+
+    if findings.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+
+    for (index, finding) in findings.iter().enumerate() {
+        println!(
+            "{:>3}. [{}] {}",
+            index + 1,
+            finding.severity,
+            finding.problem
+        );
+        println!("     fix: {}", finding.fix);
+    }
+
+    Ok(())
+}
+
+/// `ms2cc guard`: compare `database` against `baseline` and fail if
+/// coverage regressed
+pub fn guard(baseline: &Path, database: &Path) -> Result<()> {
+    let baseline_entries = load_database(baseline)?;
+    let entries = load_database(database)?;
+
+    let covered = guard::covered_files(&baseline_entries).len();
+    let missing = guard::missing_files(&baseline_entries, &entries);
+    if !missing.is_empty() {
+        for file in &missing {
+            error!("Missing: {}", file);
+        }
+        bail!(
+            "{} of {} file(s) in {} are missing from {}",
+            missing.len(),
+            covered,
+            baseline.display(),
+            database.display()
+        );
+    }
+
+    info!(
+        "{} covers all {} file(s) in {}",
+        database.display(),
+        covered,
+        baseline.display()
+    );
+    Ok(())
+}
+
+/// `ms2cc diff`: print the differences between the databases at `old` and
+/// `new`; with `exit_code`, fail if there are any
+pub fn diff(old: &Path, new: &Path, exit_code: bool) -> Result<()> {
+    let result = diff::diff(&load_database(old)?, &load_database(new)?);
+
+    println!("ms2cc diff: {} -> {}", old.display(), new.display());
+    if result.is_empty() {
+        println!("No differences.");
+        return Ok(());
+    }
+    for file in &result.added {
+        println!("+ {}", file);
+    }
+    for file in &result.removed {
+        println!("- {}", file);
+    }
+    for change in &result.changed {
+        println!("~ {}", change.file);
+        for flag in &change.added {
+            println!("    + {}", flag);
+        }
+        for flag in &change.removed {
+            println!("    - {}", flag);
+        }
+    }
+    println!(
+        "{} added, {} removed, {} with changed flags",
+        result.added.len(),
+        result.removed.len(),
+        result.changed.len()
+    );
+
+    if exit_code {
+        bail!("{} and {} differ", old.display(), new.display());
+    }
+    Ok(())
+}
+
+/// `ms2cc query`: print the entries of the database at `database` for `file`
+pub fn query(database: &Path, file: &str) -> Result<()> {
+    let entries = load_database(database)?;
+    let cwd = std::env::current_dir().context("Failed to get the current directory")?;
+
+    let (kind, matches) = query::find(&entries, file, &cwd);
+    match kind {
+        None => bail!("No entry for {} in {}", file, database.display()),
+        Some(Match::Exact) => {}
+        Some(_) => println!(
+            "No entry for exactly {}; {} entr(ies) with a similar path:",
+            file,
+            matches.len()
+        ),
+    }
+
+    for entry in matches {
+        let mut entry = entry.clone();
+        entry.apply_output_style(OutputStyle::Arguments);
+        println!();
+        println!("file:      {}", entry.absolute_file());
+        println!("directory: {}", entry.directory);
+        if let Some(output) = &entry.output {
+            println!("output:    {}", output);
+        }
+        println!("arguments:");
+        for argument in entry.arguments.iter().flatten() {
+            println!("  {}", argument);
+        }
+    }
+    Ok(())
+}
+
+/// `ms2cc validate`: validate the database at `path`, print the problems
+/// and fail on errors
+pub fn validate(path: &Path, check_files: bool) -> Result<()> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read database: {}", path.display()))?;
+    let database: Value = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse database: {}", path.display()))?;
+    let problems = validation::validate(&database, check_files)?;
+
+    println!("ms2cc validate: {}", path.display());
+    if problems.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("  {}", problem);
+    }
+
+    let errors = problems
+        .iter()
+        .filter(|problem| problem.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        bail!("{} error(s) in {}", errors, path.display());
+    }
+    Ok(())
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(directory: &str, file: &str) -> CompileCommand {
+        CompileCommand {
+            directory: directory.to_string(),
+            command: format!("cl /c {}", file),
+            file: file.to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    #[test]
+    fn test_guard_fails_on_regression() {
+        let dir = tempdir().unwrap();
+        let baseline = dir.path().join("baseline.json");
+        let database = dir.path().join("compile_commands.json");
+        fs::write(
+            &baseline,
+            serde_json::to_string(&[entry("/src", "a.cpp"), entry("/src", "b.cpp")]).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            &database,
+            serde_json::to_string(&[entry("/src", "a.cpp")]).unwrap(),
+        )
+        .unwrap();
+
+        let err = guard(&baseline, &database).unwrap_err();
+        assert!(err.to_string().contains("1 of 2"));
+        assert!(guard(&baseline, &baseline).is_ok());
+    }
+}
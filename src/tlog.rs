@@ -0,0 +1,207 @@
+//! `--tlog-dir`: source paths from the file tracker logs MSBuild leaves in
+//! the intermediate directories.
+//!
+//! `CL.read.1.tlog` starts a block for each compiled source with a line
+//! like `^C:\SRC\APP\MAIN.CPP` (several sources compiled together are
+//! joined with `|`). When a source named relative on the command line does
+//! not exist below the project directory, the tracked absolute path with
+//! the same trailing components is used instead.
+
+use crate::{
+    path_key,
+    progress::{NoProgress, ProgressSink},
+    query::ends_with_components,
+};
+use anyhow::{Context, Result};
+use encoding_rs::{UTF_8, UTF_16LE};
+use indexmap::IndexMap;
+use log::{debug, info};
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Absolute source paths recorded by the file tracker, by folded file name
+#[derive(Debug, Clone, Default)]
+pub struct TlogIndex {
+    by_name: IndexMap<String, Vec<String>>,
+}
+
+/// Check if `path` names a tracker log of files CL.exe read
+fn is_cl_read_tlog(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .is_some_and(|name| name.starts_with("cl.read.") && name.ends_with(".tlog"))
+}
+
+/// Decode a tracker log; they are written as UTF-16, but accept UTF-8 too
+fn decode(bytes: &[u8]) -> String {
+    let encoding = if bytes.contains(&0) { UTF_16LE } else { UTF_8 };
+    encoding.decode(bytes).0.into_owned()
+}
+
+impl TlogIndex {
+    /// Read the `CL.read.*.tlog` files below each of `dirs`
+    pub fn read_dirs(dirs: &[PathBuf]) -> Result<Self> {
+        Self::read_dirs_with_progress(dirs, &NoProgress)
+    }
+
+    /// [`Self::read_dirs`], reporting the directories searched to
+    /// `progress` as they are found and read
+    pub fn read_dirs_with_progress(dirs: &[PathBuf], progress: &dyn ProgressSink) -> Result<Self> {
+        let mut index = Self::default();
+        let mut tlogs = 0;
+        let mut pending: VecDeque<PathBuf> = dirs.iter().cloned().collect();
+        let mut discovered = pending.len();
+        let mut processed = 0;
+        while let Some(dir) = pending.pop_front() {
+            for subdir in index.read_dir(&dir, &mut tlogs)? {
+                pending.push_back(subdir);
+                discovered += 1;
+            }
+            processed += 1;
+            progress.index_progress(processed, discovered);
+        }
+        progress.finish_index();
+        info!(
+            "Read {} source path(s) from {} tracker log(s)",
+            index.by_name.values().map(Vec::len).sum::<usize>(),
+            tlogs
+        );
+        Ok(index)
+    }
+
+    /// Add the tracker logs in `dir`, counting them in `read`, and return
+    /// its subdirectories
+    fn read_dir(&mut self, dir: &Path, read: &mut usize) -> Result<Vec<PathBuf>> {
+        let mut subdirs = Vec::new();
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read tlog directory: {}", dir.display()))?;
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                subdirs.push(path);
+            } else if is_cl_read_tlog(&path) {
+                let bytes = fs::read(&path)
+                    .with_context(|| format!("Failed to read tlog: {}", path.display()))?;
+                self.add(&decode(&bytes));
+                debug!("Read tracker log {}", path.display());
+                *read += 1;
+            }
+        }
+        Ok(subdirs)
+    }
+
+    /// Add the sources of the blocks in a tracker log
+    fn add(&mut self, text: &str) {
+        let sources = text
+            .lines()
+            .filter_map(|line| line.trim_start_matches('\u{feff}').strip_prefix('^'))
+            .flat_map(|line| line.split('|'));
+        for source in sources.map(str::trim).filter(|source| !source.is_empty()) {
+            let Some(name) = source.rsplit(['\\', '/']).next() else {
+                continue;
+            };
+            let paths = self.by_name.entry(path_key(name)).or_default();
+            if !paths.iter().any(|path| path.eq_ignore_ascii_case(source)) {
+                paths.push(source.to_string());
+            }
+        }
+    }
+
+    /// Whether no sources were found
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// The tracked path ending with `source`, if exactly one does. Leading
+    /// `..` components of `source` are ignored.
+    pub fn resolve(&self, source: &str) -> Option<PathBuf> {
+        let parts: Vec<&str> = source
+            .split(['\\', '/'])
+            .skip_while(|part| matches!(*part, "" | "." | ".."))
+            .collect();
+        let tail = parts.join("\\");
+        let name = parts.last()?;
+        let mut matches = self
+            .by_name
+            .get(&path_key(name))?
+            .iter()
+            .filter(|path| ends_with_components(path, &tail));
+        let path = matches.next()?;
+        matches.next().is_none().then(|| PathBuf::from(path))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[derive(Default)]
+    struct IndexRecorder {
+        events: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl ProgressSink for IndexRecorder {
+        fn index_progress(&self, processed: usize, discovered: usize) {
+            let event = format!("{}/{}", processed, discovered);
+            self.events.lock().unwrap().push(event);
+        }
+        fn finish_index(&self) {
+            self.events.lock().unwrap().push("finish".to_string());
+        }
+    }
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(text.encode_utf16().flat_map(|u| u.to_le_bytes()));
+        bytes
+    }
+
+    #[test]
+    fn test_read_and_resolve() {
+        let dir = tempdir().unwrap();
+        let tlog_dir = dir.path().join("x64").join("Debug").join("app.tlog");
+        fs::create_dir_all(&tlog_dir).unwrap();
+        fs::write(
+            tlog_dir.join("CL.read.1.tlog"),
+            utf16le(
+                "^C:\\SRC\\APP\\MAIN.CPP\r\nC:\\SRC\\APP\\MAIN.H\r\n\
+                 ^C:\\SRC\\APP\\UTIL\\A.CPP|C:\\SRC\\LIB\\UTIL\\A.CPP\r\n",
+            ),
+        )
+        .unwrap();
+        fs::write(
+            tlog_dir.join("CL.write.1.tlog"),
+            utf16le("^C:\\SRC\\X.CPP\r\n"),
+        )
+        .unwrap();
+
+        let progress = IndexRecorder::default();
+        let index =
+            TlogIndex::read_dirs_with_progress(&[dir.path().to_path_buf()], &progress).unwrap();
+        // The temp dir, x64, Debug and app.tlog
+        assert_eq!(
+            *progress.events.lock().unwrap(),
+            ["1/2", "2/3", "3/4", "4/4", "finish"]
+        );
+        assert_eq!(
+            index.resolve("main.cpp"),
+            Some(PathBuf::from(r"C:\SRC\APP\MAIN.CPP"))
+        );
+        assert_eq!(
+            index.resolve(r"..\lib\util\a.cpp"),
+            Some(PathBuf::from(r"C:\SRC\LIB\UTIL\A.CPP"))
+        );
+
+        // Ambiguous, headers and sources only in other tlogs are not resolved
+        assert_eq!(index.resolve("a.cpp"), None);
+        assert_eq!(index.resolve("main.h"), None);
+        assert_eq!(index.resolve("x.cpp"), None);
+    }
+}
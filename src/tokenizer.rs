@@ -0,0 +1,286 @@
+//! Splitting logged command lines into arguments.
+//!
+//! The defaults match what MSBuild logs for CL.exe. Logs of other tools can
+//! use POSIX shell quoting instead, chosen in the `[tokenizer]` table of
+//! `.ms2cc.toml`.
+
+use serde::Deserialize;
+
+/// How quotes and backslashes group a command line into arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuoteRules {
+    /// `"` groups, backslashes only escape quotes (CommandLineToArgvW)
+    #[default]
+    Windows,
+    /// `'` and `"` group, backslashes escape the next character (sh)
+    Posix,
+}
+
+/// Splits command lines into arguments
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tokenizer {
+    /// Quoting rules of the logged command lines
+    pub quote_rules: QuoteRules,
+    /// Rejoin an unquoted executable path with spaces, like
+    /// `C:\Program Files\...\cl.exe`, into the first argument
+    pub merge_executable_path: bool,
+    /// Keep quotes and escapes as logged instead of removing them
+    pub keep_quotes: bool,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Self {
+            quote_rules: QuoteRules::Windows,
+            merge_executable_path: false,
+            keep_quotes: true,
+        }
+    }
+}
+
+/// An argument as logged and with its quoting removed
+struct Token {
+    raw: String,
+    value: String,
+}
+
+impl Tokenizer {
+    /// Split `line` into arguments
+    pub fn tokenize(&self, line: &str) -> Vec<String> {
+        let mut tokens = match self.quote_rules {
+            QuoteRules::Windows => windows_tokens(line),
+            QuoteRules::Posix => posix_tokens(line),
+        };
+        if self.merge_executable_path {
+            merge_executable_path(&mut tokens);
+        }
+        tokens
+            .into_iter()
+            .map(|token| {
+                if self.keep_quotes {
+                    token.raw
+                } else {
+                    token.value
+                }
+            })
+            .collect()
+    }
+}
+
+/// Split at unquoted blanks; `"` toggles quoting
+fn windows_tokens(line: &str) -> Vec<Token> {
+    let mut raws = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for ch in line.chars() {
+        match ch {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(ch);
+            }
+            ' ' | '\t' if !in_quotes => {
+                if !current.is_empty() {
+                    raws.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(ch),
+        }
+    }
+    if !current.is_empty() {
+        raws.push(current);
+    }
+
+    raws.into_iter()
+        .map(|raw| Token {
+            value: windows_unquote(&raw),
+            raw,
+        })
+        .collect()
+}
+
+/// Remove quotes the way CL.exe does: `/I"C:\a b"` becomes `/IC:\a b`, and
+/// backslashes before a quote are halved, an odd one left over making the
+/// quote literal
+fn windows_unquote(token: &str) -> String {
+    let mut argument = String::with_capacity(token.len());
+    let mut backslashes = 0;
+    for ch in token.chars() {
+        match ch {
+            '\\' => backslashes += 1,
+            '"' => {
+                argument.extend(std::iter::repeat_n('\\', backslashes / 2));
+                if backslashes % 2 == 1 {
+                    argument.push('"');
+                }
+                backslashes = 0;
+            }
+            _ => {
+                argument.extend(std::iter::repeat_n('\\', std::mem::take(&mut backslashes)));
+                argument.push(ch);
+            }
+        }
+    }
+    argument.extend(std::iter::repeat_n('\\', backslashes));
+    argument
+}
+
+/// Split the way `sh` does, without expansions
+fn posix_tokens(line: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut raw = String::new();
+    let mut value = String::new();
+    // Quoting starts a token even if nothing is inside, as in `''`
+    let mut started = false;
+    let mut chars = line.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ' ' | '\t' => {
+                if std::mem::take(&mut started) {
+                    tokens.push(Token {
+                        raw: std::mem::take(&mut raw),
+                        value: std::mem::take(&mut value),
+                    });
+                }
+                continue;
+            }
+            '\\' => {
+                raw.push(ch);
+                if let Some(next) = chars.next() {
+                    raw.push(next);
+                    value.push(next);
+                }
+            }
+            '\'' => {
+                raw.push(ch);
+                for ch in chars.by_ref() {
+                    raw.push(ch);
+                    if ch == '\'' {
+                        break;
+                    }
+                    value.push(ch);
+                }
+            }
+            '"' => {
+                raw.push(ch);
+                while let Some(ch) = chars.next() {
+                    raw.push(ch);
+                    match ch {
+                        '"' => break,
+                        '\\' => {
+                            let Some(next) = chars.next() else { break };
+                            raw.push(next);
+                            // Inside double quotes only these are escaped
+                            if !matches!(next, '"' | '\\' | '$' | '`') {
+                                value.push('\\');
+                            }
+                            value.push(next);
+                        }
+                        _ => value.push(ch),
+                    }
+                }
+            }
+            _ => {
+                raw.push(ch);
+                value.push(ch);
+            }
+        }
+        started = true;
+    }
+    if started {
+        tokens.push(Token { raw, value });
+    }
+    tokens
+}
+
+/// Join the leading tokens of an unquoted executable path that contains
+/// spaces, up to the first one ending in `.exe`
+fn merge_executable_path(tokens: &mut Vec<Token>) {
+    let is_exe = |token: &Token| token.raw.to_lowercase().ends_with(".exe");
+    let Some(first) = tokens.first() else {
+        return;
+    };
+    if is_exe(first) || first.raw.starts_with(['"', '\'', '/', '-']) {
+        return;
+    }
+    let Some(end) = tokens.iter().position(is_exe) else {
+        return;
+    };
+    // Flags in between mean the executable is elsewhere on the line
+    if tokens[1..end]
+        .iter()
+        .any(|token| token.raw.starts_with(['"', '\'', '/', '-']))
+    {
+        return;
+    }
+
+    let parts: Vec<Token> = tokens.drain(..=end).collect();
+    let join = |part: fn(&Token) -> &str| parts.iter().map(part).collect::<Vec<_>>().join(" ");
+    let merged = Token {
+        raw: join(|token| &token.raw),
+        value: join(|token| &token.value),
+    };
+    tokens.insert(0, merged);
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_rules() {
+        let line = r#"C:\Program Files\VC\cl.exe /I"C:\a b" /DNAME=\"x\" main.cpp"#;
+        assert_eq!(
+            Tokenizer::default().tokenize(line),
+            [
+                r"C:\Program",
+                r"Files\VC\cl.exe",
+                r#"/I"C:\a b""#,
+                r#"/DNAME=\"x\""#,
+                "main.cpp"
+            ]
+        );
+
+        let tokenizer = Tokenizer {
+            merge_executable_path: true,
+            keep_quotes: false,
+            ..Tokenizer::default()
+        };
+        assert_eq!(
+            tokenizer.tokenize(line),
+            [
+                r"C:\Program Files\VC\cl.exe",
+                r"/IC:\a b",
+                r#"/DNAME="x""#,
+                "main.cpp"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_posix_rules() {
+        let tokenizer = Tokenizer {
+            quote_rules: QuoteRules::Posix,
+            keep_quotes: false,
+            ..Tokenizer::default()
+        };
+        assert_eq!(
+            tokenizer.tokenize(r#"clang-cl '-DA=a b' "-DB=\"c\"" my\ file.cpp '' "\n""#),
+            [
+                "clang-cl",
+                "-DA=a b",
+                r#"-DB="c""#,
+                "my file.cpp",
+                "",
+                r"\n"
+            ]
+        );
+    }
+}
@@ -0,0 +1,495 @@
+//! Pluggable rewriting of database entries before they are written.
+//!
+//! Transformers are registered by name and run in the order listed by the
+//! selected profile in `.ms2cc.toml`. Embedding code can register its own
+//! transformers next to the built-in ones. The flag rewrites given on the
+//! command line (`--strip-flag`, `--add-flag`, `--replace-prefix`,
+//! `--replace-compiler`) run after the profile's transformers.
+
+use crate::{CompileCommand, join_arguments, module_flag_takes_value, tokenize_command_line};
+use anyhow::{Result, bail};
+use indexmap::IndexMap;
+use log::debug;
+use std::{fmt, sync::Arc};
+
+/// A rewrite applied to every entry before output
+pub trait EntryTransformer {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()>;
+}
+
+type Factory = Box<dyn Fn() -> Box<dyn EntryTransformer>>;
+
+/// Named transformer factories
+pub struct TransformerRegistry {
+    factories: IndexMap<String, Factory>,
+}
+
+impl Default for TransformerRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            factories: IndexMap::new(),
+        };
+        registry.register("normalize-drive-letter", || Box::new(NormalizeDriveLetter));
+        registry.register("translate-module-flags", || Box::new(TranslateModuleFlags));
+        registry.register("translate-external-includes", || {
+            Box::new(TranslateExternalIncludes)
+        });
+        registry
+    }
+}
+
+impl TransformerRegistry {
+    /// Register a transformer under `name`, replacing any existing one
+    pub fn register<F>(&mut self, name: impl Into<String>, factory: F)
+    where
+        F: Fn() -> Box<dyn EntryTransformer> + 'static,
+    {
+        self.factories.insert(name.into(), Box::new(factory));
+    }
+
+    /// Instantiate the transformers named in `names`, in order
+    pub fn chain(&self, names: &[String]) -> Result<TransformerChain> {
+        let mut transformers = Vec::with_capacity(names.len());
+        for name in names {
+            let Some(factory) = self.factories.get(name) else {
+                let available: Vec<&str> = self.factories.keys().map(String::as_str).collect();
+                bail!(
+                    "Unknown transformer '{}' (available: {})",
+                    name,
+                    available.join(", ")
+                );
+            };
+            transformers.push((name.clone(), factory()));
+        }
+        Ok(TransformerChain { transformers })
+    }
+}
+
+/// Transformers applied one after another to each entry
+#[derive(Default)]
+pub struct TransformerChain {
+    transformers: Vec<(String, Box<dyn EntryTransformer>)>,
+}
+
+impl TransformerChain {
+    /// Append `transformer` to run after the ones already in the chain
+    pub fn push(&mut self, name: impl Into<String>, transformer: Box<dyn EntryTransformer>) {
+        self.transformers.push((name.into(), transformer));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transformers.is_empty()
+    }
+
+    /// Run every transformer on every entry
+    pub fn apply(&self, entries: &mut [CompileCommand]) -> Result<()> {
+        for (name, transformer) in &self.transformers {
+            debug!("Applying transformer '{}'", name);
+            for entry in entries.iter_mut() {
+                transformer.transform(entry).map_err(|e| {
+                    e.context(format!("Transformer '{}' failed on {}", name, entry.file))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A transformer held by options that are cloned and sent to other threads,
+/// e.g. one given to [`crate::Ms2ccBuilder::transformer`]
+#[derive(Clone)]
+pub struct SharedTransformer {
+    name: String,
+    transformer: Arc<dyn EntryTransformer + Send + Sync>,
+}
+
+impl SharedTransformer {
+    pub fn new(
+        name: impl Into<String>,
+        transformer: impl EntryTransformer + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            transformer: Arc::new(transformer),
+        }
+    }
+
+    /// Name reported when the transformer fails
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Debug for SharedTransformer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedTransformer")
+            .field(&self.name)
+            .finish()
+    }
+}
+
+impl EntryTransformer for SharedTransformer {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        self.transformer.transform(entry)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Built-in transformers
+// ----------------------------------------------------------------------------
+
+/// Upper-case the drive letter of `file`, `directory` and `output`.
+///
+/// Logs can mix `c:\src` and `C:\src` for the same tree, which makes the
+/// database merge and clangd treat one file as two.
+struct NormalizeDriveLetter;
+
+impl NormalizeDriveLetter {
+    fn normalize(path: &mut String) {
+        let bytes = path.as_bytes();
+        if bytes.len() >= 2 && bytes[0].is_ascii_lowercase() && bytes[1] == b':' {
+            path[..1].make_ascii_uppercase();
+        }
+    }
+}
+
+impl EntryTransformer for NormalizeDriveLetter {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        Self::normalize(&mut entry.file);
+        Self::normalize(&mut entry.directory);
+        if let Some(output) = &mut entry.output {
+            Self::normalize(output);
+        }
+        Ok(())
+    }
+}
+
+/// Rewrite MSVC C++20 module flags for clang.
+///
+/// clang cannot read MSVC `.ifc` files, so flags naming them are dropped and
+/// clangd builds its own modules from the sources. `/interface` and
+/// `/internalPartition` become `-x c++-module` so the unit is still parsed as
+/// a module even when its extension does not say so.
+struct TranslateModuleFlags;
+
+impl EntryTransformer for TranslateModuleFlags {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let mut tokens = Vec::new();
+        let mut skip_value = false;
+        for token in tokenize_command_line(&entry.command) {
+            if std::mem::take(&mut skip_value) {
+                continue;
+            }
+            if module_flag_takes_value(&token) {
+                skip_value = true;
+                continue;
+            }
+            match token.trim_start_matches(['/', '-']) {
+                "interface" | "internalPartition" => tokens.push("/clang:-xc++-module".to_string()),
+                "exportHeader" | "ifcOnly" => {}
+                _ => tokens.push(token),
+            }
+        }
+        entry.command = tokens.join(" ");
+        Ok(())
+    }
+}
+
+/// Rewrite MSVC external include flags for older clang.
+///
+/// clang before 13 does not know `/external:I`; `/imsvc` adds the directory
+/// as a system include directory, so its headers' warnings stay quiet. The
+/// `/external:W<n>` and `/external:anglebrackets` warning controls are
+/// dropped. `/external:env:<VAR>` is kept: the variable is not known here.
+struct TranslateExternalIncludes;
+
+impl EntryTransformer for TranslateExternalIncludes {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let tokens: Vec<String> = tokenize_command_line(&entry.command)
+            .into_iter()
+            .filter_map(|token| {
+                let Some(flag) = token.strip_prefix(['/', '-']) else {
+                    return Some(token);
+                };
+                if let Some(dir) = flag.strip_prefix("external:I") {
+                    Some(format!("/imsvc{}", dir))
+                } else if flag.starts_with("external:W")
+                    || flag == "external:anglebrackets"
+                    || flag == "experimental:external"
+                {
+                    None
+                } else {
+                    Some(token)
+                }
+            })
+            .collect();
+        entry.command = tokens.join(" ");
+        Ok(())
+    }
+}
+
+/// `--strip-flag`: drop an argument from the command. A trailing `*`
+/// drops every argument starting with the rest, e.g. `/analyze*`.
+pub struct StripFlag(pub String);
+
+impl StripFlag {
+    fn matches(&self, token: &str) -> bool {
+        match self.0.strip_suffix('*') {
+            Some(prefix) => token.starts_with(prefix),
+            None => token == self.0,
+        }
+    }
+}
+
+impl EntryTransformer for StripFlag {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let mut tokens = tokenize_command_line(&entry.command);
+        let count = tokens.len();
+        // Never strip the compiler itself
+        let mut is_compiler = true;
+        tokens.retain(|token| std::mem::take(&mut is_compiler) || !self.matches(token));
+        if tokens.len() < count {
+            entry.command = tokens.join(" ");
+        }
+        Ok(())
+    }
+}
+
+/// Keep only the arguments `0` accepts; the compiler is always kept
+pub struct FlagFilter<F>(pub F);
+
+impl<F: Fn(&str) -> bool> EntryTransformer for FlagFilter<F> {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let mut tokens = tokenize_command_line(&entry.command);
+        let count = tokens.len();
+        let mut is_compiler = true;
+        tokens.retain(|token| std::mem::take(&mut is_compiler) || (self.0)(token));
+        if tokens.len() < count {
+            entry.command = tokens.join(" ");
+        }
+        Ok(())
+    }
+}
+
+/// `--add-flag`: insert arguments right after the compiler, so they come
+/// before the source file like the logged flags do
+pub struct AddFlag(pub String);
+
+impl EntryTransformer for AddFlag {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let flag = self.0.trim();
+        if flag.is_empty() {
+            return Ok(());
+        }
+        let mut tokens = tokenize_command_line(&entry.command);
+        let at = tokens.len().min(1);
+        tokens.insert(at, flag.to_string());
+        entry.command = tokens.join(" ");
+        Ok(())
+    }
+}
+
+/// `--replace-prefix FROM=TO`: rewrite the start of arguments, e.g.
+/// `/external:I=/I` to turn external include directories into plain ones
+pub struct ReplacePrefix {
+    pub from: String,
+    pub to: String,
+}
+
+impl ReplacePrefix {
+    /// Parse `FROM=TO`, splitting at the first `=`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let Some((from, to)) = spec.split_once('=') else {
+            bail!("Invalid prefix replacement '{}' (expected FROM=TO)", spec);
+        };
+        if from.is_empty() {
+            bail!("Invalid prefix replacement '{}' (FROM is empty)", spec);
+        }
+        Ok(Self {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+}
+
+impl EntryTransformer for ReplacePrefix {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let mut tokens = tokenize_command_line(&entry.command);
+        let mut changed = false;
+        for token in tokens.iter_mut().skip(1) {
+            if let Some(rest) = token.strip_prefix(self.from.as_str()) {
+                *token = format!("{}{}", self.to, rest);
+                changed = true;
+            }
+        }
+        if changed {
+            entry.command = tokens.join(" ");
+        }
+        Ok(())
+    }
+}
+
+/// `--replace-compiler PATH`: run every command with another compiler, e.g.
+/// the clang-cl of an LLVM install when the logged toolchain is missing
+pub struct ReplaceCompiler(pub String);
+
+impl EntryTransformer for ReplaceCompiler {
+    fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+        let mut tokens = tokenize_command_line(&entry.command);
+        if let Some(compiler) = tokens.first_mut() {
+            *compiler = join_arguments(std::slice::from_ref(&self.0));
+            entry.command = tokens.join(" ");
+        }
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file: &str) -> CompileCommand {
+        CompileCommand {
+            directory: r"c:\src".to_string(),
+            command: format!("cl /c {}", file),
+            file: file.to_string(),
+            output: None,
+            arguments: None,
+            failed: false,
+            configuration: None,
+        }
+    }
+
+    struct AppendFlag(&'static str);
+
+    impl EntryTransformer for AppendFlag {
+        fn transform(&self, entry: &mut CompileCommand) -> Result<()> {
+            entry.command.push(' ');
+            entry.command.push_str(self.0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_chain_runs_in_profile_order() {
+        let mut registry = TransformerRegistry::default();
+        registry.register("first", || Box::new(AppendFlag("/DFIRST")));
+        registry.register("second", || Box::new(AppendFlag("/DSECOND")));
+
+        let chain = registry
+            .chain(&["second".to_string(), "first".to_string()])
+            .unwrap();
+        let mut entries = vec![entry("a.cpp")];
+        chain.apply(&mut entries).unwrap();
+
+        assert_eq!(entries[0].command, "cl /c a.cpp /DSECOND /DFIRST");
+    }
+
+    #[test]
+    fn test_unknown_transformer_lists_available() {
+        let err = TransformerRegistry::default()
+            .chain(&["nope".to_string()])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("nope"));
+        assert!(err.to_string().contains("normalize-drive-letter"));
+    }
+
+    #[test]
+    fn test_failing_transformer_names_entry() {
+        struct Fail;
+        impl EntryTransformer for Fail {
+            fn transform(&self, _entry: &mut CompileCommand) -> Result<()> {
+                bail!("boom")
+            }
+        }
+
+        let mut registry = TransformerRegistry::default();
+        registry.register("fail", || Box::new(Fail));
+        let chain = registry.chain(&["fail".to_string()]).unwrap();
+
+        let err = chain.apply(&mut [entry("a.cpp")]).unwrap_err();
+        assert!(err.to_string().contains("'fail'"));
+        assert!(err.to_string().contains("a.cpp"));
+    }
+
+    #[test]
+    fn test_normalize_drive_letter() {
+        let mut e = entry(r"c:\src\a.cpp");
+        e.output = Some(r"d:\obj\a.obj".to_string());
+        NormalizeDriveLetter.transform(&mut e).unwrap();
+
+        assert_eq!(e.file, r"C:\src\a.cpp");
+        assert_eq!(e.directory, r"C:\src");
+        assert_eq!(e.output.as_deref(), Some(r"D:\obj\a.obj"));
+
+        let mut relative = entry("src/a.cpp");
+        NormalizeDriveLetter.transform(&mut relative).unwrap();
+        assert_eq!(relative.file, "src/a.cpp");
+    }
+
+    #[test]
+    fn test_flag_rewrites() {
+        let mut e = entry("a.cpp");
+        e.command = "cl.exe /analyze /analyze:plugin x.dll /external:Iinc /c a.cpp".to_string();
+
+        let mut chain = TransformerChain::default();
+        chain.push("strip-flag", Box::new(StripFlag("/analyze*".to_string())));
+        chain.push("strip-flag", Box::new(StripFlag("x.dll".to_string())));
+        chain.push(
+            "replace-prefix",
+            Box::new(ReplacePrefix::parse("/external:I=/I").unwrap()),
+        );
+        chain.push(
+            "add-flag",
+            Box::new(AddFlag(" -fms-extensions".to_string())),
+        );
+        let mut entries = vec![e];
+        chain.apply(&mut entries).unwrap();
+
+        assert_eq!(entries[0].command, "cl.exe -fms-extensions /Iinc /c a.cpp");
+        assert!(ReplacePrefix::parse("/I").is_err());
+        assert!(ReplacePrefix::parse("=/I").is_err());
+    }
+
+    #[test]
+    fn test_replace_compiler() {
+        let mut e = entry("a.cpp");
+        e.command = r#""C:\VC\bin\cl.exe" /c a.cpp"#.to_string();
+        let replace = ReplaceCompiler(r"C:\Program Files\LLVM\bin\clang-cl.exe".to_string());
+        replace.transform(&mut e).unwrap();
+        assert_eq!(
+            e.command,
+            r#""C:\Program Files\LLVM\bin\clang-cl.exe" /c a.cpp"#
+        );
+    }
+
+    #[test]
+    fn test_translate_external_includes() {
+        let mut e = entry("a.cpp");
+        e.command =
+            r#"cl.exe /external:I"C:\SDK\inc" /external:W0 /external:env:INC /c a.cpp"#.to_string();
+        TranslateExternalIncludes.transform(&mut e).unwrap();
+
+        assert_eq!(
+            e.command,
+            r#"cl.exe /imsvc"C:\SDK\inc" /external:env:INC /c a.cpp"#
+        );
+    }
+
+    #[test]
+    fn test_translate_module_flags() {
+        let mut e = entry("core.ixx");
+        e.command = r#"cl.exe /std:c++20 /interface /reference Base=obj\Base.ifc /ifcOutput "obj\\" /c "C:\src\core.ixx""#.to_string();
+        TranslateModuleFlags.transform(&mut e).unwrap();
+
+        assert_eq!(
+            e.command,
+            r#"cl.exe /std:c++20 /clang:-xc++-module /c "C:\src\core.ixx""#
+        );
+    }
+}
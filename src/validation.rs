@@ -0,0 +1,205 @@
+//! `ms2cc validate`: check a database against the Clang JSON Compilation
+//! Database format and against what clangd needs from it.
+//!
+//! Format: <https://clang.llvm.org/docs/JSONCompilationDatabase.html>
+
+use crate::{doctor::Severity, long_path, should_filter_flag, split_arguments};
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+use std::{fmt, path::Path};
+
+/// A problem with one entry of the database
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// Position of the entry in the database
+    pub index: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "entry {}: [{}] {}",
+            self.index, self.severity, self.message
+        )
+    }
+}
+
+/// Check if `path` is absolute on Windows or on the host. UNC shares
+/// (`\\server\share`) and `\\?\` paths are roots of their own.
+pub(crate) fn is_absolute(path: &str) -> bool {
+    let path = long_path::strip_verbatim(path);
+    let bytes = path.as_bytes();
+    let has_drive = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && matches!(bytes[2], b'\\' | b'/');
+    has_drive || path.starts_with(r"\\") || Path::new(path.as_ref()).is_absolute()
+}
+
+/// Read a string field, reporting it if it is missing or not a string
+fn string_field<'a>(
+    entry: &'a Map<String, Value>,
+    name: &str,
+    required: bool,
+    problems: &mut Vec<String>,
+) -> Option<&'a str> {
+    match entry.get(name) {
+        Some(Value::String(value)) => Some(value),
+        Some(_) => {
+            problems.push(format!("'{}' is not a string", name));
+            None
+        }
+        None if required => {
+            problems.push(format!("missing '{}'", name));
+            None
+        }
+        None => None,
+    }
+}
+
+/// Problems with one entry, most severe first
+fn validate_entry(index: usize, entry: &Value, check_files: bool) -> Vec<Problem> {
+    let error = |message: String| Problem {
+        index,
+        severity: Severity::Error,
+        message,
+    };
+    let Some(entry) = entry.as_object() else {
+        return vec![error("not an object".to_string())];
+    };
+
+    let mut errors = Vec::new();
+    let directory = string_field(entry, "directory", true, &mut errors);
+    let file = string_field(entry, "file", true, &mut errors);
+    let command = string_field(entry, "command", false, &mut errors);
+    string_field(entry, "output", false, &mut errors);
+
+    let arguments = match entry.get("arguments") {
+        Some(Value::Array(values)) => {
+            let strings: Option<Vec<String>> = values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string))
+                .collect();
+            if strings.is_none() {
+                errors.push("'arguments' is not an array of strings".to_string());
+            }
+            strings
+        }
+        Some(_) => {
+            errors.push("'arguments' is not an array".to_string());
+            None
+        }
+        None => None,
+    };
+    if !entry.contains_key("command") && !entry.contains_key("arguments") {
+        errors.push("missing both 'command' and 'arguments'".to_string());
+    }
+
+    if let Some(directory) = directory
+        && !is_absolute(directory)
+    {
+        errors.push(format!("directory '{}' is not absolute", directory));
+    }
+
+    let mut problems: Vec<Problem> = errors.into_iter().map(error).collect();
+    let warning = |message: String| Problem {
+        index,
+        severity: Severity::Warning,
+        message,
+    };
+
+    if let (Some(directory), Some(file)) = (directory, file)
+        && check_files
+    {
+        let path = Path::new(directory).join(file);
+        if !long_path::for_filesystem(&path).is_file() {
+            problems.push(warning(format!("file '{}' does not exist", path.display())));
+        }
+    }
+
+    let arguments = arguments.or_else(|| command.map(split_arguments));
+    match arguments.as_deref() {
+        Some([]) => problems.push(error("the command is empty".to_string())),
+        Some([_compiler, flags @ ..]) => {
+            if let Some(flag) = flags.iter().find(|flag| should_filter_flag(flag)) {
+                problems.push(warning(format!(
+                    "precompiled header flag '{}' breaks clangd",
+                    flag
+                )));
+            }
+            if let Some(file) = file {
+                let name = Path::new(file).file_name().and_then(|name| name.to_str());
+                let names_file =
+                    name.is_some_and(|name| flags.iter().any(|flag| flag.ends_with(name)));
+                if !names_file {
+                    problems.push(warning(format!("the command does not compile '{}'", file)));
+                }
+            }
+        }
+        None => {}
+    }
+
+    problems
+}
+
+/// Check every entry of a parsed database
+pub fn validate(database: &Value, check_files: bool) -> Result<Vec<Problem>> {
+    let Some(entries) = database.as_array() else {
+        bail!("The database is not a JSON array");
+    };
+    let mut problems: Vec<Problem> = entries
+        .iter()
+        .enumerate()
+        .flat_map(|(index, entry)| validate_entry(index, entry, check_files))
+        .collect();
+    problems.sort_by_key(|problem| (problem.severity, problem.index));
+    Ok(problems)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_valid_database() {
+        let database = json!([
+            {"directory": r"C:\src", "file": "a.cpp", "command": "cl.exe /c a.cpp"},
+            {"directory": "/src", "file": "b.c", "arguments": ["clang-cl", "/c", "/src/b.c"]},
+        ]);
+        assert!(validate(&database, false).unwrap().is_empty());
+        assert!(validate(&json!({}), false).is_err());
+    }
+
+    #[test]
+    fn test_problems_name_entries() {
+        let database = json!([
+            {"directory": r"C:\src", "file": "a.cpp", "command": "cl.exe /c a.cpp"},
+            {"directory": "src", "file": 1},
+            {"directory": r"C:\src", "file": "c.cpp", "command": "cl.exe /Yustdafx.h /c d.cpp"},
+        ]);
+        let problems: Vec<String> = validate(&database, false)
+            .unwrap()
+            .iter()
+            .map(Problem::to_string)
+            .collect();
+
+        assert_eq!(
+            problems,
+            vec![
+                "entry 1: [error] 'file' is not a string",
+                "entry 1: [error] missing both 'command' and 'arguments'",
+                "entry 1: [error] directory 'src' is not absolute",
+                "entry 2: [warning] precompiled header flag '/Yustdafx.h' breaks clangd",
+                "entry 2: [warning] the command does not compile 'c.cpp'",
+            ]
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! `--watch`: regenerate the database whenever MSBuild rewrites a log.
+
+use anyhow::{Context, Result};
+use log::{debug, error, info};
+use notify::{Event, EventKind, RecursiveMode, Watcher as _};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+/// Quiet period after the last change before regenerating; MSBuild writes
+/// the log in many small appends
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Prefix of the temporary files the output is written through
+const TEMP_FILE_PREFIX: &str = ".tmp";
+
+/// A watched path whose changes trigger a regeneration
+#[derive(Debug)]
+struct Trigger {
+    path: PathBuf,
+    is_dir: bool,
+}
+
+impl Trigger {
+    fn matches(&self, path: &Path) -> bool {
+        if self.is_dir {
+            path.starts_with(&self.path)
+        } else {
+            path == self.path
+        }
+    }
+}
+
+/// Absolute form of `path` as the notifier reports it. Files may not exist
+/// yet, so only their directory is resolved.
+fn resolve(path: &Path) -> Result<PathBuf> {
+    let resolve_dir = |dir: &Path| {
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        dir.canonicalize()
+            .with_context(|| format!("Failed to resolve directory: {}", dir.display()))
+    };
+
+    if path.is_dir() {
+        return resolve_dir(path);
+    }
+    let name = path
+        .file_name()
+        .with_context(|| format!("Not a file path: {}", path.display()))?;
+    Ok(resolve_dir(path.parent().unwrap_or(Path::new("")))?.join(name))
+}
+
+/// Paths to watch and paths whose changes are ignored
+#[derive(Debug, Default)]
+pub struct Watch {
+    triggers: Vec<Trigger>,
+    watches: Vec<(PathBuf, RecursiveMode)>,
+    ignored: Vec<PathBuf>,
+}
+
+impl Watch {
+    /// Regenerate when this file is created or written. Its directory is
+    /// watched, since MSBuild replaces the log rather than appending to it.
+    pub fn file(&mut self, path: &Path) -> Result<()> {
+        let path = resolve(path)?;
+        if let Some(dir) = path.parent() {
+            self.watches
+                .push((dir.to_path_buf(), RecursiveMode::NonRecursive));
+        }
+        self.triggers.push(Trigger {
+            path,
+            is_dir: false,
+        });
+        Ok(())
+    }
+
+    /// Regenerate when anything below this directory changes
+    pub fn dir(&mut self, path: &Path, recursive: bool) -> Result<()> {
+        let path = resolve(path)?;
+        let mode = if recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        self.watches.push((path.clone(), mode));
+        self.triggers.push(Trigger { path, is_dir: true });
+        Ok(())
+    }
+
+    /// Never regenerate because of this file, e.g. one ms2cc writes itself
+    pub fn ignore(&mut self, path: &Path) -> Result<()> {
+        self.ignored.push(resolve(path)?);
+        Ok(())
+    }
+
+    /// Check if an event should trigger a regeneration
+    fn is_relevant(&self, event: &Event) -> bool {
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            return false;
+        }
+        event.paths.iter().any(|path| {
+            let is_temp = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(TEMP_FILE_PREFIX));
+            !is_temp
+                && !self.ignored.iter().any(|ignored| ignored == path)
+                && self.triggers.iter().any(|trigger| trigger.matches(path))
+        })
+    }
+
+    /// Run `generate` now and after every relevant change, until interrupted.
+    /// Failed runs are logged and the watch goes on.
+    pub fn run(&self, mut generate: impl FnMut() -> Result<()>) -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(sender).context("Failed to start file watcher")?;
+        for (path, mode) in &self.watches {
+            watcher
+                .watch(path, *mode)
+                .with_context(|| format!("Failed to watch {}", path.display()))?;
+            debug!("Watching {}", path.display());
+        }
+
+        loop {
+            if let Err(e) = generate() {
+                error!("Regeneration failed: {:?}", e);
+            }
+            info!("Watching for changes (press Ctrl+C to stop)");
+
+            // Block until a relevant change, then wait for the writes to settle
+            loop {
+                let event = receiver.recv().context("File watcher stopped")?;
+                if self.is_relevant(&event.context("File watcher error")?) {
+                    break;
+                }
+            }
+            loop {
+                match receiver.recv_timeout(DEBOUNCE) {
+                    Ok(_) => continue,
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        anyhow::bail!("File watcher stopped")
+                    }
+                }
+            }
+            info!("Input changed, regenerating");
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, ModifyKind, RemoveKind};
+    use tempfile::tempdir;
+
+    fn event(kind: EventKind, path: PathBuf) -> Event {
+        Event::new(kind).add_path(path)
+    }
+
+    #[test]
+    fn test_log_changes_are_relevant() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("msbuild.log");
+        let mut watch = Watch::default();
+        watch.file(&log).unwrap();
+
+        let log = resolve(&log).unwrap();
+        let other = log.with_file_name("other.log");
+        assert!(watch.is_relevant(&event(EventKind::Create(CreateKind::File), log.clone())));
+        assert!(watch.is_relevant(&event(EventKind::Modify(ModifyKind::Any), log.clone())));
+        assert!(!watch.is_relevant(&event(EventKind::Remove(RemoveKind::File), log)));
+        assert!(!watch.is_relevant(&event(EventKind::Modify(ModifyKind::Any), other)));
+    }
+
+    #[test]
+    fn test_own_output_is_ignored() {
+        let dir = tempdir().unwrap();
+        let mut watch = Watch::default();
+        watch.dir(dir.path(), true).unwrap();
+        watch
+            .ignore(&dir.path().join("compile_commands.json"))
+            .unwrap();
+
+        let root = resolve(dir.path()).unwrap();
+        let modify = |path: PathBuf| event(EventKind::Modify(ModifyKind::Any), path);
+        assert!(watch.is_relevant(&modify(root.join("src").join("main.cpp"))));
+        assert!(!watch.is_relevant(&modify(root.join("compile_commands.json"))));
+        assert!(!watch.is_relevant(&modify(root.join(".tmpA1b2C3"))));
+    }
+}
@@ -1,6 +1,7 @@
 // Integration tests for ms2cc
 // Tests use sanitized real MSBuild logs from tests/fixtures/
 
+use ms2cc::doctor::Severity;
 use serde_json::Value;
 use std::fs;
 use std::path::PathBuf;
@@ -74,46 +75,18 @@ fn load_expected_json(expected_file: &str) -> Result<Value, String> {
 /// Validate that the JSON follows the Clang JSON Compilation Database spec
 /// Spec: https://clang.llvm.org/docs/JSONCompilationDatabase.html
 fn validate_spec_compliance(json: &Value) -> Result<(), String> {
-    // Check JSON is an array
-    let array = json.as_array().ok_or("JSON is not an array")?;
+    // Fixture sources do not exist on disk, so only the format is checked
+    let problems = ms2cc::validation::validate(json, false).map_err(|e| e.to_string())?;
+    if let Some(problem) = problems
+        .iter()
+        .find(|problem| problem.severity == Severity::Error)
+    {
+        return Err(problem.to_string());
+    }
 
+    let array = json.as_array().ok_or("JSON is not an array")?;
     for (idx, entry) in array.iter().enumerate() {
-        let obj = entry
-            .as_object()
-            .ok_or(format!("Entry {} is not an object", idx))?;
-
-        // Check required fields
-        if !obj.contains_key("directory") {
-            return Err(format!("Entry {} missing 'directory' field", idx));
-        }
-        if !obj.contains_key("file") {
-            return Err(format!("Entry {} missing 'file' field", idx));
-        }
-
-        // Must have either 'command' OR 'arguments' (ms2cc uses 'command')
-        if !obj.contains_key("command") && !obj.contains_key("arguments") {
-            return Err(format!(
-                "Entry {} missing both 'command' and 'arguments' fields",
-                idx
-            ));
-        }
-
-        // Validate 'directory' is a string
-        obj.get("directory")
-            .and_then(|v| v.as_str())
-            .ok_or(format!("Entry {}: 'directory' is not a string", idx))?;
-
-        // Validate 'file' is a string
-        let file_str = obj
-            .get("file")
-            .and_then(|v| v.as_str())
-            .ok_or(format!("Entry {}: 'file' is not a string", idx))?;
-
-        // Validate 'command' is a string (if present)
-        if let Some(cmd) = obj.get("command") {
-            cmd.as_str()
-                .ok_or(format!("Entry {}: 'command' is not a string", idx))?;
-        }
+        let file_str = entry["file"].as_str().unwrap_or_default();
 
         // Check file extension is a source file
         let valid_extensions = [".c", ".cpp", ".cc", ".cxx", ".C"];
@@ -384,6 +357,37 @@ fn test_missing_input_does_not_overwrite_existing_output() {
     let _ = fs::remove_file(&output_path);
 }
 
+#[test]
+fn test_empty_log_does_not_replace_existing_output() {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let input_path = dir.path().join("empty.log");
+    let output_path = dir.path().join("compile_commands.json");
+    let sentinel = r#"[{"directory":"/src","command":"cl /c a.cpp","file":"a.cpp"}]"#;
+    fs::write(&input_path, "Build started.\n").expect("Failed to create test log");
+    fs::write(&output_path, sentinel).expect("Failed to create test output file");
+
+    let args = [
+        "--input-file",
+        input_path.to_str().unwrap(),
+        "--output-file",
+        output_path.to_str().unwrap(),
+        "--overwrite",
+        "--no-progress",
+        "--log-level",
+        "off",
+    ];
+    let result = run_ms2cc_raw(&args);
+    assert!(
+        !result.status.success(),
+        "ms2cc should fail when the log has no compile commands"
+    );
+    assert_eq!(fs::read_to_string(&output_path).unwrap(), sentinel);
+
+    let result = run_ms2cc_raw(&[&args[..], &["--allow-empty"]].concat());
+    assert!(result.status.success(), "--allow-empty should succeed");
+    assert_eq!(fs::read_to_string(&output_path).unwrap(), "[]");
+}
+
 #[test]
 fn test_sequential_build() {
     // Build the binary first